@@ -0,0 +1,90 @@
+// 命令所属的 ACL 类别, 与 Redis 的 COMMAND INFO 中的 acl_categories 一致
+
+/// 所有的类别, ACL CAT 按此顺序列出
+pub const CATEGORIES: &[&str] = &[
+    "keyspace",
+    "read",
+    "write",
+    "string",
+    "hash",
+    "bitmap",
+    "hyperloglog",
+    "geo",
+    "admin",
+    "fast",
+    "slow",
+    "dangerous",
+    "connection",
+];
+
+/// (命令名, 类别)
+pub const COMMANDS: &[(&str, &[&str])] = &[
+    ("get", &["read", "string", "fast"]),
+    ("set", &["write", "string", "slow"]),
+    ("hget", &["read", "hash", "fast"]),
+    ("hset", &["write", "hash", "fast"]),
+    ("hgetall", &["read", "hash", "slow"]),
+    ("setbit", &["write", "bitmap", "slow"]),
+    ("getbit", &["read", "bitmap", "fast"]),
+    ("bitcount", &["read", "bitmap", "slow"]),
+    ("bitpos", &["read", "bitmap", "slow"]),
+    ("bitop", &["write", "bitmap", "slow"]),
+    ("bitfield", &["write", "bitmap", "slow"]),
+    ("pfadd", &["write", "hyperloglog", "fast"]),
+    ("pfcount", &["read", "hyperloglog", "slow"]),
+    ("pfmerge", &["write", "hyperloglog", "slow"]),
+    ("geoadd", &["write", "geo", "slow"]),
+    ("geodist", &["read", "geo", "slow"]),
+    ("geopos", &["read", "geo", "slow"]),
+    ("geohash", &["read", "geo", "slow"]),
+    ("geosearch", &["read", "geo", "slow"]),
+    ("geosearchstore", &["write", "geo", "slow"]),
+    ("del", &["keyspace", "write", "slow"]),
+    ("dump", &["keyspace", "read", "slow"]),
+    ("restore", &["keyspace", "write", "slow", "dangerous"]),
+    (
+        "restore-asking",
+        &["keyspace", "write", "slow", "dangerous"],
+    ),
+    ("migrate", &["keyspace", "write", "slow", "dangerous"]),
+    ("replicaof", &["admin", "slow", "dangerous"]),
+    ("slaveof", &["admin", "slow", "dangerous"]),
+    ("replconf", &["admin", "slow", "dangerous"]),
+    ("psync", &["admin", "slow", "dangerous"]),
+    ("sync", &["admin", "slow", "dangerous"]),
+    ("wait", &["slow", "connection"]),
+    ("waitaof", &["slow", "connection"]),
+    ("cluster", &["admin", "slow", "dangerous"]),
+    ("asking", &["fast", "connection"]),
+    ("sentinel", &["admin", "slow", "dangerous"]),
+    ("auth", &["fast", "connection"]),
+    ("quit", &["fast", "connection"]),
+    ("ping", &["fast", "connection"]),
+    ("acl", &["admin", "slow", "dangerous"]),
+    ("hello", &["fast", "connection"]),
+    ("save", &["admin", "slow", "dangerous"]),
+    ("bgsave", &["admin", "slow", "dangerous"]),
+    ("lastsave", &["admin", "fast", "dangerous"]),
+    ("bgrewriteaof", &["admin", "slow", "dangerous"]),
+    ("info", &["slow", "dangerous"]),
+];
+
+pub fn is_category(name: &str) -> bool {
+    CATEGORIES.contains(&name)
+}
+
+/// 命令名在表中的名字, 不存在时返回 None
+pub fn lookup(name: &str) -> Option<&'static str> {
+    COMMANDS
+        .iter()
+        .map(|(n, _)| *n)
+        .find(|n| n.eq_ignore_ascii_case(name))
+}
+
+/// 类别中的所有命令
+pub fn in_category(category: &str) -> impl Iterator<Item = &'static str> + '_ {
+    COMMANDS
+        .iter()
+        .filter(move |(_, cats)| category == "all" || cats.contains(&category))
+        .map(|(n, _)| *n)
+}
@@ -0,0 +1,97 @@
+// glob 风格的匹配, 与 Redis 的 stringmatchlen 相同: `*`, `?`, `[abc]`, `[^a-z]` 以及 `\` 转义
+
+pub fn glob_match(pattern: &[u8], s: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => s.is_empty(),
+        Some((b'*', rest)) => {
+            // 连续的 * 等同于一个
+            let rest = &rest[rest.iter().take_while(|&&b| b == b'*').count()..];
+            if rest.is_empty() {
+                return true;
+            }
+            (0..=s.len()).any(|i| glob_match(rest, &s[i..]))
+        }
+        Some((b'?', rest)) => !s.is_empty() && glob_match(rest, &s[1..]),
+        Some((b'[', rest)) => {
+            let Some((&c, s_rest)) = s.split_first() else {
+                return false;
+            };
+            match match_class(rest, c) {
+                Some((matched, rest)) => matched && glob_match(rest, s_rest),
+                // 没有闭合的 [ 作为普通字符
+                None => c == b'[' && glob_match(rest, s_rest),
+            }
+        }
+        Some((b'\\', rest)) if !rest.is_empty() => {
+            s.first() == Some(&rest[0]) && glob_match(&rest[1..], &s[1..])
+        }
+        Some((&p, rest)) => s.first() == Some(&p) && glob_match(rest, &s[1..]),
+    }
+}
+
+// `[...]` 中的字符集合, 返回是否匹配以及 `]` 之后的 pattern
+fn match_class(pattern: &[u8], c: u8) -> Option<(bool, &[u8])> {
+    let (negate, mut p) = match pattern.first() {
+        Some(b'^') => (true, &pattern[1..]),
+        _ => (false, pattern),
+    };
+    let mut matched = false;
+    loop {
+        match p {
+            [] => return None,
+            [b']', rest @ ..] => return Some((matched != negate, rest)),
+            [b'\\', x, rest @ ..] => {
+                matched |= *x == c;
+                p = rest;
+            }
+            [start, b'-', end, rest @ ..] if *end != b']' => {
+                let (lo, hi) = if start <= end {
+                    (*start, *end)
+                } else {
+                    (*end, *start)
+                };
+                matched |= (lo..=hi).contains(&c);
+                p = rest;
+            }
+            [x, rest @ ..] => {
+                matched |= *x == c;
+                p = rest;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        let cases: &[(&str, &str, bool)] = &[
+            ("*", "anything", true),
+            ("cache:*", "cache:user:1", true),
+            ("cache:*", "session:1", false),
+            ("h?llo", "hello", true),
+            ("h?llo", "hllo", false),
+            ("h[ae]llo", "hallo", true),
+            ("h[ae]llo", "hillo", false),
+            ("h[^e]llo", "hallo", true),
+            ("h[^e]llo", "hello", false),
+            ("h[a-b]llo", "hbllo", true),
+            ("a\\*b", "a*b", true),
+            ("a\\*b", "axb", false),
+            ("a**b", "ab", true),
+            ("", "", true),
+            ("", "a", false),
+        ];
+        for (pattern, s, expected) in cases {
+            assert_eq!(
+                glob_match(pattern.as_bytes(), s.as_bytes()),
+                *expected,
+                "{} {}",
+                pattern,
+                s
+            );
+        }
+    }
+}
@@ -0,0 +1,529 @@
+//! ACL: 多个用户, 每个用户有自己的密码 (保存 SHA-256), 允许执行的命令和类别, 以及可以访问的 key
+//! 和 channel 的模式. 每个命令执行前都会检查当前连接的用户, 被拒绝的命令和认证失败记录在 ACL LOG 中.
+//! 没有 Pub/Sub 命令, `&` channel 规则只保存并在 ACL LIST / GETUSER 中显示, 不做检查.
+//! 配置了 aclfile 时启动时从文件载入用户, 格式与 ACL LIST 的输出相同.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use anyhow::{anyhow, bail, Result};
+use tracing::info;
+
+use crate::{
+    backend::now_ms,
+    cmd::{Command, KeyAccess},
+    persist::write_file,
+    Backend, BulkString, RespArray, RespFrame, RespMap, ServerConfig, SimpleError,
+};
+
+mod commands;
+mod glob;
+mod sha256;
+mod user;
+
+pub use commands::{in_category, is_category, CATEGORIES};
+pub use glob::glob_match;
+pub use sha256::hash_password;
+pub use user::{Denied, User};
+
+// ACL LOG 保留的记录数, 与 Redis 的 acllog-max-len 默认值相同
+const ACL_LOG_MAX_LEN: usize = 128;
+
+#[derive(Debug)]
+pub struct AclState {
+    inner: RwLock<AclInner>,
+}
+
+#[derive(Debug)]
+struct AclInner {
+    users: BTreeMap<String, User>,
+    log: VecDeque<LogEntry>,
+    next_entry_id: u64,
+}
+
+/// ACL LOG 中的一条记录, 相同的拒绝只增加计数
+#[derive(Debug, Clone)]
+struct LogEntry {
+    count: u64,
+    // command / key / auth
+    reason: &'static str,
+    object: String,
+    username: String,
+    client_info: String,
+    entry_id: u64,
+    created: i64,
+    updated: i64,
+}
+
+impl AclState {
+    /// 只有 default 用户; 设置了 requirepass 时它的密码为 requirepass
+    pub fn new(config: &ServerConfig) -> Self {
+        let mut default = User::default_user();
+        if let Some(password) = &config.requirepass {
+            default.set_rule("resetpass").expect("valid rule");
+            default
+                .set_rule(&format!(">{}", password))
+                .expect("valid rule");
+        }
+        let users = BTreeMap::from([(default.name.clone(), default)]);
+        AclState {
+            inner: RwLock::new(AclInner {
+                users,
+                log: VecDeque::new(),
+                next_entry_id: 0,
+            }),
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, AclInner> {
+        self.inner.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, AclInner> {
+        self.inner.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl AclInner {
+    fn add_log(&mut self, reason: &'static str, object: &str, username: &str, client_info: &str) {
+        let now = now_ms();
+        let existing = self
+            .log
+            .iter_mut()
+            .find(|e| e.reason == reason && e.object == object && e.username == username);
+        if let Some(entry) = existing {
+            entry.count += 1;
+            entry.updated = now;
+            entry.client_info = client_info.to_string();
+            return;
+        }
+        self.log.push_front(LogEntry {
+            count: 1,
+            reason,
+            object: object.to_string(),
+            username: username.to_string(),
+            client_info: client_info.to_string(),
+            entry_id: self.next_entry_id,
+            created: now,
+            updated: now,
+        });
+        self.next_entry_id += 1;
+        self.log.truncate(ACL_LOG_MAX_LEN);
+    }
+}
+
+// aclfile 的内容: 每行为 `user <name> <rule> ...`, 忽略空行和 # 开始的注释
+fn parse_users(content: &str) -> Result<BTreeMap<String, User>> {
+    let mut users = BTreeMap::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        let (Some("user"), Some(name)) = (parts.next(), parts.next()) else {
+            bail!("line {}: should start with user keyword", i + 1);
+        };
+        let mut user = User::new(name);
+        for rule in parts {
+            user.set_rule(rule).map_err(|e| {
+                anyhow!(
+                    "line {}: Error in user declaration '{}': {}",
+                    i + 1,
+                    rule,
+                    e
+                )
+            })?;
+        }
+        if users.insert(name.to_string(), user).is_some() {
+            bail!("line {}: Duplicate user '{}' found", i + 1, name);
+        }
+    }
+    users
+        .entry("default".to_string())
+        .or_insert_with(User::default_user);
+    Ok(users)
+}
+
+impl Backend {
+    /// default 用户不需要密码时, 新连接自动以 default 用户认证
+    pub fn acl_default_nopass(&self) -> bool {
+        let inner = self.acl.read();
+        inner
+            .users
+            .get("default")
+            .is_some_and(|u| u.enabled && u.nopass)
+    }
+
+    /// AUTH: 用户存在、已启用且密码正确; 失败时记录到 ACL LOG
+    pub fn acl_authenticate(&self, username: &str, password: &str, client_info: &str) -> bool {
+        let mut inner = self.acl.write();
+        let ok = inner
+            .users
+            .get(username)
+            .is_some_and(|u| u.enabled && u.check_password(password));
+        if !ok {
+            inner.add_log("auth", "AUTH", username, client_info);
+        }
+        ok
+    }
+
+    /// 检查用户是否可以执行命令, 拒绝时记录到 ACL LOG 并返回 NOPERM 错误
+    pub fn acl_check(
+        &self,
+        username: &str,
+        cmd: &Command,
+        client_info: &str,
+    ) -> Result<(), RespFrame> {
+        if !cmd.needs_permission() {
+            return Ok(());
+        }
+        self.acl_check_command(username, cmd.name(), &cmd.key_access(), client_info)
+    }
+
+    pub fn acl_check_command(
+        &self,
+        username: &str,
+        command: &str,
+        keys: &[(&str, KeyAccess)],
+        client_info: &str,
+    ) -> Result<(), RespFrame> {
+        let denied = {
+            let inner = self.acl.read();
+            match inner.users.get(username) {
+                Some(user) => user.check(command, keys),
+                // 用户已被删除
+                None => Err(Denied::Command(command.to_string())),
+            }
+        };
+        let Err(denied) = denied else {
+            return Ok(());
+        };
+        let mut inner = self.acl.write();
+        match denied {
+            Denied::Command(command) => {
+                inner.add_log("command", &command, username, client_info);
+                Err(SimpleError::new(format!(
+                    "NOPERM User {} has no permissions to run the '{}' command",
+                    username, command
+                ))
+                .into())
+            }
+            Denied::Key(key) => {
+                inner.add_log("key", &key, username, client_info);
+                Err(SimpleError::new("NOPERM No permissions to access a key").into())
+            }
+        }
+    }
+
+    /// ACL SETUSER: 所有规则都有效时才修改用户
+    pub fn acl_setuser(&self, name: &str, rules: &[String]) -> Result<()> {
+        let mut inner = self.acl.write();
+        let mut user = inner
+            .users
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| User::new(name));
+        for rule in rules {
+            user.set_rule(rule)
+                .map_err(|e| anyhow!("Error in ACL SETUSER modifier '{}': {}", rule, e))?;
+        }
+        inner.users.insert(name.to_string(), user);
+        Ok(())
+    }
+
+    /// ACL GETUSER
+    pub fn acl_getuser(&self, name: &str) -> Option<RespFrame> {
+        let inner = self.acl.read();
+        let user = inner.users.get(name)?;
+        let strings = |items: Vec<String>| -> RespFrame {
+            RespArray::new(
+                items
+                    .into_iter()
+                    .map(|s| BulkString::new(s).into())
+                    .collect::<Vec<_>>(),
+            )
+            .into()
+        };
+        let mut map = RespMap::new();
+        map.insert(
+            BulkString::new("flags"),
+            strings(user.flags().iter().map(|f| f.to_string()).collect()),
+        );
+        map.insert(
+            BulkString::new("passwords"),
+            strings(user.passwords.iter().cloned().collect()),
+        );
+        map.insert(
+            BulkString::new("commands"),
+            BulkString::new(user.commands()).into(),
+        );
+        map.insert(BulkString::new("keys"), BulkString::new(user.keys()).into());
+        map.insert(
+            BulkString::new("channels"),
+            BulkString::new(user.channels()).into(),
+        );
+        Some(map.into())
+    }
+
+    /// ACL DELUSER, 返回删除的用户数
+    pub fn acl_deluser(&self, names: &[String]) -> Result<usize> {
+        if names.iter().any(|n| n == "default") {
+            bail!("The 'default' user cannot be removed");
+        }
+        let mut inner = self.acl.write();
+        Ok(names
+            .iter()
+            .filter(|n| inner.users.remove(n.as_str()).is_some())
+            .count())
+    }
+
+    pub fn acl_users(&self) -> Vec<String> {
+        self.acl.read().users.keys().cloned().collect()
+    }
+
+    /// ACL LIST
+    pub fn acl_list(&self) -> Vec<String> {
+        self.acl.read().users.values().map(User::describe).collect()
+    }
+
+    /// ACL LOG [count], 最新的记录在前
+    pub fn acl_log(&self, count: usize) -> RespFrame {
+        let now = now_ms();
+        let inner = self.acl.read();
+        let entries = inner
+            .log
+            .iter()
+            .take(count)
+            .map(|e| {
+                let mut map = RespMap::new();
+                map.insert(BulkString::new("count"), RespFrame::Integer(e.count as i64));
+                map.insert(BulkString::new("reason"), BulkString::new(e.reason).into());
+                map.insert(
+                    BulkString::new("context"),
+                    BulkString::new("toplevel").into(),
+                );
+                map.insert(
+                    BulkString::new("object"),
+                    BulkString::new(e.object.clone()).into(),
+                );
+                map.insert(
+                    BulkString::new("username"),
+                    BulkString::new(e.username.clone()).into(),
+                );
+                map.insert(
+                    BulkString::new("age-seconds"),
+                    RespFrame::Double((now - e.created) as f64 / 1000.0),
+                );
+                map.insert(
+                    BulkString::new("client-info"),
+                    BulkString::new(e.client_info.clone()).into(),
+                );
+                map.insert(
+                    BulkString::new("entry-id"),
+                    RespFrame::Integer(e.entry_id as i64),
+                );
+                map.insert(
+                    BulkString::new("timestamp-created"),
+                    RespFrame::Integer(e.created),
+                );
+                map.insert(
+                    BulkString::new("timestamp-last-updated"),
+                    RespFrame::Integer(e.updated),
+                );
+                map.into()
+            })
+            .collect::<Vec<RespFrame>>();
+        RespArray::new(entries).into()
+    }
+
+    pub fn acl_log_reset(&self) {
+        self.acl.write().log.clear();
+    }
+
+    /// 从 aclfile 载入用户, 文件中有错误时保持原来的用户
+    pub fn load_acl(&self) -> Result<()> {
+        let path = self
+            .config()
+            .aclfile
+            .as_ref()
+            .ok_or_else(|| anyhow!("This Redis instance is not configured to use an ACL file. You may want to specify users via the ACL SETUSER command and then issue a CONFIG REWRITE (assuming you have a Redis configuration file set) in order to store users in the Redis configuration."))?;
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let users = parse_users(&content).map_err(|e| anyhow!("{}:{}", path.display(), e))?;
+        info!("Loaded {} ACL users from {}", users.len(), path.display());
+        self.acl.write().users = users;
+        Ok(())
+    }
+
+    /// ACL SAVE
+    pub fn save_acl(&self) -> Result<()> {
+        let path =
+            self.config().aclfile.as_ref().ok_or_else(|| {
+                anyhow!("This Redis instance is not configured to use an ACL file.")
+            })?;
+        let mut content = self.acl_list().join("\n");
+        content.push('\n');
+        write_file(path, content.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cmd::CommandExecutor, network::testing::command};
+
+    #[test]
+    fn test_acl_check() -> Result<()> {
+        let backend = Backend::new();
+        assert!(backend.acl_default_nopass());
+        let rules = ["on", ">pass", "~cache:*", "+@read", "+set"].map(String::from);
+        backend.acl_setuser("alice", &rules)?;
+        assert!(backend.acl_authenticate("alice", "pass", "addr=127.0.0.1:1"));
+        assert!(!backend.acl_authenticate("alice", "wrong", "addr=127.0.0.1:1"));
+        assert!(!backend.acl_authenticate("bob", "pass", "addr=127.0.0.1:1"));
+
+        let cmd = command(&["SET", "cache:1", "v"])?;
+        assert!(backend.acl_check("alice", &cmd, "").is_ok());
+        cmd.execute(&backend);
+        assert!(backend
+            .acl_check("alice", &command(&["GET", "cache:1"])?, "")
+            .is_ok());
+        let denied = backend.acl_check("alice", &command(&["GET", "other"])?, "");
+        assert_eq!(
+            denied,
+            Err(SimpleError::new("NOPERM No permissions to access a key").into())
+        );
+        let denied = backend.acl_check("alice", &command(&["DEL", "cache:1"])?, "");
+        assert!(matches!(denied, Err(RespFrame::Error(e)) if e.contains("'del' command")));
+        assert!(backend
+            .acl_check("alice", &command(&["AUTH", "x"])?, "")
+            .is_ok());
+
+        // 同样的拒绝只增加计数
+        backend
+            .acl_check("alice", &command(&["DEL", "cache:1"])?, "")
+            .unwrap_err();
+        let RespFrame::Array(log) = backend.acl_log(10) else {
+            panic!("ACL LOG should be an array");
+        };
+        assert_eq!(log.len(), 4);
+        let RespFrame::Map(latest) = &log[0] else {
+            panic!("ACL LOG entry should be a map");
+        };
+        assert_eq!(latest["reason"], BulkString::new("command").into());
+        assert_eq!(latest["count"], RespFrame::Integer(2));
+        backend.acl_log_reset();
+        assert_eq!(backend.acl_log(10), RespArray::new(vec![]).into());
+
+        assert!(backend.acl_deluser(&["default".to_string()]).is_err());
+        assert_eq!(
+            backend.acl_deluser(&["alice".to_string(), "bob".to_string()])?,
+            1
+        );
+        assert!(backend
+            .acl_check("alice", &command(&["GET", "cache:1"])?, "")
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_acl_key_access() -> Result<()> {
+        let backend = Backend::new();
+        let rules = ["on", "nopass", "%R~src:*", "%W~dst:*", "+@all"].map(String::from);
+        backend.acl_setuser("bob", &rules)?;
+        let check = |args: &[&str]| -> Result<bool> {
+            Ok(backend.acl_check("bob", &command(args)?, "").is_ok())
+        };
+
+        // 目标 key 只需要写权限, 源 key 只需要读权限
+        assert!(check(&["BITOP", "AND", "dst:1", "src:1", "src:2"])?);
+        assert!(!check(&["BITOP", "AND", "src:1", "dst:1"])?);
+        assert!(check(&[
+            "GEOSEARCHSTORE",
+            "dst:1",
+            "src:1",
+            "FROMLONLAT",
+            "0",
+            "0",
+            "BYRADIUS",
+            "1",
+            "km"
+        ])?);
+        // PFMERGE 读取目标中已有的数据, 需要读写权限
+        assert!(!check(&["PFMERGE", "dst:1", "src:1"])?);
+        backend.acl_setuser("bob", &["~dst:*".to_string()])?;
+        assert!(check(&["PFMERGE", "dst:1", "src:1"])?);
+        assert!(!check(&["SETBIT", "src:1", "0", "1"])?);
+
+        let whoami = command(&["ACL", "WHOAMI"])?.execute(&backend);
+        assert!(matches!(whoami, RespFrame::Error(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_acl_dangerous() -> Result<()> {
+        let backend = Backend::new();
+        let rules = ["on", "nopass", "~*", "+@all", "-@dangerous"].map(String::from);
+        backend.acl_setuser("carol", &rules)?;
+        let check = |args: &[&str]| -> Result<bool> {
+            Ok(backend.acl_check("carol", &command(args)?, "").is_ok())
+        };
+
+        // ACL 和 CLUSTER 的修改类子命令属于 @dangerous, 不能借此提升权限
+        assert!(!check(&["ACL", "SETUSER", "carol", "+@all"])?);
+        assert!(!check(&["ACL", "DELUSER", "default"])?);
+        assert!(!check(&["CLUSTER", "SETSLOT", "1", "STABLE"])?);
+        assert!(!check(&["CLUSTER", "FAILOVER"])?);
+        // 查看自身和集群拓扑不需要权限
+        assert!(check(&["ACL", "WHOAMI"])?);
+        assert!(check(&["ACL", "CAT"])?);
+        assert!(check(&["CLUSTER", "SLOTS"])?);
+        assert!(check(&["GET", "key"])?);
+
+        backend.acl_setuser(
+            "dave",
+            &["on", "nopass", "+@all", "-@admin"].map(String::from),
+        )?;
+        assert!(backend
+            .acl_check("dave", &command(&["ACL", "SETUSER", "dave", "+@all"])?, "")
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_acl_file() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("acl-test-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let config = ServerConfig {
+            aclfile: Some(dir.join("users.acl")),
+            requirepass: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let backend = Backend::with_config(config.clone());
+        assert!(!backend.acl_default_nopass());
+        assert!(backend.acl_authenticate("default", "secret", ""));
+        backend.acl_setuser(
+            "alice",
+            &["on", ">pass", "%R~cache:*", "&events:*", "+get"].map(String::from),
+        )?;
+        backend.save_acl()?;
+
+        let other = Backend::with_config(config.clone());
+        other.load_acl()?;
+        assert_eq!(other.acl_list(), backend.acl_list());
+        assert!(other.acl_authenticate("alice", "pass", ""));
+
+        fs::write(dir.join("users.acl"), "user alice on +nosuchcommand\n")?;
+        assert!(other.load_acl().is_err());
+        assert_eq!(other.acl_users(), vec!["alice", "default"]);
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::BTreeSet;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::cmd::KeyAccess;
+
+use super::{
+    commands::{in_category, is_category, lookup, COMMANDS},
+    glob::glob_match,
+    sha256::hash_password,
+};
+
+/// 一个 ACL 用户: 密码的 hash, 允许执行的命令, 以及可以访问的 key 和 channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct User {
+    pub name: String,
+    pub enabled: bool,
+    // 任何密码都可以认证
+    pub nopass: bool,
+    pub passwords: BTreeSet<String>,
+    commands: BTreeSet<&'static str>,
+    // 命令规则的描述: 以 +@all 或 -@all 开始, 之后为依次应用的规则
+    command_rules: Vec<String>,
+    keys: Vec<KeyPattern>,
+    // 只保存, 没有 Pub/Sub 命令需要检查
+    channels: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct KeyPattern {
+    pattern: String,
+    read: bool,
+    write: bool,
+}
+
+/// 命令被拒绝的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum Denied {
+    Command(String),
+    Key(String),
+}
+
+impl User {
+    /// 新用户: 禁用, 没有密码, 不能执行任何命令, 不能访问任何 key 和 channel
+    pub fn new(name: &str) -> Self {
+        User {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: BTreeSet::new(),
+            commands: BTreeSet::new(),
+            command_rules: vec!["-@all".to_string()],
+            keys: vec![],
+            channels: vec![],
+        }
+    }
+
+    /// 未配置时的 default 用户: 不需要密码, 可以执行所有命令
+    pub fn default_user() -> Self {
+        let mut user = User::new("default");
+        for rule in ["on", "nopass", "~*", "&*", "+@all"] {
+            user.set_rule(rule).expect("valid default user rule");
+        }
+        user
+    }
+
+    /// 应用一条 ACL SETUSER 规则
+    pub fn set_rule(&mut self, rule: &str) -> Result<()> {
+        match rule.to_ascii_lowercase().as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => return self.set_rule("~*"),
+            "resetkeys" => self.keys.clear(),
+            "allchannels" => return self.set_rule("&*"),
+            "resetchannels" => self.channels.clear(),
+            "allcommands" => return self.set_rule("+@all"),
+            "nocommands" => return self.set_rule("-@all"),
+            "reset" => {
+                for rule in ["resetpass", "resetkeys", "resetchannels", "off", "-@all"] {
+                    self.set_rule(rule)?;
+                }
+            }
+            _ => self.set_pattern_rule(rule)?,
+        }
+        Ok(())
+    }
+
+    fn set_pattern_rule(&mut self, rule: &str) -> Result<()> {
+        let (prefix, rest) = rule.split_at(rule.chars().next().map_or(0, char::len_utf8));
+        match prefix {
+            ">" => {
+                self.passwords.insert(hash_password(rest));
+                self.nopass = false;
+            }
+            "#" => {
+                if rest.len() != 64 || !rest.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    bail!("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters");
+                }
+                self.passwords.insert(rest.to_ascii_lowercase());
+                self.nopass = false;
+            }
+            "<" | "!" => {
+                let hash = match prefix {
+                    "<" => hash_password(rest),
+                    _ => rest.to_ascii_lowercase(),
+                };
+                if !self.passwords.remove(&hash) {
+                    bail!("The password you are trying to remove from the user does not exist");
+                }
+            }
+            "~" => self.add_key_pattern(rest, true, true),
+            "%" => {
+                // %R~<pattern>, %W~<pattern>, %RW~<pattern>
+                let (flags, pattern) = rest
+                    .split_once('~')
+                    .ok_or_else(|| anyhow!("Syntax error"))?;
+                let flags = flags.to_ascii_uppercase();
+                if flags.is_empty() || !flags.chars().all(|c| c == 'R' || c == 'W') {
+                    bail!("Syntax error");
+                }
+                self.add_key_pattern(pattern, flags.contains('R'), flags.contains('W'));
+            }
+            "&" => {
+                if !self.channels.iter().any(|c| c == rest) {
+                    self.channels.push(rest.to_string());
+                }
+            }
+            "+" | "-" => {
+                let allow = prefix == "+";
+                let name = rest.to_ascii_lowercase();
+                if name == "@all" {
+                    // +@all / -@all 覆盖之前所有的命令规则
+                    self.commands.clear();
+                    self.command_rules.clear();
+                    if allow {
+                        self.commands.extend(COMMANDS.iter().map(|(n, _)| *n));
+                    }
+                } else {
+                    let commands: Vec<&'static str> = match name.strip_prefix('@') {
+                        Some(category) if is_category(category) => in_category(category).collect(),
+                        Some(_) => bail!("Unknown command or category name in ACL"),
+                        None => vec![lookup(&name)
+                            .ok_or_else(|| anyhow!("Unknown command or category name in ACL"))?],
+                    };
+                    for command in commands {
+                        if allow {
+                            self.commands.insert(command);
+                        } else {
+                            self.commands.remove(command);
+                        }
+                    }
+                }
+                self.command_rules.push(format!("{}{}", prefix, name));
+            }
+            _ => bail!("Syntax error"),
+        }
+        Ok(())
+    }
+
+    fn add_key_pattern(&mut self, pattern: &str, read: bool, write: bool) {
+        match self.keys.iter_mut().find(|k| k.pattern == pattern) {
+            Some(key) => {
+                key.read |= read;
+                key.write |= write;
+            }
+            None => self.keys.push(KeyPattern {
+                pattern: pattern.to_string(),
+                read,
+                write,
+            }),
+        }
+    }
+
+    /// 密码是否正确
+    pub fn check_password(&self, password: &str) -> bool {
+        self.nopass || self.passwords.contains(&hash_password(password))
+    }
+
+    /// 是否可以执行命令并访问这些 key, 每个 key 需要一个模式同时允许它所需的读写权限
+    pub fn check(&self, command: &str, keys: &[(&str, KeyAccess)]) -> Result<(), Denied> {
+        if !self.enabled || !self.commands.contains(command) {
+            return Err(Denied::Command(command.to_string()));
+        }
+        for &(key, access) in keys {
+            let allowed = self.keys.iter().any(|k| {
+                (k.read || !access.reads())
+                    && (k.write || !access.writes())
+                    && glob_match(k.pattern.as_bytes(), key.as_bytes())
+            });
+            if !allowed {
+                return Err(Denied::Key(key.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        flags
+    }
+
+    pub fn commands(&self) -> String {
+        self.command_rules.join(" ")
+    }
+
+    pub fn keys(&self) -> String {
+        self.keys
+            .iter()
+            .map(|k| match (k.read, k.write) {
+                (true, true) => format!("~{}", k.pattern),
+                (true, false) => format!("%R~{}", k.pattern),
+                _ => format!("%W~{}", k.pattern),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn channels(&self) -> String {
+        self.channels
+            .iter()
+            .map(|c| format!("&{}", c))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// ACL LIST 中的描述, 也是 aclfile 中的格式
+    pub fn describe(&self) -> String {
+        let mut parts = vec![format!("user {}", self.name)];
+        parts.extend(self.flags().iter().map(|f| f.to_string()));
+        parts.extend(self.passwords.iter().map(|p| format!("#{}", p)));
+        let keys = self.keys();
+        if !keys.is_empty() {
+            parts.push(keys);
+        }
+        match self.channels() {
+            channels if channels.is_empty() => parts.push("resetchannels".to_string()),
+            channels => parts.push(channels),
+        }
+        parts.push(self.commands());
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_rules() -> Result<()> {
+        let mut user = User::new("alice");
+        for rule in [
+            "on",
+            ">secret",
+            "~cache:*",
+            "%R~config:*",
+            "&events:*",
+            "+@read",
+            "-hgetall",
+            "+set",
+        ] {
+            user.set_rule(rule)?;
+        }
+        assert!(user.check_password("secret"));
+        assert!(!user.check_password("other"));
+        assert_eq!(user.check("get", &[("cache:1", KeyAccess::Read)]), Ok(()));
+        assert_eq!(user.check("set", &[("cache:1", KeyAccess::Write)]), Ok(()));
+        assert_eq!(user.check("get", &[("config:1", KeyAccess::Read)]), Ok(()));
+        assert_eq!(
+            user.check("set", &[("config:1", KeyAccess::Write)]),
+            Err(Denied::Key("config:1".to_string()))
+        );
+        assert_eq!(
+            user.check("hgetall", &[("cache:1", KeyAccess::Read)]),
+            Err(Denied::Command("hgetall".to_string()))
+        );
+        assert_eq!(
+            user.check("del", &[("cache:1", KeyAccess::Write)]),
+            Err(Denied::Command("del".to_string()))
+        );
+        assert_eq!(
+            user.describe(),
+            format!(
+                "user alice on #{} ~cache:* %R~config:* &events:* -@all +@read -hgetall +set",
+                hash_password("secret")
+            )
+        );
+
+        // 描述可以重新解析为相同的用户
+        let mut copy = User::new("alice");
+        for rule in user.describe().split(' ').skip(2) {
+            copy.set_rule(rule)?;
+        }
+        assert_eq!(copy.describe(), user.describe());
+
+        user.set_rule("<secret")?;
+        assert!(!user.check_password("secret"));
+        assert!(user.set_rule("<secret").is_err());
+        assert!(user.set_rule("+nosuchcommand").is_err());
+        assert!(user.set_rule("+@nosuchcategory").is_err());
+        assert!(user.set_rule("#abc").is_err());
+        assert!(user.set_rule("bogus").is_err());
+
+        user.set_rule("reset")?;
+        assert_eq!(user.describe(), "user alice off resetchannels -@all");
+        assert!(User::default_user()
+            .check("set", &[("any", KeyAccess::ReadWrite)])
+            .is_ok());
+        Ok(())
+    }
+}
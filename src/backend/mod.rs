@@ -1,17 +1,74 @@
-use std::{ops::Deref, sync::Arc};
+use std::{
+    collections::{hash_map::RandomState, HashSet},
+    hash::{BuildHasher, Hasher},
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use dashmap::DashMap;
+use bytes::Bytes;
+use dashmap::{
+    mapref::{entry::Entry, one::Ref},
+    DashMap,
+};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::warn;
 
-use crate::RespFrame;
+use crate::{
+    acl::AclState,
+    cluster::{key_hash_slot, ClusterState},
+    persist::{AofState, SaveState},
+    replication::ReplicationState,
+    sentinel::SentinelState,
+    BulkString, RespFrame, RespPush, ServerConfig,
+};
+
+mod snapshot;
+mod zset;
+
+pub use snapshot::{Snapshot, SnapshotEntry, StoredValue};
+pub use zset::SortedSet;
 
 #[derive(Debug, Clone)]
 pub struct Backend(Arc<BackendInner>);
 
+/// 值的类型, 每种类型存放在各自的 keyspace 中
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    String,
+    Hash,
+    ZSet,
+}
+
 // 使用 DashMap, 实现 Redis 存储
 #[derive(Debug)]
 pub struct BackendInner {
     map: DashMap<String, RespFrame>,
     hmap: DashMap<String, DashMap<String, RespFrame>>,
+    zset: DashMap<String, SortedSet>,
+    // key 的过期时间, unix 毫秒时间戳
+    expire: DashMap<String, i64>,
+    // 集群模式下 slot -> 其中的 key, GETKEYSINSLOT 等不需要遍历整个 keyspace
+    slot_keys: DashMap<u16, HashSet<String>>,
+    // 上次保存以来的修改次数
+    dirty: AtomicU64,
+    config: ServerConfig,
+    // 写命令在执行和传播期间持有读锁; 需要一份与命令流衔接的数据副本时
+    // (BGREWRITEAOF, replica 全量同步) 持有写锁, 保证每条命令要么在副本中, 要么在之后的命令流中
+    barrier: RwLock<()>,
+    // 写命令从执行到写入 AOF 和命令流期间持有, 保证执行顺序与 AOF, 命令流中的顺序一致
+    order: Mutex<()>,
+    pub(crate) save_state: SaveState,
+    pub(crate) aof: AofState,
+    pub(crate) repl: ReplicationState,
+    pub(crate) cluster: ClusterState,
+    pub(crate) sentinel: SentinelState,
+    pub(crate) acl: AclState,
+    // 连接 id -> 向 RESP3 连接发送 push 数据的 channel
+    clients: DashMap<u64, mpsc::Sender<RespFrame>>,
 }
 
 impl Deref for Backend {
@@ -23,9 +80,80 @@ impl Deref for Backend {
 
 impl BackendInner {
     pub fn new() -> Self {
+        Self::with_config(ServerConfig::default())
+    }
+    pub fn with_config(config: ServerConfig) -> Self {
         BackendInner {
             map: DashMap::new(),
             hmap: DashMap::new(),
+            zset: DashMap::new(),
+            expire: DashMap::new(),
+            slot_keys: DashMap::new(),
+            dirty: AtomicU64::new(0),
+            cluster: ClusterState::new(&config),
+            sentinel: SentinelState::new(&config),
+            acl: AclState::new(&config),
+            config,
+            barrier: RwLock::new(()),
+            order: Mutex::new(()),
+            save_state: SaveState::default(),
+            aof: AofState::default(),
+            repl: ReplicationState::default(),
+            clients: DashMap::new(),
+        }
+    }
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+    pub fn dirty(&self) -> u64 {
+        self.dirty.load(Ordering::Relaxed)
+    }
+    pub(crate) fn sub_dirty(&self, n: u64) {
+        let _ = self
+            .dirty
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |d| {
+                Some(d.saturating_sub(n))
+            });
+    }
+    fn touch(&self) {
+        self.dirty.fetch_add(1, Ordering::Relaxed);
+    }
+    /// 执行写命令前获取
+    pub fn write_barrier(&self) -> RwLockReadGuard<'_, ()> {
+        self.barrier.read().unwrap_or_else(|e| e.into_inner())
+    }
+    /// 执行写命令并传播时获取, 在 write_barrier 之后获取
+    pub(crate) fn write_order(&self) -> MutexGuard<'_, ()> {
+        self.order.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    /// 阻塞所有写命令, 直到返回的 guard 被释放
+    pub fn block_writes(&self) -> RwLockWriteGuard<'_, ()> {
+        self.barrier.write().unwrap_or_else(|e| e.into_inner())
+    }
+    /// 注册 RESP3 连接, 之后可以通过 push 向连接发送数据
+    pub fn register_client(&self, id: u64, tx: mpsc::Sender<RespFrame>) {
+        self.clients.insert(id, tx);
+    }
+    pub fn unregister_client(&self, id: u64) {
+        self.clients.remove(&id);
+    }
+    /// 向 RESP3 连接发送 push 数据, 在两个回复之间送达; 连接不存在或使用 RESP2 时返回 false.
+    /// 连接来不及发送, 缓冲区已满时丢弃 channel, 连接随之关闭
+    pub fn push(&self, id: u64, push: RespPush) -> bool {
+        let Some(tx) = self.clients.get(&id).map(|tx| tx.clone()) else {
+            return false;
+        };
+        match tx.try_send(push.into()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                warn!(
+                    "Client id={} closed for overcoming of push buffer limits",
+                    id
+                );
+                self.clients.remove(&id);
+                false
+            }
+            Err(TrySendError::Closed(_)) => false,
         }
     }
 }
@@ -36,10 +164,7 @@ impl Default for Backend {
 }
 impl Default for BackendInner {
     fn default() -> Self {
-        BackendInner {
-            map: DashMap::new(),
-            hmap: DashMap::new(),
-        }
+        Self::new()
     }
 }
 
@@ -47,22 +172,380 @@ impl Backend {
     pub fn new() -> Self {
         Self::default()
     }
+    pub fn with_config(config: ServerConfig) -> Self {
+        Self(Arc::new(BackendInner::with_config(config)))
+    }
     pub fn get(&self, key: &str) -> Option<RespFrame> {
+        self.expire_if_needed(key);
         self.map.get(key).map(|v| v.value().clone())
     }
     pub fn set(&self, key: String, value: RespFrame) {
-        self.map.insert(key, value);
+        // 与 Redis 一致, SET 会清除 key 原有的过期时间, 并覆盖其它类型的值
+        self.expire.remove(&key);
+        self.hmap.remove(&key);
+        self.zset.remove(&key);
+        self.map.insert(key.clone(), own_small(value));
+        self.index_key(&key);
+        self.touch();
+    }
+    /// 在持有 key 所在分片锁的情况下读改写 key 的值, 保证操作的原子性.
+    /// key 不存在时 `f` 收到 `None`; `f` 返回后值为 `None` 则删除该 key.
+    /// 值没有变化时 (如 PFCOUNT 命中缓存) 不计入修改次数.
+    pub fn update<R>(&self, key: &str, f: impl FnOnce(&mut Option<RespFrame>) -> R) -> R {
+        self.expire_if_needed(key);
+        match self.map.entry(key.to_string()) {
+            Entry::Occupied(mut entry) => {
+                // 字符串是 Bytes, 复制只增加引用计数
+                let mut value = Some(entry.get().clone());
+                let ret = f(&mut value);
+                match value {
+                    Some(value) if value == *entry.get() => {}
+                    Some(value) => {
+                        *entry.get_mut() = own_small(value);
+                        self.touch();
+                    }
+                    None => {
+                        entry.remove();
+                        self.expire.remove(key);
+                        self.unindex_key(key);
+                        self.touch();
+                    }
+                }
+                ret
+            }
+            Entry::Vacant(entry) => {
+                let mut value = None;
+                let ret = f(&mut value);
+                if let Some(value) = value {
+                    entry.insert(own_small(value));
+                    self.index_key(key);
+                    self.touch();
+                }
+                ret
+            }
+        }
     }
     pub fn hget(&self, key: &str, field: &str) -> Option<RespFrame> {
+        self.expire_if_needed(key);
         self.hmap
             .get(key)
             .and_then(|v| v.get(field).map(|v| v.value().clone()))
     }
     pub fn hset(&self, key: String, field: String, value: RespFrame) {
-        let hmap = self.hmap.entry(key).or_default();
-        hmap.insert(field, value);
+        self.expire_if_needed(&key);
+        self.touch();
+        self.hmap
+            .entry(key.clone())
+            .or_default()
+            .insert(field, own_small(value));
+        self.index_key(&key);
     }
     pub fn hgetall(&self, key: &str) -> Option<DashMap<String, RespFrame>> {
+        self.expire_if_needed(key);
         self.hmap.get(key).map(|v| v.clone())
     }
+    pub fn zget(&self, key: &str) -> Option<Ref<'_, String, SortedSet>> {
+        self.expire_if_needed(key);
+        self.zset.get(key)
+    }
+    /// 原子地修改有序集合, key 不存在时先创建空集合, 修改后为空则删除
+    pub fn zupdate<R>(&self, key: &str, f: impl FnOnce(&mut SortedSet) -> R) -> R {
+        self.expire_if_needed(key);
+        self.touch();
+        let mut zset = self.zset.entry(key.to_string()).or_default();
+        let ret = f(&mut zset);
+        let empty = zset.is_empty();
+        drop(zset);
+        if !empty {
+            self.index_key(key);
+        } else if self.zset.remove_if(key, |_, v| v.is_empty()).is_some() {
+            self.expire.remove(key);
+            self.unindex_key(key);
+        }
+        ret
+    }
+    /// 用新的有序集合覆盖 key, 集合为空时删除 key
+    pub fn zstore(&self, key: String, zset: SortedSet) {
+        self.expire.remove(&key);
+        self.map.remove(&key);
+        self.hmap.remove(&key);
+        self.touch();
+        if zset.is_empty() {
+            self.zset.remove(&key);
+            self.unindex_key(&key);
+        } else {
+            self.zset.insert(key.clone(), zset);
+            self.index_key(&key);
+        }
+    }
+    /// 设置 key 的过期时间 (unix 毫秒时间戳), key 不存在时返回 false
+    pub fn expire_at(&self, key: &str, when_ms: i64) -> bool {
+        if !self.exists(key) {
+            return false;
+        }
+        self.expire.insert(key.to_string(), when_ms);
+        self.touch();
+        true
+    }
+    /// key 的过期时间 (unix 毫秒时间戳), 未设置过期时间或 key 不存在时返回 None
+    pub fn expire_time(&self, key: &str) -> Option<i64> {
+        self.expire_if_needed(key);
+        self.expire.get(key).map(|v| *v)
+    }
+    /// key 的类型, key 不存在时返回 None
+    pub fn key_type(&self, key: &str) -> Option<KeyType> {
+        self.expire_if_needed(key);
+        if self.map.contains_key(key) {
+            Some(KeyType::String)
+        } else if self.hmap.contains_key(key) {
+            Some(KeyType::Hash)
+        } else if self.zset.contains_key(key) {
+            Some(KeyType::ZSet)
+        } else {
+            None
+        }
+    }
+    /// key 存在且不是 ty 类型, 命令应返回 WRONGTYPE
+    pub fn is_wrong_type(&self, key: &str, ty: KeyType) -> bool {
+        self.key_type(key).is_some_and(|t| t != ty)
+    }
+    pub fn exists(&self, key: &str) -> bool {
+        self.expire_if_needed(key);
+        self.map.contains_key(key) || self.hmap.contains_key(key) || self.zset.contains_key(key)
+    }
+    /// 所有未过期的 key
+    pub fn keys(&self) -> Vec<String> {
+        let now = now_ms();
+        let keys = self.map.iter().map(|e| e.key().clone());
+        let keys = keys.chain(self.hmap.iter().map(|e| e.key().clone()));
+        let keys = keys.chain(self.zset.iter().map(|e| e.key().clone()));
+        keys.filter(|k| self.expire.get(k).is_none_or(|t| *t > now))
+            .collect()
+    }
+    /// 删除 key, 不论其类型
+    pub fn del(&self, key: &str) -> bool {
+        self.expire.remove(key);
+        let removed = self.map.remove(key).is_some()
+            | self.hmap.remove(key).is_some()
+            | self.zset.remove(key).is_some();
+        if removed {
+            self.unindex_key(key);
+            self.touch();
+        }
+        removed
+    }
+    /// slot 中最多 count 个未过期的 key
+    pub fn keys_in_slot(&self, slot: u16, count: usize) -> Vec<String> {
+        let now = now_ms();
+        let Some(keys) = self.slot_keys.get(&slot) else {
+            return vec![];
+        };
+        keys.iter()
+            .filter(|k| self.expire.get(*k).is_none_or(|t| *t > now))
+            .take(count)
+            .cloned()
+            .collect()
+    }
+    // key 写入任一 keyspace 之后调用, 不能持有 keyspace 的锁
+    fn index_key(&self, key: &str) {
+        if !self.config.cluster_enabled {
+            return;
+        }
+        let mut keys = self
+            .slot_keys
+            .entry(key_hash_slot(key.as_bytes()))
+            .or_default();
+        if !keys.contains(key) {
+            keys.insert(key.to_string());
+        }
+    }
+    // key 从 keyspace 删除之后调用. 持有 slot 的锁时确认 key 已不存在才移除,
+    // 并发写入同一个 key 时, 写入方的 index_key 要么在此之后, 要么已经使 key 存在
+    fn unindex_key(&self, key: &str) {
+        if !self.config.cluster_enabled {
+            return;
+        }
+        let slot = key_hash_slot(key.as_bytes());
+        if let Entry::Occupied(mut keys) = self.slot_keys.entry(slot) {
+            let exists = self.map.contains_key(key)
+                || self.hmap.contains_key(key)
+                || self.zset.contains_key(key);
+            if !exists {
+                keys.get_mut().remove(key);
+                if keys.get().is_empty() {
+                    keys.remove();
+                }
+            }
+        }
+    }
+    // 惰性删除: 访问 key 时发现已过期则删除
+    fn expire_if_needed(&self, key: &str) {
+        let expired = self.expire.get(key).is_some_and(|t| *t <= now_ms());
+        if expired {
+            self.del(key);
+        }
+    }
+}
+
+/// 40 个十六进制字符的随机 ID, 用作 replication ID 和集群节点 ID
+pub(crate) fn random_id() -> String {
+    let mut id = String::with_capacity(48);
+    while id.len() < 40 {
+        id.push_str(&format!("{:016x}", random_u64()));
+    }
+    id.truncate(40);
+    id
+}
+
+// 小于此长度的字符串保存前复制一份
+const SHARED_MIN_LEN: usize = 4096;
+
+/// 解码得到的字符串是读缓冲区的切片, 保存切片会使整个缓冲区无法释放.
+/// 较小的值复制后再保存, 较大的值本身占了缓冲区的大部分, 直接保存切片
+pub(crate) fn own_small(value: RespFrame) -> RespFrame {
+    match value {
+        RespFrame::BulkString(s) if s.len() < SHARED_MIN_LEN => {
+            BulkString(Bytes::copy_from_slice(&s)).into()
+        }
+        value => value,
+    }
+}
+
+/// 随机数, 每个 RandomState 使用不同的随机 key
+pub(crate) fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// 当前 unix 毫秒时间戳
+pub(crate) fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use bytes::BytesMut;
+
+    use super::*;
+    use crate::{
+        cmd::{Command, CommandExecutor},
+        RespArray, RespDecode, RespEncode,
+    };
+
+    // 值的数据是否在 buf 中, 即是否为 buf 的切片
+    fn in_buffer(value: &RespFrame, buf: &Range<*const u8>) -> bool {
+        let RespFrame::BulkString(s) = value else {
+            panic!("expect a bulk string");
+        };
+        buf.contains(&s.as_ptr())
+    }
+
+    #[test]
+    fn test_small_values_do_not_pin_buffer() -> anyhow::Result<()> {
+        let backend = Backend::new();
+        let large = vec![b'x'; SHARED_MIN_LEN];
+        let mut buf = BytesMut::new();
+        for (key, value) in [("small", &b"value"[..]), ("large", &large[..])] {
+            let frame: RespFrame = RespArray::new(vec![
+                BulkString::new("SET").into(),
+                BulkString::new(key).into(),
+                BulkString::new(value).into(),
+            ])
+            .into();
+            frame.encode_to(&mut buf);
+        }
+        let hset: RespFrame = RespArray::new(vec![
+            BulkString::new("HSET").into(),
+            BulkString::new("hash").into(),
+            BulkString::new("field").into(),
+            BulkString::new("value").into(),
+        ])
+        .into();
+        hset.encode_to(&mut buf);
+        let input = buf.as_ptr_range();
+
+        while !buf.is_empty() {
+            let frame = RespFrame::decode(&mut buf)?;
+            Command::try_from(frame)?.execute(&backend);
+        }
+        let small = backend.get("small").unwrap();
+        assert_eq!(small, BulkString::new("value").into());
+        assert!(!in_buffer(&small, &input));
+        assert!(!in_buffer(&backend.hget("hash", "field").unwrap(), &input));
+        // 较大的值不复制
+        assert!(in_buffer(&backend.get("large").unwrap(), &input));
+
+        let data = Bytes::from(vec![b'y'; SHARED_MIN_LEN * 2]);
+        backend.update("small", |v| *v = Some(BulkString(data.slice(..5)).into()));
+        let small = backend.get("small").unwrap();
+        assert_eq!(small, BulkString::new("yyyyy").into());
+        assert!(!in_buffer(&small, &data.as_ptr_range()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_slot_index() {
+        let backend = Backend::with_config(ServerConfig {
+            cluster_enabled: true,
+            ..Default::default()
+        });
+        let count = |slot| backend.keys_in_slot(slot, usize::MAX).len();
+        // {bar} 的 slot 为 5061
+        backend.set("{bar}1".to_string(), BulkString::new("1").into());
+        backend.hset(
+            "{bar}2".to_string(),
+            "f".to_string(),
+            BulkString::new("1").into(),
+        );
+        backend.zupdate("{bar}3", |z| z.insert("m".to_string(), 1.0));
+        backend.update("{bar}4", |v| *v = Some(BulkString::new("1").into()));
+        backend.set("{bar}1".to_string(), BulkString::new("2").into());
+        assert_eq!(count(5061), 4);
+        assert_eq!(backend.keys_in_slot(5061, 2).len(), 2);
+
+        // 删除, 清空和过期的 key 都不再计入
+        backend.del("{bar}1");
+        backend.zupdate("{bar}3", |z| z.remove("m"));
+        backend.update("{bar}4", |v| *v = None);
+        assert_eq!(backend.keys_in_slot(5061, 10), vec!["{bar}2".to_string()]);
+        backend.expire_at("{bar}2", now_ms() - 1);
+        assert_eq!(count(5061), 0);
+        backend.restore_entry(SnapshotEntry {
+            key: "{bar}5".to_string(),
+            value: StoredValue::String(BulkString::new("1").into()),
+            expire_at: None,
+        });
+        assert_eq!(count(5061), 1);
+        backend.flush();
+        assert_eq!(count(5061), 0);
+
+        // 并发写入和删除同一组 key 之后, 索引与 keyspace 一致
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let backend = backend.clone();
+                std::thread::spawn(move || {
+                    for i in 0..1000 {
+                        let key = format!("{{bar}}{}", i % 8);
+                        if (i + t) % 2 == 0 {
+                            backend.set(key, BulkString::new("1").into());
+                        } else {
+                            backend.del(&key);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        let mut expected = backend.keys();
+        expected.sort();
+        let mut keys = backend.keys_in_slot(5061, usize::MAX);
+        keys.sort();
+        assert_eq!(keys, expected);
+    }
 }
@@ -0,0 +1,241 @@
+use std::sync::RwLockWriteGuard;
+
+use dashmap::{mapref::entry::Entry, DashMap};
+
+use crate::{Backend, RespFrame, SortedSet};
+
+use super::{now_ms, own_small};
+
+/// 某一时刻全部数据的副本, 是各种持久化格式的公共中间表示
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotEntry {
+    pub key: String,
+    pub value: StoredValue,
+    /// 过期时间, unix 毫秒时间戳
+    pub expire_at: Option<i64>,
+}
+
+/// Backend 中各类型的值
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoredValue {
+    String(RespFrame),
+    Hash(Vec<(String, RespFrame)>),
+    ZSet(SortedSet),
+}
+
+impl Backend {
+    /// 复制当前数据. 复制期间阻塞写命令, 保证快照是同一时刻的数据,
+    /// 修改多个 key 的命令 (BITOP, PFMERGE 等) 不会只有一部分被复制.
+    pub fn snapshot(&self) -> Snapshot {
+        let guard = self.block_writes();
+        self.snapshot_locked(&guard)
+    }
+
+    /// 与 snapshot 相同, 调用方已经持有 block_writes 返回的 guard
+    pub(crate) fn snapshot_locked(&self, _guard: &RwLockWriteGuard<'_, ()>) -> Snapshot {
+        let now = now_ms();
+        let mut entries = Vec::with_capacity(self.map.len() + self.hmap.len() + self.zset.len());
+        let expire_at = |key: &str| self.expire.get(key).map(|v| *v);
+        let alive = |t: Option<i64>| t.is_none_or(|t| t > now);
+
+        for v in self.map.iter() {
+            let expire_at = expire_at(v.key());
+            if alive(expire_at) {
+                entries.push(SnapshotEntry {
+                    key: v.key().clone(),
+                    value: StoredValue::String(v.value().clone()),
+                    expire_at,
+                });
+            }
+        }
+        for v in self.hmap.iter() {
+            let expire_at = expire_at(v.key());
+            if alive(expire_at) {
+                let fields = v
+                    .value()
+                    .iter()
+                    .map(|f| (f.key().clone(), f.value().clone()))
+                    .collect();
+                entries.push(SnapshotEntry {
+                    key: v.key().clone(),
+                    value: StoredValue::Hash(fields),
+                    expire_at,
+                });
+            }
+        }
+        for v in self.zset.iter() {
+            let expire_at = expire_at(v.key());
+            if alive(expire_at) {
+                entries.push(SnapshotEntry {
+                    key: v.key().clone(),
+                    value: StoredValue::ZSet(v.value().clone()),
+                    expire_at,
+                });
+            }
+        }
+        Snapshot { entries }
+    }
+
+    /// 复制一个 key 的值及过期时间
+    pub fn entry(&self, key: &str) -> Option<SnapshotEntry> {
+        // expire_time 会先删除已过期的 key
+        let expire_at = self.expire_time(key);
+        let value = if let Some(v) = self.map.get(key) {
+            StoredValue::String(v.clone())
+        } else if let Some(v) = self.hmap.get(key) {
+            let fields = v
+                .iter()
+                .map(|f| (f.key().clone(), f.value().clone()))
+                .collect();
+            StoredValue::Hash(fields)
+        } else if let Some(v) = self.zset.get(key) {
+            StoredValue::ZSet(v.clone())
+        } else {
+            return None;
+        };
+        Some(SnapshotEntry {
+            key: key.to_string(),
+            value,
+            expire_at,
+        })
+    }
+
+    /// 写入一个 key, 覆盖同名的已有 key
+    pub fn restore_entry(&self, entry: SnapshotEntry) {
+        self.del(&entry.key);
+        match entry.value {
+            StoredValue::String(v) => {
+                self.map.insert(entry.key.clone(), own_small(v));
+            }
+            StoredValue::Hash(fields) => {
+                let hmap = fields
+                    .into_iter()
+                    .map(|(k, v)| (k, own_small(v)))
+                    .collect::<DashMap<_, _>>();
+                self.hmap.insert(entry.key.clone(), hmap);
+            }
+            StoredValue::ZSet(zset) => {
+                self.zset.insert(entry.key.clone(), zset);
+            }
+        }
+        self.index_key(&entry.key);
+        if let Some(t) = entry.expire_at {
+            self.expire.insert(entry.key, t);
+        }
+        self.touch();
+    }
+
+    /// 写入一个 key, key 已存在时不写入并返回 false.
+    /// 检查和写入期间持有三个 keyspace 中该 key 所在分片的锁
+    pub fn restore_entry_nx(&self, entry: SnapshotEntry) -> bool {
+        self.expire_if_needed(&entry.key);
+        // 固定按 map, hmap, zset 的顺序加锁, 避免死锁; 离开作用域时释放, 之后再更新 slot 索引
+        {
+            let (Entry::Vacant(s), Entry::Vacant(h), Entry::Vacant(z)) = (
+                self.map.entry(entry.key.clone()),
+                self.hmap.entry(entry.key.clone()),
+                self.zset.entry(entry.key.clone()),
+            ) else {
+                return false;
+            };
+            match entry.value {
+                StoredValue::String(v) => {
+                    s.insert(own_small(v));
+                }
+                StoredValue::Hash(fields) => {
+                    h.insert(fields.into_iter().map(|(k, v)| (k, own_small(v))).collect());
+                }
+                StoredValue::ZSet(zset) => {
+                    z.insert(zset);
+                }
+            }
+        }
+        self.index_key(&entry.key);
+        if let Some(t) = entry.expire_at {
+            self.expire.insert(entry.key, t);
+        }
+        self.touch();
+        true
+    }
+
+    /// 清空当前数据并载入快照, 已过期的 key 会被跳过
+    pub fn restore(&self, snapshot: Snapshot) {
+        self.flush();
+        let now = now_ms();
+        for entry in snapshot.entries {
+            if entry.expire_at.is_none_or(|t| t > now) {
+                self.restore_entry(entry);
+            }
+        }
+    }
+
+    pub fn flush(&self) {
+        self.map.clear();
+        self.hmap.clear();
+        self.zset.clear();
+        self.expire.clear();
+        self.slot_keys.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::BulkString;
+
+    #[test]
+    fn test_snapshot_restore() {
+        let backend = Backend::new();
+        backend.set("str".to_string(), BulkString::new("hello").into());
+        backend.hset(
+            "hash".to_string(),
+            "field".to_string(),
+            BulkString::new("value").into(),
+        );
+        backend.zupdate("zset", |z| z.insert("member".to_string(), 1.5));
+        backend.expire_at("str", now_ms() + 100_000);
+        backend.set("gone".to_string(), BulkString::new("x").into());
+        backend.expire_at("gone", now_ms() - 1);
+
+        let snapshot = backend.snapshot();
+        assert_eq!(snapshot.entries.len(), 3);
+
+        let other = Backend::new();
+        other.set("old".to_string(), BulkString::new("x").into());
+        other.restore(snapshot.clone());
+        assert_eq!(other.get("old"), None);
+        assert_eq!(other.get("str"), Some(BulkString::new("hello").into()));
+        assert_eq!(other.expire_time("str"), backend.expire_time("str"));
+        assert_eq!(
+            other.hget("hash", "field"),
+            Some(BulkString::new("value").into())
+        );
+        assert_eq!(
+            other.zget("zset").and_then(|z| z.score("member")),
+            Some(1.5)
+        );
+        assert_eq!(other.snapshot().entries.len(), 3);
+    }
+
+    #[test]
+    fn test_snapshot_waits_for_writes() {
+        let backend = Backend::new();
+        let guard = backend.write_barrier();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let other = backend.clone();
+        std::thread::spawn(move || tx.send(other.snapshot()).unwrap());
+        // 写命令执行完之前不能复制
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+        backend.set("key".to_string(), BulkString::new("value").into());
+        drop(guard);
+        let snapshot = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(snapshot.entries.len(), 1);
+    }
+}
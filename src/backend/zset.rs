@@ -0,0 +1,102 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+
+/// 有序集合: 按 (score, member) 排序, 同时支持按 member 查找 score
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SortedSet {
+    scores: HashMap<String, f64>,
+    ordered: BTreeSet<(Score, String)>,
+}
+
+// f64 没有实现 Ord, 使用 total_cmp 定义全序
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl SortedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+    pub fn score(&self, member: &str) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+    /// 插入或更新 member, 返回旧的 score
+    pub fn insert(&mut self, member: String, score: f64) -> Option<f64> {
+        let old = self.scores.insert(member.clone(), score);
+        if let Some(old) = old {
+            self.ordered.remove(&(Score(old), member.clone()));
+        }
+        self.ordered.insert((Score(score), member));
+        old
+    }
+    pub fn remove(&mut self, member: &str) -> Option<f64> {
+        let old = self.scores.remove(member)?;
+        self.ordered.remove(&(Score(old), member.to_string()));
+        Some(old)
+    }
+    /// 按 score 升序遍历
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&str, f64)> {
+        self.ordered.iter().map(|(s, m)| (m.as_str(), s.0))
+    }
+    /// score 在 `[min, max)` 内的元素, 按 score 升序
+    pub fn range_by_score(&self, min: f64, max: f64) -> impl Iterator<Item = (&str, f64)> {
+        let range = if min < max {
+            Some((Score(min), String::new())..(Score(max), String::new()))
+        } else {
+            None
+        };
+        range
+            .into_iter()
+            .flat_map(|r| self.ordered.range(r))
+            .map(|(s, m)| (m.as_str(), s.0))
+    }
+}
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Score {}
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sorted_set() {
+        let mut zset = SortedSet::new();
+        assert_eq!(zset.insert("b".to_string(), 2.0), None);
+        assert_eq!(zset.insert("a".to_string(), 3.0), None);
+        assert_eq!(zset.insert("c".to_string(), 1.0), None);
+        assert_eq!(zset.insert("a".to_string(), 2.0), Some(3.0));
+        assert_eq!(zset.len(), 3);
+
+        let members: Vec<_> = zset.iter().collect();
+        assert_eq!(members, vec![("c", 1.0), ("a", 2.0), ("b", 2.0)]);
+
+        let members: Vec<_> = zset.range_by_score(2.0, 3.0).collect();
+        assert_eq!(members, vec![("a", 2.0), ("b", 2.0)]);
+        assert_eq!(zset.range_by_score(3.0, 2.0).count(), 0);
+
+        assert_eq!(zset.remove("a"), Some(2.0));
+        assert_eq!(zset.remove("a"), None);
+        assert_eq!(zset.score("b"), Some(2.0));
+        assert_eq!(zset.len(), 2);
+    }
+}
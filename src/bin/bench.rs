@@ -0,0 +1,69 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use bytes::BytesMut;
+
+use simple_redis::cmd::{Command, CommandExecutor};
+use simple_redis::{Backend, BulkString, RespArray, RespEncode, RespFrame, RespParser};
+
+const READ_SIZE: usize = 64 * 1024;
+
+/// 解码并执行 SET 命令的吞吐量.
+///
+/// 只测量当前的实现. 与其它版本比较时, 在对应的 commit 上运行同一个文件:
+/// `cargo run --release --bin bench -- [total-mb] [payload-size ...]`
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let total_mb: usize = args.next().map(|s| s.parse()).transpose()?.unwrap_or(64);
+    let mut sizes = args.map(|s| s.parse()).collect::<Result<Vec<usize>, _>>()?;
+    if sizes.is_empty() {
+        sizes = vec![16, 1024, 16 * 1024, 256 * 1024];
+    }
+
+    println!("{:>10} {:>10} {:>12}", "payload", "commands", "MB/s");
+    for size in sizes {
+        let count = (total_mb * 1024 * 1024 / size.max(1)).max(1);
+        let input = commands(count, size);
+        let elapsed = run(&input)?;
+        let mb = input.len() as f64 / 1024.0 / 1024.0;
+        println!(
+            "{:>10} {:>10} {:>12.1}",
+            size,
+            count,
+            mb / elapsed.as_secs_f64()
+        );
+    }
+    Ok(())
+}
+
+// count 条 `SET key:<i> <payload>`, 与从 socket 读到的数据相同
+fn commands(count: usize, size: usize) -> BytesMut {
+    let payload = vec![b'x'; size];
+    let mut buf = BytesMut::new();
+    for i in 0..count {
+        let frame: RespFrame = RespArray::new(vec![
+            BulkString::new("SET").into(),
+            BulkString::new(format!("key:{}", i % 1024)).into(),
+            BulkString::new(payload.clone()).into(),
+        ])
+        .into();
+        buf.extend_from_slice(&frame.encode());
+    }
+    buf
+}
+
+// 每次向读缓冲区追加 READ_SIZE 字节并解码其中完整的命令, 与从 socket 读取相同.
+// 与 RespFrameCodec 一样使用同一个解析器, 不完整的 frame 下次从停下的位置继续解析
+fn run(input: &[u8]) -> Result<Duration> {
+    let backend = Backend::new();
+    let mut parser = RespParser::new();
+    let mut buf = BytesMut::with_capacity(READ_SIZE);
+    let start = Instant::now();
+    for data in input.chunks(READ_SIZE) {
+        buf.extend_from_slice(data);
+        while let Some(frame) = parser.parse(&mut buf)? {
+            Command::try_from(frame)?.execute(&backend);
+        }
+    }
+    Ok(start.elapsed())
+}
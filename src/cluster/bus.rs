@@ -0,0 +1,356 @@
+//! 集群总线: 节点之间通过 cport 交换 PING/PONG 等消息. 每个消息都带有发送者的角色、epoch、
+//! 负责的 slot, 以及发送者所知道的其他节点的状态 (gossip). 消息以 RESP 数组编码.
+
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use bytes::BytesMut;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    task::JoinSet,
+};
+use tracing::{info, warn};
+
+use crate::{Backend, BulkString, RespArray, RespDecode, RespEncode, RespError, RespFrame};
+
+use super::{nodes::parse_slot_range, NodeHealth};
+
+// 定时检查故障和发送消息的间隔
+const CRON_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum MessageType {
+    Ping,
+    Pong,
+    /// 邀请对方加入集群
+    Meet,
+    /// 节点已被超过半数的 master 认定为 FAIL
+    Fail(String),
+    /// replica 请求 master 投票
+    AuthRequest,
+    AuthAck,
+    /// replica 请求 master 暂停写入, 开始手动故障转移
+    MfStart,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BusMessage {
+    pub kind: MessageType,
+    pub sender: String,
+    pub ip: String,
+    pub port: u16,
+    pub cport: u16,
+    pub master: Option<String>,
+    // replica 发送的是它的 master 的 config epoch 和 slot
+    pub config_epoch: u64,
+    pub current_epoch: u64,
+    pub repl_offset: u64,
+    pub slots: Vec<(u16, u16)>,
+    // master 在手动故障转移中暂停了写入
+    pub paused: bool,
+    // 手动故障转移的投票请求, master 没有 FAIL 也可以投票
+    pub force: bool,
+    pub gossip: Vec<Gossip>,
+}
+
+/// 发送者所知道的一个节点
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Gossip {
+    pub id: String,
+    pub ip: String,
+    pub port: u16,
+    pub cport: u16,
+    pub health: NodeHealth,
+}
+
+/// 接受其他节点的连接, 并定期向其他节点发送消息、检测故障.
+/// 所有连接都属于这个任务, 任务被取消时一起关闭
+pub async fn cluster_bus(backend: Backend, listener: TcpListener) -> Result<()> {
+    info!("Cluster bus listening on {}", listener.local_addr()?);
+    let timeout = Duration::from_millis(backend.config().cluster_node_timeout);
+    let mut tasks = JoinSet::new();
+    // 到其他节点的连接, 以 ip:cport 区分
+    let mut links: HashMap<String, mpsc::UnboundedSender<BusMessage>> = HashMap::new();
+    let mut cron = tokio::time::interval(CRON_INTERVAL);
+    loop {
+        tokio::select! {
+            ret = listener.accept() => {
+                let (stream, _) = ret?;
+                tasks.spawn(serve_link(stream, None, backend.clone()));
+            }
+            _ = cron.tick() => {
+                for (addr, msg) in backend.cluster_cron() {
+                    let msg = match links.get(&addr) {
+                        Some(tx) => match tx.send(msg) {
+                            Ok(()) => continue,
+                            Err(e) => e.0,
+                        },
+                        None => msg,
+                    };
+                    // 连接还没有建立或已经断开
+                    let (tx, rx) = mpsc::unbounded_channel();
+                    let _ = tx.send(msg);
+                    links.insert(addr.clone(), tx);
+                    tasks.spawn(connect(addr, rx, timeout, backend.clone()));
+                }
+            }
+            Some(_) = tasks.join_next() => {}
+        }
+    }
+}
+
+async fn connect(
+    addr: String,
+    rx: mpsc::UnboundedReceiver<BusMessage>,
+    timeout: Duration,
+    backend: Backend,
+) -> Result<()> {
+    let stream = tokio::time::timeout(timeout, TcpStream::connect(&addr)).await??;
+    serve_link(stream, Some(rx), backend).await
+}
+
+// 发送 rx 中的消息 (只有主动建立的连接有), 并处理收到的消息, PING 和 MEET 在同一连接上回复 PONG
+async fn serve_link(
+    stream: TcpStream,
+    mut rx: Option<mpsc::UnboundedReceiver<BusMessage>>,
+    backend: Backend,
+) -> Result<()> {
+    let (mut reader, mut writer) = stream.into_split();
+    let mut buf = BytesMut::new();
+    loop {
+        tokio::select! {
+            msg = recv(&mut rx) => match msg {
+                Some(msg) => writer.write_all(&RespFrame::from(msg).encode()).await?,
+                None => return Ok(()),
+            },
+            n = reader.read_buf(&mut buf) => {
+                if n? == 0 {
+                    return Ok(());
+                }
+                loop {
+                    let frame = match RespFrame::decode(&mut buf) {
+                        Ok(frame) => frame,
+                        Err(RespError::NotComplete) => break,
+                        Err(e) => return Err(e.into()),
+                    };
+                    let msg = match BusMessage::try_from(frame) {
+                        Ok(msg) => msg,
+                        Err(e) => {
+                            warn!("Invalid cluster bus message: {}", e);
+                            continue;
+                        }
+                    };
+                    // 手动故障转移开始时需要等待正在执行的写命令, 不能占用 tokio 的工作线程
+                    let process = backend.clone();
+                    let reply = tokio::task::spawn_blocking(move || process.cluster_process(msg)).await?;
+                    if let Some(reply) = reply {
+                        writer.write_all(&RespFrame::from(reply).encode()).await?;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn recv(rx: &mut Option<mpsc::UnboundedReceiver<BusMessage>>) -> Option<BusMessage> {
+    match rx {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+//===================  消息的编码
+// [type, target, sender, ip, port, cport, master, config-epoch, current-epoch, repl-offset,
+//  slots, flags, gossip...], gossip 为 "id ip port cport health"
+impl From<BusMessage> for RespFrame {
+    fn from(msg: BusMessage) -> Self {
+        let (kind, target) = match &msg.kind {
+            MessageType::Ping => ("ping", "-"),
+            MessageType::Pong => ("pong", "-"),
+            MessageType::Meet => ("meet", "-"),
+            MessageType::Fail(id) => ("fail", id.as_str()),
+            MessageType::AuthRequest => ("auth-request", "-"),
+            MessageType::AuthAck => ("auth-ack", "-"),
+            MessageType::MfStart => ("mfstart", "-"),
+        };
+        let slots = msg
+            .slots
+            .iter()
+            .map(|(start, end)| format!("{}-{}", start, end))
+            .collect::<Vec<_>>()
+            .join(",");
+        let flags = [(msg.paused, "paused"), (msg.force, "force")]
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>()
+            .join(",");
+        let mut fields = vec![
+            kind.to_string(),
+            target.to_string(),
+            msg.sender,
+            msg.ip,
+            msg.port.to_string(),
+            msg.cport.to_string(),
+            msg.master.unwrap_or_else(|| "-".to_string()),
+            msg.config_epoch.to_string(),
+            msg.current_epoch.to_string(),
+            msg.repl_offset.to_string(),
+            or_dash(slots),
+            or_dash(flags),
+        ];
+        fields.extend(msg.gossip.into_iter().map(|g| {
+            let health = match g.health {
+                NodeHealth::Ok => "ok",
+                NodeHealth::PFail => "pfail",
+                NodeHealth::Fail => "fail",
+            };
+            format!("{} {} {} {} {}", g.id, g.ip, g.port, g.cport, health)
+        }));
+        RespArray::new(
+            fields
+                .into_iter()
+                .map(|f| BulkString::new(f).into())
+                .collect::<Vec<_>>(),
+        )
+        .into()
+    }
+}
+
+impl TryFrom<RespFrame> for BusMessage {
+    type Error = anyhow::Error;
+
+    fn try_from(frame: RespFrame) -> Result<Self> {
+        let RespFrame::Array(array) = frame else {
+            bail!("cluster bus message must be an array");
+        };
+        let fields = array
+            .iter()
+            .map(|f| match f {
+                RespFrame::BulkString(s) => Ok(String::from_utf8_lossy(s).into_owned()),
+                _ => Err(anyhow!("cluster bus message field must be a bulk string")),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let [kind, target, sender, ip, port, cport, master, config_epoch, current_epoch, repl_offset, slots, flags, gossip @ ..] =
+            fields.as_slice()
+        else {
+            bail!("invalid cluster bus message: {:?}", fields);
+        };
+        let kind = match kind.as_str() {
+            "ping" => MessageType::Ping,
+            "pong" => MessageType::Pong,
+            "meet" => MessageType::Meet,
+            "fail" => MessageType::Fail(target.clone()),
+            "auth-request" => MessageType::AuthRequest,
+            "auth-ack" => MessageType::AuthAck,
+            "mfstart" => MessageType::MfStart,
+            _ => bail!("unknown cluster bus message type: {}", kind),
+        };
+        // 检查 slot 范围, 之后按 slot 下标访问时不再检查
+        let slots = match slots.as_str() {
+            "-" => vec![],
+            slots => slots
+                .split(',')
+                .map(parse_slot_range)
+                .collect::<Result<_>>()?,
+        };
+        let flags = flags.split(',').collect::<Vec<_>>();
+        let gossip = gossip
+            .iter()
+            .map(|g| match g.split(' ').collect::<Vec<_>>().as_slice() {
+                [id, ip, port, cport, health] => Ok(Gossip {
+                    id: id.to_string(),
+                    ip: ip.to_string(),
+                    port: port.parse()?,
+                    cport: cport.parse()?,
+                    health: match *health {
+                        "pfail" => NodeHealth::PFail,
+                        "fail" => NodeHealth::Fail,
+                        _ => NodeHealth::Ok,
+                    },
+                }),
+                _ => bail!("invalid gossip: {}", g),
+            })
+            .collect::<Result<_>>()?;
+        Ok(BusMessage {
+            kind,
+            sender: sender.clone(),
+            ip: ip.clone(),
+            port: port.parse()?,
+            cport: cport.parse()?,
+            master: (master != "-").then(|| master.clone()),
+            config_epoch: config_epoch.parse()?,
+            current_epoch: current_epoch.parse()?,
+            repl_offset: repl_offset.parse()?,
+            slots,
+            paused: flags.contains(&"paused"),
+            force: flags.contains(&"force"),
+            gossip,
+        })
+    }
+}
+
+fn or_dash(s: String) -> String {
+    if s.is_empty() {
+        "-".to_string()
+    } else {
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bus_message_encode_decode() -> Result<()> {
+        let msg = BusMessage {
+            kind: MessageType::Fail("b".repeat(40)),
+            sender: "a".repeat(40),
+            ip: "127.0.0.1".to_string(),
+            port: 7000,
+            cport: 17000,
+            master: Some("c".repeat(40)),
+            config_epoch: 3,
+            current_epoch: 5,
+            repl_offset: 1024,
+            slots: vec![(0, 100), (200, 200)],
+            paused: false,
+            force: true,
+            gossip: vec![Gossip {
+                id: "b".repeat(40),
+                ip: "127.0.0.1".to_string(),
+                port: 7001,
+                cport: 17001,
+                health: NodeHealth::PFail,
+            }],
+        };
+        let mut buf = BytesMut::from(&RespFrame::from(msg.clone()).encode()[..]);
+        let decoded = BusMessage::try_from(RespFrame::decode(&mut buf)?)?;
+        assert_eq!(decoded, msg);
+
+        let empty = BusMessage {
+            kind: MessageType::Ping,
+            master: None,
+            slots: vec![],
+            force: false,
+            gossip: vec![],
+            ..msg
+        };
+        let decoded = BusMessage::try_from(RespFrame::from(empty.clone()))?;
+        assert_eq!(decoded, empty);
+        assert!(BusMessage::try_from(RespFrame::Integer(1)).is_err());
+
+        // slot 超出范围或 start > end
+        for slots in [vec![(0, 16384)], vec![(10, 5)]] {
+            let invalid = BusMessage {
+                slots,
+                ..empty.clone()
+            };
+            assert!(BusMessage::try_from(RespFrame::from(invalid)).is_err());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+/// 集群的 hash slot 数量
+pub const CLUSTER_SLOTS: usize = 16384;
+
+// CRC16-CCITT (XMODEM): 多项式 0x1021, 初始值 0, 与 Redis 的 crc16.c 相同
+const fn crc16_table() -> [u16; 256] {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const CRC16_TABLE: [u16; 256] = crc16_table();
+
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, &b| {
+        (crc << 8) ^ CRC16_TABLE[((crc >> 8) as u8 ^ b) as usize]
+    })
+}
+
+/// key 所属的 slot. key 中包含非空的 `{...}` 时只对第一个 `{` 与之后第一个 `}` 之间的部分计算,
+/// 使相关的 key 可以落在同一个 slot
+pub fn key_hash_slot(key: &[u8]) -> u16 {
+    let hashed = key
+        .iter()
+        .position(|&b| b == b'{')
+        .and_then(|start| {
+            let rest = &key[start + 1..];
+            let end = rest.iter().position(|&b| b == b'}')?;
+            (end > 0).then(|| &rest[..end])
+        })
+        .unwrap_or(key);
+    crc16(hashed) & (CLUSTER_SLOTS as u16 - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_hash_slot() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(key_hash_slot(b"foo"), 12182);
+        assert_eq!(key_hash_slot(b"bar"), 5061);
+        assert_eq!(
+            key_hash_slot(b"{user1000}.following"),
+            key_hash_slot(b"{user1000}.followers")
+        );
+        assert_eq!(
+            key_hash_slot(b"{user1000}.following"),
+            key_hash_slot(b"user1000")
+        );
+        // 空的 {} 不是 hash tag, 对整个 key 计算
+        assert_eq!(key_hash_slot(b"foo{}{bar}"), crc16(b"foo{}{bar}") & 0x3fff);
+        assert_eq!(key_hash_slot(b"foo{{bar}}zap"), key_hash_slot(b"{bar"));
+        assert_eq!(key_hash_slot(b"foo{bar}{zap}"), key_hash_slot(b"bar"));
+    }
+}
@@ -0,0 +1,911 @@
+//! 故障检测与故障转移: 节点超过 node timeout 没有回复时标记为 PFAIL, 超过半数的 master
+//! 通过 gossip 报告后标记为 FAIL. master FAIL 后它的 replica 发起选举, 得到超过半数 master
+//! 的投票后以新的 config epoch 接管 slot, 其他节点 (包括恢复后的原 master) 看到更大的 epoch 后更新配置.
+
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Result};
+use tracing::{info, warn};
+
+use crate::backend::{now_ms, random_u64};
+use crate::Backend;
+
+use super::bus::{BusMessage, Gossip, MessageType};
+use super::{ClusterInner, ClusterNode, NodeHealth};
+
+// 手动故障转移的超时时间
+const MF_TIMEOUT: i64 = 5000;
+
+/// CLUSTER FAILOVER 的选项
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailoverMode {
+    /// 与 master 协商: master 暂停写入, replica 追上 offset 后发起选举
+    Default,
+    /// 不与 master 协商, 直接发起选举
+    Force,
+    /// 不经过选举, 直接增加自己的 config epoch 接管 slot
+    Takeover,
+}
+
+/// replica 的选举和手动故障转移的状态
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct FailoverState {
+    // 计划发起选举的时间, 0 表示还没有计划
+    auth_time: i64,
+    auth_sent: bool,
+    auth_epoch: u64,
+    // 为本轮选举投票的 master
+    voters: BTreeSet<String>,
+    // 手动故障转移的截止时间, 0 表示没有进行中的手动故障转移
+    mf_end: i64,
+    // replica: 可以开始手动故障转移的选举
+    mf_can_start: bool,
+    // replica: master 暂停写入时的复制 offset
+    mf_master_offset: Option<u64>,
+}
+
+impl ClusterInner {
+    // 负责 slot 的 master 数量的一半加一
+    fn quorum(&self) -> usize {
+        let masters = self
+            .nodes
+            .values()
+            .filter(|n| n.master.is_none() && self.has_slots(&n.id))
+            .count();
+        masters / 2 + 1
+    }
+
+    // 自己的状态, replica 发送它的 master 的 config epoch 和 slot
+    fn message(&self, kind: MessageType, repl_offset: u64) -> BusMessage {
+        let myself = self.myself();
+        let master = myself
+            .master
+            .as_ref()
+            .and_then(|id| self.nodes.get(id))
+            .unwrap_or(myself);
+        let gossip = self
+            .nodes
+            .values()
+            .filter(|n| n.id != self.myself)
+            .map(|n| Gossip {
+                id: n.id.clone(),
+                ip: n.ip.clone(),
+                port: n.port,
+                cport: n.cport,
+                health: n.health,
+            })
+            .collect();
+        BusMessage {
+            kind,
+            sender: myself.id.clone(),
+            ip: myself.ip.clone(),
+            port: myself.port,
+            cport: myself.cport,
+            master: myself.master.clone(),
+            config_epoch: master.config_epoch,
+            current_epoch: self.current_epoch,
+            repl_offset,
+            slots: self.slot_ranges(&master.id),
+            paused: myself.master.is_none() && self.failover.mf_end != 0,
+            force: self.failover.mf_end != 0,
+            gossip,
+        }
+    }
+
+    fn send(&mut self, id: &str, kind: MessageType, repl_offset: u64) {
+        let msg = self.message(kind, repl_offset);
+        let addr = self.bus_addr(id);
+        self.outbox.push((addr, msg));
+    }
+
+    fn broadcast(&mut self, kind: MessageType, repl_offset: u64) {
+        let msg = self.message(kind, repl_offset);
+        for node in self.nodes.values().filter(|n| n.id != self.myself) {
+            let addr = format!("{}:{}", node.ip, node.cport);
+            self.outbox.push((addr, msg.clone()));
+        }
+    }
+
+    // 自己认为节点 PFAIL, 且加上自己超过半数的 master 报告了故障时, 标记为 FAIL 并通知所有节点
+    fn mark_failing(&mut self, id: &str, now: i64, repl_offset: u64) {
+        let quorum = self.quorum();
+        let myself_is_master = self.myself().master.is_none();
+        let Some(node) = self.nodes.get_mut(id) else {
+            return;
+        };
+        if node.health != NodeHealth::PFail
+            || node.fail_reports.len() + usize::from(myself_is_master) < quorum
+        {
+            return;
+        }
+        info!("Marking node {} as failing (quorum reached)", id);
+        node.health = NodeHealth::Fail;
+        node.fail_time = now;
+        self.broadcast(MessageType::Fail(id.to_string()), repl_offset);
+    }
+
+    // 更新发送者声明的 slot: config epoch 更大的 master 获得 slot.
+    // 自己或自己的 master 因此失去了所有 slot 时, 成为发送者的 replica, 返回它的地址
+    fn update_slots(&mut self, msg: &BusMessage) -> (bool, Option<(String, u16)>) {
+        let myself = self.myself.clone();
+        let my_master = self.myself().master.clone().unwrap_or(myself.clone());
+        let had_slots = self.has_slots(&my_master);
+        let (mut changed, mut lost) = (false, false);
+        for &(start, end) in &msg.slots {
+            for slot in start..=end {
+                let owner = self.slots[slot as usize].as_deref();
+                if owner == Some(msg.sender.as_str()) || self.importing.contains_key(&slot) {
+                    continue;
+                }
+                let owner_epoch = owner
+                    .and_then(|id| self.nodes.get(id))
+                    .map(|n| n.config_epoch);
+                if owner_epoch.is_some_and(|e| e >= msg.config_epoch) {
+                    continue;
+                }
+                lost |= owner == Some(my_master.as_str());
+                self.slots[slot as usize] = Some(msg.sender.clone());
+                changed = true;
+            }
+        }
+        if !(lost && had_slots && !self.has_slots(&my_master)) {
+            return (changed, None);
+        }
+        info!(
+            "Configuration change detected. Reconfiguring myself as a replica of {}",
+            msg.sender
+        );
+        if let Some(node) = self.nodes.get_mut(&myself) {
+            node.master = Some(msg.sender.clone());
+        }
+        self.failover = FailoverState::default();
+        self.migrating.clear();
+        (true, Some((msg.ip.clone(), msg.port)))
+    }
+
+    // 为发起选举的 replica 投票: 每个 epoch 只投一次, 且 replica 的 master 已经 FAIL (手动故障转移除外)
+    fn vote(&mut self, msg: &BusMessage, now: i64, timeout: i64, repl_offset: u64) -> bool {
+        if self.myself().master.is_some() || !self.has_slots(&self.myself) {
+            return false;
+        }
+        if msg.current_epoch < self.current_epoch || self.last_vote_epoch == self.current_epoch {
+            return false;
+        }
+        let Some(master) = msg.master.as_ref().and_then(|id| self.nodes.get(id)) else {
+            return false;
+        };
+        if (master.health != NodeHealth::Fail && !msg.force)
+            || now - master.voted_time < timeout * 2
+        {
+            return false;
+        }
+        // 请求的 slot 不能已经属于 config epoch 更大的 master
+        for &(start, end) in &msg.slots {
+            for slot in start..=end {
+                let owner = self.slots[slot as usize].as_ref();
+                if owner
+                    .and_then(|id| self.nodes.get(id))
+                    .is_some_and(|n| n.config_epoch > msg.config_epoch)
+                {
+                    return false;
+                }
+            }
+        }
+        let master = master.id.clone();
+        self.last_vote_epoch = self.current_epoch;
+        if let Some(master) = self.nodes.get_mut(&master) {
+            master.voted_time = now;
+        }
+        info!(
+            "Failover auth granted to {} for epoch {}",
+            msg.sender, self.current_epoch
+        );
+        self.send(&msg.sender, MessageType::AuthAck, repl_offset);
+        true
+    }
+
+    // 收到投票, 得到超过半数的投票时返回 true
+    fn count_vote(&mut self, msg: &BusMessage) -> bool {
+        let failover = &self.failover;
+        if self.myself().master.is_none()
+            || !failover.auth_sent
+            || msg.current_epoch < failover.auth_epoch
+            || msg.master.is_some()
+            || !self.has_slots(&msg.sender)
+        {
+            return false;
+        }
+        self.failover.voters.insert(msg.sender.clone());
+        info!(
+            "Failover auth granted by {}, {} of {} needed votes",
+            msg.sender,
+            self.failover.voters.len(),
+            self.quorum()
+        );
+        self.failover.voters.len() >= self.quorum()
+    }
+
+    // 成为 master, 以新的 config epoch 接管原 master 的 slot, 并立即通知所有节点
+    fn replace_master(&mut self, config_epoch: u64, repl_offset: u64) {
+        let myself = self.myself.clone();
+        let Some(node) = self.nodes.get_mut(&myself) else {
+            return;
+        };
+        let Some(old) = node.master.take() else {
+            return;
+        };
+        node.config_epoch = config_epoch;
+        for slot in self.slots.iter_mut() {
+            if slot.as_deref() == Some(old.as_str()) {
+                *slot = Some(myself.clone());
+            }
+        }
+        info!(
+            "Failover succeeded, I'm the new master with config epoch {}",
+            config_epoch
+        );
+        self.failover = FailoverState::default();
+        self.broadcast(MessageType::Pong, repl_offset);
+    }
+
+    // replica 在 master FAIL 或手动故障转移时发起选举, 返回是否更新了 epoch
+    fn handle_replica_failover(&mut self, now: i64, timeout: i64, repl_offset: u64) -> bool {
+        let Some(master_id) = self.myself().master.clone() else {
+            return false;
+        };
+        let Some(master) = self.nodes.get(&master_id) else {
+            return false;
+        };
+        let manual = self.failover.mf_end != 0 && self.failover.mf_can_start;
+        if (master.health != NodeHealth::Fail && !manual) || !self.has_slots(&master_id) {
+            return false;
+        }
+        let auth_timeout = (timeout * 2).max(2000);
+        if self.failover.auth_time == 0 || now - self.failover.auth_time > auth_timeout * 2 {
+            // 复制进度越靠前的 replica 越早发起选举, 更有可能当选
+            let rank = self
+                .replicas_of(&master_id)
+                .filter(|n| n.id != self.myself && n.repl_offset > repl_offset)
+                .count() as i64;
+            let delay = if manual {
+                0
+            } else {
+                500 + (random_u64() % 500) as i64 + rank * 1000
+            };
+            info!(
+                "Start of election delayed for {} milliseconds (rank #{}, offset {})",
+                delay, rank, repl_offset
+            );
+            self.failover.auth_time = now + delay;
+            self.failover.auth_sent = false;
+            self.failover.voters.clear();
+        }
+        if now < self.failover.auth_time
+            || now - self.failover.auth_time > auth_timeout
+            || self.failover.auth_sent
+        {
+            return false;
+        }
+        self.current_epoch += 1;
+        self.failover.auth_epoch = self.current_epoch;
+        self.failover.auth_sent = true;
+        info!(
+            "Starting a failover election for epoch {}",
+            self.current_epoch
+        );
+        self.broadcast(MessageType::AuthRequest, repl_offset);
+        true
+    }
+}
+
+impl Backend {
+    fn node_timeout(&self) -> i64 {
+        self.config().cluster_node_timeout as i64
+    }
+
+    /// 处理集群总线上收到的消息, 返回需要在同一连接上回复的消息
+    pub(crate) fn cluster_process(&self, msg: BusMessage) -> Option<BusMessage> {
+        let now = now_ms();
+        let timeout = self.node_timeout();
+        let (_, offset) = self.repl_offset();
+        let (mut dirty, mut follow, mut promoted) = (false, None, false);
+        let mut mf_replica = None;
+        let reply = {
+            let mut inner = self.cluster.write();
+            if msg.sender == inner.myself {
+                return None;
+            }
+            if !inner.nodes.contains_key(&msg.sender) {
+                // 只有 MEET 和 MEET 的回复会加入新节点, 其他节点通过 gossip 得知
+                match msg.kind {
+                    MessageType::Meet | MessageType::Pong => {
+                        info!(
+                            "Node {} ({}:{}) joined the cluster",
+                            msg.sender, msg.ip, msg.port
+                        );
+                        let node = ClusterNode::new(
+                            msg.sender.clone(),
+                            msg.ip.clone(),
+                            msg.port,
+                            msg.cport,
+                        );
+                        inner.nodes.insert(msg.sender.clone(), node);
+                        dirty = true;
+                    }
+                    MessageType::Ping => return Some(inner.message(MessageType::Pong, offset)),
+                    _ => return None,
+                }
+            }
+            if msg.current_epoch > inner.current_epoch {
+                inner.current_epoch = msg.current_epoch;
+                dirty = true;
+            }
+
+            // 收到消息说明节点在线
+            let sender_has_slots = inner.has_slots(&msg.sender);
+            let node = inner.nodes.get_mut(&msg.sender)?;
+            if (node.ip.as_str(), node.port, node.cport) != (msg.ip.as_str(), msg.port, msg.cport) {
+                node.ip = msg.ip.clone();
+                node.port = msg.port;
+                node.cport = msg.cport;
+                dirty = true;
+            }
+            node.repl_offset = msg.repl_offset;
+            node.ping_sent = 0;
+            node.pong_received = now;
+            match node.health {
+                NodeHealth::PFail => node.health = NodeHealth::Ok,
+                // 不再负责 slot 的节点, 或者 FAIL 已经足够久的 master 恢复正常
+                NodeHealth::Fail
+                    if msg.master.is_some()
+                        || !sender_has_slots
+                        || now - node.fail_time > timeout * 2 =>
+                {
+                    info!(
+                        "Clear FAIL state for node {}: it is reachable again",
+                        node.id
+                    );
+                    node.health = NodeHealth::Ok;
+                }
+                _ => {}
+            }
+            if node.master != msg.master {
+                node.master = msg.master.clone();
+                dirty = true;
+            }
+            if msg.master.is_some() {
+                // 成为 replica 的节点不再负责 slot
+                for slot in inner.slots.iter_mut() {
+                    if slot.as_deref() == Some(msg.sender.as_str()) {
+                        *slot = None;
+                        dirty = true;
+                    }
+                }
+            } else {
+                if node.config_epoch != msg.config_epoch {
+                    node.config_epoch = msg.config_epoch;
+                    dirty = true;
+                }
+                let (changed, master) = inner.update_slots(&msg);
+                dirty |= changed;
+                follow = master;
+                // 两个 master 的 config epoch 相同时, ID 较大的一方使用新的 epoch
+                let myself = inner.myself.clone();
+                let my_epoch = inner.myself().config_epoch;
+                if inner.myself().master.is_none()
+                    && msg.config_epoch == my_epoch
+                    && msg.sender < myself
+                {
+                    inner.current_epoch += 1;
+                    let epoch = inner.current_epoch;
+                    if let Some(node) = inner.nodes.get_mut(&myself) {
+                        node.config_epoch = epoch;
+                    }
+                    warn!(
+                        "configEpoch collision with node {}. configEpoch set to {}",
+                        msg.sender, epoch
+                    );
+                    dirty = true;
+                }
+            }
+
+            // master 的 gossip 中的故障报告
+            for g in &msg.gossip {
+                if g.id == inner.myself {
+                    continue;
+                }
+                match inner.nodes.get_mut(&g.id) {
+                    Some(node) if msg.master.is_none() => {
+                        if g.health == NodeHealth::Ok {
+                            node.fail_reports.remove(&msg.sender);
+                        } else {
+                            node.fail_reports.insert(msg.sender.clone(), now);
+                            inner.mark_failing(&g.id, now, offset);
+                        }
+                    }
+                    Some(_) => {}
+                    None if g.health == NodeHealth::Ok => {
+                        let node = ClusterNode::new(g.id.clone(), g.ip.clone(), g.port, g.cport);
+                        inner.nodes.insert(g.id.clone(), node);
+                        dirty = true;
+                    }
+                    None => {}
+                }
+            }
+
+            let myself = inner.myself.clone();
+            let from_my_master = inner.myself().master.as_ref() == Some(&msg.sender);
+            // 手动故障转移中 master 暂停写入时的 offset
+            if from_my_master
+                && msg.paused
+                && inner.failover.mf_end != 0
+                && inner.failover.mf_master_offset.is_none()
+            {
+                info!(
+                    "Received replication offset for paused master manual failover: {}",
+                    msg.repl_offset
+                );
+                inner.failover.mf_master_offset = Some(msg.repl_offset);
+            }
+            match &msg.kind {
+                MessageType::Ping | MessageType::Meet => {
+                    Some(inner.message(MessageType::Pong, offset))
+                }
+                MessageType::Pong => None,
+                MessageType::Fail(id) => {
+                    if *id != myself {
+                        if let Some(node) = inner.nodes.get_mut(id) {
+                            if node.health != NodeHealth::Fail {
+                                info!("FAIL message received from {} about {}", msg.sender, id);
+                                node.health = NodeHealth::Fail;
+                                node.fail_time = now;
+                            }
+                        }
+                    }
+                    None
+                }
+                MessageType::AuthRequest => {
+                    dirty |= inner.vote(&msg, now, timeout, offset);
+                    None
+                }
+                MessageType::AuthAck => {
+                    if inner.count_vote(&msg) {
+                        let epoch = inner.failover.auth_epoch;
+                        inner.replace_master(epoch, offset);
+                        promoted = true;
+                        dirty = true;
+                    }
+                    None
+                }
+                MessageType::MfStart => {
+                    if msg.master.as_ref() == Some(&myself) && inner.myself().master.is_none() {
+                        info!("Manual failover requested by replica {}", msg.sender);
+                        inner.failover.mf_end = now + MF_TIMEOUT;
+                        mf_replica = Some(msg.sender.clone());
+                    }
+                    None
+                }
+            }
+        };
+        if let Some(replica) = mf_replica {
+            // 写命令在持有 write_barrier 时检查暂停; 等已经开始执行的写命令完成后,
+            // 再告知 replica 暂停写入后的 offset. 不能在持有集群状态的锁时等待
+            drop(self.block_writes());
+            let (_, offset) = self.repl_offset();
+            self.cluster
+                .write()
+                .send(&replica, MessageType::Ping, offset);
+        }
+        self.apply_cluster_changes(dirty, follow, promoted);
+        reply
+    }
+
+    /// 每 100 毫秒执行一次: 发送 PING, 检测故障, 处理选举. 返回需要发送的消息: (ip:cport, 消息)
+    pub(crate) fn cluster_cron(&self) -> Vec<(String, BusMessage)> {
+        let now = now_ms();
+        let timeout = self.node_timeout();
+        let (_, offset) = self.repl_offset();
+        let (dirty, messages) = {
+            let mut inner = self.cluster.write();
+            let ping = now - inner.last_ping >= (timeout / 2).clamp(1, 1000);
+            if ping {
+                inner.last_ping = now;
+            }
+            let ids = inner
+                .nodes
+                .keys()
+                .filter(|id| **id != inner.myself)
+                .cloned()
+                .collect::<Vec<_>>();
+            for id in ids {
+                let Some(node) = inner.nodes.get_mut(&id) else {
+                    continue;
+                };
+                node.fail_reports.retain(|_, t| now - *t <= timeout * 2);
+                if node.ping_sent != 0
+                    && now - node.ping_sent > timeout
+                    && node.health == NodeHealth::Ok
+                {
+                    info!("*** NODE {} possibly failing", id);
+                    node.health = NodeHealth::PFail;
+                }
+                if ping {
+                    if node.ping_sent == 0 {
+                        node.ping_sent = now;
+                    }
+                    inner.send(&id, MessageType::Ping, offset);
+                }
+                inner.mark_failing(&id, now, offset);
+            }
+
+            let failover = &mut inner.failover;
+            if failover.mf_end != 0 && now > failover.mf_end {
+                warn!("Manual failover timed out");
+                failover.mf_end = 0;
+                failover.mf_can_start = false;
+                failover.mf_master_offset = None;
+            }
+            // replica 已经追上了暂停写入的 master
+            if failover.mf_master_offset.is_some_and(|o| offset >= o) && !failover.mf_can_start {
+                info!("All master replication stream processed, manual failover can start");
+                failover.mf_can_start = true;
+            }
+            let dirty = inner.handle_replica_failover(now, timeout, offset);
+            (dirty, std::mem::take(&mut inner.outbox))
+        };
+        self.apply_cluster_changes(dirty, None, false);
+        messages
+    }
+
+    // 配置变化后保存, 角色变化后开始或停止复制
+    fn apply_cluster_changes(&self, dirty: bool, follow: Option<(String, u16)>, promoted: bool) {
+        if dirty {
+            if let Err(e) = self.save_cluster_config() {
+                warn!("Failed to save cluster config: {}", e);
+            }
+        }
+        if follow.is_some() {
+            self.replicaof(follow);
+        } else if promoted {
+            self.replicaof(None);
+        }
+    }
+
+    /// 手动故障转移期间, master 暂停执行写命令
+    pub fn cluster_writes_paused(&self) -> bool {
+        if !self.cluster_enabled() {
+            return false;
+        }
+        let inner = self.cluster.read();
+        inner.myself().master.is_none() && inner.failover.mf_end > now_ms()
+    }
+
+    /// CLUSTER MEET: 向节点发送 MEET, 收到 PONG 后把它加入集群
+    pub fn cluster_meet(&self, ip: &str, port: u16, cport: Option<u16>) -> Result<()> {
+        let Some(cport) = cport.or_else(|| port.checked_add(10000)) else {
+            bail!("Invalid node address specified: {}:{}", ip, port);
+        };
+        let (_, offset) = self.repl_offset();
+        let mut inner = self.cluster.write();
+        let msg = inner.message(MessageType::Meet, offset);
+        inner.outbox.push((format!("{}:{}", ip, cport), msg));
+        Ok(())
+    }
+
+    /// CLUSTER REPLICATE: 成为指定 master 的 replica
+    pub fn cluster_replicate(&self, id: &str) -> Result<()> {
+        let addr = {
+            let mut inner = self.cluster.write();
+            inner.check_node(id)?;
+            if id == inner.myself {
+                bail!("Can't replicate myself");
+            }
+            if inner.nodes[id].master.is_some() {
+                bail!("I can only replicate a master, not a replica.");
+            }
+            let myself = inner.myself.clone();
+            if inner.myself().master.is_none()
+                && (inner.has_slots(&myself) || !self.keys().is_empty())
+            {
+                bail!("To set a master the node must be empty and without assigned slots.");
+            }
+            if let Some(node) = inner.nodes.get_mut(&myself) {
+                node.master = Some(id.to_string());
+            }
+            inner.failover = FailoverState::default();
+            let master = &inner.nodes[id];
+            (master.ip.clone(), master.port)
+        };
+        self.save_cluster_config()?;
+        self.replicaof(Some(addr));
+        Ok(())
+    }
+
+    /// CLUSTER FAILOVER: 由 replica 接替它的 master
+    pub fn cluster_failover(&self, mode: FailoverMode) -> Result<()> {
+        let now = now_ms();
+        let (_, offset) = self.repl_offset();
+        {
+            let mut inner = self.cluster.write();
+            let Some(master) = inner.myself().master.clone() else {
+                bail!("You should send CLUSTER FAILOVER to a replica");
+            };
+            let failed = inner
+                .nodes
+                .get(&master)
+                .is_none_or(|m| m.health == NodeHealth::Fail);
+            match mode {
+                FailoverMode::Default if failed => {
+                    bail!("Master is down or failed, please use CLUSTER FAILOVER FORCE")
+                }
+                FailoverMode::Default => {
+                    info!("Manual failover user request accepted.");
+                    inner.failover = FailoverState {
+                        mf_end: now + MF_TIMEOUT,
+                        ..Default::default()
+                    };
+                    inner.send(&master, MessageType::MfStart, offset);
+                    return Ok(());
+                }
+                FailoverMode::Force => {
+                    info!("Forced failover user request accepted.");
+                    inner.failover = FailoverState {
+                        mf_end: now + MF_TIMEOUT,
+                        mf_can_start: true,
+                        ..Default::default()
+                    };
+                    return Ok(());
+                }
+                FailoverMode::Takeover => {
+                    // 不经过投票, 直接使用新的 epoch
+                    info!("Taking over the master (user request).");
+                    inner.current_epoch += 1;
+                    let epoch = inner.current_epoch;
+                    inner.replace_master(epoch, offset);
+                }
+            }
+        }
+        self.apply_cluster_changes(true, None, true);
+        Ok(())
+    }
+
+    /// CLUSTER COUNT-FAILURE-REPORTS
+    pub fn cluster_count_failure_reports(&self, id: &str) -> Result<usize> {
+        let inner = self.cluster.read();
+        match inner.nodes.get(id) {
+            Some(node) => Ok(node.fail_reports.len()),
+            None => bail!("Unknown node {}", id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, future::Future, time::Duration};
+
+    use anyhow::Result;
+    use tokio::{net::TcpListener, task::JoinHandle};
+
+    use super::*;
+    use crate::{
+        cluster::cluster_bus, cmd::Command, network, BulkString, RespArray, RespFrame,
+        ServerConfig, SimpleString,
+    };
+
+    struct Node {
+        backend: Backend,
+        cport: u16,
+        bus: JoinHandle<Result<()>>,
+    }
+
+    impl Node {
+        async fn start() -> Result<Node> {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let port = listener.local_addr()?.port();
+            let dir = std::env::temp_dir().join(format!(
+                "simple-redis-failover-{}-{}",
+                std::process::id(),
+                port
+            ));
+            fs::create_dir_all(&dir)?;
+            let backend = Backend::with_config(ServerConfig {
+                bind: "127.0.0.1".to_string(),
+                port,
+                dir,
+                cluster_enabled: true,
+                cluster_node_timeout: 300,
+                ..Default::default()
+            });
+            backend.load_cluster_config()?;
+            network::testing::serve(listener, backend.clone());
+            let bus = TcpListener::bind("127.0.0.1:0").await?;
+            let cport = bus.local_addr()?.port();
+            let myself = backend.cluster_myid();
+            backend
+                .cluster
+                .write()
+                .nodes
+                .get_mut(&myself)
+                .unwrap()
+                .cport = cport;
+            let bus = tokio::spawn(cluster_bus(backend.clone(), bus));
+            Ok(Node {
+                backend,
+                cport,
+                bus,
+            })
+        }
+
+        // 集群总线以同样的端口重新启动
+        async fn restart_bus(&mut self) -> Result<()> {
+            let listener = TcpListener::bind(("127.0.0.1", self.cport)).await?;
+            self.bus = tokio::spawn(cluster_bus(self.backend.clone(), listener));
+            Ok(())
+        }
+
+        fn id(&self) -> String {
+            self.backend.cluster_myid()
+        }
+
+        fn master(&self) -> Option<String> {
+            self.backend.cluster.read().myself().master.clone()
+        }
+
+        fn slot_owner(&self, slot: u16) -> Option<String> {
+            self.backend.cluster.read().slots[slot as usize].clone()
+        }
+
+        fn health(&self, id: &str) -> Option<NodeHealth> {
+            self.backend.cluster.read().nodes.get(id).map(|n| n.health)
+        }
+    }
+
+    async fn wait_until(what: &str, f: impl Fn() -> bool) -> Result<()> {
+        for _ in 0..1000 {
+            if f() {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        bail!("timed out waiting for {}", what)
+    }
+
+    async fn timeout<T>(f: impl Future<Output = Result<T>>) -> Result<T> {
+        tokio::time::timeout(Duration::from_secs(60), f).await?
+    }
+
+    #[test]
+    fn test_writes_paused_under_barrier() -> Result<()> {
+        let backend = Backend::with_config(ServerConfig {
+            cluster_enabled: true,
+            ..Default::default()
+        });
+        backend.cluster.write().failover.mf_end = now_ms() + MF_TIMEOUT;
+        let frame: RespFrame = RespArray::new(vec![
+            BulkString::new("SET").into(),
+            BulkString::new("foo").into(),
+            BulkString::new("bar").into(),
+        ])
+        .into();
+        let cmd = Command::try_from(frame.clone())?;
+        let Err(paused) = backend.try_execute_write(cmd, frame) else {
+            panic!("writes should be refused while paused");
+        };
+        assert_eq!(backend.get("foo"), None);
+        assert_eq!(backend.repl_offset().1, 0);
+
+        backend.cluster.write().failover.mf_end = 0;
+        let (cmd, frame) = *paused;
+        let ret = backend.try_execute_write(cmd, frame).ok();
+        assert_eq!(ret, Some(SimpleString::new("OK").into()));
+        assert!(backend.get("foo").is_some());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_cluster_failover() -> Result<()> {
+        timeout(cluster_failover()).await
+    }
+
+    async fn cluster_failover() -> Result<()> {
+        // 三个 master 和 a 的一个 replica d
+        let mut nodes = vec![];
+        for _ in 0..4 {
+            nodes.push(Node::start().await?);
+        }
+        let ranges = [(0, 5460), (5461, 10922), (10923, 16383)];
+        for (node, (start, end)) in nodes.iter().zip(ranges) {
+            node.backend
+                .cluster_add_slots(&(start..=end).collect::<Vec<_>>())?;
+        }
+        let a_port = nodes[0].backend.config().port;
+        for node in &nodes[1..] {
+            node.backend
+                .cluster_meet("127.0.0.1", a_port, Some(nodes[0].cport))?;
+        }
+        wait_until("all nodes to know each other", || {
+            nodes
+                .iter()
+                .all(|n| n.backend.cluster.read().nodes.len() == 4)
+        })
+        .await?;
+        let (a, b, c, d) = (&nodes[0], &nodes[1], &nodes[2], &nodes[3]);
+        let (a_id, d_id) = (a.id(), d.id());
+        d.backend.cluster_replicate(&a_id)?;
+        assert!(d.backend.cluster_replicate(&d_id).is_err());
+        assert!(a.backend.cluster_replicate(&b.id()).is_err());
+        wait_until("d to be known as a replica", || {
+            [b, c]
+                .iter()
+                .all(|n| n.backend.cluster.read().nodes[&d_id].master.as_ref() == Some(&a_id))
+        })
+        .await?;
+        assert!(b.backend.cluster_info().contains("cluster_state:ok\r\n"));
+
+        // a 故障: 其他 master 标记 FAIL, d 当选后接管 a 的 slot
+        a.bus.abort();
+        wait_until("a to be marked as FAIL", || {
+            b.health(&a_id) == Some(NodeHealth::Fail) || b.slot_owner(0) == Some(d_id.clone())
+        })
+        .await?;
+        wait_until("d to be promoted", || {
+            d.master().is_none()
+                && [b, c, d]
+                    .iter()
+                    .all(|n| n.slot_owner(0).as_ref() == Some(&d_id))
+        })
+        .await?;
+        assert!(!d.backend.is_replica());
+        let epoch = d.backend.cluster.read().myself().config_epoch;
+        assert!(c
+            .backend
+            .cluster
+            .read()
+            .nodes
+            .values()
+            .all(|n| n.id == d_id || n.config_epoch < epoch));
+
+        // a 恢复后发现自己的 slot 已经属于 epoch 更大的 d, 成为 d 的 replica
+        nodes[0].restart_bus().await?;
+        let (a, b, d) = (&nodes[0], &nodes[1], &nodes[3]);
+        wait_until("a to become a replica of d", || {
+            a.master().as_ref() == Some(&d_id)
+                && a.slot_owner(0).as_ref() == Some(&d_id)
+                && b.health(&a_id) == Some(NodeHealth::Ok)
+                && a.backend.is_replica()
+        })
+        .await?;
+
+        // 手动故障转移: d 暂停写入, a 追上后当选
+        d.backend
+            .set("foo".to_string(), BulkString::new("bar").into());
+        assert!(d.backend.cluster_failover(FailoverMode::Default).is_err());
+        wait_until("a to catch up", || {
+            a.backend.repl_offset().1 == d.backend.repl_offset().1
+        })
+        .await?;
+        a.backend.cluster_failover(FailoverMode::Default)?;
+        wait_until("manual failover", || {
+            a.master().is_none()
+                && d.master().as_ref() == Some(&a_id)
+                && b.slot_owner(0).as_ref() == Some(&a_id)
+        })
+        .await?;
+        assert!(!d.backend.cluster_writes_paused());
+
+        // TAKEOVER 不需要投票
+        d.backend.cluster_failover(FailoverMode::Takeover)?;
+        assert_eq!(d.master(), None);
+        wait_until("takeover", || {
+            a.master().as_ref() == Some(&d_id) && b.slot_owner(0).as_ref() == Some(&d_id)
+        })
+        .await?;
+
+        for node in nodes {
+            node.bus.abort();
+            // 已经在 blocking 线程上执行的消息处理可能还在保存 nodes.conf
+            let _ = fs::remove_dir_all(&node.backend.config().dir);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,350 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use bytes::BytesMut;
+
+use crate::{
+    backend::now_ms, persist::encode_dump, Backend, BulkString, RespArray, RespDecode, RespEncode,
+    RespError, RespFrame,
+};
+
+/// MIGRATE 的选项
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrateOptions {
+    /// 保留本地的 key
+    pub copy: bool,
+    /// 覆盖目标节点上已有的 key
+    pub replace: bool,
+    /// AUTH password / AUTH2 username password
+    pub auth: Option<(Option<String>, String)>,
+    pub timeout: Duration,
+}
+
+impl Backend {
+    /// MIGRATE: 通过 RESTORE-ASKING 把 key 发送到目标节点, 不是 COPY 时删除本地的 key.
+    /// 返回迁移的 key 数量, 0 表示 key 都不存在.
+    /// 与 Redis 一样同步阻塞执行, 错误信息带有错误码前缀
+    pub fn migrate(
+        &self,
+        host: &str,
+        port: u16,
+        keys: &[String],
+        opts: &MigrateOptions,
+    ) -> Result<usize> {
+        // 从读取 key 到删除本地 key 期间阻塞写命令, 否则迁移期间的修改会随 DEL 丢失
+        let _guard = self.block_writes();
+        let now = now_ms();
+        let mut entries = Vec::new();
+        for key in keys {
+            let Some(entry) = self.entry(key) else {
+                continue;
+            };
+            let ttl = match entry.expire_at {
+                Some(t) if t <= now => continue,
+                Some(t) => t - now,
+                None => 0,
+            };
+            entries.push((entry.key, ttl, encode_dump(&entry.value)));
+        }
+        if entries.is_empty() {
+            return Ok(0);
+        }
+
+        let mut conn = MigrateConn::connect(host, port, opts.timeout)
+            .map_err(|_| anyhow!("IOERR error or timeout connecting to the client"))?;
+        if let Some((username, password)) = &opts.auth {
+            let mut args = vec!["AUTH".into()];
+            args.extend(username.iter().map(|u| u.as_bytes().to_vec()));
+            args.push(password.as_bytes().to_vec());
+            conn.command(args)?;
+        }
+        for (key, ttl, payload) in &entries {
+            let mut args = vec![
+                b"RESTORE-ASKING".to_vec(),
+                key.as_bytes().to_vec(),
+                ttl.to_string().into_bytes(),
+                payload.clone(),
+            ];
+            if opts.replace {
+                args.push(b"REPLACE".to_vec());
+            }
+            conn.command(args)?;
+        }
+
+        if !opts.copy {
+            // 已经持有 block_writes, 直接删除, 再以 DEL 的形式传播到 AOF 和 replicas
+            let mut args = vec![BulkString::new("DEL").into()];
+            for (key, _, _) in &entries {
+                self.del(key);
+                args.push(BulkString::new(key.clone()).into());
+            }
+            self.propagate(RespArray::new(args).into());
+        }
+        Ok(entries.len())
+    }
+}
+
+// 到目标节点的同步连接
+struct MigrateConn {
+    stream: TcpStream,
+    buf: BytesMut,
+}
+
+impl MigrateConn {
+    fn connect(host: &str, port: u16, timeout: Duration) -> Result<Self> {
+        let addr = (host, port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("invalid address {}:{}", host, port))?;
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        Ok(MigrateConn {
+            stream,
+            buf: BytesMut::new(),
+        })
+    }
+
+    // 发送命令, 目标节点回复错误时失败
+    fn command(&mut self, args: Vec<Vec<u8>>) -> Result<RespFrame> {
+        let frame: RespFrame = RespArray::new(
+            args.into_iter()
+                .map(|a| BulkString::new(a).into())
+                .collect::<Vec<_>>(),
+        )
+        .into();
+        let io_error = |_| anyhow!("IOERR error or timeout writing to target instance");
+        self.stream.write_all(&frame.encode()).map_err(io_error)?;
+        let mut chunk = [0u8; 4096];
+        loop {
+            match RespFrame::decode(&mut self.buf) {
+                Ok(RespFrame::Error(e)) => {
+                    bail!("ERR Target instance replied with error: {}", e.as_str())
+                }
+                Ok(reply) => return Ok(reply),
+                Err(RespError::NotComplete) => {}
+                Err(e) => bail!("ERR invalid reply from target instance: {}", e),
+            }
+            let n = self
+                .stream
+                .read(&mut chunk)
+                .map_err(|_| anyhow!("IOERR error or timeout reading to target instance"))?;
+            if n == 0 {
+                bail!("IOERR error or timeout reading to target instance");
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::Result;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+    use crate::{
+        cluster::ClusterInner,
+        cmd::Command,
+        network::testing::{self, request},
+        ServerConfig, SimpleString,
+    };
+
+    const A: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const B: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+    // 两个节点: A 负责 0-8191, B 负责 8192-16383
+    async fn start_node(myself: &str, ports: &mut Vec<u16>) -> Result<(Backend, TcpListener)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        ports.push(listener.local_addr()?.port());
+        let dir = std::env::temp_dir().join(format!(
+            "simple-redis-migrate-{}-{}",
+            std::process::id(),
+            &myself[..1]
+        ));
+        fs::create_dir_all(&dir)?;
+        let backend = Backend::with_config(ServerConfig {
+            dir,
+            cluster_enabled: true,
+            ..Default::default()
+        });
+        Ok((backend, listener))
+    }
+
+    fn serve(backend: Backend, listener: TcpListener, ports: &[u16], myself: &str) -> Result<()> {
+        let flags = |id: &str| {
+            if id == myself {
+                "myself,master"
+            } else {
+                "master"
+            }
+        };
+        let conf = format!(
+            "{A} 127.0.0.1:{}@1 {} - 0 0 1 connected 0-8191\n\
+             {B} 127.0.0.1:{}@1 {} - 0 0 2 connected 8192-16383\n\
+             vars currentEpoch 2 lastVoteEpoch 0\n",
+            ports[0],
+            flags(A),
+            ports[1],
+            flags(B),
+        );
+        *backend.cluster.write() = ClusterInner::parse(&conf)?;
+        testing::serve(listener, backend);
+        Ok(())
+    }
+
+    fn error(frame: &RespFrame) -> String {
+        match frame {
+            RespFrame::Error(e) => e.as_str().to_string(),
+            other => format!("{:?}", other),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_migrate_slot() -> Result<()> {
+        let mut ports = vec![];
+        let (a, listener_a) = start_node(A, &mut ports).await?;
+        let (b, listener_b) = start_node(B, &mut ports).await?;
+        serve(a.clone(), listener_a, &ports, A)?;
+        serve(b.clone(), listener_b, &ports, B)?;
+        let (port_a, port_b) = (ports[0].to_string(), ports[1].to_string());
+        let mut client_a = TcpStream::connect(("127.0.0.1", ports[0])).await?;
+        let mut client_b = TcpStream::connect(("127.0.0.1", ports[1])).await?;
+        let ok: RespFrame = SimpleString::new("OK").into();
+
+        // bar 与 {bar}2 都在 slot 5061
+        request(&mut client_a, &["SET", "bar", "1"]).await?;
+        request(&mut client_a, &["SET", "{bar}2", "2"]).await?;
+        let ret = request(
+            &mut client_b,
+            &["CLUSTER", "SETSLOT", "5061", "IMPORTING", A],
+        )
+        .await?;
+        assert_eq!(ret, ok);
+        let ret = request(
+            &mut client_a,
+            &["CLUSTER", "SETSLOT", "5061", "MIGRATING", B],
+        )
+        .await?;
+        assert_eq!(ret, ok);
+        let ret = request(&mut client_a, &["CLUSTER", "GETKEYSINSLOT", "5061", "10"]).await?;
+        assert!(matches!(ret, RespFrame::Array(keys) if keys.len() == 2));
+
+        let ret = request(
+            &mut client_a,
+            &[
+                "MIGRATE",
+                "127.0.0.1",
+                &port_b,
+                "",
+                "0",
+                "1000",
+                "KEYS",
+                "bar",
+            ],
+        )
+        .await?;
+        assert_eq!(ret, ok);
+        assert_eq!(a.get("bar"), None);
+        assert_eq!(b.get("bar"), Some(BulkString::new("1").into()));
+
+        // 已经迁走的 key 回复 ASK, 部分迁走时回复 TRYAGAIN
+        let ret = request(&mut client_a, &["GET", "bar"]).await?;
+        assert_eq!(error(&ret), format!("ASK 5061 127.0.0.1:{}", port_b));
+        let ret = request(&mut client_a, &["PFCOUNT", "bar", "{bar}2"]).await?;
+        assert!(error(&ret).starts_with("TRYAGAIN"));
+        // 目标节点只处理 ASKING 之后的一条命令
+        let ret = request(&mut client_b, &["GET", "bar"]).await?;
+        assert_eq!(error(&ret), format!("MOVED 5061 127.0.0.1:{}", port_a));
+        assert_eq!(request(&mut client_b, &["ASKING"]).await?, ok);
+        let ret = request(&mut client_b, &["GET", "bar"]).await?;
+        assert_eq!(ret, BulkString::new("1").into());
+        let ret = request(&mut client_b, &["GET", "bar"]).await?;
+        assert!(error(&ret).starts_with("MOVED"));
+
+        // COPY 保留本地的 key; 目标已存在时需要 REPLACE
+        let migrate = ["MIGRATE", "127.0.0.1", &port_b, "{bar}2", "0", "1000"];
+        let ret = request(&mut client_a, &[&migrate[..], &["COPY"]].concat()).await?;
+        assert_eq!(ret, ok);
+        assert!(a.get("{bar}2").is_some());
+        let ret = request(&mut client_a, &migrate).await?;
+        assert!(error(&ret).starts_with("ERR Target instance replied with error: BUSYKEY"));
+        let ret = request(&mut client_a, &[&migrate[..], &["REPLACE"]].concat()).await?;
+        assert_eq!(ret, ok);
+        assert_eq!(a.get("{bar}2"), None);
+        let ret = request(&mut client_a, &migrate).await?;
+        assert_eq!(ret, SimpleString::new("NOKEY").into());
+
+        // 迁移完成后两个节点都把 slot 分配给 B
+        let ret = request(&mut client_b, &["CLUSTER", "SETSLOT", "5061", "NODE", B]).await?;
+        assert_eq!(ret, ok);
+        let ret = request(&mut client_a, &["CLUSTER", "SETSLOT", "5061", "NODE", B]).await?;
+        assert_eq!(ret, ok);
+        let ret = request(&mut client_a, &["GET", "bar"]).await?;
+        assert_eq!(error(&ret), format!("MOVED 5061 127.0.0.1:{}", port_b));
+        let ret = request(&mut client_b, &["GET", "bar"]).await?;
+        assert_eq!(ret, BulkString::new("1").into());
+        assert!(b.cluster_nodes().contains(&format!(
+            "{} 127.0.0.1:{}@1 myself,master - 0 0 3 connected 5061",
+            B, port_b
+        )));
+
+        // foo: 12182, 目标地址不可达时回复 IOERR
+        request(&mut client_b, &["SET", "foo", "1"]).await?;
+        let ret = request(
+            &mut client_b,
+            &["MIGRATE", "127.0.0.1", "1", "foo", "0", "100"],
+        )
+        .await?;
+        assert!(error(&ret).starts_with("IOERR"));
+        assert!(b.get("foo").is_some());
+        fs::remove_dir_all(a.config().dir.clone())?;
+        fs::remove_dir_all(b.config().dir.clone())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_blocks_writes() -> Result<()> {
+        // 目标节点收到 RESTORE-ASKING 后过一段时间才回复
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).unwrap();
+            std::thread::sleep(Duration::from_millis(200));
+            stream.write_all(b"+OK\r\n").unwrap();
+        });
+
+        let backend = Backend::new();
+        backend.set("key".to_string(), BulkString::new("1").into());
+        let source = backend.clone();
+        let migrate = std::thread::spawn(move || {
+            let opts = MigrateOptions {
+                copy: false,
+                replace: false,
+                auth: None,
+                timeout: Duration::from_secs(1),
+            };
+            source.migrate("127.0.0.1", port, &["key".to_string()], &opts)
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        // 迁移期间的写入在本地 key 删除之后才执行, 不会丢失
+        let frame: RespFrame = RespArray::new(vec![
+            BulkString::new("SET").into(),
+            BulkString::new("key").into(),
+            BulkString::new("2").into(),
+        ])
+        .into();
+        backend.execute_write(Command::try_from(frame.clone())?, frame);
+        assert_eq!(migrate.join().unwrap()?, 1);
+        assert_eq!(backend.get("key"), Some(BulkString::new("2").into()));
+        Ok(())
+    }
+}
@@ -0,0 +1,674 @@
+//! 集群模式: key 按 CRC16 分布到 16384 个 slot, 每个 slot 由一个 master 节点负责.
+//! 节点和 slot 的分配保存在节点配置文件中, 节点之间通过集群总线交换状态, master 故障时由 replica 接替.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use anyhow::{bail, Result};
+use tracing::info;
+
+use crate::{
+    backend::random_id, cmd::Command, persist::write_file, Backend, BulkString, RespArray,
+    RespFrame, RespMap, ServerConfig, SimpleError,
+};
+
+mod bus;
+mod crc16;
+mod failover;
+mod migrate;
+mod nodes;
+
+pub use bus::cluster_bus;
+pub use crc16::{crc16, key_hash_slot, CLUSTER_SLOTS};
+pub use failover::FailoverMode;
+pub use migrate::MigrateOptions;
+
+use bus::BusMessage;
+use failover::FailoverState;
+
+/// 集群的拓扑
+#[derive(Debug)]
+pub struct ClusterState {
+    inner: RwLock<ClusterInner>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ClusterInner {
+    pub myself: String,
+    pub current_epoch: u64,
+    pub last_vote_epoch: u64,
+    pub nodes: BTreeMap<String, ClusterNode>,
+    // 每个 slot 所属的节点 ID
+    pub slots: Vec<Option<String>>,
+    // 正在迁出到目标节点 / 从源节点迁入的 slot
+    pub migrating: BTreeMap<u16, String>,
+    pub importing: BTreeMap<u16, String>,
+    // 以下为运行时的状态, 不保存到配置文件
+    // 上一次向所有节点发送 PING 的时间
+    pub last_ping: i64,
+    pub failover: FailoverState,
+    // 等待通过集群总线发送的消息: (ip:cport, 消息)
+    pub outbox: Vec<(String, BusMessage)>,
+}
+
+/// CLUSTER SETSLOT 的操作
+#[derive(Debug, Clone, PartialEq)]
+pub enum SlotState {
+    Importing(String),
+    Migrating(String),
+    Node(String),
+    Stable,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ClusterNode {
+    pub id: String,
+    pub ip: String,
+    pub port: u16,
+    // 集群总线端口
+    pub cport: u16,
+    // replica 所属的 master ID
+    pub master: Option<String>,
+    pub config_epoch: u64,
+    // 以下为运行时的状态
+    pub health: NodeHealth,
+    // 尚未收到回复的 PING 的发送时间, 0 表示没有
+    pub ping_sent: i64,
+    pub pong_received: i64,
+    pub fail_time: i64,
+    // 报告该节点 PFAIL/FAIL 的 master 与报告时间
+    pub fail_reports: BTreeMap<String, i64>,
+    pub repl_offset: u64,
+    // 最近一次为该 master 的 replica 投票的时间
+    pub voted_time: i64,
+}
+
+/// 节点的故障状态
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) enum NodeHealth {
+    #[default]
+    Ok,
+    /// 自己认为节点可能故障: 超过 node timeout 没有回复
+    PFail,
+    /// 超过半数 master 认为节点故障
+    Fail,
+}
+
+impl ClusterNode {
+    fn new(id: String, ip: String, port: u16, cport: u16) -> Self {
+        ClusterNode {
+            id,
+            ip,
+            port,
+            cport,
+            master: None,
+            config_epoch: 0,
+            health: NodeHealth::Ok,
+            ping_sent: 0,
+            pong_received: 0,
+            fail_time: 0,
+            fail_reports: BTreeMap::new(),
+            repl_offset: 0,
+            voted_time: 0,
+        }
+    }
+}
+
+impl ClusterState {
+    // 新的节点: 只包含自己, 不负责任何 slot
+    pub fn new(config: &ServerConfig) -> Self {
+        // 监听所有地址时, 以本机地址告知客户端
+        let ip = match config.bind.as_str() {
+            "0.0.0.0" | "::" => "127.0.0.1".to_string(),
+            ip => ip.to_string(),
+        };
+        let myself = ClusterNode::new(
+            random_id(),
+            ip,
+            config.port,
+            config.port.wrapping_add(10000),
+        );
+        ClusterState {
+            inner: RwLock::new(ClusterInner {
+                myself: myself.id.clone(),
+                current_epoch: 0,
+                last_vote_epoch: 0,
+                nodes: BTreeMap::from([(myself.id.clone(), myself)]),
+                slots: vec![None; CLUSTER_SLOTS],
+                migrating: BTreeMap::new(),
+                importing: BTreeMap::new(),
+                last_ping: 0,
+                failover: FailoverState::default(),
+                outbox: Vec::new(),
+            }),
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, ClusterInner> {
+        self.inner.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, ClusterInner> {
+        self.inner.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl ClusterInner {
+    fn myself(&self) -> &ClusterNode {
+        &self.nodes[&self.myself]
+    }
+
+    // 节点负责的连续 slot 区间
+    pub fn slot_ranges(&self, id: &str) -> Vec<(u16, u16)> {
+        let mut ranges: Vec<(u16, u16)> = Vec::new();
+        for (slot, owner) in self.slots.iter().enumerate() {
+            if owner.as_deref() != Some(id) {
+                continue;
+            }
+            let slot = slot as u16;
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == slot => *end = slot,
+                _ => ranges.push((slot, slot)),
+            }
+        }
+        ranges
+    }
+
+    fn node_addr(&self, id: &str) -> String {
+        let node = &self.nodes[id];
+        format!("{}:{}", node.ip, node.port)
+    }
+
+    // 集群总线地址
+    fn bus_addr(&self, id: &str) -> String {
+        let node = &self.nodes[id];
+        format!("{}:{}", node.ip, node.cport)
+    }
+
+    fn has_slots(&self, id: &str) -> bool {
+        self.slots.iter().any(|s| s.as_deref() == Some(id))
+    }
+
+    fn replicas_of<'a>(&'a self, id: &'a str) -> impl Iterator<Item = &'a ClusterNode> + 'a {
+        self.nodes
+            .values()
+            .filter(move |n| n.master.as_deref() == Some(id))
+    }
+
+    fn check_node(&self, id: &str) -> Result<()> {
+        if !self.nodes.contains_key(id) {
+            bail!("I don't know about node {}", id);
+        }
+        Ok(())
+    }
+
+    fn assigned_slots(&self) -> usize {
+        self.slots.iter().filter(|s| s.is_some()).count()
+    }
+}
+
+impl Backend {
+    pub fn cluster_enabled(&self) -> bool {
+        self.config().cluster_enabled
+    }
+
+    /// 启动时载入节点配置文件, 不存在时以当前节点创建; 配置中本节点是 replica 时开始复制
+    pub fn load_cluster_config(&self) -> Result<()> {
+        let path = self.config().cluster_config_path();
+        if path.exists() {
+            let inner = ClusterInner::parse(&fs::read_to_string(&path)?)?;
+            info!("Cluster config loaded, node ID {}", inner.myself);
+            *self.cluster.write() = inner;
+        } else {
+            self.save_cluster_config()?;
+            info!(
+                "No cluster configuration found, I'm {}",
+                self.cluster_myid()
+            );
+        }
+        let master = {
+            let inner = self.cluster.read();
+            let master = inner.myself().master.as_ref();
+            master
+                .and_then(|id| inner.nodes.get(id))
+                .map(|m| (m.ip.clone(), m.port))
+        };
+        if master.is_some() {
+            self.replicaof(master);
+        }
+        Ok(())
+    }
+
+    /// 集群总线监听的地址
+    pub fn cluster_bus_addr(&self) -> String {
+        let cport = self.cluster.read().myself().cport;
+        format!("{}:{}", self.config().bind, cport)
+    }
+
+    pub(crate) fn save_cluster_config(&self) -> Result<()> {
+        let conf = self.cluster.read().nodes_conf();
+        write_file(&self.config().cluster_config_path(), conf.as_bytes())
+    }
+
+    /// 集群模式下检查命令的 key 是否由本节点负责, 需要重定向时返回错误回复.
+    /// asking 为连接上一条命令是否为 ASKING
+    pub fn cluster_redirect(&self, cmd: &Command, asking: bool) -> Option<RespFrame> {
+        if !self.cluster_enabled() {
+            return None;
+        }
+        let keys = cmd.keys();
+        let (first, rest) = keys.split_first()?;
+        let slot = key_hash_slot(first.as_bytes());
+        if rest.iter().any(|k| key_hash_slot(k.as_bytes()) != slot) {
+            return Some(
+                SimpleError::new("CROSSSLOT Keys in request don't hash to the same slot").into(),
+            );
+        }
+        let asking = asking || cmd.is_asking();
+        let inner = self.cluster.read();
+        match &inner.slots[slot as usize] {
+            Some(id) if *id == inner.myself => {
+                // 迁出中的 slot: 已经迁走的 key 由目标节点处理
+                let target = inner.migrating.get(&slot)?;
+                // 与 Redis 一样, MIGRATE 总是在源节点执行
+                if matches!(cmd, Command::Migrate(_)) {
+                    return None;
+                }
+                let missing = keys.iter().filter(|k| !self.exists(k)).count();
+                if missing == keys.len() {
+                    let addr = inner.node_addr(target);
+                    Some(SimpleError::new(format!("ASK {} {}", slot, addr)).into())
+                } else if missing > 0 {
+                    Some(
+                        SimpleError::new("TRYAGAIN Multiple keys request during rehashing of slot")
+                            .into(),
+                    )
+                } else {
+                    None
+                }
+            }
+            // 迁入中的 slot 只处理带有 ASKING 的请求
+            _ if asking && inner.importing.contains_key(&slot) => None,
+            Some(id) => {
+                let addr = inner.node_addr(id);
+                Some(SimpleError::new(format!("MOVED {} {}", slot, addr)).into())
+            }
+            None => Some(SimpleError::new("CLUSTERDOWN Hash slot not served").into()),
+        }
+    }
+
+    pub fn cluster_myid(&self) -> String {
+        self.cluster.read().myself.clone()
+    }
+
+    /// CLUSTER NODES
+    pub fn cluster_nodes(&self) -> String {
+        self.cluster.read().nodes_info()
+    }
+
+    /// CLUSTER ADDSLOTS: 由本节点负责这些 slot
+    pub fn cluster_add_slots(&self, slots: &[u16]) -> Result<()> {
+        {
+            let mut inner = self.cluster.write();
+            for &slot in slots {
+                if inner.slots[slot as usize].is_some() {
+                    bail!("Slot {} is already busy", slot);
+                }
+            }
+            let myself = inner.myself.clone();
+            for &slot in slots {
+                inner.slots[slot as usize] = Some(myself.clone());
+            }
+        }
+        self.save_cluster_config()
+    }
+
+    /// CLUSTER DELSLOTS: 这些 slot 不再由任何节点负责
+    pub fn cluster_del_slots(&self, slots: &[u16]) -> Result<()> {
+        {
+            let mut inner = self.cluster.write();
+            for &slot in slots {
+                if inner.slots[slot as usize].is_none() {
+                    bail!("Slot {} is already unassigned", slot);
+                }
+            }
+            for &slot in slots {
+                inner.slots[slot as usize] = None;
+            }
+        }
+        self.save_cluster_config()
+    }
+
+    /// CLUSTER SETSLOT
+    pub fn cluster_set_slot(&self, slot: u16, state: SlotState) -> Result<()> {
+        {
+            let mut inner = self.cluster.write();
+            let owned = inner.slots[slot as usize].as_ref() == Some(&inner.myself);
+            match state {
+                SlotState::Importing(id) => {
+                    if owned {
+                        bail!("I'm already the owner of hash slot {}", slot);
+                    }
+                    inner.check_node(&id)?;
+                    inner.importing.insert(slot, id);
+                }
+                SlotState::Migrating(id) => {
+                    if !owned {
+                        bail!("I'm not the owner of hash slot {}", slot);
+                    }
+                    inner.check_node(&id)?;
+                    inner.migrating.insert(slot, id);
+                }
+                SlotState::Stable => {
+                    inner.migrating.remove(&slot);
+                    inner.importing.remove(&slot);
+                }
+                SlotState::Node(id) => {
+                    inner.check_node(&id)?;
+                    if owned && id != inner.myself && self.count_keys_in_slot(slot) > 0 {
+                        bail!("Can't assign hashslot {} to a different node while I still hold keys for this hash slot.", slot);
+                    }
+                    if id != inner.myself {
+                        inner.migrating.remove(&slot);
+                    } else if inner.importing.remove(&slot).is_some() {
+                        // 迁入完成, 使用新的 epoch 使本节点的分配优先
+                        inner.current_epoch += 1;
+                        let epoch = inner.current_epoch;
+                        let myself = inner.myself.clone();
+                        if let Some(node) = inner.nodes.get_mut(&myself) {
+                            node.config_epoch = epoch;
+                        }
+                    }
+                    inner.slots[slot as usize] = Some(id);
+                }
+            }
+        }
+        self.save_cluster_config()
+    }
+
+    /// slot 中 key 的数量
+    pub fn count_keys_in_slot(&self, slot: u16) -> usize {
+        self.keys_in_slot(slot, usize::MAX).len()
+    }
+
+    /// CLUSTER SLOTS: [start, end, master, replica...], 节点为 [ip, port, id]
+    pub fn cluster_slots(&self) -> RespFrame {
+        let inner = self.cluster.read();
+        let node_frame = |node: &ClusterNode| -> RespFrame {
+            RespArray::new(vec![
+                BulkString::new(node.ip.clone()).into(),
+                RespFrame::Integer(node.port as i64),
+                BulkString::new(node.id.clone()).into(),
+            ])
+            .into()
+        };
+        let mut ranges = Vec::new();
+        for node in inner.nodes.values() {
+            for (start, end) in inner.slot_ranges(&node.id) {
+                ranges.push((start, end, node));
+            }
+        }
+        ranges.sort_by_key(|(start, _, _)| *start);
+        let frames = ranges
+            .into_iter()
+            .map(|(start, end, node)| {
+                let mut frame = vec![
+                    RespFrame::Integer(start as i64),
+                    RespFrame::Integer(end as i64),
+                    node_frame(node),
+                ];
+                frame.extend(inner.replicas_of(&node.id).map(node_frame));
+                RespArray::new(frame).into()
+            })
+            .collect::<Vec<RespFrame>>();
+        RespArray::new(frames).into()
+    }
+
+    /// CLUSTER SHARDS: 每个 master 和它的 replica 组成一个 shard
+    pub fn cluster_shards(&self) -> RespFrame {
+        let inner = self.cluster.read();
+        let (_, my_offset) = self.repl_offset();
+        let node_frame = |node: &ClusterNode| -> RespFrame {
+            let mut map = RespMap::new();
+            let role = if node.master.is_some() {
+                "replica"
+            } else {
+                "master"
+            };
+            // 只知道自己的复制 offset
+            let offset = if node.id == inner.myself {
+                my_offset
+            } else {
+                0
+            };
+            map.insert(
+                BulkString::new("id"),
+                BulkString::new(node.id.clone()).into(),
+            );
+            map.insert(
+                BulkString::new("port"),
+                RespFrame::Integer(node.port as i64),
+            );
+            map.insert(
+                BulkString::new("ip"),
+                BulkString::new(node.ip.clone()).into(),
+            );
+            map.insert(
+                BulkString::new("endpoint"),
+                BulkString::new(node.ip.clone()).into(),
+            );
+            map.insert(BulkString::new("role"), BulkString::new(role).into());
+            map.insert(
+                BulkString::new("replication-offset"),
+                RespFrame::Integer(offset as i64),
+            );
+            map.insert(BulkString::new("health"), BulkString::new("online").into());
+            map.into()
+        };
+        let shards = inner
+            .nodes
+            .values()
+            .filter(|n| n.master.is_none())
+            .map(|master| {
+                let slots = inner
+                    .slot_ranges(&master.id)
+                    .into_iter()
+                    .flat_map(|(start, end)| {
+                        [
+                            RespFrame::Integer(start as i64),
+                            RespFrame::Integer(end as i64),
+                        ]
+                    })
+                    .collect::<Vec<_>>();
+                let mut nodes = vec![node_frame(master)];
+                nodes.extend(inner.replicas_of(&master.id).map(node_frame));
+                let mut shard = RespMap::new();
+                shard.insert(BulkString::new("slots"), RespArray::new(slots).into());
+                shard.insert(BulkString::new("nodes"), RespArray::new(nodes).into());
+                shard.into()
+            })
+            .collect::<Vec<RespFrame>>();
+        RespArray::new(shards).into()
+    }
+
+    /// CLUSTER INFO
+    pub fn cluster_info(&self) -> String {
+        let inner = self.cluster.read();
+        let assigned = inner.assigned_slots();
+        let size = inner
+            .nodes
+            .values()
+            .filter(|n| !inner.slot_ranges(&n.id).is_empty())
+            .count();
+        let slots_in = |health: NodeHealth| {
+            inner
+                .slots
+                .iter()
+                .flatten()
+                .filter(|id| inner.nodes.get(*id).is_some_and(|n| n.health == health))
+                .count()
+        };
+        let (pfail, fail) = (slots_in(NodeHealth::PFail), slots_in(NodeHealth::Fail));
+        let state = if assigned == CLUSTER_SLOTS && fail == 0 {
+            "ok"
+        } else {
+            "fail"
+        };
+        let mut info = String::new();
+        let mut field = |name: &str, value: &dyn std::fmt::Display| {
+            let _ = write!(info, "{}:{}\r\n", name, value);
+        };
+        field("cluster_enabled", &1);
+        field("cluster_state", &state);
+        field("cluster_slots_assigned", &assigned);
+        field("cluster_slots_ok", &(assigned - pfail - fail));
+        field("cluster_slots_pfail", &pfail);
+        field("cluster_slots_fail", &fail);
+        field("cluster_known_nodes", &inner.nodes.len());
+        field("cluster_size", &size);
+        field("cluster_current_epoch", &inner.current_epoch);
+        field("cluster_my_epoch", &inner.myself().config_epoch);
+        info
+    }
+
+    /// INFO cluster
+    pub fn info_cluster(&self) -> String {
+        format!(
+            "# Cluster\r\ncluster_enabled:{}\r\n",
+            self.cluster_enabled() as u8
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::testing::command;
+
+    const CONF: &str = "\
+aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa 127.0.0.1:7000@17000 myself,master - 0 0 1 connected 0-8191
+bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb 127.0.0.1:7001@17001 master - 0 0 2 connected 8192-16383
+cccccccccccccccccccccccccccccccccccccccc 127.0.0.1:7002@17002 slave bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb 0 0 2 connected
+vars currentEpoch 2 lastVoteEpoch 0
+";
+
+    fn cluster_backend() -> Result<Backend> {
+        let backend = Backend::with_config(ServerConfig {
+            cluster_enabled: true,
+            ..Default::default()
+        });
+        *backend.cluster.write() = ClusterInner::parse(CONF)?;
+        Ok(backend)
+    }
+
+    #[test]
+    fn test_cluster_redirect() -> Result<()> {
+        let backend = cluster_backend()?;
+        // bar: 5061, foo: 12182
+        assert_eq!(
+            backend.cluster_redirect(&command(&["GET", "bar"])?, false),
+            None
+        );
+        assert_eq!(
+            backend.cluster_redirect(&command(&["SET", "foo", "1"])?, false),
+            Some(SimpleError::new("MOVED 12182 127.0.0.1:7001").into())
+        );
+        assert_eq!(
+            backend.cluster_redirect(&command(&["PFCOUNT", "bar", "foo"])?, false),
+            Some(SimpleError::new("CROSSSLOT Keys in request don't hash to the same slot").into())
+        );
+        assert_eq!(
+            backend.cluster_redirect(&command(&["PFCOUNT", "{bar}1", "{bar}2"])?, false),
+            None
+        );
+        // 没有 key 的命令不需要重定向
+        assert_eq!(
+            backend.cluster_redirect(&command(&["CLUSTER", "SLOTS"])?, false),
+            None
+        );
+
+        backend.cluster.write().slots[5061] = None;
+        assert_eq!(
+            backend.cluster_redirect(&command(&["GET", "bar"])?, false),
+            Some(SimpleError::new("CLUSTERDOWN Hash slot not served").into())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cluster_slots() -> Result<()> {
+        let backend = cluster_backend()?;
+        let node = |port: i64, id: char| -> RespFrame {
+            RespArray::new(vec![
+                BulkString::new("127.0.0.1").into(),
+                RespFrame::Integer(port),
+                BulkString::new(id.to_string().repeat(40)).into(),
+            ])
+            .into()
+        };
+        let expected: RespFrame = RespArray::new(vec![
+            RespArray::new(vec![
+                RespFrame::Integer(0),
+                RespFrame::Integer(8191),
+                node(7000, 'a'),
+            ])
+            .into(),
+            RespArray::new(vec![
+                RespFrame::Integer(8192),
+                RespFrame::Integer(16383),
+                node(7001, 'b'),
+                node(7002, 'c'),
+            ])
+            .into(),
+        ])
+        .into();
+        assert_eq!(backend.cluster_slots(), expected);
+
+        let RespFrame::Array(shards) = backend.cluster_shards() else {
+            panic!("CLUSTER SHARDS should return an array");
+        };
+        assert_eq!(shards.len(), 2);
+        assert!(backend.cluster_info().contains("cluster_state:ok\r\n"));
+        assert!(backend.cluster_info().contains("cluster_size:2\r\n"));
+
+        backend.set("{bar}1".to_string(), BulkString::new("1").into());
+        backend.set("bar".to_string(), BulkString::new("1").into());
+        backend.set("foo".to_string(), BulkString::new("1").into());
+        assert_eq!(backend.count_keys_in_slot(5061), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cluster_add_del_slots() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("simple-redis-cluster-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let backend = Backend::with_config(ServerConfig {
+            dir: dir.clone(),
+            cluster_enabled: true,
+            ..Default::default()
+        });
+        backend.load_cluster_config()?;
+        let myid = backend.cluster_myid();
+
+        backend.cluster_add_slots(&[0, 1, 2, 100])?;
+        assert!(backend.cluster_add_slots(&[2]).is_err());
+        backend.cluster_del_slots(&[1])?;
+        assert!(backend.cluster_del_slots(&[1]).is_err());
+        assert!(backend.cluster_nodes().contains(&format!(
+            "{} 127.0.0.1:6379@16379 myself,master - 0 0 0 connected 0 2 100\n",
+            myid
+        )));
+
+        // 重启后从配置文件恢复
+        let restarted = Backend::with_config(backend.config().clone());
+        restarted.load_cluster_config()?;
+        assert_eq!(restarted.cluster_myid(), myid);
+        assert_eq!(restarted.cluster_nodes(), backend.cluster_nodes());
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
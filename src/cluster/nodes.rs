@@ -0,0 +1,222 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use anyhow::{anyhow, bail, Result};
+
+use super::{ClusterInner, ClusterNode, FailoverState, NodeHealth, CLUSTER_SLOTS};
+
+// 节点配置文件与 CLUSTER NODES 的格式相同, 每个节点一行:
+// <id> <ip:port@cport[,hostname]> <flags> <master> <ping-sent> <pong-recv> <config-epoch> <link-state> <slot> ...
+// 最后一行为 "vars currentEpoch <n> lastVoteEpoch <n>"
+impl ClusterInner {
+    pub fn parse(conf: &str) -> Result<Self> {
+        let mut myself = None;
+        let mut nodes = BTreeMap::new();
+        let mut slots = vec![None; CLUSTER_SLOTS];
+        let (mut migrating, mut importing) = (BTreeMap::new(), BTreeMap::new());
+        let (mut current_epoch, mut last_vote_epoch) = (0, 0);
+        for line in conf.lines() {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            match fields.as_slice() {
+                [] => {}
+                ["vars", vars @ ..] => {
+                    for pair in vars.chunks(2) {
+                        match pair {
+                            ["currentEpoch", n] => current_epoch = n.parse()?,
+                            ["lastVoteEpoch", n] => last_vote_epoch = n.parse()?,
+                            _ => {}
+                        }
+                    }
+                }
+                [id, addr, flags, master, _, _, epoch, _, node_slots @ ..] => {
+                    let (ip, port, cport) = parse_addr(addr)?;
+                    let flags = flags.split(',').collect::<Vec<_>>();
+                    if flags.contains(&"myself") {
+                        myself = Some(id.to_string());
+                    }
+                    let master = (*master != "-").then(|| master.to_string());
+                    for range in node_slots {
+                        // 迁移中的 slot: [slot->-id] / [slot-<-id]
+                        if let Some(entry) =
+                            range.strip_prefix('[').and_then(|r| r.strip_suffix(']'))
+                        {
+                            if let Some((slot, target)) = entry.split_once("->-") {
+                                migrating.insert(slot.parse()?, target.to_string());
+                            } else if let Some((slot, source)) = entry.split_once("-<-") {
+                                importing.insert(slot.parse()?, source.to_string());
+                            } else {
+                                bail!("invalid slot migration entry: {}", range);
+                            }
+                            continue;
+                        }
+                        let (start, end) = parse_slot_range(range)?;
+                        for slot in start..=end {
+                            slots[slot as usize] = Some(id.to_string());
+                        }
+                    }
+                    let node = ClusterNode {
+                        master,
+                        config_epoch: epoch.parse()?,
+                        ..ClusterNode::new(id.to_string(), ip, port, cport)
+                    };
+                    nodes.insert(id.to_string(), node);
+                }
+                _ => bail!("invalid cluster config line: {}", line),
+            }
+        }
+        Ok(ClusterInner {
+            myself: myself.ok_or_else(|| anyhow!("myself node not found in cluster config"))?,
+            current_epoch,
+            last_vote_epoch,
+            nodes,
+            slots,
+            migrating,
+            importing,
+            last_ping: 0,
+            failover: FailoverState::default(),
+            outbox: Vec::new(),
+        })
+    }
+
+    /// CLUSTER NODES
+    pub fn nodes_info(&self) -> String {
+        let mut out = String::new();
+        for node in self.nodes.values() {
+            let role = if node.master.is_some() {
+                "slave"
+            } else {
+                "master"
+            };
+            let myself = node.id == self.myself;
+            let mut flags = if myself {
+                format!("myself,{}", role)
+            } else {
+                role.to_string()
+            };
+            match node.health {
+                NodeHealth::Ok => {}
+                NodeHealth::PFail => flags.push_str(",fail?"),
+                NodeHealth::Fail => flags.push_str(",fail"),
+            }
+            // 最近一次 PING 有回复时连接正常
+            let link = if myself || (node.pong_received > 0 && node.ping_sent == 0) {
+                "connected"
+            } else {
+                "disconnected"
+            };
+            let _ = write!(
+                out,
+                "{} {}:{}@{} {} {} {} {} {} {}",
+                node.id,
+                node.ip,
+                node.port,
+                node.cport,
+                flags,
+                node.master.as_deref().unwrap_or("-"),
+                node.ping_sent,
+                node.pong_received,
+                node.config_epoch,
+                link
+            );
+            for (start, end) in self.slot_ranges(&node.id) {
+                let _ = match start == end {
+                    true => write!(out, " {}", start),
+                    false => write!(out, " {}-{}", start, end),
+                };
+            }
+            if node.id == self.myself {
+                for (slot, target) in &self.migrating {
+                    let _ = write!(out, " [{}->-{}]", slot, target);
+                }
+                for (slot, source) in &self.importing {
+                    let _ = write!(out, " [{}-<-{}]", slot, source);
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// 写入节点配置文件的内容
+    pub fn nodes_conf(&self) -> String {
+        format!(
+            "{}vars currentEpoch {} lastVoteEpoch {}\n",
+            self.nodes_info(),
+            self.current_epoch,
+            self.last_vote_epoch
+        )
+    }
+}
+
+// "ip:port@cport", 可能带有 ",hostname"; ip 可能为 IPv6
+fn parse_addr(addr: &str) -> Result<(String, u16, u16)> {
+    let addr = addr.split(',').next().unwrap_or_default();
+    let (addr, cport) = addr
+        .split_once('@')
+        .ok_or_else(|| anyhow!("invalid node address: {}", addr))?;
+    let (ip, port) = addr
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("invalid node address: {}", addr))?;
+    Ok((ip.to_string(), port.parse()?, cport.parse()?))
+}
+
+/// "5" 或 "0-5460"
+pub(super) fn parse_slot_range(range: &str) -> Result<(u16, u16)> {
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (start.parse::<u16>()?, end.parse::<u16>()?),
+        None => {
+            let slot = range.parse::<u16>()?;
+            (slot, slot)
+        }
+    };
+    if start > end || end as usize >= CLUSTER_SLOTS {
+        bail!("invalid slot range: {}", range);
+    }
+    Ok((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONF: &str = "\
+07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:30004@31004 slave e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 0 0 3 connected
+e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 127.0.0.1:30001@31001,host1 myself,master - 0 0 1 connected 0-5460 [0->-67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1] [10923-<-67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1]
+67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 127.0.0.1:30002@31002 master - 0 0 2 connected 5461-10923
+vars currentEpoch 6 lastVoteEpoch 0
+";
+
+    #[test]
+    fn test_parse_nodes_conf() -> Result<()> {
+        let inner = ClusterInner::parse(CONF)?;
+        assert_eq!(inner.myself, "e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca");
+        assert_eq!(inner.current_epoch, 6);
+        assert_eq!(inner.nodes.len(), 3);
+        let replica = &inner.nodes["07c37dfeb235213a872192d90877d0cd55635b91"];
+        assert_eq!(
+            (replica.ip.as_str(), replica.port, replica.cport),
+            ("127.0.0.1", 30004, 31004)
+        );
+        assert_eq!(replica.master.as_deref(), Some(inner.myself.as_str()));
+        assert_eq!(inner.slots[0].as_deref(), Some(inner.myself.as_str()));
+        assert_eq!(
+            inner.slots[10922].as_deref(),
+            Some("67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1")
+        );
+        assert_eq!(inner.slots[10924], None);
+        let other = "67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1".to_string();
+        assert_eq!(inner.migrating, BTreeMap::from([(0, other.clone())]));
+        assert_eq!(inner.importing, BTreeMap::from([(10923, other)]));
+
+        // 重新生成的配置可以再次解析
+        let conf = inner.nodes_conf();
+        assert!(conf.contains(
+            "e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 127.0.0.1:30001@31001 myself,master - 0 0 1 connected 0-5460 [0->-67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1] [10923-<-67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1]\n"
+        ));
+        assert_eq!(ClusterInner::parse(&conf)?, inner);
+
+        assert!(ClusterInner::parse("vars currentEpoch 0 lastVoteEpoch 0\n").is_err());
+        assert!(parse_slot_range("16384").is_err());
+        assert!(parse_slot_range("10-5").is_err());
+        Ok(())
+    }
+}
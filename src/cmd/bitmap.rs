@@ -0,0 +1,833 @@
+use crate::cmd::{
+    extract_args, parse_num, parse_string, validate_command, validate_command_min, BitCount,
+    BitField, BitOp, BitPos, CommandError, CommandExecutor, GetBit, SetBit, RESP_WRONGTYPE,
+};
+use crate::{Backend, BulkString, KeyType, RespArray, RespFrame, RespNull};
+
+/// 字符串最大 512MB, 即 bit offset 必须小于 2^32
+const MAX_BIT_OFFSET: u64 = (1 << 32) - 1;
+
+/// BITCOUNT / BITPOS 中 start, end 的单位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitRangeUnit {
+    Byte,
+    Bit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOpKind {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+/// BITFIELD 的整数类型: `i<bits>` (1..=64) 或 `u<bits>` (1..=63)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitFieldType {
+    signed: bool,
+    bits: u8,
+}
+
+/// BITFIELD 的溢出策略, 作用于其后的 SET / INCRBY
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitFieldOverflow {
+    Wrap,
+    Sat,
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitFieldOp {
+    Get(BitFieldType, u64),
+    Set(BitFieldType, u64, i64),
+    IncrBy(BitFieldType, u64, i64),
+    Overflow(BitFieldOverflow),
+}
+
+//===================  实现 CommandExecutor trait for Command
+impl CommandExecutor for SetBit {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if backend.is_wrong_type(&self.key, KeyType::String) {
+            return RESP_WRONGTYPE.clone();
+        }
+        backend.update(&self.key, |value| {
+            let mut bytes = match value.take() {
+                None => Vec::new(),
+                Some(RespFrame::BulkString(s)) => s.0.to_vec(),
+                Some(other) => {
+                    *value = Some(other);
+                    return RESP_WRONGTYPE.clone();
+                }
+            };
+            let old = set_bit(&mut bytes, self.offset, self.value);
+            *value = Some(BulkString::new(bytes).into());
+            RespFrame::Integer(old as i64)
+        })
+    }
+}
+impl CommandExecutor for GetBit {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if backend.is_wrong_type(&self.key, KeyType::String) {
+            return RESP_WRONGTYPE.clone();
+        }
+        match backend.get(&self.key) {
+            None => RespFrame::Integer(0),
+            Some(RespFrame::BulkString(s)) => RespFrame::Integer(get_bit(&s, self.offset) as i64),
+            Some(_) => RESP_WRONGTYPE.clone(),
+        }
+    }
+}
+impl CommandExecutor for BitCount {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if backend.is_wrong_type(&self.key, KeyType::String) {
+            return RESP_WRONGTYPE.clone();
+        }
+        let bytes = match backend.get(&self.key) {
+            None => return RespFrame::Integer(0),
+            Some(RespFrame::BulkString(s)) => s.0,
+            Some(_) => return RESP_WRONGTYPE.clone(),
+        };
+        let len = bytes.len() as i64;
+        let range = match self.range {
+            None => normalize_range(0, -1, len * 8),
+            Some((start, end, BitRangeUnit::Byte)) => {
+                normalize_range(start, end, len).map(|(s, e)| (s * 8, e * 8 + 7))
+            }
+            Some((start, end, BitRangeUnit::Bit)) => normalize_range(start, end, len * 8),
+        };
+        let count = range.map_or(0, |(s, e)| count_bits(&bytes, s, e));
+        RespFrame::Integer(count as i64)
+    }
+}
+impl CommandExecutor for BitPos {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if backend.is_wrong_type(&self.key, KeyType::String) {
+            return RESP_WRONGTYPE.clone();
+        }
+        let bytes = match backend.get(&self.key) {
+            // 不存在的 key 视为全 0 的空字符串
+            None => return RespFrame::Integer(if self.bit { -1 } else { 0 }),
+            Some(RespFrame::BulkString(s)) => s.0,
+            Some(_) => return RESP_WRONGTYPE.clone(),
+        };
+        let len = bytes.len() as i64;
+        let (start, end) = (self.start.unwrap_or(0), self.end.unwrap_or(-1));
+        let range = match self.unit {
+            BitRangeUnit::Byte => normalize_range(start, end, len).map(|(s, e)| (s * 8, e * 8 + 7)),
+            BitRangeUnit::Bit => normalize_range(start, end, len * 8),
+        };
+        let Some((start, end)) = range else {
+            return RespFrame::Integer(-1);
+        };
+        match find_bit(&bytes, self.bit, start, end) {
+            Some(pos) => RespFrame::Integer(pos as i64),
+            // 查找 0 且未指定 end 时, 字符串右侧视为补 0
+            None if !self.bit && self.end.is_none() => RespFrame::Integer(end as i64 + 1),
+            None => RespFrame::Integer(-1),
+        }
+    }
+}
+impl CommandExecutor for BitOp {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let mut values = Vec::with_capacity(self.keys.len());
+        for key in &self.keys {
+            if backend.is_wrong_type(key, KeyType::String) {
+                return RESP_WRONGTYPE.clone();
+            }
+            match backend.get(key) {
+                None => values.push(Vec::new()),
+                Some(RespFrame::BulkString(s)) => values.push(s.0.to_vec()),
+                Some(_) => return RESP_WRONGTYPE.clone(),
+            }
+        }
+        let len = values.iter().map(Vec::len).max().unwrap_or(0);
+        let result: Vec<u8> = match self.op {
+            BitOpKind::Not => values[0].iter().map(|b| !b).collect(),
+            op => (0..len)
+                .map(|i| {
+                    let mut bytes = values.iter().map(|v| v.get(i).copied().unwrap_or(0));
+                    let first = bytes.next().unwrap_or(0);
+                    bytes.fold(first, |acc, b| match op {
+                        BitOpKind::And => acc & b,
+                        BitOpKind::Or => acc | b,
+                        _ => acc ^ b,
+                    })
+                })
+                .collect(),
+        };
+        // 与 SET 相同, 覆盖 dest 原有的值 (不论类型) 和过期时间
+        if result.is_empty() {
+            backend.del(&self.dest);
+        } else {
+            backend.set(self.dest, BulkString::new(result).into());
+        }
+        RespFrame::Integer(len as i64)
+    }
+}
+impl CommandExecutor for BitField {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if backend.is_wrong_type(&self.key, KeyType::String) {
+            return RESP_WRONGTYPE.clone();
+        }
+        backend.update(&self.key, |value| {
+            let existed = value.is_some();
+            let mut bytes = match value.take() {
+                None => Vec::new(),
+                Some(RespFrame::BulkString(s)) => s.0.to_vec(),
+                Some(other) => {
+                    *value = Some(other);
+                    return RESP_WRONGTYPE.clone();
+                }
+            };
+            let mut overflow = BitFieldOverflow::Wrap;
+            let mut changed = false;
+            let mut ret = Vec::with_capacity(self.ops.len());
+            for op in self.ops {
+                match op {
+                    BitFieldOp::Overflow(o) => overflow = o,
+                    BitFieldOp::Get(ty, offset) => {
+                        ret.push(RespFrame::Integer(read_field(&bytes, ty, offset)));
+                    }
+                    BitFieldOp::Set(ty, offset, v) => {
+                        let old = read_field(&bytes, ty, offset);
+                        match apply_overflow(ty, v as i128, overflow) {
+                            Some(v) => {
+                                write_field(&mut bytes, ty, offset, v);
+                                changed = true;
+                                ret.push(RespFrame::Integer(old));
+                            }
+                            None => ret.push(RespFrame::Null(RespNull)),
+                        }
+                    }
+                    BitFieldOp::IncrBy(ty, offset, incr) => {
+                        let old = read_field(&bytes, ty, offset);
+                        match apply_overflow(ty, old as i128 + incr as i128, overflow) {
+                            Some(v) => {
+                                write_field(&mut bytes, ty, offset, v);
+                                changed = true;
+                                ret.push(RespFrame::Integer(v));
+                            }
+                            None => ret.push(RespFrame::Null(RespNull)),
+                        }
+                    }
+                }
+            }
+            if existed || changed {
+                *value = Some(BulkString::new(bytes).into());
+            }
+            RespArray::new(ret).into()
+        })
+    }
+}
+
+//===================  实现 TryFrom trait for Command
+impl TryFrom<RespArray> for SetBit {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["setbit"], 3)?;
+        let mut args = extract_args(value, 1)?.into_iter();
+        let key = parse_string(args.next(), "key")?;
+        let offset = parse_bit_offset(args.next())?;
+        let value = match parse_num::<u8>(args.next(), "bit")? {
+            0 => false,
+            1 => true,
+            _ => {
+                return Err(CommandError::InvalidCommandArguments(
+                    "bit is not an integer or out of range".to_string(),
+                ))
+            }
+        };
+        Ok(SetBit { key, offset, value })
+    }
+}
+impl TryFrom<RespArray> for GetBit {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["getbit"], 2)?;
+        let mut args = extract_args(value, 1)?.into_iter();
+        Ok(GetBit {
+            key: parse_string(args.next(), "key")?,
+            offset: parse_bit_offset(args.next())?,
+        })
+    }
+}
+impl TryFrom<RespArray> for BitCount {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command_min(&value, &["bitcount"], 1)?;
+        let n_args = value.len() - 1;
+        let mut args = extract_args(value, 1)?.into_iter();
+        let key = parse_string(args.next(), "key")?;
+        let range = match n_args {
+            1 => None,
+            3 | 4 => {
+                let start = parse_num(args.next(), "start")?;
+                let end = parse_num(args.next(), "end")?;
+                let unit = parse_range_unit(args.next())?;
+                Some((start, end, unit))
+            }
+            _ => return Err(syntax_error()),
+        };
+        Ok(BitCount { key, range })
+    }
+}
+impl TryFrom<RespArray> for BitPos {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command_min(&value, &["bitpos"], 2)?;
+        if value.len() > 6 {
+            return Err(syntax_error());
+        }
+        let mut args = extract_args(value, 1)?.into_iter();
+        let key = parse_string(args.next(), "key")?;
+        let bit = match parse_num::<u8>(args.next(), "bit")? {
+            0 => false,
+            1 => true,
+            _ => {
+                return Err(CommandError::InvalidCommandArguments(
+                    "The bit argument must be 1 or 0".to_string(),
+                ))
+            }
+        };
+        let start = args
+            .next()
+            .map(|v| parse_num(Some(v), "start"))
+            .transpose()?;
+        let end = args.next().map(|v| parse_num(Some(v), "end")).transpose()?;
+        let unit = parse_range_unit(args.next())?;
+        Ok(BitPos {
+            key,
+            bit,
+            start,
+            end,
+            unit,
+        })
+    }
+}
+impl TryFrom<RespArray> for BitOp {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command_min(&value, &["bitop"], 3)?;
+        let mut args = extract_args(value, 1)?.into_iter();
+        let op = match parse_string(args.next(), "operation")?
+            .to_ascii_uppercase()
+            .as_str()
+        {
+            "AND" => BitOpKind::And,
+            "OR" => BitOpKind::Or,
+            "XOR" => BitOpKind::Xor,
+            "NOT" => BitOpKind::Not,
+            _ => return Err(syntax_error()),
+        };
+        let dest = parse_string(args.next(), "destkey")?;
+        let keys = args
+            .map(|v| parse_string(Some(v), "key"))
+            .collect::<Result<Vec<_>, _>>()?;
+        if op == BitOpKind::Not && keys.len() != 1 {
+            return Err(CommandError::InvalidCommandArguments(
+                "BITOP NOT must be called with a single source key.".to_string(),
+            ));
+        }
+        Ok(BitOp { op, dest, keys })
+    }
+}
+impl TryFrom<RespArray> for BitField {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command_min(&value, &["bitfield"], 1)?;
+        let mut args = extract_args(value, 1)?.into_iter();
+        let key = parse_string(args.next(), "key")?;
+        let mut ops = Vec::new();
+        while let Some(sub) = args.next() {
+            let op = match parse_string(Some(sub), "subcommand")?
+                .to_ascii_uppercase()
+                .as_str()
+            {
+                "GET" => {
+                    let ty = parse_field_type(args.next())?;
+                    BitFieldOp::Get(ty, parse_field_offset(args.next(), ty)?)
+                }
+                "SET" => {
+                    let ty = parse_field_type(args.next())?;
+                    let offset = parse_field_offset(args.next(), ty)?;
+                    BitFieldOp::Set(ty, offset, parse_num(args.next(), "value")?)
+                }
+                "INCRBY" => {
+                    let ty = parse_field_type(args.next())?;
+                    let offset = parse_field_offset(args.next(), ty)?;
+                    BitFieldOp::IncrBy(ty, offset, parse_num(args.next(), "increment")?)
+                }
+                "OVERFLOW" => match parse_string(args.next(), "overflow type")?
+                    .to_ascii_uppercase()
+                    .as_str()
+                {
+                    "WRAP" => BitFieldOp::Overflow(BitFieldOverflow::Wrap),
+                    "SAT" => BitFieldOp::Overflow(BitFieldOverflow::Sat),
+                    "FAIL" => BitFieldOp::Overflow(BitFieldOverflow::Fail),
+                    _ => {
+                        return Err(CommandError::InvalidCommandArguments(
+                            "Invalid OVERFLOW type specified".to_string(),
+                        ))
+                    }
+                },
+                _ => return Err(syntax_error()),
+            };
+            ops.push(op);
+        }
+        Ok(BitField { key, ops })
+    }
+}
+
+fn syntax_error() -> CommandError {
+    CommandError::InvalidCommandArguments("syntax error".to_string())
+}
+
+fn parse_bit_offset(arg: Option<RespFrame>) -> Result<u64, CommandError> {
+    match parse_num::<u64>(arg, "bit offset") {
+        Ok(offset) if offset <= MAX_BIT_OFFSET => Ok(offset),
+        _ => Err(CommandError::InvalidCommandArguments(
+            "bit offset is not an integer or out of range".to_string(),
+        )),
+    }
+}
+
+fn parse_range_unit(arg: Option<RespFrame>) -> Result<BitRangeUnit, CommandError> {
+    match arg {
+        None => Ok(BitRangeUnit::Byte),
+        Some(v) => match parse_string(Some(v), "unit")?.to_ascii_uppercase().as_str() {
+            "BYTE" => Ok(BitRangeUnit::Byte),
+            "BIT" => Ok(BitRangeUnit::Bit),
+            _ => Err(syntax_error()),
+        },
+    }
+}
+
+fn parse_field_type(arg: Option<RespFrame>) -> Result<BitFieldType, CommandError> {
+    let s = parse_string(arg, "bitfield type")?;
+    let bits = s.get(1..).and_then(|b| b.parse::<u8>().ok());
+    match (s.as_bytes().first().map(u8::to_ascii_lowercase), bits) {
+        (Some(b'i'), Some(bits @ 1..=64)) => Ok(BitFieldType { signed: true, bits }),
+        (Some(b'u'), Some(bits @ 1..=63)) => Ok(BitFieldType {
+            signed: false,
+            bits,
+        }),
+        _ => Err(CommandError::InvalidCommandArguments(
+            "Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.".to_string(),
+        )),
+    }
+}
+
+// offset 可以写成 `#N`, 表示第 N 个该类型宽度的整数
+fn parse_field_offset(arg: Option<RespFrame>, ty: BitFieldType) -> Result<u64, CommandError> {
+    let s = parse_string(arg, "bit offset")?;
+    let offset = match s.strip_prefix('#') {
+        Some(n) => n
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(ty.bits as u64)),
+        None => s.parse::<u64>().ok(),
+    };
+    match offset {
+        Some(offset)
+            if offset
+                .checked_add(ty.bits as u64 - 1)
+                .is_some_and(|last| last <= MAX_BIT_OFFSET) =>
+        {
+            Ok(offset)
+        }
+        _ => Err(CommandError::InvalidCommandArguments(
+            "bit offset is not an integer or out of range".to_string(),
+        )),
+    }
+}
+
+// 负数下标从尾部计数, 返回闭区间 [start, end]; 区间为空时返回 None
+fn normalize_range(start: i64, end: i64, len: i64) -> Option<(u64, u64)> {
+    if len == 0 {
+        return None;
+    }
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let end = if end < 0 { len + end } else { end }.clamp(0, len - 1);
+    (start <= end).then_some((start as u64, end as u64))
+}
+
+// bit 按大端序编号: offset 0 是第一个字节的最高位
+fn get_bit(bytes: &[u8], offset: u64) -> bool {
+    let idx = (offset >> 3) as usize;
+    idx < bytes.len() && (bytes[idx] >> (7 - (offset & 7))) & 1 == 1
+}
+
+// 设置 bit 并返回旧值, 长度不够时补 0
+fn set_bit(bytes: &mut Vec<u8>, offset: u64, on: bool) -> bool {
+    let idx = (offset >> 3) as usize;
+    if idx >= bytes.len() {
+        bytes.resize(idx + 1, 0);
+    }
+    let mask = 1 << (7 - (offset & 7));
+    let old = bytes[idx] & mask != 0;
+    if on {
+        bytes[idx] |= mask;
+    } else {
+        bytes[idx] &= !mask;
+    }
+    old
+}
+
+fn count_bits(bytes: &[u8], start: u64, end: u64) -> u64 {
+    let (first, last) = ((start >> 3) as usize, (end >> 3) as usize);
+    if first == last {
+        return (start..=end).filter(|&i| get_bit(bytes, i)).count() as u64;
+    }
+    let head = (start..(first as u64 + 1) * 8)
+        .filter(|&i| get_bit(bytes, i))
+        .count() as u64;
+    let body: u64 = bytes[first + 1..last]
+        .iter()
+        .map(|b| b.count_ones() as u64)
+        .sum();
+    let tail = (last as u64 * 8..=end)
+        .filter(|&i| get_bit(bytes, i))
+        .count() as u64;
+    head + body + tail
+}
+
+fn find_bit(bytes: &[u8], bit: bool, start: u64, end: u64) -> Option<u64> {
+    let skip = if bit { 0x00 } else { 0xff };
+    let mut i = start;
+    while i <= end {
+        // 整字节都不匹配时直接跳过
+        if i & 7 == 0 && i + 7 <= end && bytes[(i >> 3) as usize] == skip {
+            i += 8;
+            continue;
+        }
+        if get_bit(bytes, i) == bit {
+            return Some(i);
+        }
+        i += 1;
+    }
+    None
+}
+
+fn read_field(bytes: &[u8], ty: BitFieldType, offset: u64) -> i64 {
+    let bits = ty.bits as u64;
+    let mut v = (0..bits).fold(0_u64, |acc, i| {
+        (acc << 1) | get_bit(bytes, offset + i) as u64
+    });
+    if ty.signed && bits < 64 && (v >> (bits - 1)) & 1 == 1 {
+        v |= u64::MAX << bits;
+    }
+    v as i64
+}
+
+fn write_field(bytes: &mut Vec<u8>, ty: BitFieldType, offset: u64, value: i64) {
+    let bits = ty.bits as u64;
+    let v = value as u64;
+    for i in 0..bits {
+        set_bit(bytes, offset + i, (v >> (bits - 1 - i)) & 1 == 1);
+    }
+}
+
+// 按溢出策略处理结果, FAIL 溢出时返回 None
+fn apply_overflow(ty: BitFieldType, v: i128, policy: BitFieldOverflow) -> Option<i64> {
+    let bits = ty.bits as u32;
+    let (min, max) = if ty.signed {
+        (-(1_i128 << (bits - 1)), (1_i128 << (bits - 1)) - 1)
+    } else {
+        (0, (1_i128 << bits) - 1)
+    };
+    if (min..=max).contains(&v) {
+        return Some(v as i64);
+    }
+    match policy {
+        BitFieldOverflow::Fail => None,
+        BitFieldOverflow::Sat => Some(v.clamp(min, max) as i64),
+        BitFieldOverflow::Wrap => {
+            let modulo = 1_i128 << bits;
+            let mut wrapped = v.rem_euclid(modulo);
+            if wrapped > max {
+                wrapped -= modulo;
+            }
+            Some(wrapped as i64)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use bytes::BytesMut;
+
+    use crate::RespDecode;
+
+    use super::*;
+
+    fn bulk(s: &[u8]) -> RespFrame {
+        BulkString::new(s.to_vec()).into()
+    }
+
+    #[test]
+    fn test_setbit_from_resp_array() -> Result<()> {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"*4\r\n$6\r\nSETBIT\r\n$5\r\nmykey\r\n$2\r\n10\r\n$1\r\n1\r\n");
+
+        let frame = RespArray::decode(&mut buf)?;
+        let result: SetBit = frame.try_into()?;
+        assert_eq!(result.key, "mykey");
+        assert_eq!(result.offset, 10);
+        assert!(result.value);
+
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"*4\r\n$6\r\nSETBIT\r\n$5\r\nmykey\r\n$2\r\n10\r\n$1\r\n2\r\n");
+        let frame = RespArray::decode(&mut buf)?;
+        assert!(SetBit::try_from(frame).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bitfield_from_resp_array() -> Result<()> {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(
+            b"*9\r\n$8\r\nBITFIELD\r\n$5\r\nmykey\r\n$8\r\noverflow\r\n$3\r\nSAT\r\n$6\r\nINCRBY\r\n$2\r\nu8\r\n$2\r\n#1\r\n$3\r\n100\r\n$3\r\nGET\r\n",
+        );
+        let frame = RespArray::decode(&mut buf)?;
+        // GET 缺少参数
+        assert!(BitField::try_from(frame).is_err());
+
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(
+            b"*10\r\n$8\r\nBITFIELD\r\n$5\r\nmykey\r\n$8\r\noverflow\r\n$3\r\nSAT\r\n$6\r\nINCRBY\r\n$2\r\nu8\r\n$2\r\n#1\r\n$3\r\n100\r\n$3\r\nGET\r\n$3\r\ni64\r\n",
+        );
+        let frame = RespArray::decode(&mut buf)?;
+        assert!(BitField::try_from(frame).is_err());
+
+        // offset 加上类型宽度后溢出
+        let frame = RespArray::new(vec![
+            bulk(b"BITFIELD"),
+            bulk(b"mykey"),
+            bulk(b"GET"),
+            bulk(b"u8"),
+            bulk(b"18446744073709551615"),
+        ]);
+        assert!(BitField::try_from(frame).is_err());
+
+        let unsigned = BitFieldType {
+            signed: false,
+            bits: 8,
+        };
+        let frame = RespArray::new(vec![
+            bulk(b"BITFIELD"),
+            bulk(b"mykey"),
+            bulk(b"OVERFLOW"),
+            bulk(b"sat"),
+            bulk(b"incrby"),
+            bulk(b"u8"),
+            bulk(b"#1"),
+            bulk(b"100"),
+        ]);
+        let result: BitField = frame.try_into()?;
+        assert_eq!(
+            result.ops,
+            vec![
+                BitFieldOp::Overflow(BitFieldOverflow::Sat),
+                BitFieldOp::IncrBy(unsigned, 8, 100)
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_setbit_getbit_bitcount_execute() {
+        let backend = Backend::new();
+        for offset in [1, 2, 4, 9, 10, 11, 23] {
+            let cmd = SetBit {
+                key: "bits".to_string(),
+                offset,
+                value: true,
+            };
+            assert_eq!(cmd.execute(&backend), RespFrame::Integer(0));
+        }
+        // 0b0110_1000, 0b0111_0000, 0b0000_0001
+        assert_eq!(backend.get("bits"), Some(bulk(&[0x68, 0x70, 0x01])));
+
+        let cmd = SetBit {
+            key: "bits".to_string(),
+            offset: 2,
+            value: false,
+        };
+        assert_eq!(cmd.execute(&backend), RespFrame::Integer(1));
+
+        let cmd = GetBit {
+            key: "bits".to_string(),
+            offset: 9,
+        };
+        assert_eq!(cmd.execute(&backend), RespFrame::Integer(1));
+        let cmd = GetBit {
+            key: "bits".to_string(),
+            offset: 1000,
+        };
+        assert_eq!(cmd.execute(&backend), RespFrame::Integer(0));
+
+        let count = |range| {
+            BitCount {
+                key: "bits".to_string(),
+                range,
+            }
+            .execute(&backend)
+        };
+        assert_eq!(count(None), RespFrame::Integer(6));
+        assert_eq!(
+            count(Some((1, -1, BitRangeUnit::Byte))),
+            RespFrame::Integer(4)
+        );
+        assert_eq!(
+            count(Some((5, 10, BitRangeUnit::Bit))),
+            RespFrame::Integer(2)
+        );
+        assert_eq!(
+            count(Some((2, 1, BitRangeUnit::Byte))),
+            RespFrame::Integer(0)
+        );
+
+        backend.set("str".to_string(), RespFrame::Integer(1));
+        let cmd = GetBit {
+            key: "str".to_string(),
+            offset: 0,
+        };
+        assert_eq!(cmd.execute(&backend), RESP_WRONGTYPE.clone());
+    }
+
+    #[test]
+    fn test_bitpos_execute() {
+        let backend = Backend::new();
+        backend.set("k".to_string(), bulk(&[0xff, 0xf0, 0x00]));
+        let pos = |bit, start, end, unit| {
+            BitPos {
+                key: "k".to_string(),
+                bit,
+                start,
+                end,
+                unit,
+            }
+            .execute(&backend)
+        };
+        assert_eq!(pos(false, None, None, BitRangeUnit::Byte), 12.into());
+        assert_eq!(pos(true, Some(1), None, BitRangeUnit::Byte), 8.into());
+        assert_eq!(pos(true, Some(2), None, BitRangeUnit::Byte), (-1).into());
+        assert_eq!(pos(true, Some(5), Some(20), BitRangeUnit::Bit), 5.into());
+
+        backend.set("ones".to_string(), bulk(&[0xff, 0xff]));
+        let pos = |start, end| {
+            BitPos {
+                key: "ones".to_string(),
+                bit: false,
+                start,
+                end,
+                unit: BitRangeUnit::Byte,
+            }
+            .execute(&backend)
+        };
+        // 未指定 end 时右侧视为补 0
+        assert_eq!(pos(None, None), 16.into());
+        assert_eq!(pos(Some(0), Some(-1)), (-1).into());
+
+        let cmd = BitPos {
+            key: "missing".to_string(),
+            bit: false,
+            start: None,
+            end: None,
+            unit: BitRangeUnit::Byte,
+        };
+        assert_eq!(cmd.execute(&backend), 0.into());
+    }
+
+    #[test]
+    fn test_bitop_execute() {
+        let backend = Backend::new();
+        backend.set("a".to_string(), bulk(b"foobar"));
+        backend.set("b".to_string(), bulk(b"abcdef"));
+
+        let op = |op, keys: &[&str]| {
+            BitOp {
+                op,
+                dest: "dest".to_string(),
+                keys: keys.iter().map(|s| s.to_string()).collect(),
+            }
+            .execute(&backend)
+        };
+        assert_eq!(op(BitOpKind::And, &["a", "b"]), 6.into());
+        assert_eq!(backend.get("dest"), Some(bulk(b"`bc`ab")));
+        assert_eq!(op(BitOpKind::Or, &["a", "b"]), 6.into());
+        assert_eq!(backend.get("dest"), Some(bulk(b"goofev")));
+        assert_eq!(op(BitOpKind::Xor, &["a", "missing"]), 6.into());
+        assert_eq!(backend.get("dest"), Some(bulk(b"foobar")));
+        assert_eq!(op(BitOpKind::And, &["a", "missing"]), 6.into());
+        assert_eq!(backend.get("dest"), Some(bulk(&[0; 6])));
+        assert_eq!(op(BitOpKind::Not, &["a"]), 6.into());
+        assert_eq!(backend.get("dest"), Some(bulk(&b"foobar".map(|b| !b))));
+        assert_eq!(op(BitOpKind::Not, &["missing"]), 0.into());
+        assert_eq!(backend.get("dest"), None);
+    }
+
+    #[test]
+    fn test_bitfield_execute() {
+        let backend = Backend::new();
+        let i8 = BitFieldType {
+            signed: true,
+            bits: 8,
+        };
+        let u2 = BitFieldType {
+            signed: false,
+            bits: 2,
+        };
+        let field = |ops| {
+            BitField {
+                key: "bf".to_string(),
+                ops,
+            }
+            .execute(&backend)
+        };
+
+        let ret = field(vec![BitFieldOp::Get(i8, 0)]);
+        assert_eq!(ret, RespArray::new(vec![0.into()]).into());
+        assert_eq!(backend.get("bf"), None);
+
+        let ret = field(vec![
+            BitFieldOp::Set(i8, 0, -100),
+            BitFieldOp::IncrBy(i8, 0, -100),
+            BitFieldOp::Get(i8, 0),
+        ]);
+        assert_eq!(
+            ret,
+            RespArray::new(vec![0.into(), 56.into(), 56.into()]).into()
+        );
+
+        let ret = field(vec![
+            BitFieldOp::Overflow(BitFieldOverflow::Sat),
+            BitFieldOp::IncrBy(i8, 0, 100),
+            BitFieldOp::IncrBy(u2, 100, -5),
+            BitFieldOp::Overflow(BitFieldOverflow::Fail),
+            BitFieldOp::IncrBy(u2, 100, 4),
+            BitFieldOp::Overflow(BitFieldOverflow::Wrap),
+            BitFieldOp::IncrBy(u2, 100, 5),
+        ]);
+        assert_eq!(
+            ret,
+            RespArray::new(vec![
+                127.into(),
+                0.into(),
+                RespFrame::Null(RespNull),
+                1.into()
+            ])
+            .into()
+        );
+    }
+}
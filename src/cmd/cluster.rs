@@ -0,0 +1,297 @@
+use crate::cluster::{key_hash_slot, FailoverMode, SlotState, CLUSTER_SLOTS};
+use crate::cmd::{
+    extract_args, parse_num, parse_string, validate_command, validate_command_min, Asking, Cluster,
+    CommandError, CommandExecutor, RESP_OK,
+};
+use crate::{Backend, BulkString, RespArray, RespFrame, SimpleError};
+
+/// CLUSTER 的子命令
+#[derive(Debug, PartialEq)]
+pub enum ClusterOp {
+    MyId,
+    Info,
+    Nodes,
+    Slots,
+    Shards,
+    KeySlot(String),
+    CountKeysInSlot(u16),
+    AddSlots(Vec<u16>),
+    DelSlots(Vec<u16>),
+    SetSlot(u16, SlotState),
+    GetKeysInSlot(u16, usize),
+    Meet(String, u16, Option<u16>),
+    Replicate(String),
+    Failover(FailoverMode),
+    CountFailureReports(String),
+}
+
+//===================  实现 CommandExecutor trait for Command
+impl CommandExecutor for Cluster {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if !backend.cluster_enabled() {
+            return SimpleError::new("ERR This instance has cluster support disabled").into();
+        }
+        let ret = match self.op {
+            ClusterOp::MyId => Ok(BulkString::new(backend.cluster_myid()).into()),
+            ClusterOp::Info => Ok(BulkString::new(backend.cluster_info()).into()),
+            ClusterOp::Nodes => Ok(BulkString::new(backend.cluster_nodes()).into()),
+            ClusterOp::Slots => Ok(backend.cluster_slots()),
+            ClusterOp::Shards => Ok(backend.cluster_shards()),
+            ClusterOp::KeySlot(key) => Ok(RespFrame::Integer(key_hash_slot(key.as_bytes()) as i64)),
+            ClusterOp::CountKeysInSlot(slot) => {
+                Ok(RespFrame::Integer(backend.count_keys_in_slot(slot) as i64))
+            }
+            ClusterOp::AddSlots(slots) => {
+                backend.cluster_add_slots(&slots).map(|_| RESP_OK.clone())
+            }
+            ClusterOp::DelSlots(slots) => {
+                backend.cluster_del_slots(&slots).map(|_| RESP_OK.clone())
+            }
+            ClusterOp::SetSlot(slot, state) => backend
+                .cluster_set_slot(slot, state)
+                .map(|_| RESP_OK.clone()),
+            ClusterOp::GetKeysInSlot(slot, count) => Ok(RespArray::new(
+                backend
+                    .keys_in_slot(slot, count)
+                    .into_iter()
+                    .map(|k| BulkString::new(k).into())
+                    .collect::<Vec<_>>(),
+            )
+            .into()),
+            ClusterOp::Meet(ip, port, cport) => backend
+                .cluster_meet(&ip, port, cport)
+                .map(|_| RESP_OK.clone()),
+            ClusterOp::Replicate(id) => backend.cluster_replicate(&id).map(|_| RESP_OK.clone()),
+            ClusterOp::Failover(mode) => backend.cluster_failover(mode).map(|_| RESP_OK.clone()),
+            ClusterOp::CountFailureReports(id) => backend
+                .cluster_count_failure_reports(&id)
+                .map(|n| RespFrame::Integer(n as i64)),
+        };
+        ret.unwrap_or_else(|e| SimpleError::new(format!("ERR {}", e)).into())
+    }
+}
+
+// 连接状态由 network 维护, 这里只检查是否开启了集群模式
+impl CommandExecutor for Asking {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if !backend.cluster_enabled() {
+            return SimpleError::new("ERR This instance has cluster support disabled").into();
+        }
+        RESP_OK.clone()
+    }
+}
+
+//===================  实现 TryFrom trait for Command
+impl TryFrom<RespArray> for Cluster {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command_min(&value, &["cluster"], 1)?;
+        let sub = match &value[1] {
+            RespFrame::BulkString(s) => String::from_utf8_lossy(s).to_ascii_lowercase(),
+            _ => return Err(invalid("subcommand")),
+        };
+        let n_args = value.len() - 2;
+        let mut args = extract_args(value, 2)?.into_iter();
+        let op = match (sub.as_str(), n_args) {
+            ("myid", 0) => ClusterOp::MyId,
+            ("info", 0) => ClusterOp::Info,
+            ("nodes", 0) => ClusterOp::Nodes,
+            ("slots", 0) => ClusterOp::Slots,
+            ("shards", 0) => ClusterOp::Shards,
+            ("keyslot", 1) => ClusterOp::KeySlot(parse_string(args.next(), "key")?),
+            ("countkeysinslot", 1) => ClusterOp::CountKeysInSlot(parse_slot(args.next())?),
+            ("getkeysinslot", 2) => {
+                let slot = parse_slot(args.next())?;
+                let count: i64 = parse_num(args.next(), "count")?;
+                if count < 0 {
+                    return Err(CommandError::InvalidCommandArguments(
+                        "Invalid number of keys".to_string(),
+                    ));
+                }
+                ClusterOp::GetKeysInSlot(slot, count as usize)
+            }
+            // MEET <ip> <port> [<cluster-bus-port>]
+            ("meet", 2 | 3) => {
+                let ip = parse_string(args.next(), "ip")?;
+                let port = parse_num(args.next(), "port")?;
+                let cport = args
+                    .next()
+                    .map(|p| parse_num(Some(p), "port"))
+                    .transpose()?;
+                ClusterOp::Meet(ip, port, cport)
+            }
+            ("replicate", 1) => ClusterOp::Replicate(parse_string(args.next(), "node id")?),
+            ("failover", 0) => ClusterOp::Failover(FailoverMode::Default),
+            ("failover", 1) => {
+                let mode = match parse_string(args.next(), "option")?
+                    .to_ascii_lowercase()
+                    .as_str()
+                {
+                    "force" => FailoverMode::Force,
+                    "takeover" => FailoverMode::Takeover,
+                    _ => return Err(syntax_error()),
+                };
+                ClusterOp::Failover(mode)
+            }
+            ("count-failure-reports", 1) => {
+                ClusterOp::CountFailureReports(parse_string(args.next(), "node id")?)
+            }
+            // SETSLOT <slot> IMPORTING <node-id> | MIGRATING <node-id> | NODE <node-id> | STABLE
+            ("setslot", 2 | 3) => {
+                let slot = parse_slot(args.next())?;
+                let action = parse_string(args.next(), "action")?.to_ascii_lowercase();
+                let id = args.next().map(|id| parse_string(Some(id), "node id"));
+                let state = match (action.as_str(), id) {
+                    ("importing", Some(id)) => SlotState::Importing(id?),
+                    ("migrating", Some(id)) => SlotState::Migrating(id?),
+                    ("node", Some(id)) => SlotState::Node(id?),
+                    ("stable", None) => SlotState::Stable,
+                    _ => {
+                        return Err(CommandError::InvalidCommandArguments(
+                            "Invalid CLUSTER SETSLOT action or number of arguments".to_string(),
+                        ))
+                    }
+                };
+                ClusterOp::SetSlot(slot, state)
+            }
+            ("addslots", 1..) => ClusterOp::AddSlots(
+                args.map(|s| parse_slot(Some(s)))
+                    .collect::<Result<_, _>>()?,
+            ),
+            ("delslots", 1..) => ClusterOp::DelSlots(
+                args.map(|s| parse_slot(Some(s)))
+                    .collect::<Result<_, _>>()?,
+            ),
+            ("addslotsrange", n) if n > 0 && n.is_multiple_of(2) => {
+                ClusterOp::AddSlots(parse_slot_ranges(args)?)
+            }
+            ("delslotsrange", n) if n > 0 && n.is_multiple_of(2) => {
+                ClusterOp::DelSlots(parse_slot_ranges(args)?)
+            }
+            _ => {
+                return Err(CommandError::InvalidCommandArguments(format!(
+                    "unknown subcommand or wrong number of arguments for '{}'",
+                    sub
+                )))
+            }
+        };
+        Ok(Cluster { op })
+    }
+}
+
+impl TryFrom<RespArray> for Asking {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["asking"], 0)?;
+        Ok(Asking)
+    }
+}
+
+fn parse_slot(arg: Option<RespFrame>) -> Result<u16, CommandError> {
+    let slot: i64 = parse_num(arg, "slot")?;
+    u16::try_from(slot)
+        .ok()
+        .filter(|s| (*s as usize) < CLUSTER_SLOTS)
+        .ok_or_else(|| {
+            CommandError::InvalidCommandArguments("Invalid or out of range slot".to_string())
+        })
+}
+
+// <start> <end> [<start> <end> ...]
+fn parse_slot_ranges(args: impl Iterator<Item = RespFrame>) -> Result<Vec<u16>, CommandError> {
+    let args = args.collect::<Vec<_>>();
+    let mut slots = Vec::new();
+    for pair in args.chunks(2) {
+        let start = parse_slot(Some(pair[0].clone()))?;
+        let end = parse_slot(Some(pair[1].clone()))?;
+        if start > end {
+            return Err(CommandError::InvalidCommandArguments(format!(
+                "start slot number {} is greater than end slot number {}",
+                start, end
+            )));
+        }
+        slots.extend(start..=end);
+    }
+    Ok(slots)
+}
+
+fn syntax_error() -> CommandError {
+    CommandError::InvalidCommandArguments("syntax error".to_string())
+}
+
+fn invalid(what: &str) -> CommandError {
+    CommandError::InvalidCommandArguments(format!("Invalid {}", what))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::network::testing::array;
+
+    fn cluster(args: &[&str]) -> Result<Cluster, CommandError> {
+        array(&[&["CLUSTER"], args].concat()).try_into()
+    }
+
+    #[test]
+    fn test_cluster_from_resp_array() -> Result<()> {
+        assert_eq!(cluster(&["SLOTS"])?.op, ClusterOp::Slots);
+        assert_eq!(
+            cluster(&["keyslot", "{user}.name"])?.op,
+            ClusterOp::KeySlot("{user}.name".to_string())
+        );
+        assert_eq!(
+            cluster(&["ADDSLOTSRANGE", "1", "3", "10", "10"])?.op,
+            ClusterOp::AddSlots(vec![1, 2, 3, 10])
+        );
+        assert_eq!(
+            cluster(&["DELSLOTS", "5", "7"])?.op,
+            ClusterOp::DelSlots(vec![5, 7])
+        );
+        assert_eq!(
+            cluster(&["SETSLOT", "7", "migrating", "abc"])?.op,
+            ClusterOp::SetSlot(7, SlotState::Migrating("abc".to_string()))
+        );
+        assert_eq!(
+            cluster(&["SETSLOT", "7", "STABLE"])?.op,
+            ClusterOp::SetSlot(7, SlotState::Stable)
+        );
+        assert_eq!(
+            cluster(&["GETKEYSINSLOT", "7", "10"])?.op,
+            ClusterOp::GetKeysInSlot(7, 10)
+        );
+        assert_eq!(
+            cluster(&["MEET", "127.0.0.1", "7001"])?.op,
+            ClusterOp::Meet("127.0.0.1".to_string(), 7001, None)
+        );
+        assert_eq!(
+            cluster(&["failover", "TAKEOVER"])?.op,
+            ClusterOp::Failover(FailoverMode::Takeover)
+        );
+        assert_eq!(
+            cluster(&["FAILOVER"])?.op,
+            ClusterOp::Failover(FailoverMode::Default)
+        );
+        assert!(cluster(&["FAILOVER", "NOW"]).is_err());
+        assert!(cluster(&["MEET", "127.0.0.1", "70000"]).is_err());
+        assert!(cluster(&["SETSLOT", "7", "NODE"]).is_err());
+        assert!(cluster(&["SETSLOT", "7", "STABLE", "abc"]).is_err());
+        assert!(cluster(&["COUNTKEYSINSLOT", "16384"]).is_err());
+        assert!(cluster(&["ADDSLOTSRANGE", "3", "1"]).is_err());
+        assert!(cluster(&["ADDSLOTSRANGE", "1"]).is_err());
+        assert!(cluster(&["SLOTS", "extra"]).is_err());
+        assert!(cluster(&["FOO"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cluster_execute() -> Result<()> {
+        let backend = Backend::new();
+        let ret = cluster(&["KEYSLOT", "foo"])?.execute(&backend);
+        assert!(matches!(ret, RespFrame::Error(e) if e.contains("cluster support disabled")));
+        Ok(())
+    }
+}
@@ -0,0 +1,432 @@
+use anyhow::anyhow;
+
+use crate::acl::{in_category, is_category, CATEGORIES};
+use crate::cmd::{
+    extract_args, parse_string, validate_command, validate_command_min, Acl, Auth, CommandError,
+    CommandExecutor, Hello, Quit, RESP_OK,
+};
+use crate::{Backend, BulkString, RespArray, RespFrame, RespMap, RespNull, SimpleError};
+
+/// ACL 的子命令
+#[derive(Debug, PartialEq)]
+pub enum AclOp {
+    SetUser(String, Vec<String>),
+    GetUser(String),
+    DelUser(Vec<String>),
+    Users,
+    List,
+    WhoAmI,
+    Log(usize),
+    LogReset,
+    Cat(Option<String>),
+    Load,
+    Save,
+}
+
+impl Auth {
+    /// 认证当前连接, 成功时返回用户名
+    pub fn authenticate(self, backend: &Backend, client_info: &str) -> Result<String, RespFrame> {
+        // AUTH <password> 用于 default 用户, 它不需要密码时说明配置有误
+        if self.username.is_none() && backend.acl_default_nopass() {
+            return Err(SimpleError::new(
+                "ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?",
+            )
+            .into());
+        }
+        let username = self.username.unwrap_or_else(|| "default".to_string());
+        if backend.acl_authenticate(&username, &self.password, client_info) {
+            Ok(username)
+        } else {
+            Err(
+                SimpleError::new("WRONGPASS invalid username-password pair or user is disabled.")
+                    .into(),
+            )
+        }
+    }
+}
+
+/// HELLO 成功后连接需要更新的状态
+#[derive(Debug, PartialEq)]
+pub struct Handshake {
+    pub protover: Option<u8>,
+    // AUTH 成功时认证的用户
+    pub user: Option<String>,
+    pub name: Option<String>,
+}
+
+impl Hello {
+    /// 检查协议版本并认证, 未认证的连接必须同时使用 AUTH
+    pub fn handshake(
+        self,
+        backend: &Backend,
+        client_info: &str,
+        authenticated: bool,
+    ) -> Result<Handshake, RespFrame> {
+        let protover = match self.protover {
+            None => None,
+            Some(v @ (2 | 3)) => Some(v as u8),
+            Some(_) => return Err(SimpleError::new("NOPROTO unsupported protocol version").into()),
+        };
+        let user = match self.auth {
+            Some((username, password)) => Some(
+                Auth {
+                    username: Some(username),
+                    password,
+                }
+                .authenticate(backend, client_info)?,
+            ),
+            None if !authenticated => {
+                return Err(SimpleError::new("NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time").into())
+            }
+            None => None,
+        };
+        Ok(Handshake {
+            protover,
+            user,
+            name: self.setname,
+        })
+    }
+}
+
+/// HELLO 回复的服务器信息
+pub fn server_info(backend: &Backend, id: u64, protover: u8) -> RespFrame {
+    let config = backend.config();
+    let mode = if config.sentinel {
+        "sentinel"
+    } else if config.cluster_enabled {
+        "cluster"
+    } else {
+        "standalone"
+    };
+    let role = if backend.is_replica() {
+        "replica"
+    } else {
+        "master"
+    };
+    let mut map = RespMap::new();
+    map.insert(BulkString::new("server"), BulkString::new("redis").into());
+    map.insert(
+        BulkString::new("version"),
+        BulkString::new(env!("CARGO_PKG_VERSION")).into(),
+    );
+    map.insert(
+        BulkString::new("proto"),
+        RespFrame::Integer(protover as i64),
+    );
+    map.insert(BulkString::new("id"), RespFrame::Integer(id as i64));
+    map.insert(BulkString::new("mode"), BulkString::new(mode).into());
+    map.insert(BulkString::new("role"), BulkString::new(role).into());
+    map.insert(BulkString::new("modules"), RespArray::new(vec![]).into());
+    map.into()
+}
+
+impl Acl {
+    /// ACL WHOAMI 需要连接的用户, 由连接处理
+    pub fn is_whoami(&self) -> bool {
+        self.op == AclOp::WhoAmI
+    }
+}
+
+//===================  实现 CommandExecutor trait for Command
+impl CommandExecutor for Auth {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        match self.authenticate(backend, "") {
+            Ok(_) => RESP_OK.clone(),
+            Err(e) => e,
+        }
+    }
+}
+impl CommandExecutor for Quit {
+    fn execute(self, _: &Backend) -> RespFrame {
+        RESP_OK.clone()
+    }
+}
+impl CommandExecutor for Hello {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        // 没有连接时只回复服务器信息
+        match self.handshake(backend, "", true) {
+            Ok(handshake) => server_info(backend, 0, handshake.protover.unwrap_or(2)),
+            Err(e) => e,
+        }
+    }
+}
+impl CommandExecutor for Acl {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let strings = |items: Vec<String>| -> RespFrame {
+            RespArray::new(
+                items
+                    .into_iter()
+                    .map(|s| BulkString::new(s).into())
+                    .collect::<Vec<_>>(),
+            )
+            .into()
+        };
+        let ret = match self.op {
+            AclOp::SetUser(name, rules) => {
+                backend.acl_setuser(&name, &rules).map(|_| RESP_OK.clone())
+            }
+            AclOp::GetUser(name) => Ok(backend.acl_getuser(&name).unwrap_or(RespNull.into())),
+            AclOp::DelUser(names) => backend
+                .acl_deluser(&names)
+                .map(|n| RespFrame::Integer(n as i64)),
+            AclOp::Users => Ok(strings(backend.acl_users())),
+            AclOp::List => Ok(strings(backend.acl_list())),
+            // 连接的用户由连接处理, 这里没有连接信息
+            AclOp::WhoAmI => Err(anyhow!("ACL WHOAMI requires a client connection")),
+            AclOp::Log(count) => Ok(backend.acl_log(count)),
+            AclOp::LogReset => {
+                backend.acl_log_reset();
+                Ok(RESP_OK.clone())
+            }
+            AclOp::Cat(None) => Ok(strings(CATEGORIES.iter().map(|c| c.to_string()).collect())),
+            AclOp::Cat(Some(category)) if is_category(&category) => {
+                Ok(strings(in_category(&category).map(String::from).collect()))
+            }
+            AclOp::Cat(Some(category)) => Err(anyhow!("Unknown category '{}'", category)),
+            AclOp::Load => backend.load_acl().map(|_| RESP_OK.clone()),
+            AclOp::Save => backend.save_acl().map(|_| RESP_OK.clone()),
+        };
+        match ret {
+            Ok(frame) => frame,
+            Err(e) => SimpleError::new(format!("ERR {}", e)).into(),
+        }
+    }
+}
+
+//===================  实现 TryFrom trait for Command
+impl TryFrom<RespArray> for Auth {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        // AUTH [username] password
+        validate_command_min(&value, &["auth"], 1)?;
+        let mut args = extract_args(value, 1)?;
+        if args.len() > 2 {
+            return Err(CommandError::InvalidCommandArguments(
+                "syntax error".to_string(),
+            ));
+        }
+        let password = parse_string(args.pop(), "password")?;
+        let username = args
+            .pop()
+            .map(|u| parse_string(Some(u), "username"))
+            .transpose()?;
+        Ok(Auth { username, password })
+    }
+}
+impl TryFrom<RespArray> for Acl {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command_min(&value, &["acl"], 1)?;
+        let sub = parse_string(value.get(1).cloned(), "subcommand")?.to_ascii_lowercase();
+        let n_args = value.len() - 2;
+        let mut args = extract_args(value, 2)?.into_iter();
+        let op = match (sub.as_str(), n_args) {
+            ("setuser", 1..) => {
+                let name = parse_string(args.next(), "username")?;
+                let rules = args
+                    .map(|r| parse_string(Some(r), "rule"))
+                    .collect::<Result<_, _>>()?;
+                AclOp::SetUser(name, rules)
+            }
+            ("getuser", 1) => AclOp::GetUser(parse_string(args.next(), "username")?),
+            ("deluser", 1..) => AclOp::DelUser(
+                args.map(|u| parse_string(Some(u), "username"))
+                    .collect::<Result<_, _>>()?,
+            ),
+            ("users", 0) => AclOp::Users,
+            ("list", 0) => AclOp::List,
+            ("whoami", 0) => AclOp::WhoAmI,
+            // ACL LOG [count | RESET]
+            ("log", 0) => AclOp::Log(10),
+            ("log", 1) => {
+                let arg = parse_string(args.next(), "count")?;
+                if arg.eq_ignore_ascii_case("reset") {
+                    AclOp::LogReset
+                } else {
+                    AclOp::Log(arg.parse().map_err(|_| {
+                        CommandError::InvalidCommandArguments(
+                            "value is out of range, must be positive".to_string(),
+                        )
+                    })?)
+                }
+            }
+            ("cat", 0) => AclOp::Cat(None),
+            ("cat", 1) => AclOp::Cat(Some(
+                parse_string(args.next(), "category")?.to_ascii_lowercase(),
+            )),
+            ("load", 0) => AclOp::Load,
+            ("save", 0) => AclOp::Save,
+            _ => {
+                return Err(CommandError::InvalidCommandArguments(format!(
+                    "Unknown ACL subcommand or wrong number of arguments for '{}'",
+                    sub
+                )))
+            }
+        };
+        Ok(Acl { op })
+    }
+}
+impl TryFrom<RespArray> for Hello {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        // HELLO [protover [AUTH username password] [SETNAME clientname]]
+        validate_command_min(&value, &["hello"], 0)?;
+        let mut args = extract_args(value, 1)?.into_iter();
+        let mut hello = Hello {
+            protover: None,
+            auth: None,
+            setname: None,
+        };
+        let Some(protover) = args.next() else {
+            return Ok(hello);
+        };
+        let protover = parse_string(Some(protover), "protover")?;
+        hello.protover = Some(protover.parse().map_err(|_| {
+            CommandError::InvalidCommandArguments(
+                "Protocol version is not an integer or out of range".to_string(),
+            )
+        })?);
+        while let Some(option) = args.next() {
+            let option = parse_string(Some(option), "option")?.to_ascii_lowercase();
+            match (option.as_str(), args.len()) {
+                ("auth", 2..) => {
+                    let username = parse_string(args.next(), "username")?;
+                    let password = parse_string(args.next(), "password")?;
+                    hello.auth = Some((username, password));
+                }
+                ("setname", 1..) => {
+                    let name = parse_string(args.next(), "clientname")?;
+                    // 与 CLIENT SETNAME 相同, 名字中不能有空格和特殊字符
+                    if name.bytes().any(|b| !(b'!'..=b'~').contains(&b)) {
+                        return Err(CommandError::InvalidCommandArguments(
+                            "Client names cannot contain spaces, newlines or special characters."
+                                .to_string(),
+                        ));
+                    }
+                    hello.setname = Some(name);
+                }
+                _ => {
+                    return Err(CommandError::InvalidCommandArguments(format!(
+                        "Syntax error in HELLO option '{}'",
+                        option
+                    )))
+                }
+            }
+        }
+        Ok(hello)
+    }
+}
+impl TryFrom<RespArray> for Quit {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["quit"], 0)?;
+        Ok(Quit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use bytes::BytesMut;
+
+    use super::*;
+    use crate::{RespDecode, ServerConfig};
+
+    #[test]
+    fn test_auth_from_resp_array() -> Result<()> {
+        let mut buf = BytesMut::from(&b"*2\r\n$4\r\nAUTH\r\n$6\r\nsecret\r\n"[..]);
+        let auth: Auth = RespArray::decode(&mut buf)?.try_into()?;
+        assert_eq!(auth.username, None);
+        assert_eq!(auth.password, "secret");
+
+        buf.extend_from_slice(b"*3\r\n$4\r\nauth\r\n$7\r\ndefault\r\n$6\r\nsecret\r\n");
+        let auth: Auth = RespArray::decode(&mut buf)?.try_into()?;
+        assert_eq!(auth.username.as_deref(), Some("default"));
+
+        buf.extend_from_slice(b"*1\r\n$4\r\nauth\r\n");
+        assert!(Auth::try_from(RespArray::decode(&mut buf)?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_auth_execute() {
+        let auth = |username: Option<&str>, password: &str| Auth {
+            username: username.map(String::from),
+            password: password.to_string(),
+        };
+        let backend = Backend::new();
+        assert!(matches!(
+            auth(None, "secret").execute(&backend),
+            RespFrame::Error(e) if e.starts_with("ERR AUTH")
+        ));
+        assert_eq!(
+            auth(Some("default"), "any").execute(&backend),
+            RESP_OK.clone()
+        );
+
+        let backend = Backend::with_config(ServerConfig {
+            requirepass: Some("secret".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(auth(None, "secret").execute(&backend), RESP_OK.clone());
+        assert_eq!(
+            auth(Some("default"), "secret").execute(&backend),
+            RESP_OK.clone()
+        );
+        for reply in [
+            auth(None, "secret2").execute(&backend),
+            auth(None, "").execute(&backend),
+            auth(Some("alice"), "secret").execute(&backend),
+        ] {
+            assert!(matches!(reply, RespFrame::Error(e) if e.starts_with("WRONGPASS")));
+        }
+    }
+
+    #[test]
+    fn test_hello() -> Result<()> {
+        let mut buf = BytesMut::from(
+            &b"*7\r\n$5\r\nHELLO\r\n$1\r\n3\r\n$4\r\nAUTH\r\n$7\r\ndefault\r\n$6\r\nsecret\r\n$7\r\nSETNAME\r\n$3\r\ncli\r\n"[..],
+        );
+        let hello: Hello = RespArray::decode(&mut buf)?.try_into()?;
+        assert_eq!(hello.protover, Some(3));
+        assert_eq!(hello.setname.as_deref(), Some("cli"));
+
+        let backend = Backend::with_config(ServerConfig {
+            requirepass: Some("secret".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(
+            hello.handshake(&backend, "", false),
+            Ok(Handshake {
+                protover: Some(3),
+                user: Some("default".to_string()),
+                name: Some("cli".to_string()),
+            })
+        );
+
+        buf.extend_from_slice(b"*2\r\n$5\r\nhello\r\n$1\r\n3\r\n");
+        let hello: Hello = RespArray::decode(&mut buf)?.try_into()?;
+        let reply = hello.handshake(&backend, "", false);
+        assert!(matches!(reply, Err(RespFrame::Error(e)) if e.starts_with("NOAUTH")));
+
+        buf.extend_from_slice(b"*2\r\n$5\r\nhello\r\n$1\r\n4\r\n");
+        let hello: Hello = RespArray::decode(&mut buf)?.try_into()?;
+        let reply = hello.handshake(&backend, "", true);
+        assert!(matches!(reply, Err(RespFrame::Error(e)) if e.starts_with("NOPROTO")));
+
+        buf.extend_from_slice(b"*3\r\n$5\r\nhello\r\n$1\r\n3\r\n$7\r\nSETNAME\r\n");
+        assert!(Hello::try_from(RespArray::decode(&mut buf)?).is_err());
+
+        let RespFrame::Map(info) = server_info(&backend, 7, 3) else {
+            panic!("HELLO should reply a map");
+        };
+        assert_eq!(info["proto"], RespFrame::Integer(3));
+        assert_eq!(info["id"], RespFrame::Integer(7));
+        assert_eq!(info["mode"], BulkString::new("standalone").into());
+        Ok(())
+    }
+}
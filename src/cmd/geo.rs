@@ -0,0 +1,829 @@
+use crate::cmd::{
+    extract_args, parse_num, parse_string, validate_command, validate_command_min, CommandError,
+    CommandExecutor, GeoAdd, GeoDist, GeoHash, GeoPos, GeoSearch, GeoSearchStore, RESP_WRONGTYPE,
+};
+use crate::{Backend, BulkString, KeyType, RespArray, RespFrame, RespNull, SimpleError, SortedSet};
+
+// 与 Redis 相同的 geohash 参数: 经纬度各 26 bit, 交织后得到 52 bit 的 score
+const GEO_STEP_MAX: u32 = 26;
+const GEO_LAT_MIN: f64 = -85.051_128_78;
+const GEO_LAT_MAX: f64 = 85.051_128_78;
+const GEO_LONG_MIN: f64 = -180.0;
+const GEO_LONG_MAX: f64 = 180.0;
+const EARTH_RADIUS_IN_METERS: f64 = 6_372_797.560_856;
+const GEOHASH_ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// GEOSEARCH / GEOSEARCHSTORE 的查询条件
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoQuery {
+    from: GeoFrom,
+    shape: GeoShape,
+    /// 每个单位对应的米数
+    unit: f64,
+    order: Option<GeoOrder>,
+    count: Option<(usize, bool)>,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoFrom {
+    Member(String),
+    LonLat(f64, f64),
+}
+
+/// 搜索范围, 单位为米
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoShape {
+    Radius(f64),
+    Box(f64, f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeoOrder {
+    Asc,
+    Desc,
+}
+
+// 搜索命中的成员
+#[derive(Debug)]
+struct GeoPoint {
+    member: String,
+    score: f64,
+    dist: f64,
+    lon: f64,
+    lat: f64,
+}
+
+//===================  实现 CommandExecutor trait for Command
+impl CommandExecutor for GeoAdd {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if backend.is_wrong_type(&self.key, KeyType::ZSet) {
+            return RESP_WRONGTYPE.clone();
+        }
+        backend.zupdate(&self.key, |zset| {
+            let mut count = 0;
+            for (lon, lat, member) in self.items {
+                let score = geohash_encode(lon, lat, GEO_LAT_MIN, GEO_LAT_MAX, GEO_STEP_MAX) as f64;
+                let old = zset.score(&member);
+                if (self.nx && old.is_some()) || (self.xx && old.is_none()) {
+                    continue;
+                }
+                zset.insert(member, score);
+                match old {
+                    None => count += 1,
+                    Some(old) if self.ch && old != score => count += 1,
+                    _ => {}
+                }
+            }
+            RespFrame::Integer(count)
+        })
+    }
+}
+impl CommandExecutor for GeoDist {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if backend.is_wrong_type(&self.key, KeyType::ZSet) {
+            return RESP_WRONGTYPE.clone();
+        }
+        let Some(zset) = backend.zget(&self.key) else {
+            return RespFrame::Null(RespNull);
+        };
+        match (zset.score(&self.member1), zset.score(&self.member2)) {
+            (Some(s1), Some(s2)) => {
+                let (lon1, lat1) = geohash_decode_score(s1);
+                let (lon2, lat2) = geohash_decode_score(s2);
+                let dist = geo_distance(lon1, lat1, lon2, lat2) / self.unit;
+                BulkString::new(format!("{:.4}", dist)).into()
+            }
+            _ => RespFrame::Null(RespNull),
+        }
+    }
+}
+impl CommandExecutor for GeoPos {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if backend.is_wrong_type(&self.key, KeyType::ZSet) {
+            return RESP_WRONGTYPE.clone();
+        }
+        let zset = backend.zget(&self.key);
+        let ret = self
+            .members
+            .iter()
+            .map(|m| match zset.as_ref().and_then(|z| z.score(m)) {
+                Some(score) => {
+                    let (lon, lat) = geohash_decode_score(score);
+                    coord_frame(lon, lat)
+                }
+                None => RespFrame::Null(RespNull),
+            })
+            .collect::<Vec<_>>();
+        RespArray::new(ret).into()
+    }
+}
+impl CommandExecutor for GeoHash {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if backend.is_wrong_type(&self.key, KeyType::ZSet) {
+            return RESP_WRONGTYPE.clone();
+        }
+        let zset = backend.zget(&self.key);
+        let ret = self
+            .members
+            .iter()
+            .map(|m| match zset.as_ref().and_then(|z| z.score(m)) {
+                Some(score) => BulkString::new(geohash_string(score)).into(),
+                None => RespFrame::Null(RespNull),
+            })
+            .collect::<Vec<_>>();
+        RespArray::new(ret).into()
+    }
+}
+impl CommandExecutor for GeoSearch {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let points = match geo_search(backend, &self.key, &self.query) {
+            Ok(points) => points,
+            Err(e) => return e,
+        };
+        let ret = points
+            .iter()
+            .map(|p| point_frame(p, &self.query))
+            .collect::<Vec<_>>();
+        RespArray::new(ret).into()
+    }
+}
+impl CommandExecutor for GeoSearchStore {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let points = match geo_search(backend, &self.key, &self.query) {
+            Ok(points) => points,
+            Err(e) => return e,
+        };
+        let mut zset = SortedSet::new();
+        for p in points {
+            let score = if self.store_dist {
+                p.dist / self.query.unit
+            } else {
+                p.score
+            };
+            zset.insert(p.member, score);
+        }
+        let len = zset.len();
+        backend.zstore(self.dest, zset);
+        RespFrame::Integer(len as i64)
+    }
+}
+
+//===================  实现 TryFrom trait for Command
+impl TryFrom<RespArray> for GeoAdd {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command_min(&value, &["geoadd"], 4)?;
+        let mut args = extract_args(value, 1)?.into_iter().peekable();
+        let key = parse_string(args.next(), "key")?;
+        let (mut nx, mut xx, mut ch) = (false, false, false);
+        while let Some(RespFrame::BulkString(opt)) = args.peek() {
+            match opt.to_ascii_uppercase().as_slice() {
+                b"NX" => nx = true,
+                b"XX" => xx = true,
+                b"CH" => ch = true,
+                _ => break,
+            }
+            args.next();
+        }
+        if nx && xx {
+            return Err(CommandError::InvalidCommandArguments(
+                "XX and NX options at the same time are not compatible".to_string(),
+            ));
+        }
+        let args = args.collect::<Vec<_>>();
+        if args.is_empty() || args.len() % 3 != 0 {
+            return Err(syntax_error());
+        }
+        let mut items = Vec::with_capacity(args.len() / 3);
+        let mut args = args.into_iter();
+        while let Some(lon) = args.next() {
+            let lon: f64 = parse_num(Some(lon), "longitude")?;
+            let lat: f64 = parse_num(args.next(), "latitude")?;
+            if !(GEO_LONG_MIN..=GEO_LONG_MAX).contains(&lon)
+                || !(GEO_LAT_MIN..=GEO_LAT_MAX).contains(&lat)
+            {
+                return Err(CommandError::InvalidCommandArguments(format!(
+                    "invalid longitude,latitude pair {:.6},{:.6}",
+                    lon, lat
+                )));
+            }
+            items.push((lon, lat, parse_string(args.next(), "member")?));
+        }
+        Ok(GeoAdd {
+            key,
+            nx,
+            xx,
+            ch,
+            items,
+        })
+    }
+}
+impl TryFrom<RespArray> for GeoDist {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let unit = value.len() == 5;
+        validate_command(&value, &["geodist"], if unit { 4 } else { 3 })?;
+        let mut args = extract_args(value, 1)?.into_iter();
+        Ok(GeoDist {
+            key: parse_string(args.next(), "key")?,
+            member1: parse_string(args.next(), "member")?,
+            member2: parse_string(args.next(), "member")?,
+            unit: match args.next() {
+                Some(unit) => parse_unit(Some(unit))?,
+                None => 1.0,
+            },
+        })
+    }
+}
+impl TryFrom<RespArray> for GeoPos {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command_min(&value, &["geopos"], 1)?;
+        let (key, members) = parse_key_members(value)?;
+        Ok(GeoPos { key, members })
+    }
+}
+impl TryFrom<RespArray> for GeoHash {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command_min(&value, &["geohash"], 1)?;
+        let (key, members) = parse_key_members(value)?;
+        Ok(GeoHash { key, members })
+    }
+}
+impl TryFrom<RespArray> for GeoSearch {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command_min(&value, &["geosearch"], 5)?;
+        let mut args = extract_args(value, 1)?.into_iter();
+        let key = parse_string(args.next(), "key")?;
+        let (query, _) = parse_query(args, false)?;
+        Ok(GeoSearch { key, query })
+    }
+}
+impl TryFrom<RespArray> for GeoSearchStore {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command_min(&value, &["geosearchstore"], 6)?;
+        let mut args = extract_args(value, 1)?.into_iter();
+        let dest = parse_string(args.next(), "destination")?;
+        let key = parse_string(args.next(), "source")?;
+        let (query, store_dist) = parse_query(args, true)?;
+        Ok(GeoSearchStore {
+            dest,
+            key,
+            query,
+            store_dist,
+        })
+    }
+}
+
+fn syntax_error() -> CommandError {
+    CommandError::InvalidCommandArguments("syntax error".to_string())
+}
+
+fn parse_key_members(value: RespArray) -> Result<(String, Vec<String>), CommandError> {
+    let mut args = extract_args(value, 1)?.into_iter();
+    let key = parse_string(args.next(), "key")?;
+    let members = args
+        .map(|v| parse_string(Some(v), "member"))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((key, members))
+}
+
+fn parse_unit(arg: Option<RespFrame>) -> Result<f64, CommandError> {
+    match parse_string(arg, "unit")?.to_ascii_lowercase().as_str() {
+        "m" => Ok(1.0),
+        "km" => Ok(1000.0),
+        "ft" => Ok(0.3048),
+        "mi" => Ok(1609.34),
+        _ => Err(CommandError::InvalidCommandArguments(
+            "unsupported unit provided. please use M, KM, FT, MI".to_string(),
+        )),
+    }
+}
+
+// 解析 GEOSEARCH 的选项; `store` 为 true 时按 GEOSEARCHSTORE 的规则解析
+fn parse_query(
+    mut args: impl Iterator<Item = RespFrame>,
+    store: bool,
+) -> Result<(GeoQuery, bool), CommandError> {
+    let (mut from, mut shape, mut unit) = (None, None, 1.0);
+    let (mut order, mut count, mut any) = (None, None, false);
+    let (mut with_coord, mut with_dist, mut with_hash, mut store_dist) =
+        (false, false, false, false);
+    while let Some(arg) = args.next() {
+        match parse_string(Some(arg), "option")?
+            .to_ascii_uppercase()
+            .as_str()
+        {
+            "FROMMEMBER" if from.is_none() => {
+                from = Some(GeoFrom::Member(parse_string(args.next(), "member")?));
+            }
+            "FROMLONLAT" if from.is_none() => {
+                let lon = parse_num(args.next(), "longitude")?;
+                let lat = parse_num(args.next(), "latitude")?;
+                from = Some(GeoFrom::LonLat(lon, lat));
+            }
+            "FROMMEMBER" | "FROMLONLAT" => {
+                return Err(CommandError::InvalidCommandArguments(
+                    "exactly one of FROMMEMBER or FROMLONLAT can be specified".to_string(),
+                ))
+            }
+            "BYRADIUS" if shape.is_none() => {
+                let radius: f64 = parse_num(args.next(), "radius")?;
+                unit = parse_unit(args.next())?;
+                if radius < 0.0 {
+                    return Err(CommandError::InvalidCommandArguments(
+                        "radius cannot be negative".to_string(),
+                    ));
+                }
+                shape = Some(GeoShape::Radius(radius * unit));
+            }
+            "BYBOX" if shape.is_none() => {
+                let width: f64 = parse_num(args.next(), "width")?;
+                let height: f64 = parse_num(args.next(), "height")?;
+                unit = parse_unit(args.next())?;
+                if width < 0.0 || height < 0.0 {
+                    return Err(CommandError::InvalidCommandArguments(
+                        "height or width cannot be negative".to_string(),
+                    ));
+                }
+                shape = Some(GeoShape::Box(width * unit, height * unit));
+            }
+            "BYRADIUS" | "BYBOX" => {
+                return Err(CommandError::InvalidCommandArguments(
+                    "exactly one of BYRADIUS and BYBOX can be specified".to_string(),
+                ))
+            }
+            "ASC" => order = Some(GeoOrder::Asc),
+            "DESC" => order = Some(GeoOrder::Desc),
+            "COUNT" => {
+                let n: usize = parse_num(args.next(), "COUNT")?;
+                if n == 0 {
+                    return Err(CommandError::InvalidCommandArguments(
+                        "COUNT must be > 0".to_string(),
+                    ));
+                }
+                count = Some(n);
+            }
+            "ANY" => any = true,
+            "WITHCOORD" if !store => with_coord = true,
+            "WITHDIST" if !store => with_dist = true,
+            "WITHHASH" if !store => with_hash = true,
+            "STOREDIST" if store => store_dist = true,
+            _ => return Err(syntax_error()),
+        }
+    }
+    let (Some(from), Some(shape)) = (from, shape) else {
+        return Err(CommandError::InvalidCommandArguments(
+            "exactly one of FROMMEMBER or FROMLONLAT and one of BYRADIUS or BYBOX can be specified"
+                .to_string(),
+        ));
+    };
+    if any && count.is_none() {
+        return Err(CommandError::InvalidCommandArguments(
+            "the ANY argument requires COUNT argument".to_string(),
+        ));
+    }
+    // 指定 COUNT 但未指定 ANY 时, 需要先按距离排序再截取最近的 N 个
+    if count.is_some() && !any && order.is_none() {
+        order = Some(GeoOrder::Asc);
+    }
+    let query = GeoQuery {
+        from,
+        shape,
+        unit,
+        order,
+        count: count.map(|n| (n, any)),
+        with_coord,
+        with_dist,
+        with_hash,
+    };
+    Ok((query, store_dist))
+}
+
+fn geo_search(backend: &Backend, key: &str, query: &GeoQuery) -> Result<Vec<GeoPoint>, RespFrame> {
+    if backend.is_wrong_type(key, KeyType::ZSet) {
+        return Err(RESP_WRONGTYPE.clone());
+    }
+    let Some(zset) = backend.zget(key) else {
+        return Ok(Vec::new());
+    };
+    let (lon, lat) = match &query.from {
+        GeoFrom::LonLat(lon, lat) => (*lon, *lat),
+        GeoFrom::Member(member) => match zset.score(member) {
+            Some(score) => geohash_decode_score(score),
+            None => {
+                return Err(SimpleError::new(
+                    "ERR could not decode requested zset member".to_string(),
+                )
+                .into())
+            }
+        },
+    };
+    let radius = match query.shape {
+        GeoShape::Radius(r) => r,
+        GeoShape::Box(w, h) => (w / 2.0).hypot(h / 2.0),
+    };
+    let limit = match query.count {
+        Some((n, true)) => n,
+        _ => usize::MAX,
+    };
+
+    let mut points = Vec::new();
+    'cells: for (min, max) in search_ranges(lon, lat, radius) {
+        for (member, score) in zset.range_by_score(min, max) {
+            let (plon, plat) = geohash_decode_score(score);
+            let dist = match query.shape {
+                GeoShape::Radius(r) => Some(geo_distance(lon, lat, plon, plat)).filter(|&d| d <= r),
+                GeoShape::Box(w, h) => distance_if_in_box(w, h, lon, lat, plon, plat),
+            };
+            if let Some(dist) = dist {
+                points.push(GeoPoint {
+                    member: member.to_string(),
+                    score,
+                    dist,
+                    lon: plon,
+                    lat: plat,
+                });
+                if points.len() >= limit {
+                    break 'cells;
+                }
+            }
+        }
+    }
+
+    match query.order {
+        Some(GeoOrder::Asc) => points.sort_by(|a, b| a.dist.total_cmp(&b.dist)),
+        Some(GeoOrder::Desc) => points.sort_by(|a, b| b.dist.total_cmp(&a.dist)),
+        None => {}
+    }
+    if let Some((n, _)) = query.count {
+        points.truncate(n);
+    }
+    Ok(points)
+}
+
+/// 需要扫描的 score 区间: 中心点所在的格子以及周围 8 个格子.
+/// 格子的精度保证其宽和高都不小于搜索半径, 这样 9 个格子一定能覆盖整个搜索范围.
+fn search_ranges(lon: f64, lat: f64, radius: f64) -> Vec<(f64, f64)> {
+    let mut step = GEO_STEP_MAX;
+    while step > 0 {
+        let cells = (1_u64 << step) as f64;
+        let height = ((GEO_LAT_MAX - GEO_LAT_MIN) / cells).to_radians() * EARTH_RADIUS_IN_METERS;
+        // 取搜索范围内离赤道最远的纬度计算格子宽度
+        let far_lat = (lat.abs() + (radius / EARTH_RADIUS_IN_METERS).to_degrees()).min(90.0);
+        let width = ((GEO_LONG_MAX - GEO_LONG_MIN) / cells).to_radians()
+            * EARTH_RADIUS_IN_METERS
+            * far_lat.to_radians().cos();
+        if height >= radius && width >= radius {
+            break;
+        }
+        step -= 1;
+    }
+
+    let cells = 1_i64 << step;
+    let (lat_idx, lon_idx) =
+        deinterleave64(geohash_encode(lon, lat, GEO_LAT_MIN, GEO_LAT_MAX, step));
+    let shift = 2 * (GEO_STEP_MAX - step);
+    let mut hashes = Vec::with_capacity(9);
+    for dlat in -1..=1 {
+        let lat_idx = lat_idx as i64 + dlat;
+        if !(0..cells).contains(&lat_idx) {
+            continue;
+        }
+        for dlon in -1..=1 {
+            // 经度方向首尾相接
+            let lon_idx = (lon_idx as i64 + dlon).rem_euclid(cells);
+            hashes.push(interleave64(lat_idx as u32, lon_idx as u32));
+        }
+    }
+    hashes.sort_unstable();
+    hashes.dedup();
+    hashes
+        .into_iter()
+        .map(|h| ((h << shift) as f64, ((h + 1) << shift) as f64))
+        .collect()
+}
+
+fn point_frame(p: &GeoPoint, query: &GeoQuery) -> RespFrame {
+    let member: RespFrame = BulkString::new(p.member.as_bytes()).into();
+    if !(query.with_coord || query.with_dist || query.with_hash) {
+        return member;
+    }
+    let mut item = vec![member];
+    if query.with_dist {
+        item.push(BulkString::new(format!("{:.4}", p.dist / query.unit)).into());
+    }
+    if query.with_hash {
+        item.push(RespFrame::Integer(p.score as i64));
+    }
+    if query.with_coord {
+        item.push(coord_frame(p.lon, p.lat));
+    }
+    RespArray::new(item).into()
+}
+
+fn coord_frame(lon: f64, lat: f64) -> RespFrame {
+    RespArray::new(vec![
+        BulkString::new(lon.to_string()).into(),
+        BulkString::new(lat.to_string()).into(),
+    ])
+    .into()
+}
+
+// 将 x 放在偶数位, y 放在奇数位
+fn interleave64(x: u32, y: u32) -> u64 {
+    (0..32).fold(0, |acc, i| {
+        acc | (((x as u64 >> i) & 1) << (2 * i)) | (((y as u64 >> i) & 1) << (2 * i + 1))
+    })
+}
+
+fn deinterleave64(v: u64) -> (u32, u32) {
+    (0..32).fold((0, 0), |(x, y), i| {
+        (
+            x | (((v >> (2 * i)) & 1) as u32) << i,
+            y | (((v >> (2 * i + 1)) & 1) as u32) << i,
+        )
+    })
+}
+
+fn geohash_encode(lon: f64, lat: f64, lat_min: f64, lat_max: f64, step: u32) -> u64 {
+    let cells = (1_u64 << step) as f64;
+    let lat_offset = ((lat - lat_min) / (lat_max - lat_min) * cells).clamp(0.0, cells - 1.0);
+    let lon_offset =
+        ((lon - GEO_LONG_MIN) / (GEO_LONG_MAX - GEO_LONG_MIN) * cells).clamp(0.0, cells - 1.0);
+    interleave64(lat_offset as u32, lon_offset as u32)
+}
+
+/// 52 bit score 还原为所在格子的中心点 (经度, 纬度)
+fn geohash_decode_score(score: f64) -> (f64, f64) {
+    let (lat_idx, lon_idx) = deinterleave64(score as u64);
+    let cells = (1_u64 << GEO_STEP_MAX) as f64;
+    let lat_unit = (GEO_LAT_MAX - GEO_LAT_MIN) / cells;
+    let lon_unit = (GEO_LONG_MAX - GEO_LONG_MIN) / cells;
+    let lat = GEO_LAT_MIN + (lat_idx as f64 + 0.5) * lat_unit;
+    let lon = GEO_LONG_MIN + (lon_idx as f64 + 0.5) * lon_unit;
+    (
+        lon.clamp(GEO_LONG_MIN, GEO_LONG_MAX),
+        lat.clamp(GEO_LAT_MIN, GEO_LAT_MAX),
+    )
+}
+
+/// 标准的 11 位 geohash 字符串 (纬度范围为 [-90, 90])
+fn geohash_string(score: f64) -> String {
+    let (lon, lat) = geohash_decode_score(score);
+    let bits = geohash_encode(lon, lat, -90.0, 90.0, GEO_STEP_MAX);
+    (0..11)
+        .map(|i| {
+            // 52 bit 只够 10 个字符, 最后一个字符补 0
+            let idx = if i == 10 {
+                0
+            } else {
+                (bits >> (52 - (i + 1) * 5)) & 0x1f
+            };
+            GEOHASH_ALPHABET[idx as usize] as char
+        })
+        .collect()
+}
+
+// haversine 公式计算球面距离, 单位为米
+fn geo_distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let v = ((lon2 - lon1).to_radians() / 2.0).sin();
+    2.0 * EARTH_RADIUS_IN_METERS * (u * u + lat1.cos() * lat2.cos() * v * v).sqrt().asin()
+}
+
+fn distance_if_in_box(
+    width: f64,
+    height: f64,
+    lon: f64,
+    lat: f64,
+    plon: f64,
+    plat: f64,
+) -> Option<f64> {
+    if geo_distance(plon, plat, plon, lat) > height / 2.0
+        || geo_distance(plon, plat, lon, plat) > width / 2.0
+    {
+        return None;
+    }
+    Some(geo_distance(lon, lat, plon, plat))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use bytes::BytesMut;
+
+    use crate::RespDecode;
+
+    use super::*;
+
+    fn bulk(s: &str) -> RespFrame {
+        BulkString::new(s).into()
+    }
+
+    fn sicily() -> Backend {
+        let backend = Backend::new();
+        GeoAdd {
+            key: "Sicily".to_string(),
+            nx: false,
+            xx: false,
+            ch: false,
+            items: vec![
+                (13.361389, 38.115556, "Palermo".to_string()),
+                (15.087269, 37.502669, "Catania".to_string()),
+                (12.758489, 38.788135, "edge1".to_string()),
+                (17.241510, 38.788135, "edge2".to_string()),
+            ],
+        }
+        .execute(&backend);
+        backend
+    }
+
+    #[test]
+    fn test_geoadd_from_resp_array() -> Result<()> {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(
+            b"*6\r\n$6\r\nGEOADD\r\n$6\r\nSicily\r\n$2\r\nCH\r\n$9\r\n13.361389\r\n$9\r\n38.115556\r\n$7\r\nPalermo\r\n",
+        );
+        let frame = RespArray::decode(&mut buf)?;
+        let result: GeoAdd = frame.try_into()?;
+        assert_eq!(result.key, "Sicily");
+        assert!(result.ch);
+        assert_eq!(
+            result.items,
+            vec![(13.361389, 38.115556, "Palermo".to_string())]
+        );
+
+        let frame = RespArray::new(vec![
+            bulk("GEOADD"),
+            bulk("Sicily"),
+            bulk("200"),
+            bulk("10"),
+            bulk("x"),
+        ]);
+        assert!(GeoAdd::try_from(frame).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_geosearch_from_resp_array() -> Result<()> {
+        let frame = RespArray::new(vec![
+            bulk("GEOSEARCH"),
+            bulk("Sicily"),
+            bulk("FROMLONLAT"),
+            bulk("15"),
+            bulk("37"),
+            bulk("BYBOX"),
+            bulk("400"),
+            bulk("400"),
+            bulk("km"),
+            bulk("COUNT"),
+            bulk("2"),
+            bulk("WITHDIST"),
+        ]);
+        let result: GeoSearch = frame.try_into()?;
+        assert_eq!(result.query.from, GeoFrom::LonLat(15.0, 37.0));
+        assert_eq!(result.query.shape, GeoShape::Box(400_000.0, 400_000.0));
+        assert_eq!(result.query.order, Some(GeoOrder::Asc));
+        assert_eq!(result.query.count, Some((2, false)));
+        assert!(result.query.with_dist);
+
+        let frame = RespArray::new(vec![
+            bulk("GEOSEARCH"),
+            bulk("Sicily"),
+            bulk("FROMLONLAT"),
+            bulk("15"),
+            bulk("37"),
+            bulk("ANY"),
+            bulk("BYRADIUS"),
+            bulk("200"),
+            bulk("km"),
+        ]);
+        assert!(GeoSearch::try_from(frame).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_geo_dist_pos_hash() {
+        let backend = sicily();
+        let dist = GeoDist {
+            key: "Sicily".to_string(),
+            member1: "Palermo".to_string(),
+            member2: "Catania".to_string(),
+            unit: 1000.0,
+        }
+        .execute(&backend);
+        assert_eq!(dist, bulk("166.2742"));
+
+        let hash = GeoHash {
+            key: "Sicily".to_string(),
+            members: vec![
+                "Palermo".to_string(),
+                "Catania".to_string(),
+                "x".to_string(),
+            ],
+        }
+        .execute(&backend);
+        assert_eq!(
+            hash,
+            RespArray::new(vec![
+                bulk("sqc8b49rny0"),
+                bulk("sqdtr74hyu0"),
+                RespFrame::Null(RespNull)
+            ])
+            .into()
+        );
+
+        let RespFrame::Array(pos) = (GeoPos {
+            key: "Sicily".to_string(),
+            members: vec!["Palermo".to_string()],
+        })
+        .execute(&backend) else {
+            panic!("GEOPOS should return an array");
+        };
+        let RespFrame::Array(ref coord) = pos[0] else {
+            panic!("coordinate should be an array");
+        };
+        let RespFrame::BulkString(ref lon) = coord[0] else {
+            panic!("longitude should be a bulk string");
+        };
+        let lon: f64 = String::from_utf8_lossy(lon).parse().unwrap_or_default();
+        assert!((lon - 13.361389).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_geosearch_execute() {
+        let backend = sicily();
+        let query = GeoQuery {
+            from: GeoFrom::LonLat(15.0, 37.0),
+            shape: GeoShape::Radius(200_000.0),
+            unit: 1000.0,
+            order: Some(GeoOrder::Asc),
+            count: None,
+            with_coord: false,
+            with_dist: true,
+            with_hash: false,
+        };
+        let ret = GeoSearch {
+            key: "Sicily".to_string(),
+            query: query.clone(),
+        }
+        .execute(&backend);
+        assert_eq!(
+            ret,
+            RespArray::new(vec![
+                RespArray::new(vec![bulk("Catania"), bulk("56.4413")]).into(),
+                RespArray::new(vec![bulk("Palermo"), bulk("190.4424")]).into(),
+            ])
+            .into()
+        );
+
+        let ret = GeoSearch {
+            key: "Sicily".to_string(),
+            query: GeoQuery {
+                shape: GeoShape::Box(400_000.0, 400_000.0),
+                order: Some(GeoOrder::Desc),
+                with_dist: false,
+                ..query.clone()
+            },
+        }
+        .execute(&backend);
+        assert_eq!(
+            ret,
+            RespArray::new(vec![
+                bulk("edge1"),
+                bulk("edge2"),
+                bulk("Palermo"),
+                bulk("Catania")
+            ])
+            .into()
+        );
+
+        let ret = GeoSearchStore {
+            dest: "near".to_string(),
+            key: "Sicily".to_string(),
+            query: GeoQuery {
+                from: GeoFrom::Member("Palermo".to_string()),
+                count: Some((1, true)),
+                ..query
+            },
+            store_dist: false,
+        }
+        .execute(&backend);
+        assert_eq!(ret, RespFrame::Integer(1));
+        assert_eq!(backend.zget("near").map(|z| z.len()), Some(1));
+    }
+}
@@ -1,12 +1,17 @@
-use crate::cmd::{extract_args, validate_command, CommandExecutor, HGetAll, HSet, RESP_OK};
+use crate::cmd::{
+    extract_args, validate_command, CommandExecutor, HGetAll, HSet, RESP_OK, RESP_WRONGTYPE,
+};
 use crate::{
     cmd::{CommandError, HGet},
-    Backend, BulkString, RespArray, RespFrame, RespNull,
+    Backend, BulkString, KeyType, RespArray, RespFrame, RespNull,
 };
 
 //===================  实现 CommandExecutor trait for Command
 impl CommandExecutor for HGet {
     fn execute(self, backend: &Backend) -> RespFrame {
+        if backend.is_wrong_type(&self.key, KeyType::Hash) {
+            return RESP_WRONGTYPE.clone();
+        }
         backend
             .hget(&self.key, &self.field)
             .unwrap_or(RespFrame::Null(RespNull))
@@ -14,6 +19,9 @@ impl CommandExecutor for HGet {
 }
 impl CommandExecutor for HGetAll {
     fn execute(self, backend: &Backend) -> RespFrame {
+        if backend.is_wrong_type(&self.key, KeyType::Hash) {
+            return RESP_WRONGTYPE.clone();
+        }
         let hmap = backend.hgetall(&self.key);
         match hmap {
             Some(hmap) => {
@@ -38,6 +46,9 @@ impl CommandExecutor for HGetAll {
 }
 impl CommandExecutor for HSet {
     fn execute(self, backend: &Backend) -> RespFrame {
+        if backend.is_wrong_type(&self.key, KeyType::Hash) {
+            return RESP_WRONGTYPE.clone();
+        }
         backend.hset(self.key, self.field, self.value);
         RESP_OK.clone()
     }
@@ -52,8 +63,8 @@ impl TryFrom<RespArray> for HGet {
         let mut args = extract_args(value, 1)?.into_iter();
         match (args.next(), args.next()) {
             (Some(RespFrame::BulkString(key)), Some(RespFrame::BulkString(field))) => Ok(HGet {
-                key: String::from_utf8(key.0)?,
-                field: String::from_utf8(field.0)?,
+                key: String::from_utf8(key.0.to_vec())?,
+                field: String::from_utf8(field.0.to_vec())?,
             }),
             _ => Err(CommandError::InvalidCommand(
                 "Invalid key or field for HGET command".to_string(),
@@ -69,7 +80,7 @@ impl TryFrom<RespArray> for HGetAll {
         let mut args = extract_args(value, 1)?.into_iter();
         match args.next() {
             Some(RespFrame::BulkString(key)) => Ok(HGetAll {
-                key: String::from_utf8(key.0)?,
+                key: String::from_utf8(key.0.to_vec())?,
                 sort: false,
             }),
             _ => Err(CommandError::InvalidCommand(
@@ -87,8 +98,8 @@ impl TryFrom<RespArray> for HSet {
         match (args.next(), args.next(), args.next()) {
             (Some(RespFrame::BulkString(key)), Some(RespFrame::BulkString(field)), Some(value)) => {
                 Ok(HSet {
-                    key: String::from_utf8(key.0)?,
-                    field: String::from_utf8(field.0)?,
+                    key: String::from_utf8(key.0.to_vec())?,
+                    field: String::from_utf8(field.0.to_vec())?,
                     value,
                 })
             }
@@ -0,0 +1,431 @@
+use std::time::Duration;
+
+use crate::backend::now_ms;
+use crate::cluster::MigrateOptions;
+use crate::cmd::{
+    extract_args, parse_num, parse_string, validate_command, validate_command_min, CommandError,
+    CommandExecutor, Del, Dump, Migrate, Restore, RESP_OK,
+};
+use crate::persist::{decode_dump, encode_dump};
+use crate::{
+    Backend, BulkString, RespArray, RespFrame, RespNull, SimpleError, SimpleString, SnapshotEntry,
+};
+
+//===================  实现 CommandExecutor trait for Command
+impl CommandExecutor for Del {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let n = self.keys.iter().filter(|k| backend.del(k)).count();
+        RespFrame::Integer(n as i64)
+    }
+}
+impl CommandExecutor for Dump {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        match backend.entry(&self.key) {
+            Some(entry) => BulkString::new(encode_dump(&entry.value)).into(),
+            None => RespFrame::Null(RespNull),
+        }
+    }
+}
+impl CommandExecutor for Restore {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let value = match decode_dump(&self.payload) {
+            Ok(value) => value,
+            Err(e) => return SimpleError::new(format!("ERR {}", e)).into(),
+        };
+        let expire_at = match self.ttl {
+            0 => None,
+            t if self.absttl => Some(t),
+            t => Some(now_ms().saturating_add(t)),
+        };
+        // 已经过期: 不创建 key, REPLACE 时删除原有的 key
+        if expire_at.is_some_and(|t| t <= now_ms()) {
+            if !self.replace && backend.exists(&self.key) {
+                return busy_key();
+            }
+            if self.replace {
+                backend.del(&self.key);
+            }
+            return RESP_OK.clone();
+        }
+        let entry = SnapshotEntry {
+            key: self.key,
+            value,
+            expire_at,
+        };
+        if self.replace {
+            backend.restore_entry(entry);
+        } else if !backend.restore_entry_nx(entry) {
+            return busy_key();
+        }
+        RESP_OK.clone()
+    }
+}
+impl Restore {
+    /// 把相对 TTL 换算为绝对时间, 返回用于传播的 `RESTORE key unix-ms payload ABSTTL [REPLACE]`.
+    /// 相对 TTL 在 replica 和 AOF 重放时会从执行的时刻重新计算, 过期时间会被推迟
+    pub(crate) fn make_absttl(&mut self) -> Option<RespFrame> {
+        if self.ttl == 0 || self.absttl {
+            return None;
+        }
+        self.ttl = now_ms().saturating_add(self.ttl);
+        self.absttl = true;
+        let mut args: Vec<RespFrame> = vec![
+            BulkString::new("RESTORE").into(),
+            BulkString::new(self.key.clone()).into(),
+            BulkString::new(self.ttl.to_string()).into(),
+            BulkString::new(self.payload.clone()).into(),
+            BulkString::new("ABSTTL").into(),
+        ];
+        if self.replace {
+            args.push(BulkString::new("REPLACE").into());
+        }
+        Some(RespArray::new(args).into())
+    }
+}
+// 连接目标节点并等待回复, 会阻塞当前线程
+impl CommandExecutor for Migrate {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        match backend.migrate(&self.host, self.port, &self.keys, &self.options) {
+            Ok(0) => SimpleString::new("NOKEY").into(),
+            Ok(_) => RESP_OK.clone(),
+            Err(e) => SimpleError::new(e.to_string()).into(),
+        }
+    }
+}
+
+//===================  实现 TryFrom trait for Command
+impl TryFrom<RespArray> for Del {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command_min(&value, &["del"], 1)?;
+        let keys = extract_args(value, 1)?
+            .into_iter()
+            .map(|k| parse_string(Some(k), "key"))
+            .collect::<Result<_, _>>()?;
+        Ok(Del { keys })
+    }
+}
+impl TryFrom<RespArray> for Dump {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["dump"], 1)?;
+        let mut args = extract_args(value, 1)?.into_iter();
+        Ok(Dump {
+            key: parse_string(args.next(), "key")?,
+        })
+    }
+}
+impl TryFrom<RespArray> for Restore {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        // RESTORE key ttl serialized-value [REPLACE] [ABSTTL] [IDLETIME seconds] [FREQ frequency]
+        let asking = matches!(value.first(), Some(RespFrame::BulkString(s)) if s.eq_ignore_ascii_case(b"restore-asking"));
+        let name = if asking { "restore-asking" } else { "restore" };
+        validate_command_min(&value, &[name], 3)?;
+        let mut args = extract_args(value, 1)?.into_iter();
+        let key = parse_string(args.next(), "key")?;
+        let ttl: i64 = parse_num(args.next(), "ttl")?;
+        if ttl < 0 {
+            return Err(CommandError::InvalidCommandArguments(
+                "Invalid TTL value, must be >= 0".to_string(),
+            ));
+        }
+        let payload = match args.next() {
+            Some(RespFrame::BulkString(s)) => s.0.to_vec(),
+            _ => {
+                return Err(CommandError::InvalidCommandArguments(
+                    "Invalid serialized-value".to_string(),
+                ))
+            }
+        };
+
+        let (mut replace, mut absttl) = (false, false);
+        // 没有 LRU/LFU 淘汰, IDLETIME 和 FREQ 只做校验
+        let (mut idletime, mut freq) = (false, false);
+        while let Some(opt) = args.next() {
+            let RespFrame::BulkString(opt) = opt else {
+                return Err(syntax_error());
+            };
+            match opt.to_ascii_uppercase().as_slice() {
+                b"REPLACE" => replace = true,
+                b"ABSTTL" => absttl = true,
+                b"IDLETIME" if !freq => {
+                    let idle: i64 = parse_num(args.next(), "IDLETIME")?;
+                    if idle < 0 {
+                        return Err(CommandError::InvalidCommandArguments(
+                            "Invalid IDLETIME value, must be >= 0".to_string(),
+                        ));
+                    }
+                    idletime = true;
+                }
+                b"FREQ" if !idletime => {
+                    let n: i64 = parse_num(args.next(), "FREQ")?;
+                    if !(0..=255).contains(&n) {
+                        return Err(CommandError::InvalidCommandArguments(
+                            "Invalid FREQ value, must be >= 0 and <= 255".to_string(),
+                        ));
+                    }
+                    freq = true;
+                }
+                _ => return Err(syntax_error()),
+            }
+        }
+        Ok(Restore {
+            key,
+            ttl,
+            payload,
+            replace,
+            absttl,
+            asking,
+        })
+    }
+}
+impl TryFrom<RespArray> for Migrate {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        // MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE]
+        //   [AUTH password | AUTH2 username password] [KEYS key [key ...]]
+        validate_command_min(&value, &["migrate"], 5)?;
+        let mut args = extract_args(value, 1)?.into_iter();
+        let host = parse_string(args.next(), "host")?;
+        let port: u16 = parse_num(args.next(), "port")?;
+        let key = parse_string(args.next(), "key")?;
+        let db: i64 = parse_num(args.next(), "destination-db")?;
+        if db != 0 {
+            return Err(CommandError::InvalidCommandArguments(
+                "destination-db must be 0".to_string(),
+            ));
+        }
+        let timeout: i64 = parse_num(args.next(), "timeout")?;
+        let mut options = MigrateOptions {
+            copy: false,
+            replace: false,
+            auth: None,
+            // 与 Redis 一样, timeout 不大于 0 时使用 1 秒
+            timeout: Duration::from_millis(if timeout > 0 { timeout as u64 } else { 1000 }),
+        };
+        let mut keys = Vec::new();
+        while let Some(opt) = args.next() {
+            let RespFrame::BulkString(opt) = opt else {
+                return Err(syntax_error());
+            };
+            match opt.to_ascii_uppercase().as_slice() {
+                b"COPY" => options.copy = true,
+                b"REPLACE" => options.replace = true,
+                b"AUTH" => options.auth = Some((None, parse_string(args.next(), "password")?)),
+                b"AUTH2" => {
+                    let username = parse_string(args.next(), "username")?;
+                    let password = parse_string(args.next(), "password")?;
+                    options.auth = Some((Some(username), password));
+                }
+                b"KEYS" => {
+                    if !key.is_empty() {
+                        return Err(CommandError::InvalidCommandArguments(
+                            "When using MIGRATE KEYS option, the key argument must be set to the empty string".to_string(),
+                        ));
+                    }
+                    keys = args
+                        .by_ref()
+                        .map(|k| parse_string(Some(k), "key"))
+                        .collect::<Result<_, _>>()?;
+                }
+                _ => return Err(syntax_error()),
+            }
+        }
+        if !key.is_empty() {
+            keys.push(key);
+        }
+        if keys.is_empty() {
+            return Err(syntax_error());
+        }
+        Ok(Migrate {
+            host,
+            port,
+            keys,
+            options,
+        })
+    }
+}
+
+fn syntax_error() -> CommandError {
+    CommandError::InvalidCommandArguments("syntax error".to_string())
+}
+
+fn busy_key() -> RespFrame {
+    SimpleError::new("BUSYKEY Target key name already exists.".to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use bytes::BytesMut;
+
+    use crate::{network::testing::array, RespDecode};
+
+    use super::*;
+
+    fn restore(key: &str, ttl: i64, payload: &[u8], opts: &[&str]) -> Result<Restore> {
+        let mut args = vec![
+            BulkString::new("RESTORE").into(),
+            BulkString::new(key).into(),
+            BulkString::new(ttl.to_string()).into(),
+            BulkString::new(payload.to_vec()).into(),
+        ];
+        args.extend(array(opts).iter().cloned());
+        Ok(RespArray::new(args).try_into()?)
+    }
+
+    #[test]
+    fn test_restore_from_resp_array() -> Result<()> {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"*6\r\n$7\r\nRESTORE\r\n$3\r\nkey\r\n$3\r\n100\r\n$3\r\nabc\r\n$7\r\nreplace\r\n$6\r\nABSTTL\r\n");
+        let frame = RespArray::decode(&mut buf)?;
+        let cmd: Restore = frame.try_into()?;
+        assert_eq!(cmd.key, "key");
+        assert_eq!(cmd.ttl, 100);
+        assert_eq!(cmd.payload, b"abc");
+        assert!(cmd.replace && cmd.absttl);
+
+        assert!(restore("key", 0, b"abc", &["IDLETIME", "10"]).is_ok());
+        assert!(restore("key", 0, b"abc", &["FREQ", "256"]).is_err());
+        assert!(restore("key", 0, b"abc", &["IDLETIME", "10", "FREQ", "1"]).is_err());
+        assert!(restore("key", -1, b"abc", &[]).is_err());
+        Ok(())
+    }
+
+    fn migrate(args: &[&str]) -> Result<Migrate, CommandError> {
+        array(&[&["MIGRATE"], args].concat()).try_into()
+    }
+
+    #[test]
+    fn test_migrate_from_resp_array() -> Result<()> {
+        let cmd = migrate(&["127.0.0.1", "7001", "key", "0", "0", "copy"])?;
+        assert_eq!((cmd.host.as_str(), cmd.port), ("127.0.0.1", 7001));
+        assert_eq!(cmd.keys, vec!["key"]);
+        assert!(cmd.options.copy && !cmd.options.replace);
+        assert_eq!(cmd.options.timeout, Duration::from_secs(1));
+
+        let cmd = migrate(&[
+            "h", "1", "", "0", "500", "REPLACE", "AUTH2", "user", "pw", "KEYS", "a", "b",
+        ])?;
+        assert_eq!(cmd.keys, vec!["a", "b"]);
+        assert!(cmd.options.replace);
+        assert_eq!(
+            cmd.options.auth,
+            Some((Some("user".to_string()), "pw".to_string()))
+        );
+        assert_eq!(cmd.options.timeout, Duration::from_millis(500));
+
+        assert!(migrate(&["h", "1", "key", "1", "0"]).is_err());
+        assert!(migrate(&["h", "1", "", "0", "0"]).is_err());
+        assert!(migrate(&["h", "1", "key", "0", "0", "KEYS", "a"]).is_err());
+
+        let frame = RespArray::new(vec![
+            BulkString::new("restore-asking").into(),
+            BulkString::new("key").into(),
+            BulkString::new("0").into(),
+            BulkString::new("abc").into(),
+        ]);
+        let cmd: Restore = frame.try_into()?;
+        assert!(cmd.asking);
+        assert!(!restore("key", 0, b"abc", &[])?.asking);
+        Ok(())
+    }
+
+    #[test]
+    fn test_del_execute() -> Result<()> {
+        let backend = Backend::new();
+        backend.set("a".to_string(), BulkString::new("1").into());
+        backend.set("b".to_string(), BulkString::new("1").into());
+        let del = Del {
+            keys: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+        };
+        assert_eq!(del.execute(&backend), RespFrame::Integer(2));
+        assert!(!backend.exists("a"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_restore_execute() -> Result<()> {
+        let backend = Backend::new();
+        backend.hset(
+            "hash".to_string(),
+            "field".to_string(),
+            BulkString::new("value").into(),
+        );
+        let dump = Dump {
+            key: "hash".to_string(),
+        };
+        let RespFrame::BulkString(payload) = dump.execute(&backend) else {
+            panic!("DUMP should return a bulk string");
+        };
+        let missing = Dump {
+            key: "missing".to_string(),
+        };
+        assert_eq!(missing.execute(&backend), RespFrame::Null(RespNull));
+
+        // key 已存在时需要 REPLACE
+        let ret = restore("hash", 0, &payload, &[])?.execute(&backend);
+        assert!(matches!(ret, RespFrame::Error(e) if e.starts_with("BUSYKEY")));
+        let ret = restore("copy", 100_000, &payload, &[])?.execute(&backend);
+        assert_eq!(ret, RESP_OK.clone());
+        assert_eq!(
+            backend.hget("copy", "field"),
+            Some(BulkString::new("value").into())
+        );
+        assert!(backend.expire_time("copy").is_some());
+
+        // 绝对时间已过期, 不创建 key
+        let ret = restore("hash", 1, &payload, &["REPLACE", "ABSTTL"])?.execute(&backend);
+        assert_eq!(ret, RESP_OK.clone());
+        assert!(!backend.exists("hash"));
+
+        let mut corrupted = payload.to_vec();
+        corrupted[3] ^= 0xff;
+        let ret = restore("bad", 0, &corrupted, &[])?.execute(&backend);
+        assert_eq!(
+            ret,
+            SimpleError::new("ERR DUMP payload version or checksum are wrong".to_string()).into()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_make_absttl() -> Result<()> {
+        let mut cmd = restore("key", 100_000, b"abc", &["REPLACE"])?;
+        let now = now_ms();
+        let Some(RespFrame::Array(frame)) = cmd.make_absttl() else {
+            panic!("RESTORE with a relative TTL should be rewritten");
+        };
+        let rewritten: Restore = frame.try_into()?;
+        assert!(rewritten.absttl && rewritten.replace);
+        assert!((now + 100_000..now + 101_000).contains(&rewritten.ttl));
+        assert_eq!(rewritten.ttl, cmd.ttl);
+        assert_eq!(rewritten.payload, b"abc");
+        // 已经是绝对时间或不过期时保持原样
+        assert!(cmd.make_absttl().is_none());
+        assert!(restore("key", 0, b"abc", &[])?.make_absttl().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_nx_any_type() -> Result<()> {
+        let backend = Backend::new();
+        backend.zupdate("key", |z| z.insert("member".to_string(), 1.0));
+        let entry = SnapshotEntry {
+            key: "key".to_string(),
+            value: crate::StoredValue::String(BulkString::new("v").into()),
+            expire_at: None,
+        };
+        assert!(!backend.restore_entry_nx(entry.clone()));
+        assert!(backend.get("key").is_none());
+        backend.del("key");
+        assert!(backend.restore_entry_nx(entry));
+        assert_eq!(backend.get("key"), Some(BulkString::new("v").into()));
+        Ok(())
+    }
+}
@@ -1,18 +1,21 @@
-use crate::cmd::RESP_OK;
+use crate::cmd::{RESP_OK, RESP_WRONGTYPE};
 use crate::{
     cmd::{extract_args, validate_command, CommandError, CommandExecutor, Get, Set},
-    Backend, RespArray, RespFrame, RespNull,
+    Backend, KeyType, RespArray, RespFrame, RespNull,
 };
 
 //===================  实现 CommandExecutor trait for Command
 impl CommandExecutor for Get {
     fn execute(self, backend: &Backend) -> RespFrame {
+        if backend.is_wrong_type(&self.key, KeyType::String) {
+            return RESP_WRONGTYPE.clone();
+        }
         backend.get(&self.key).unwrap_or(RespFrame::Null(RespNull))
     }
 }
 impl CommandExecutor for Set {
     fn execute(self, backend: &Backend) -> RespFrame {
-        backend.set(self.key.clone(), self.value.clone());
+        backend.set(self.key, self.value);
         RESP_OK.clone()
     }
 }
@@ -28,7 +31,7 @@ impl TryFrom<RespArray> for Get {
 
         match args.next() {
             Some(RespFrame::BulkString(key)) => Ok(Get {
-                key: String::from_utf8(key.0)?,
+                key: String::from_utf8(key.0.to_vec())?,
             }),
             _ => Err(CommandError::InvalidCommand("Invalid key".to_string())),
         }
@@ -43,7 +46,7 @@ impl TryFrom<RespArray> for Set {
         let mut args = args.into_iter();
         match (args.next(), args.next()) {
             (Some(RespFrame::BulkString(key)), Some(value)) => Ok(Set {
-                key: String::from_utf8(key.0)?,
+                key: String::from_utf8(key.0.to_vec())?,
                 value,
             }),
             _ => Err(CommandError::InvalidCommand(
@@ -58,7 +61,7 @@ mod tests {
     use anyhow::Result;
     use bytes::BytesMut;
 
-    use crate::{BulkString, RespDecode};
+    use crate::{network::testing::command, BulkString, RespDecode};
 
     use super::*;
 
@@ -108,4 +111,32 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_wrong_type() -> Result<()> {
+        let backend = Backend::new();
+        let run = |args: &[&str]| -> Result<RespFrame> { Ok(command(args)?.execute(&backend)) };
+        let wrong_type = RESP_WRONGTYPE.clone();
+
+        run(&["set", "str", "1"])?;
+        assert_eq!(run(&["geoadd", "str", "13.36", "38.11", "a"])?, wrong_type);
+        assert_eq!(run(&["hset", "str", "f", "v"])?, wrong_type);
+
+        run(&["geoadd", "geo", "13.36", "38.11", "a"])?;
+        assert_eq!(run(&["get", "geo"])?, wrong_type);
+        assert_eq!(run(&["setbit", "geo", "1", "1"])?, wrong_type);
+        assert_eq!(run(&["bitop", "and", "dest", "geo"])?, wrong_type);
+        assert_eq!(run(&["hget", "geo", "f"])?, wrong_type);
+        assert_eq!(run(&["pfadd", "geo", "a"])?, wrong_type);
+
+        run(&["hset", "hash", "f", "v"])?;
+        assert_eq!(run(&["geopos", "hash", "a"])?, wrong_type);
+
+        // SET 和 *STORE 覆盖其它类型的值
+        run(&["set", "geo", "1"])?;
+        assert_eq!(backend.key_type("geo"), Some(KeyType::String));
+        run(&["bitop", "not", "hash", "str"])?;
+        assert_eq!(backend.key_type("hash"), Some(KeyType::String));
+        Ok(())
+    }
 }
@@ -1,16 +1,36 @@
+use bytes::Bytes;
 use enum_dispatch::enum_dispatch;
 use lazy_static::lazy_static;
 use thiserror::Error;
 use tracing::info;
 
-use crate::{Backend, RespArray, RespError, RespFrame, SimpleString};
+use crate::cluster::MigrateOptions;
+use crate::{Backend, RespArray, RespError, RespFrame, SimpleError, SimpleString};
 
+mod bitmap;
+mod cluster;
+mod connection;
+mod geo;
 mod hmap;
+mod hyperloglog;
+mod keys;
 mod map;
+mod replication;
+mod sentinel;
+mod server;
+
+pub use bitmap::{BitFieldOp, BitFieldOverflow, BitFieldType, BitOpKind, BitRangeUnit};
+pub use cluster::ClusterOp;
+pub use connection::{server_info, AclOp, Handshake};
+pub use geo::{GeoFrom, GeoOrder, GeoQuery, GeoShape};
+pub use sentinel::SentinelOp;
 
 lazy_static! {
     ///  you can use `once_cell`  instead of using lazy_static
     static ref RESP_OK: RespFrame = RespFrame::SimpleString(SimpleString::new("OK".to_string()));
+    static ref RESP_WRONGTYPE: RespFrame = RespFrame::Error(SimpleError::new(
+        "WRONGTYPE Operation against a key holding the wrong kind of value".to_string()
+    ));
 }
 #[derive(Error, Debug)]
 pub enum CommandError {
@@ -38,6 +58,23 @@ pub trait CommandExecutor {
     fn execute(self, backend: &Backend) -> RespFrame;
 }
 
+/// 命令对一个 key 的访问方式, ACL 据此检查 key 的读写权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl KeyAccess {
+    pub fn reads(self) -> bool {
+        matches!(self, KeyAccess::Read | KeyAccess::ReadWrite)
+    }
+    pub fn writes(self) -> bool {
+        matches!(self, KeyAccess::Write | KeyAccess::ReadWrite)
+    }
+}
+
 #[derive(Debug)]
 #[enum_dispatch(CommandExecutor)]
 pub enum Command {
@@ -47,6 +84,60 @@ pub enum Command {
     HSet(HSet),
     HGetAll(HGetAll),
 
+    // bitmap
+    SetBit(SetBit),
+    GetBit(GetBit),
+    BitCount(BitCount),
+    BitPos(BitPos),
+    BitOp(BitOp),
+    BitField(BitField),
+
+    // hyperloglog
+    PfAdd(PfAdd),
+    PfCount(PfCount),
+    PfMerge(PfMerge),
+
+    // geo
+    GeoAdd(GeoAdd),
+    GeoDist(GeoDist),
+    GeoPos(GeoPos),
+    GeoHash(GeoHash),
+    GeoSearch(GeoSearch),
+    GeoSearchStore(GeoSearchStore),
+
+    // keyspace
+    Del(Del),
+    Dump(Dump),
+    Restore(Restore),
+    Migrate(Migrate),
+
+    // replication
+    ReplicaOf(ReplicaOf),
+    ReplConf(ReplConf),
+    Wait(Wait),
+    WaitAof(WaitAof),
+
+    // cluster
+    Cluster(Cluster),
+    Asking(Asking),
+
+    // sentinel
+    Sentinel(Sentinel),
+
+    // connection
+    Auth(Auth),
+    Quit(Quit),
+    Acl(Acl),
+    Hello(Hello),
+
+    // persistence
+    Save(Save),
+    BgSave(BgSave),
+    LastSave(LastSave),
+    BgRewriteAof(BgRewriteAof),
+    Info(Info),
+    Ping(Ping),
+
     // unrecognized command
     Unrecognized(Unrecognized),
 }
@@ -77,6 +168,182 @@ pub struct HGetAll {
     sort: bool,
 }
 #[derive(Debug)]
+pub struct SetBit {
+    key: String,
+    offset: u64,
+    value: bool,
+}
+#[derive(Debug)]
+pub struct GetBit {
+    key: String,
+    offset: u64,
+}
+#[derive(Debug)]
+pub struct BitCount {
+    key: String,
+    range: Option<(i64, i64, BitRangeUnit)>,
+}
+#[derive(Debug)]
+pub struct BitPos {
+    key: String,
+    bit: bool,
+    start: Option<i64>,
+    end: Option<i64>,
+    unit: BitRangeUnit,
+}
+#[derive(Debug)]
+pub struct BitOp {
+    op: BitOpKind,
+    dest: String,
+    keys: Vec<String>,
+}
+#[derive(Debug)]
+pub struct BitField {
+    key: String,
+    ops: Vec<BitFieldOp>,
+}
+#[derive(Debug)]
+pub struct PfAdd {
+    key: String,
+    elements: Vec<Bytes>,
+}
+#[derive(Debug)]
+pub struct PfCount {
+    keys: Vec<String>,
+}
+#[derive(Debug)]
+pub struct PfMerge {
+    dest: String,
+    sources: Vec<String>,
+}
+#[derive(Debug)]
+pub struct GeoAdd {
+    key: String,
+    nx: bool,
+    xx: bool,
+    ch: bool,
+    items: Vec<(f64, f64, String)>,
+}
+#[derive(Debug)]
+pub struct GeoDist {
+    key: String,
+    member1: String,
+    member2: String,
+    unit: f64,
+}
+#[derive(Debug)]
+pub struct GeoPos {
+    key: String,
+    members: Vec<String>,
+}
+#[derive(Debug)]
+pub struct GeoHash {
+    key: String,
+    members: Vec<String>,
+}
+#[derive(Debug)]
+pub struct GeoSearch {
+    key: String,
+    query: GeoQuery,
+}
+#[derive(Debug)]
+pub struct GeoSearchStore {
+    dest: String,
+    key: String,
+    query: GeoQuery,
+    store_dist: bool,
+}
+#[derive(Debug)]
+pub struct Del {
+    keys: Vec<String>,
+}
+#[derive(Debug)]
+pub struct Dump {
+    key: String,
+}
+#[derive(Debug)]
+pub struct Restore {
+    key: String,
+    // 毫秒, 0 表示不过期; ABSTTL 时为 unix 毫秒时间戳
+    ttl: i64,
+    payload: Vec<u8>,
+    replace: bool,
+    absttl: bool,
+    // RESTORE-ASKING: MIGRATE 发送到迁入中的节点
+    asking: bool,
+}
+#[derive(Debug)]
+pub struct Migrate {
+    host: String,
+    port: u16,
+    keys: Vec<String>,
+    options: MigrateOptions,
+}
+#[derive(Debug)]
+pub struct ReplicaOf {
+    master: Option<(String, u16)>,
+}
+#[derive(Debug)]
+pub struct ReplConf;
+#[derive(Debug)]
+pub struct Wait {
+    numreplicas: usize,
+    // 毫秒, 0 表示一直等待
+    timeout: u64,
+}
+#[derive(Debug)]
+pub struct WaitAof {
+    numlocal: usize,
+    numreplicas: usize,
+    timeout: u64,
+}
+#[derive(Debug)]
+pub struct Cluster {
+    op: ClusterOp,
+}
+#[derive(Debug)]
+pub struct Asking;
+#[derive(Debug)]
+pub struct Sentinel {
+    op: SentinelOp,
+}
+#[derive(Debug)]
+pub struct Auth {
+    username: Option<String>,
+    password: String,
+}
+#[derive(Debug)]
+pub struct Quit;
+#[derive(Debug)]
+pub struct Acl {
+    op: AclOp,
+}
+#[derive(Debug)]
+pub struct Hello {
+    protover: Option<i64>,
+    // AUTH <username> <password>
+    auth: Option<(String, String)>,
+    setname: Option<String>,
+}
+#[derive(Debug)]
+pub struct Save {
+    rdb: bool,
+}
+#[derive(Debug)]
+pub struct BgSave;
+#[derive(Debug)]
+pub struct LastSave;
+#[derive(Debug)]
+pub struct Info {
+    sections: Vec<String>,
+}
+#[derive(Debug)]
+pub struct BgRewriteAof;
+#[derive(Debug)]
+pub struct Ping {
+    message: Option<String>,
+}
+#[derive(Debug)]
 pub struct Unrecognized;
 
 impl TryFrom<RespFrame> for Command {
@@ -91,6 +358,198 @@ impl TryFrom<RespFrame> for Command {
     }
 }
 
+impl Command {
+    /// 执行前是否需要检查 ACL 权限. ACL 和 CLUSTER 属于 @admin 和 @dangerous,
+    /// 但只查看当前用户或集群拓扑的子命令不需要权限, 集群客户端连接后都会使用它们
+    pub fn needs_permission(&self) -> bool {
+        match self {
+            // 不认识的命令由执行时处理
+            Command::Auth(_) | Command::Hello(_) | Command::Quit(_) | Command::Unrecognized(_) => {
+                false
+            }
+            Command::Acl(acl) => !matches!(acl.op, AclOp::WhoAmI | AclOp::Cat(_)),
+            Command::Cluster(cluster) => !matches!(
+                cluster.op,
+                ClusterOp::MyId
+                    | ClusterOp::Info
+                    | ClusterOp::Nodes
+                    | ClusterOp::Slots
+                    | ClusterOp::Shards
+                    | ClusterOp::KeySlot(_)
+            ),
+            _ => true,
+        }
+    }
+
+    /// 是否会修改数据, 执行成功的写命令需要追加到 AOF
+    pub fn is_write(&self) -> bool {
+        // 不使用通配符, 新增命令时必须在这里归类
+        match self {
+            Command::Set(_)
+            | Command::HSet(_)
+            | Command::SetBit(_)
+            | Command::BitOp(_)
+            | Command::BitField(_)
+            | Command::PfAdd(_)
+            | Command::PfMerge(_)
+            | Command::GeoAdd(_)
+            | Command::GeoSearchStore(_)
+            | Command::Del(_)
+            | Command::Restore(_) => true,
+            // MIGRATE 通过 DEL 传播删除
+            Command::Get(_)
+            | Command::HGet(_)
+            | Command::HGetAll(_)
+            | Command::GetBit(_)
+            | Command::BitCount(_)
+            | Command::BitPos(_)
+            | Command::PfCount(_)
+            | Command::GeoDist(_)
+            | Command::GeoPos(_)
+            | Command::GeoHash(_)
+            | Command::GeoSearch(_)
+            | Command::Dump(_)
+            | Command::Migrate(_)
+            | Command::ReplicaOf(_)
+            | Command::ReplConf(_)
+            | Command::Wait(_)
+            | Command::WaitAof(_)
+            | Command::Cluster(_)
+            | Command::Asking(_)
+            | Command::Sentinel(_)
+            | Command::Auth(_)
+            | Command::Quit(_)
+            | Command::Acl(_)
+            | Command::Hello(_)
+            | Command::Save(_)
+            | Command::BgSave(_)
+            | Command::LastSave(_)
+            | Command::BgRewriteAof(_)
+            | Command::Info(_)
+            | Command::Ping(_)
+            | Command::Unrecognized(_) => false,
+        }
+    }
+}
+
+impl Command {
+    /// 命令访问的 key, 集群模式下据此计算 slot
+    pub fn keys(&self) -> Vec<&str> {
+        self.key_access().into_iter().map(|(key, _)| key).collect()
+    }
+
+    /// 命令访问的 key 及访问方式, 与 Redis 的 key spec 一致, ACL 据此检查每个 key
+    pub fn key_access(&self) -> Vec<(&str, KeyAccess)> {
+        use KeyAccess::{Read, ReadWrite, Write};
+        fn each(keys: &[String], access: KeyAccess) -> Vec<(&str, KeyAccess)> {
+            keys.iter().map(|k| (k.as_str(), access)).collect()
+        }
+        match self {
+            Command::Get(c) => vec![(&c.key, Read)],
+            Command::Set(c) => vec![(&c.key, Write)],
+            Command::HGet(c) => vec![(&c.key, Read)],
+            Command::HSet(c) => vec![(&c.key, Write)],
+            Command::HGetAll(c) => vec![(&c.key, Read)],
+            // SETBIT 返回原来的值
+            Command::SetBit(c) => vec![(&c.key, ReadWrite)],
+            Command::GetBit(c) => vec![(&c.key, Read)],
+            Command::BitCount(c) => vec![(&c.key, Read)],
+            Command::BitPos(c) => vec![(&c.key, Read)],
+            Command::BitOp(c) => [vec![(c.dest.as_str(), Write)], each(&c.keys, Read)].concat(),
+            Command::BitField(c) => vec![(&c.key, ReadWrite)],
+            Command::PfAdd(c) => vec![(&c.key, Write)],
+            // 只更新缓存的基数, 不需要写权限
+            Command::PfCount(c) => each(&c.keys, Read),
+            Command::PfMerge(c) => {
+                [vec![(c.dest.as_str(), ReadWrite)], each(&c.sources, Read)].concat()
+            }
+            Command::GeoAdd(c) => vec![(&c.key, Write)],
+            Command::GeoDist(c) => vec![(&c.key, Read)],
+            Command::GeoPos(c) => vec![(&c.key, Read)],
+            Command::GeoHash(c) => vec![(&c.key, Read)],
+            Command::GeoSearch(c) => vec![(&c.key, Read)],
+            Command::GeoSearchStore(c) => vec![(&c.dest, Write), (&c.key, Read)],
+            Command::Del(c) => each(&c.keys, Write),
+            Command::Dump(c) => vec![(&c.key, Read)],
+            Command::Restore(c) => vec![(&c.key, Write)],
+            Command::Migrate(c) => each(&c.keys, ReadWrite),
+            // 不访问 key 的命令; 新增命令时需要在这里决定它访问哪些 key
+            Command::ReplicaOf(_)
+            | Command::ReplConf(_)
+            | Command::Wait(_)
+            | Command::WaitAof(_)
+            | Command::Cluster(_)
+            | Command::Asking(_)
+            | Command::Sentinel(_)
+            | Command::Auth(_)
+            | Command::Quit(_)
+            | Command::Acl(_)
+            | Command::Hello(_)
+            | Command::Save(_)
+            | Command::BgSave(_)
+            | Command::LastSave(_)
+            | Command::BgRewriteAof(_)
+            | Command::Info(_)
+            | Command::Ping(_)
+            | Command::Unrecognized(_) => vec![],
+        }
+    }
+
+    /// 命令名, 与 ACL 规则中使用的名字相同
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Get(_) => "get",
+            Command::Set(_) => "set",
+            Command::HGet(_) => "hget",
+            Command::HSet(_) => "hset",
+            Command::HGetAll(_) => "hgetall",
+            Command::SetBit(_) => "setbit",
+            Command::GetBit(_) => "getbit",
+            Command::BitCount(_) => "bitcount",
+            Command::BitPos(_) => "bitpos",
+            Command::BitOp(_) => "bitop",
+            Command::BitField(_) => "bitfield",
+            Command::PfAdd(_) => "pfadd",
+            Command::PfCount(_) => "pfcount",
+            Command::PfMerge(_) => "pfmerge",
+            Command::GeoAdd(_) => "geoadd",
+            Command::GeoDist(_) => "geodist",
+            Command::GeoPos(_) => "geopos",
+            Command::GeoHash(_) => "geohash",
+            Command::GeoSearch(_) => "geosearch",
+            Command::GeoSearchStore(_) => "geosearchstore",
+            Command::Del(_) => "del",
+            Command::Dump(_) => "dump",
+            Command::Restore(c) if c.asking => "restore-asking",
+            Command::Restore(_) => "restore",
+            Command::Migrate(_) => "migrate",
+            Command::ReplicaOf(_) => "replicaof",
+            Command::ReplConf(_) => "replconf",
+            Command::Wait(_) => "wait",
+            Command::WaitAof(_) => "waitaof",
+            Command::Cluster(_) => "cluster",
+            Command::Asking(_) => "asking",
+            Command::Sentinel(_) => "sentinel",
+            Command::Auth(_) => "auth",
+            Command::Quit(_) => "quit",
+            Command::Acl(_) => "acl",
+            Command::Hello(_) => "hello",
+            Command::Save(_) => "save",
+            Command::BgSave(_) => "bgsave",
+            Command::LastSave(_) => "lastsave",
+            Command::BgRewriteAof(_) => "bgrewriteaof",
+            Command::Info(_) => "info",
+            Command::Ping(_) => "ping",
+            Command::Unrecognized(_) => "unknown",
+        }
+    }
+
+    /// RESTORE-ASKING 与 ASKING 之后的命令一样可以访问迁入中的 slot
+    pub fn is_asking(&self) -> bool {
+        matches!(self, Command::Restore(c) if c.asking)
+    }
+}
+
 impl CommandExecutor for Unrecognized {
     fn execute(self, _: &Backend) -> RespFrame {
         // RespFrame::Error(SimpleError::new("Unrecognized command".to_string()))
@@ -112,6 +571,42 @@ impl TryFrom<RespArray> for Command {
                     "hget" => Ok(HGet::try_from(v)?.into()),
                     "hset" => Ok(HSet::try_from(v)?.into()),
                     "hgetall" => Ok(HGetAll::try_from(v)?.into()),
+                    "setbit" => Ok(SetBit::try_from(v)?.into()),
+                    "getbit" => Ok(GetBit::try_from(v)?.into()),
+                    "bitcount" => Ok(BitCount::try_from(v)?.into()),
+                    "bitpos" => Ok(BitPos::try_from(v)?.into()),
+                    "bitop" => Ok(BitOp::try_from(v)?.into()),
+                    "bitfield" => Ok(BitField::try_from(v)?.into()),
+                    "pfadd" => Ok(PfAdd::try_from(v)?.into()),
+                    "pfcount" => Ok(PfCount::try_from(v)?.into()),
+                    "pfmerge" => Ok(PfMerge::try_from(v)?.into()),
+                    "geoadd" => Ok(GeoAdd::try_from(v)?.into()),
+                    "geodist" => Ok(GeoDist::try_from(v)?.into()),
+                    "geopos" => Ok(GeoPos::try_from(v)?.into()),
+                    "geohash" => Ok(GeoHash::try_from(v)?.into()),
+                    "geosearch" => Ok(GeoSearch::try_from(v)?.into()),
+                    "geosearchstore" => Ok(GeoSearchStore::try_from(v)?.into()),
+                    "del" => Ok(Del::try_from(v)?.into()),
+                    "dump" => Ok(Dump::try_from(v)?.into()),
+                    "restore" | "restore-asking" => Ok(Restore::try_from(v)?.into()),
+                    "migrate" => Ok(Migrate::try_from(v)?.into()),
+                    "replicaof" | "slaveof" => Ok(ReplicaOf::try_from(v)?.into()),
+                    "replconf" => Ok(ReplConf::try_from(v)?.into()),
+                    "wait" => Ok(Wait::try_from(v)?.into()),
+                    "waitaof" => Ok(WaitAof::try_from(v)?.into()),
+                    "cluster" => Ok(Cluster::try_from(v)?.into()),
+                    "asking" => Ok(Asking::try_from(v)?.into()),
+                    "sentinel" => Ok(Sentinel::try_from(v)?.into()),
+                    "auth" => Ok(Auth::try_from(v)?.into()),
+                    "quit" => Ok(Quit::try_from(v)?.into()),
+                    "acl" => Ok(Acl::try_from(v)?.into()),
+                    "hello" => Ok(Hello::try_from(v)?.into()),
+                    "save" => Ok(Save::try_from(v)?.into()),
+                    "bgsave" => Ok(BgSave::try_from(v)?.into()),
+                    "lastsave" => Ok(LastSave::try_from(v)?.into()),
+                    "info" => Ok(Info::try_from(v)?.into()),
+                    "bgrewriteaof" => Ok(BgRewriteAof::try_from(v)?.into()),
+                    "ping" => Ok(Ping::try_from(v)?.into()),
                     _ => Ok(Unrecognized.into()),
                 }
             }
@@ -136,6 +631,27 @@ fn validate_command(
             value.len()
         )));
     }
+    validate_command_name(value, names)
+}
+
+// 变长参数命令: 只校验参数个数下限
+fn validate_command_min(
+    value: &RespArray,
+    names: &[&'static str],
+    min_args: usize,
+) -> Result<(), CommandError> {
+    if value.len() < min_args + names.len() {
+        return Err(CommandError::InvalidCommandArguments(format!(
+            "{} command must have at least {} arguments, but got {}",
+            names.join(" "),
+            min_args + 1,
+            value.len()
+        )));
+    }
+    validate_command_name(value, names)
+}
+
+fn validate_command_name(value: &RespArray, names: &[&'static str]) -> Result<(), CommandError> {
     for (i, name) in names.iter().enumerate() {
         match value[i] {
             RespFrame::BulkString(ref cmd) => {
@@ -166,3 +682,33 @@ fn extract_args(value: RespArray, start: usize) -> Result<Vec<RespFrame>, Comman
         .cloned()
         .collect::<Vec<RespFrame>>())
 }
+
+// 参数转为 String
+fn parse_string(arg: Option<RespFrame>, what: &str) -> Result<String, CommandError> {
+    match arg {
+        Some(RespFrame::BulkString(s)) => Ok(String::from_utf8(s.0.to_vec())?),
+        _ => Err(CommandError::InvalidCommandArguments(format!(
+            "Invalid {}",
+            what
+        ))),
+    }
+}
+
+// 参数转为整数/浮点数等可 parse 的类型
+fn parse_num<T: std::str::FromStr>(arg: Option<RespFrame>, what: &str) -> Result<T, CommandError> {
+    match arg {
+        Some(RespFrame::BulkString(s)) => std::str::from_utf8(&s)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| {
+                CommandError::InvalidCommandArguments(format!(
+                    "{} is not a number or out of range",
+                    what
+                ))
+            }),
+        _ => Err(CommandError::InvalidCommandArguments(format!(
+            "Invalid {}",
+            what
+        ))),
+    }
+}
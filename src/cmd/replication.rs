@@ -0,0 +1,251 @@
+use std::time::Duration;
+
+use crate::cmd::{
+    extract_args, parse_num, parse_string, validate_command, validate_command_min, CommandError,
+    CommandExecutor, ReplConf, ReplicaOf, Wait, WaitAof, RESP_OK,
+};
+use crate::{Backend, RespArray, RespFrame, SimpleError, SimpleString};
+
+//===================  实现 CommandExecutor trait for Command
+impl CommandExecutor for ReplicaOf {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if backend.replicaof(self.master) {
+            SimpleString::new("OK Already connected to specified master").into()
+        } else {
+            RESP_OK.clone()
+        }
+    }
+}
+impl CommandExecutor for ReplConf {
+    fn execute(self, _: &Backend) -> RespFrame {
+        RESP_OK.clone()
+    }
+}
+// WAIT / WAITAOF 需要阻塞连接, 由 network 调用 wait; 同步执行时不等待, 直接返回当前的确认数
+impl CommandExecutor for Wait {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        match self.check(backend) {
+            Some(err) => err,
+            None => RespFrame::Integer(backend.acked_replicas(false) as i64),
+        }
+    }
+}
+impl CommandExecutor for WaitAof {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if let Some(err) = self.check(backend) {
+            return err;
+        }
+        let local = (backend.aof_enabled() && backend.aof_fsync()) as i64;
+        let replicas = backend.acked_replicas(true) as i64;
+        RespArray::new(vec![
+            RespFrame::Integer(local),
+            RespFrame::Integer(replicas),
+        ])
+        .into()
+    }
+}
+
+impl Wait {
+    /// 阻塞直到 numreplicas 个 replica 确认之前的写命令, 或者超时
+    pub async fn wait(self, backend: &Backend) -> RespFrame {
+        if let Some(err) = self.check(backend) {
+            return err;
+        }
+        let n = backend
+            .wait_replicas(self.numreplicas, timeout(self.timeout), false)
+            .await;
+        RespFrame::Integer(n as i64)
+    }
+
+    fn check(&self, backend: &Backend) -> Option<RespFrame> {
+        backend.is_replica().then(|| {
+            SimpleError::new("ERR WAIT cannot be used with replica instances. Please also note that since Redis 4.0 if a replica is configured to be writable (which is not the default) writes to replicas are just local and are not propagated.").into()
+        })
+    }
+}
+impl WaitAof {
+    /// 阻塞直到本地 AOF 和 numreplicas 个 replica 的 AOF 都已写盘, 或者超时
+    pub async fn wait(self, backend: &Backend) -> RespFrame {
+        if let Some(err) = self.check(backend) {
+            return err;
+        }
+        let local = if backend.aof_enabled() {
+            let cloned = backend.clone();
+            tokio::task::spawn_blocking(move || cloned.aof_fsync())
+                .await
+                .unwrap_or(false) as i64
+        } else {
+            0
+        };
+        let replicas = backend
+            .wait_replicas(self.numreplicas, timeout(self.timeout), true)
+            .await as i64;
+        RespArray::new(vec![
+            RespFrame::Integer(local),
+            RespFrame::Integer(replicas),
+        ])
+        .into()
+    }
+
+    fn check(&self, backend: &Backend) -> Option<RespFrame> {
+        if backend.is_replica() {
+            return Some(SimpleError::new("ERR WAITAOF cannot be used with replica instances. Please also note that writes to replicas are just local and are not propagated.").into());
+        }
+        if self.numlocal > 0 && !backend.aof_enabled() {
+            return Some(
+                SimpleError::new(
+                    "ERR WAITAOF cannot be used when numlocal is set but appendonly is disabled.",
+                )
+                .into(),
+            );
+        }
+        None
+    }
+}
+
+fn timeout(ms: u64) -> Option<Duration> {
+    (ms > 0).then(|| Duration::from_millis(ms))
+}
+
+//===================  实现 TryFrom trait for Command
+impl TryFrom<RespArray> for ReplicaOf {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        // REPLICAOF host port | REPLICAOF NO ONE, SLAVEOF 为旧的名称
+        let name = match value.first() {
+            Some(RespFrame::BulkString(s)) if s.eq_ignore_ascii_case(b"slaveof") => "slaveof",
+            _ => "replicaof",
+        };
+        validate_command(&value, &[name], 2)?;
+        let mut args = extract_args(value, 1)?.into_iter();
+        let host = parse_string(args.next(), "host")?;
+        let port = args.next();
+        if let Some(RespFrame::BulkString(s)) = &port {
+            if host.eq_ignore_ascii_case("no") && s.eq_ignore_ascii_case(b"one") {
+                return Ok(ReplicaOf { master: None });
+            }
+        }
+        let port: u16 = parse_num(port, "port")?;
+        Ok(ReplicaOf {
+            master: Some((host, port)),
+        })
+    }
+}
+impl TryFrom<RespArray> for ReplConf {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        // REPLCONF <option> <value> [<option> <value> ...]
+        validate_command_min(&value, &["replconf"], 2)?;
+        Ok(ReplConf)
+    }
+}
+impl TryFrom<RespArray> for Wait {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        // WAIT numreplicas timeout
+        validate_command(&value, &["wait"], 2)?;
+        let mut args = extract_args(value, 1)?.into_iter();
+        Ok(Wait {
+            numreplicas: parse_num(args.next(), "numreplicas")?,
+            timeout: parse_timeout(args.next())?,
+        })
+    }
+}
+impl TryFrom<RespArray> for WaitAof {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        // WAITAOF numlocal numreplicas timeout
+        validate_command(&value, &["waitaof"], 3)?;
+        let mut args = extract_args(value, 1)?.into_iter();
+        Ok(WaitAof {
+            numlocal: parse_num(args.next(), "numlocal")?,
+            numreplicas: parse_num(args.next(), "numreplicas")?,
+            timeout: parse_timeout(args.next())?,
+        })
+    }
+}
+
+fn parse_timeout(arg: Option<RespFrame>) -> Result<u64, CommandError> {
+    let timeout: i64 = parse_num(arg, "timeout")?;
+    u64::try_from(timeout)
+        .map_err(|_| CommandError::InvalidCommandArguments("timeout is negative".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use bytes::BytesMut;
+
+    use crate::RespDecode;
+
+    use super::*;
+
+    #[test]
+    fn test_replicaof_from_resp_array() -> Result<()> {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"*3\r\n$9\r\nREPLICAOF\r\n$9\r\n127.0.0.1\r\n$4\r\n6380\r\n");
+        let frame = RespArray::decode(&mut buf)?;
+        let cmd: ReplicaOf = frame.try_into()?;
+        assert_eq!(cmd.master, Some(("127.0.0.1".to_string(), 6380)));
+
+        buf.extend_from_slice(b"*3\r\n$7\r\nslaveof\r\n$2\r\nno\r\n$3\r\none\r\n");
+        let frame = RespArray::decode(&mut buf)?;
+        let cmd: ReplicaOf = frame.try_into()?;
+        assert_eq!(cmd.master, None);
+
+        buf.extend_from_slice(b"*3\r\n$9\r\nreplicaof\r\n$4\r\nhost\r\n$4\r\nport\r\n");
+        let frame = RespArray::decode(&mut buf)?;
+        assert!(ReplicaOf::try_from(frame).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_from_resp_array() -> Result<()> {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"*3\r\n$4\r\nWAIT\r\n$1\r\n2\r\n$3\r\n100\r\n");
+        let frame = RespArray::decode(&mut buf)?;
+        let cmd: Wait = frame.try_into()?;
+        assert_eq!((cmd.numreplicas, cmd.timeout), (2, 100));
+
+        buf.extend_from_slice(b"*3\r\n$4\r\nWAIT\r\n$1\r\n2\r\n$2\r\n-1\r\n");
+        let frame = RespArray::decode(&mut buf)?;
+        assert!(Wait::try_from(frame).is_err());
+
+        buf.extend_from_slice(b"*4\r\n$7\r\nWAITAOF\r\n$1\r\n1\r\n$1\r\n0\r\n$1\r\n0\r\n");
+        let frame = RespArray::decode(&mut buf)?;
+        let cmd: WaitAof = frame.try_into()?;
+        assert_eq!((cmd.numlocal, cmd.numreplicas, cmd.timeout), (1, 0, 0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wait_without_replicas() {
+        let backend = Backend::new();
+        let wait = Wait {
+            numreplicas: 1,
+            timeout: 20,
+        };
+        assert_eq!(wait.wait(&backend).await, RespFrame::Integer(0));
+
+        // 没有开启 AOF 时不能等待本地写盘
+        let wait = WaitAof {
+            numlocal: 1,
+            numreplicas: 0,
+            timeout: 0,
+        };
+        assert!(matches!(wait.wait(&backend).await, RespFrame::Error(_)));
+        let wait = WaitAof {
+            numlocal: 0,
+            numreplicas: 0,
+            timeout: 0,
+        };
+        assert_eq!(
+            wait.wait(&backend).await,
+            RespArray::new(vec![RespFrame::Integer(0), RespFrame::Integer(0)]).into()
+        );
+    }
+}
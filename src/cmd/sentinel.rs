@@ -0,0 +1,226 @@
+use crate::cmd::{
+    extract_args, parse_num, parse_string, validate_command_min, CommandError, CommandExecutor,
+    Sentinel, RESP_OK,
+};
+use crate::sentinel::Hello;
+use crate::{Backend, BulkString, RespArray, RespFrame, RespNull, SimpleError};
+
+/// SENTINEL 的子命令
+#[derive(Debug, PartialEq)]
+pub enum SentinelOp {
+    MyId,
+    Masters,
+    Master(String),
+    Replicas(String),
+    Sentinels(String),
+    GetMasterAddrByName(String),
+    // ip, port, current-epoch, runid
+    IsMasterDownByAddr(String, u16, u64, String),
+    Hello(Hello),
+    Failover(String),
+}
+
+//===================  实现 CommandExecutor trait for Command
+impl CommandExecutor for Sentinel {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if !backend.config().sentinel {
+            return SimpleError::new("ERR unknown command 'sentinel'").into();
+        }
+        let ret = match self.op {
+            SentinelOp::MyId => Ok(BulkString::new(backend.sentinel_myid()).into()),
+            SentinelOp::Masters => Ok(backend.sentinel_masters()),
+            SentinelOp::Master(name) => backend.sentinel_master_info(&name),
+            SentinelOp::Replicas(name) => backend.sentinel_replicas(&name),
+            SentinelOp::Sentinels(name) => backend.sentinel_sentinels(&name),
+            SentinelOp::GetMasterAddrByName(name) => {
+                Ok(match backend.sentinel_master_addr(&name) {
+                    Some((host, port)) => RespArray::new(vec![
+                        BulkString::new(host).into(),
+                        BulkString::new(port.to_string()).into(),
+                    ])
+                    .into(),
+                    None => RespNull.into(),
+                })
+            }
+            SentinelOp::IsMasterDownByAddr(host, port, epoch, runid) => {
+                Ok(backend.sentinel_is_master_down(&host, port, epoch, &runid))
+            }
+            SentinelOp::Hello(hello) => {
+                backend.sentinel_hello(hello);
+                Ok(RESP_OK.clone())
+            }
+            SentinelOp::Failover(name) => backend.sentinel_failover(&name).map(|_| RESP_OK.clone()),
+        };
+        match ret {
+            Ok(frame) => frame,
+            Err(e) => {
+                // 错误信息以错误码开头 (INPROG, NOGOODSLAVE) 时原样返回
+                let msg = e.to_string();
+                let code = msg.split(' ').next().unwrap_or_default();
+                if code.len() > 1 && code.bytes().all(|c| c.is_ascii_uppercase()) {
+                    SimpleError::new(msg).into()
+                } else {
+                    SimpleError::new(format!("ERR {}", msg)).into()
+                }
+            }
+        }
+    }
+}
+//===================  实现 TryFrom trait for Command
+impl TryFrom<RespArray> for Sentinel {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command_min(&value, &["sentinel"], 1)?;
+        let sub = match &value[1] {
+            RespFrame::BulkString(s) => String::from_utf8_lossy(s).to_ascii_lowercase(),
+            _ => return Err(invalid("subcommand")),
+        };
+        let n_args = value.len() - 2;
+        let mut args = extract_args(value, 2)?.into_iter();
+        let mut name = || parse_string(args.next(), "master name");
+        let op = match (sub.as_str(), n_args) {
+            ("myid", 0) => SentinelOp::MyId,
+            ("masters", 0) => SentinelOp::Masters,
+            ("master", 1) => SentinelOp::Master(name()?),
+            ("replicas" | "slaves", 1) => SentinelOp::Replicas(name()?),
+            ("sentinels", 1) => SentinelOp::Sentinels(name()?),
+            ("get-master-addr-by-name", 1) => SentinelOp::GetMasterAddrByName(name()?),
+            ("failover", 1) => SentinelOp::Failover(name()?),
+            // IS-MASTER-DOWN-BY-ADDR <ip> <port> <current-epoch> <runid>
+            ("is-master-down-by-addr", 4) => SentinelOp::IsMasterDownByAddr(
+                parse_string(args.next(), "ip")?,
+                parse_num(args.next(), "port")?,
+                parse_num(args.next(), "current epoch")?,
+                parse_string(args.next(), "runid")?,
+            ),
+            // HELLO <ip> <port> <runid> <current-epoch> <master-name> <master-ip> <master-port> <master-config-epoch>
+            ("hello", 8) => SentinelOp::Hello(Hello {
+                ip: parse_string(args.next(), "ip")?,
+                port: parse_num(args.next(), "port")?,
+                runid: parse_string(args.next(), "runid")?,
+                current_epoch: parse_num(args.next(), "current epoch")?,
+                master_name: parse_string(args.next(), "master name")?,
+                master_ip: parse_string(args.next(), "master ip")?,
+                master_port: parse_num(args.next(), "master port")?,
+                master_config_epoch: parse_num(args.next(), "config epoch")?,
+            }),
+            _ => {
+                return Err(CommandError::InvalidCommandArguments(format!(
+                    "Unknown sentinel subcommand or wrong number of arguments for '{}'",
+                    sub
+                )))
+            }
+        };
+        Ok(Sentinel { op })
+    }
+}
+fn invalid(what: &str) -> CommandError {
+    CommandError::InvalidCommandArguments(format!("Invalid {}", what))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use bytes::BytesMut;
+
+    use super::*;
+    use crate::{RespDecode, SentinelMonitor, ServerConfig};
+
+    fn decode(cmd: &[u8]) -> Result<RespArray> {
+        let mut buf = BytesMut::from(cmd);
+        Ok(RespArray::decode(&mut buf)?)
+    }
+
+    #[test]
+    fn test_sentinel_from_resp_array() -> Result<()> {
+        let frame = decode(
+            b"*3\r\n$8\r\nSENTINEL\r\n$23\r\nget-master-addr-by-name\r\n$8\r\nmymaster\r\n",
+        )?;
+        let cmd: Sentinel = frame.try_into()?;
+        assert_eq!(
+            cmd.op,
+            SentinelOp::GetMasterAddrByName("mymaster".to_string())
+        );
+
+        let frame = decode(b"*6\r\n$8\r\nsentinel\r\n$22\r\nis-master-down-by-addr\r\n$9\r\n127.0.0.1\r\n$4\r\n6379\r\n$1\r\n3\r\n$1\r\n*\r\n")?;
+        let cmd: Sentinel = frame.try_into()?;
+        assert_eq!(
+            cmd.op,
+            SentinelOp::IsMasterDownByAddr("127.0.0.1".to_string(), 6379, 3, "*".to_string())
+        );
+
+        let frame = decode(b"*2\r\n$8\r\nsentinel\r\n$6\r\nmaster\r\n")?;
+        assert!(Sentinel::try_from(frame).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sentinel_execute() -> Result<()> {
+        let config = ServerConfig {
+            sentinel: true,
+            sentinel_monitors: vec![SentinelMonitor {
+                name: "mymaster".to_string(),
+                host: "127.0.0.1".to_string(),
+                port: 6379,
+                quorum: 2,
+                down_after: 30000,
+                failover_timeout: 180000,
+                known_sentinels: vec![],
+            }],
+            ..Default::default()
+        };
+        let backend = Backend::with_config(config);
+        let addr = Sentinel {
+            op: SentinelOp::GetMasterAddrByName("mymaster".to_string()),
+        }
+        .execute(&backend);
+        assert_eq!(
+            addr,
+            RespArray::new(vec![
+                BulkString::new("127.0.0.1").into(),
+                BulkString::new("6379").into()
+            ])
+            .into()
+        );
+        let unknown = Sentinel {
+            op: SentinelOp::GetMasterAddrByName("other".to_string()),
+        }
+        .execute(&backend);
+        assert_eq!(unknown, RespNull.into());
+
+        // 每个 epoch 只投一次票
+        let vote = |runid: &str, epoch: u64| {
+            Sentinel {
+                op: SentinelOp::IsMasterDownByAddr(
+                    "127.0.0.1".to_string(),
+                    6379,
+                    epoch,
+                    runid.to_string(),
+                ),
+            }
+            .execute(&backend)
+        };
+        let leader = |reply: RespFrame| match reply {
+            RespFrame::Array(a) => a[1].clone(),
+            _ => panic!("unexpected reply"),
+        };
+        assert_eq!(leader(vote("a", 1)), BulkString::new("a").into());
+        assert_eq!(leader(vote("b", 1)), BulkString::new("a").into());
+        assert_eq!(leader(vote("b", 2)), BulkString::new("b").into());
+
+        // 没有可以提升的 replica
+        let reply = Sentinel {
+            op: SentinelOp::Failover("mymaster".to_string()),
+        }
+        .execute(&backend);
+        assert!(matches!(reply, RespFrame::Error(e) if e.starts_with("NOGOODSLAVE")));
+
+        let reply = Sentinel {
+            op: SentinelOp::Master("other".to_string()),
+        }
+        .execute(&backend);
+        assert!(matches!(reply, RespFrame::Error(e) if e.starts_with("ERR")));
+        Ok(())
+    }
+}
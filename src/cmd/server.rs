@@ -0,0 +1,210 @@
+use crate::cmd::{
+    extract_args, parse_string, validate_command, validate_command_min, BgRewriteAof, BgSave,
+    CommandError, CommandExecutor, Info, LastSave, Ping, Save, RESP_OK,
+};
+use crate::{Backend, BulkString, RespArray, RespFrame, SimpleError, SimpleString, VerbatimString};
+
+//===================  实现 CommandExecutor trait for Command
+impl CommandExecutor for Save {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        // SAVE RDB: 导出为 Redis 的 RDB 格式
+        let ret = if self.rdb {
+            backend.save_rdb().map(|_| ())
+        } else {
+            backend.save()
+        };
+        match ret {
+            Ok(_) => RESP_OK.clone(),
+            Err(e) => SimpleError::new(format!("ERR {}", e)).into(),
+        }
+    }
+}
+impl CommandExecutor for BgSave {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        match backend.bgsave() {
+            Ok(_) => SimpleString::new("Background saving started").into(),
+            Err(e) => SimpleError::new(format!("ERR {}", e)).into(),
+        }
+    }
+}
+impl CommandExecutor for LastSave {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        RespFrame::Integer(backend.lastsave())
+    }
+}
+impl CommandExecutor for BgRewriteAof {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        match backend.bgrewriteaof() {
+            Ok(_) => SimpleString::new("Background append only file rewriting started").into(),
+            Err(e) => SimpleError::new(format!("ERR {}", e)).into(),
+        }
+    }
+}
+impl CommandExecutor for Info {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        // 不认识的 section 返回空内容
+        let all = self.sections.is_empty()
+            || self
+                .sections
+                .iter()
+                .any(|s| matches!(s.as_str(), "all" | "default" | "everything"));
+        let mut sections = Vec::new();
+        // sentinel 不保存数据, 只有 sentinel section
+        if backend.config().sentinel {
+            if all || self.sections.iter().any(|s| s == "sentinel") {
+                sections.push(backend.info_sentinel());
+            }
+            return VerbatimString::text(sections.join("\r\n")).into();
+        }
+        if all || self.sections.iter().any(|s| s == "replication") {
+            sections.push(backend.info_replication());
+        }
+        if all || self.sections.iter().any(|s| s == "cluster") {
+            sections.push(backend.info_cluster());
+        }
+        // RESP2 客户端收到的是 bulk string
+        VerbatimString::text(sections.join("\r\n")).into()
+    }
+}
+impl CommandExecutor for Ping {
+    fn execute(self, _: &Backend) -> RespFrame {
+        match self.message {
+            Some(message) => BulkString::new(message).into(),
+            None => SimpleString::new("PONG").into(),
+        }
+    }
+}
+
+//===================  实现 TryFrom trait for Command
+impl TryFrom<RespArray> for Save {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        // SAVE [RDB]
+        if value.len() == 1 {
+            return Ok(Save { rdb: false });
+        }
+        validate_command(&value, &["save"], 1)?;
+        match extract_args(value, 1)?.pop() {
+            Some(RespFrame::BulkString(s)) if s.eq_ignore_ascii_case(b"rdb") => {
+                Ok(Save { rdb: true })
+            }
+            _ => Err(CommandError::InvalidCommandArguments(
+                "SAVE only accepts the RDB option".to_string(),
+            )),
+        }
+    }
+}
+impl TryFrom<RespArray> for BgSave {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        // BGSAVE [SCHEDULE]: 不支持排队, 忽略 SCHEDULE 参数
+        if value.len() != 2 {
+            validate_command(&value, &["bgsave"], 0)?;
+        }
+        Ok(BgSave)
+    }
+}
+impl TryFrom<RespArray> for LastSave {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["lastsave"], 0)?;
+        Ok(LastSave)
+    }
+}
+impl TryFrom<RespArray> for Info {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        // INFO [section [section ...]]
+        validate_command_min(&value, &["info"], 0)?;
+        let sections = extract_args(value, 1)?
+            .into_iter()
+            .map(|s| parse_string(Some(s), "section").map(|s| s.to_ascii_lowercase()))
+            .collect::<Result<_, _>>()?;
+        Ok(Info { sections })
+    }
+}
+impl TryFrom<RespArray> for BgRewriteAof {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["bgrewriteaof"], 0)?;
+        Ok(BgRewriteAof)
+    }
+}
+impl TryFrom<RespArray> for Ping {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        // PING [message]
+        validate_command_min(&value, &["ping"], 0)?;
+        if value.len() > 2 {
+            return Err(CommandError::InvalidCommandArgumentsLength(value.len()));
+        }
+        let message = extract_args(value, 1)?
+            .pop()
+            .map(|m| parse_string(Some(m), "message"))
+            .transpose()?;
+        Ok(Ping { message })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use bytes::BytesMut;
+
+    use crate::RespDecode;
+
+    use super::*;
+
+    #[test]
+    fn test_save_from_resp_array() -> Result<()> {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"*1\r\n$4\r\nSAVE\r\n");
+        let frame = RespArray::decode(&mut buf)?;
+        let _: Save = frame.try_into()?;
+
+        buf.extend_from_slice(b"*2\r\n$4\r\nSAVE\r\n$3\r\nrdb\r\n");
+        let frame = RespArray::decode(&mut buf)?;
+        let save: Save = frame.try_into()?;
+        assert!(save.rdb);
+
+        buf.extend_from_slice(b"*2\r\n$4\r\nSAVE\r\n$3\r\nAOF\r\n");
+        let frame = RespArray::decode(&mut buf)?;
+        assert!(Save::try_from(frame).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lastsave_execute() {
+        let backend = Backend::new();
+        let RespFrame::Integer(t) = LastSave.execute(&backend) else {
+            panic!("LASTSAVE should return an integer");
+        };
+        assert!(t > 0);
+    }
+
+    #[test]
+    fn test_info_execute() {
+        let backend = Backend::new();
+        let info = Info {
+            sections: vec!["replication".to_string()],
+        };
+        let RespFrame::VerbatimString(s) = info.execute(&backend) else {
+            panic!("INFO should return a verbatim string");
+        };
+        assert_eq!(s.format(), b"txt");
+        let s = String::from_utf8_lossy(s.data());
+        assert!(s.starts_with("# Replication\r\nrole:master\r\nconnected_slaves:0\r\n"));
+        assert!(s.contains("master_repl_offset:0\r\n"));
+
+        let info = Info {
+            sections: vec!["keyspace".to_string()],
+        };
+        assert_eq!(info.execute(&backend), VerbatimString::text("").into());
+    }
+}
@@ -0,0 +1,366 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Result};
+
+/// 服务端配置, 通过命令行参数 `--<name> <value>` 设置, 参数名与 redis.conf 保持一致
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    pub bind: String,
+    pub port: u16,
+    /// 持久化文件所在目录
+    pub dir: PathBuf,
+    pub dbfilename: String,
+    /// `save <seconds> <changes>`: 距上次保存超过 seconds 秒且至少有 changes 次修改时触发 BGSAVE
+    pub save: Vec<(u64, u64)>,
+    pub appendonly: bool,
+    pub appendfilename: String,
+    pub appendfsync: AppendFsync,
+    /// 启动时作为 replica 连接的 master
+    pub replicaof: Option<(String, u16)>,
+    pub replica_read_only: bool,
+    /// replication backlog 的大小, replica 断线后只要缺少的数据仍在 backlog 中就可以部分重同步
+    pub repl_backlog_size: usize,
+    pub cluster_enabled: bool,
+    /// 集群节点配置文件, 位于 dir 中, 格式与 CLUSTER NODES 的输出相同
+    pub cluster_config_file: String,
+    /// 节点超过该毫秒数没有回复 PING 时被认为 PFAIL
+    pub cluster_node_timeout: u64,
+    /// sentinel 模式: 不存储数据, 只监控 master 和它的 replica, 并在 master 故障时切换
+    pub sentinel: bool,
+    pub sentinel_monitors: Vec<SentinelMonitor>,
+    /// 其他 sentinel 需要认证时, 连接它们使用的密码
+    pub sentinel_pass: Option<String>,
+    /// 客户端需要先通过 AUTH 认证
+    pub requirepass: Option<String>,
+    /// 作为 replica 连接需要认证的 master 时使用的密码
+    pub masterauth: Option<String>,
+    /// 没有设置密码时只接受来自 loopback 地址的连接
+    pub protected_mode: bool,
+    /// ACL 用户的配置文件, 格式与 ACL LIST 的输出相同
+    pub aclfile: Option<PathBuf>,
+}
+
+/// `sentinel monitor <name> <ip> <port> <quorum>` 以及该 master 的其他 sentinel 配置
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentinelMonitor {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    /// 认定 master 客观下线所需的 sentinel 数量
+    pub quorum: usize,
+    /// 超过该毫秒数没有有效回复时认为实例主观下线
+    pub down_after: u64,
+    pub failover_timeout: u64,
+    /// 监控同一个 master 的其他 sentinel
+    pub known_sentinels: Vec<(String, u16)>,
+}
+
+/// AOF 的 fsync 策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppendFsync {
+    /// 每个写命令都 fsync
+    Always,
+    /// 每秒 fsync 一次
+    EverySec,
+    /// 由操作系统决定何时写盘
+    No,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: "0.0.0.0".to_string(),
+            port: 6379,
+            dir: PathBuf::from("."),
+            dbfilename: "dump.srdb".to_string(),
+            save: vec![(3600, 1), (300, 100), (60, 10000)],
+            appendonly: false,
+            appendfilename: "appendonly.aof".to_string(),
+            appendfsync: AppendFsync::EverySec,
+            replicaof: None,
+            replica_read_only: true,
+            repl_backlog_size: 1024 * 1024,
+            cluster_enabled: false,
+            cluster_config_file: "nodes.conf".to_string(),
+            cluster_node_timeout: 15000,
+            sentinel: false,
+            sentinel_monitors: Vec::new(),
+            sentinel_pass: None,
+            requirepass: None,
+            masterauth: None,
+            protected_mode: true,
+            aclfile: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut config = ServerConfig::default();
+        let mut port_set = false;
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                bail!("invalid argument: {}", arg);
+            };
+            // --sentinel 开启 sentinel 模式, 之后到下一个 --<name> 之前的参数为一条 sentinel 配置,
+            // 例如 --sentinel monitor mymaster 127.0.0.1 6379 2
+            if name.eq_ignore_ascii_case("sentinel") {
+                config.sentinel = true;
+                let mut directive = Vec::new();
+                while let Some(value) = args.next_if(|a| !a.starts_with("--")) {
+                    directive.push(value);
+                }
+                if !directive.is_empty() {
+                    config.set_sentinel(&directive.join(" "))?;
+                }
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| anyhow!("missing value for argument: {}", arg))?;
+            port_set |= name.eq_ignore_ascii_case("port");
+            config.set(name, &value)?;
+        }
+        // 与 Redis 一样, sentinel 默认使用 26379 端口
+        if config.sentinel && !port_set {
+            config.port = 26379;
+        }
+        Ok(config)
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        match name.to_ascii_lowercase().as_str() {
+            "bind" => self.bind = value.to_string(),
+            "port" => self.port = value.parse()?,
+            "dir" => self.dir = PathBuf::from(value),
+            "dbfilename" => self.dbfilename = value.to_string(),
+            "save" => self.save = parse_save_rules(value)?,
+            "appendonly" => self.appendonly = parse_bool(value)?,
+            "appendfilename" => self.appendfilename = value.to_string(),
+            "appendfsync" => {
+                self.appendfsync = match value.to_ascii_lowercase().as_str() {
+                    "always" => AppendFsync::Always,
+                    "everysec" => AppendFsync::EverySec,
+                    "no" => AppendFsync::No,
+                    _ => bail!("invalid appendfsync: {}", value),
+                }
+            }
+            "replicaof" => self.replicaof = parse_replicaof(value)?,
+            "replica-read-only" => self.replica_read_only = parse_bool(value)?,
+            "repl-backlog-size" => self.repl_backlog_size = parse_memory(value)?,
+            "cluster-enabled" => self.cluster_enabled = parse_bool(value)?,
+            "cluster-config-file" => self.cluster_config_file = value.to_string(),
+            "cluster-node-timeout" => self.cluster_node_timeout = value.parse()?,
+            // 空字符串表示不需要密码
+            "requirepass" => self.requirepass = Some(value.to_string()).filter(|p| !p.is_empty()),
+            "masterauth" => self.masterauth = Some(value.to_string()).filter(|p| !p.is_empty()),
+            "protected-mode" => self.protected_mode = parse_bool(value)?,
+            "aclfile" => self.aclfile = (!value.is_empty()).then(|| PathBuf::from(value)),
+            _ => bail!("unknown config: {}", name),
+        }
+        Ok(())
+    }
+
+    /// sentinel 配置: monitor / down-after-milliseconds / failover-timeout / known-sentinel / sentinel-pass
+    pub fn set_sentinel(&mut self, directive: &str) -> Result<()> {
+        let parts = directive.split_whitespace().collect::<Vec<_>>();
+        if let ["sentinel-pass", password] = parts.as_slice() {
+            self.sentinel_pass = Some(password.to_string());
+            return Ok(());
+        }
+        if let ["monitor", name, host, port, quorum] = parts.as_slice() {
+            let quorum = quorum.parse()?;
+            if quorum == 0 {
+                bail!("quorum must be 1 or greater");
+            }
+            self.sentinel_monitors.push(SentinelMonitor {
+                name: name.to_string(),
+                host: host.to_string(),
+                port: port.parse()?,
+                quorum,
+                down_after: 30000,
+                failover_timeout: 180000,
+                known_sentinels: Vec::new(),
+            });
+            return Ok(());
+        }
+        let name = parts.get(1).copied().unwrap_or_default();
+        let monitor = self
+            .sentinel_monitors
+            .iter_mut()
+            .find(|m| m.name == name)
+            .ok_or_else(|| anyhow!("no such master with specified name: {}", name))?;
+        match parts.as_slice() {
+            ["down-after-milliseconds", _, ms] => monitor.down_after = ms.parse()?,
+            ["failover-timeout", _, ms] => monitor.failover_timeout = ms.parse()?,
+            // 与 Redis 的配置兼容, 可以带有 sentinel 的 run ID
+            ["known-sentinel", _, ip, port, ..] => monitor
+                .known_sentinels
+                .push((ip.to_string(), port.parse()?)),
+            _ => bail!("invalid sentinel config: {}", directive),
+        }
+        Ok(())
+    }
+
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.bind, self.port)
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.dir.join(&self.dbfilename)
+    }
+
+    pub fn aof_path(&self) -> PathBuf {
+        self.dir.join(&self.appendfilename)
+    }
+
+    pub fn cluster_config_path(&self) -> PathBuf {
+        self.dir.join(&self.cluster_config_file)
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => bail!("argument must be 'yes' or 'no': {}", value),
+    }
+}
+
+// "1mb", "64kb", "1024" 等, 与 redis.conf 一样 k/m/g 为 1000 的倍数, kb/mb/gb 为 1024 的倍数
+fn parse_memory(value: &str) -> Result<usize> {
+    let lower = value.to_ascii_lowercase();
+    let split = lower
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(lower.len());
+    let (num, unit) = lower.split_at(split);
+    let unit = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => bail!("invalid memory size: {}", value),
+    };
+    let num: usize = num.parse()?;
+    num.checked_mul(unit)
+        .ok_or_else(|| anyhow!("invalid memory size: {}", value))
+}
+
+// "host port" 或 "no one"
+fn parse_replicaof(value: &str) -> Result<Option<(String, u16)>> {
+    let parts = value.split_whitespace().collect::<Vec<_>>();
+    match parts.as_slice() {
+        [no, one] if no.eq_ignore_ascii_case("no") && one.eq_ignore_ascii_case("one") => Ok(None),
+        [host, port] => Ok(Some((host.to_string(), port.parse()?))),
+        _ => bail!("invalid replicaof: {}", value),
+    }
+}
+
+// "3600 1 300 100" => [(3600, 1), (300, 100)]; 空字符串表示关闭自动保存
+fn parse_save_rules(value: &str) -> Result<Vec<(u64, u64)>> {
+    let nums = value
+        .split_whitespace()
+        .map(|s| s.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()?;
+    if nums.len() % 2 != 0 {
+        bail!("invalid save rules: {}", value);
+    }
+    Ok(nums.chunks(2).map(|c| (c[0], c[1])).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_from_args() -> Result<()> {
+        let args = ["--port", "6380", "--save", "60 1 10 100", "--dir", "/tmp"];
+        let config = ServerConfig::from_args(args.iter().map(|s| s.to_string()))?;
+        assert_eq!(config.addr(), "0.0.0.0:6380");
+        assert_eq!(config.save, vec![(60, 1), (10, 100)]);
+        assert_eq!(config.db_path(), PathBuf::from("/tmp/dump.srdb"));
+
+        let config = ServerConfig::from_args(["--save".to_string(), "".to_string()])?;
+        assert!(config.save.is_empty());
+
+        let args = ["--appendonly", "yes", "--appendfsync", "always"];
+        let config = ServerConfig::from_args(args.iter().map(|s| s.to_string()))?;
+        assert!(config.appendonly);
+        assert_eq!(config.appendfsync, AppendFsync::Always);
+        assert_eq!(config.aof_path(), PathBuf::from("./appendonly.aof"));
+
+        let args = ["--replicaof", "127.0.0.1 6380", "--replica-read-only", "no"];
+        let config = ServerConfig::from_args(args.iter().map(|s| s.to_string()))?;
+        assert_eq!(config.replicaof, Some(("127.0.0.1".to_string(), 6380)));
+        assert!(!config.replica_read_only);
+        assert_eq!(config.repl_backlog_size, 1024 * 1024);
+
+        let config = ServerConfig::from_args(["--repl-backlog-size", "64kb"].map(String::from))?;
+        assert_eq!(config.repl_backlog_size, 64 * 1024);
+        assert_eq!(parse_memory("2m")?, 2_000_000);
+
+        let args = ["--requirepass", "secret", "--protected-mode", "no"];
+        let config = ServerConfig::from_args(args.iter().map(|s| s.to_string()))?;
+        assert_eq!(config.requirepass, Some("secret".to_string()));
+        assert!(!config.protected_mode);
+        let config = ServerConfig::from_args(["--requirepass", ""].map(String::from))?;
+        assert_eq!(config.requirepass, None);
+        assert!(config.protected_mode);
+        let config = ServerConfig::from_args(["--aclfile", "/etc/users.acl"].map(String::from))?;
+        assert_eq!(config.aclfile, Some(PathBuf::from("/etc/users.acl")));
+
+        let config = ServerConfig::from_args(["--cluster-enabled", "yes"].map(String::from))?;
+        assert!(config.cluster_enabled);
+        assert_eq!(config.cluster_config_path(), PathBuf::from("./nodes.conf"));
+        assert_eq!(config.cluster_node_timeout, 15000);
+        let config = ServerConfig::from_args(["--cluster-node-timeout", "500"].map(String::from))?;
+        assert_eq!(config.cluster_node_timeout, 500);
+        assert!(parse_memory("1tb").is_err());
+
+        let args = [
+            "--sentinel",
+            "monitor",
+            "mymaster",
+            "127.0.0.1",
+            "6380",
+            "2",
+            "--sentinel",
+            "down-after-milliseconds mymaster 5000",
+            "--sentinel",
+            "known-sentinel",
+            "mymaster",
+            "127.0.0.1",
+            "26380",
+        ];
+        let config = ServerConfig::from_args(args.map(String::from))?;
+        assert!(config.sentinel);
+        assert_eq!(config.port, 26379);
+        assert_eq!(
+            config.sentinel_monitors,
+            vec![SentinelMonitor {
+                name: "mymaster".to_string(),
+                host: "127.0.0.1".to_string(),
+                port: 6380,
+                quorum: 2,
+                down_after: 5000,
+                failover_timeout: 180000,
+                known_sentinels: vec![("127.0.0.1".to_string(), 26380)],
+            }]
+        );
+        let args = ["--sentinel", "sentinel-pass", "secret"];
+        let config = ServerConfig::from_args(args.map(String::from))?;
+        assert_eq!(config.sentinel_pass, Some("secret".to_string()));
+        let config = ServerConfig::from_args(["--port", "26380", "--sentinel"].map(String::from))?;
+        assert_eq!((config.sentinel, config.port), (true, 26380));
+        let args = ["--sentinel", "failover-timeout", "other", "1000"];
+        assert!(ServerConfig::from_args(args.map(String::from)).is_err());
+
+        assert!(ServerConfig::from_args(["--port".to_string()]).is_err());
+        assert!(ServerConfig::from_args(["--save".to_string(), "60".to_string()]).is_err());
+        Ok(())
+    }
+}
@@ -1,7 +1,14 @@
+pub mod acl;
 mod backend;
+pub mod cluster;
 pub mod cmd;
+mod config;
 pub mod network;
+pub mod persist;
+pub mod replication;
 mod resp;
+pub mod sentinel;
 
 pub use backend::*;
+pub use config::{AppendFsync, SentinelMonitor, ServerConfig};
 pub use resp::*;
@@ -2,7 +2,7 @@ use anyhow::Result;
 use tokio::net::TcpListener;
 use tracing::{error, info};
 
-use simple_redis::{network, Backend};
+use simple_redis::{cluster, network, persist, sentinel, AppendFsync, Backend, ServerConfig};
 
 /// Server data flow and data structure processing.
 ///
@@ -17,11 +17,21 @@ async fn main() -> Result<()> {
     // Initialize tracing library
     tracing_subscriber::fmt::init();
 
-    let addr = "0.0.0.0:6379";
-    info!("Simple-Redis-Server is listening on {}", addr);
+    let config = ServerConfig::from_args(std::env::args().skip(1))?;
+    let addr = config.addr();
+    let backend = Backend::with_config(config);
+    if backend.config().aclfile.is_some() {
+        backend.load_acl()?;
+    }
+    // sentinel 只监控其他实例, 不载入数据
+    if backend.config().sentinel {
+        tokio::spawn(sentinel::run(backend.clone()));
+    } else {
+        setup(&backend).await?;
+    }
 
-    let listener = TcpListener::bind(addr).await?;
-    let backend = Backend::new();
+    info!("Simple-Redis-Server is listening on {}", addr);
+    let listener = TcpListener::bind(&addr).await?;
     loop {
         let (stream, raddr) = listener.accept().await?;
         info!("Accepted connection from: {}", raddr);
@@ -36,3 +46,28 @@ async fn main() -> Result<()> {
     #[allow(unreachable_code)]
     Ok(())
 }
+
+// 载入数据, 启动定期保存、集群总线和复制
+async fn setup(backend: &Backend) -> Result<()> {
+    // 启动时载入数据, 开启 AOF 时优先使用 AOF; 并按 save 规则定期保存
+    if backend.config().appendonly {
+        let loaded = backend.load_aof()?;
+        info!("DB loaded from append only file: {} records", loaded);
+        if backend.config().appendfsync == AppendFsync::EverySec {
+            tokio::spawn(persist::aof_fsync_scheduler(backend.clone()));
+        }
+    } else {
+        let loaded = backend.load_snapshot()?;
+        info!("DB loaded from disk: {} keys", loaded);
+    }
+    tokio::spawn(persist::save_scheduler(backend.clone()));
+    if backend.cluster_enabled() {
+        backend.load_cluster_config()?;
+        let listener = TcpListener::bind(backend.cluster_bus_addr()).await?;
+        tokio::spawn(cluster::cluster_bus(backend.clone(), listener));
+    }
+    if let Some(master) = backend.config().replicaof.clone() {
+        backend.replicaof(Some(master));
+    }
+    Ok(())
+}
@@ -1,16 +1,34 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
 use anyhow::Result;
 use bytes::BytesMut;
 use futures::SinkExt;
-use tokio::net::TcpStream;
+use tokio::{net::TcpStream, sync::mpsc};
 use tokio_stream::StreamExt;
 use tokio_util::codec::{Decoder, Encoder, Framed};
 use tracing::info;
 
-use crate::cmd::{Command, CommandExecutor};
-use crate::{Backend, RespDecode, RespEncode, RespError, RespFrame};
+use crate::cmd::{server_info, Command, CommandExecutor, Handshake};
+use crate::{
+    replication, Backend, BulkString, RespEncode, RespFrame, RespParser, SimpleError, SimpleString,
+};
 
-#[derive(Debug)]
-struct RespFrameCodec;
+const PROTECTED_MODE_DENIED: &str = "DENIED Redis is running in protected mode because protected mode is enabled and no password is set for the default user. In this mode connections are only accepted from the loopback interface. If you want to connect from external computers to Redis you may either set a password with --requirepass, or disable protected mode with --protected-mode no.";
+
+// 连接的 id, 从 1 开始
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+// 每个连接最多缓存的 push 数据, 超过时断开连接
+const PUSH_BUFFER_LIMIT: usize = 1024;
+
+// 参数中可能含有密码的命令, 日志中只记录命令名
+const REDACTED_COMMANDS: &[&str] = &["auth", "hello", "acl", "migrate"];
+
+// 解析器保存未完成 frame 的进度, 数据分多次到达时不需要从头解析
+#[derive(Debug, Default)]
+struct RespFrameCodec(RespParser);
 
 #[derive(Debug)]
 struct RedisRequest {
@@ -22,25 +40,125 @@ struct RedisResponse {
     frame: RespFrame,
 }
 
+// 日志中的请求, 不输出 REDACTED_COMMANDS 的参数
+struct Redacted<'a>(&'a RespFrame);
+
+impl fmt::Debug for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let RespFrame::Array(array) = self.0 {
+            if let Some(RespFrame::BulkString(name)) = array.first() {
+                let name = String::from_utf8_lossy(name);
+                if REDACTED_COMMANDS
+                    .iter()
+                    .any(|c| c.eq_ignore_ascii_case(&name))
+                {
+                    return write!(f, "{} (arguments redacted)", name);
+                }
+            }
+        }
+        self.0.fmt(f)
+    }
+}
+
+// 连接级别的状态
+#[derive(Debug, Default)]
+struct ConnState {
+    // replica 在 PSYNC 之前通过 REPLCONF 告知的端口
+    listening_port: Option<u16>,
+    // 上一条命令为 ASKING, 只对下一条命令有效
+    asking: bool,
+    // default 用户需要密码时, 需要先通过 AUTH 认证
+    authenticated: bool,
+    // 当前连接的 ACL 用户
+    user: String,
+    id: u64,
+    addr: String,
+    // HELLO SETNAME 设置的名字
+    name: Option<String>,
+    // HELLO 协商的协议版本, 默认为 RESP2
+    protover: u8,
+    // 只有 RESP3 连接可以接收 push 数据
+    pushes: Option<mpsc::Receiver<RespFrame>>,
+    // 收到 QUIT, 回复后关闭连接
+    closing: bool,
+}
+
 pub async fn handle_connection(stream: TcpStream, backend: Backend) -> Result<()> {
     // how to get a frame from the stream
     // call request_handler to handle the request
     // send the response back to the stream
-    let mut framed = Framed::new(stream, RespFrameCodec);
+    // protected mode: 没有设置密码时拒绝非 loopback 地址的客户端.
+    // sentinel 同样如此, 否则任何客户端都可以通过 SENTINEL HELLO 改变 master 的地址
+    let config = backend.config();
+    let peer = stream.peer_addr()?;
+    if config.protected_mode && backend.acl_default_nopass() && !peer.ip().is_loopback() {
+        let mut framed = Framed::new(stream, RespFrameCodec::default());
+        return framed
+            .send(SimpleError::new(PROTECTED_MODE_DENIED).into())
+            .await;
+    }
+    let mut framed = Framed::new(stream, RespFrameCodec::default());
+    let mut state = ConnState {
+        authenticated: backend.acl_default_nopass(),
+        user: "default".to_string(),
+        id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+        addr: peer.to_string(),
+        protover: 2,
+        ..Default::default()
+    };
+    let _client = ClientGuard {
+        backend: backend.clone(),
+        id: state.id,
+    };
 
     loop {
         let cloned_backend = backend.clone(); // Clone 一个 backend 供子任务使用
-        match framed.next().await {
+        let frame = tokio::select! {
+            frame = framed.next() => frame,
+            // push 数据在两个回复之间发送
+            push = recv_push(&mut state.pushes) => match push {
+                Some(push) => {
+                    framed.send(push).await?;
+                    continue;
+                }
+                // 缓冲区已满, backend 已经丢弃了 channel
+                None => return Ok(()),
+            },
+        };
+        match frame {
             Some(Ok(frame)) => {
-                info!("Received frame: {:?}", frame);
+                info!("Received frame: {:?}", Redacted(&frame));
+                if state.authenticated && replication::is_sync_command(&frame) {
+                    let acl =
+                        backend.acl_check_command(&state.user, "psync", &[], &state.client_info());
+                    if let Err(denied) = acl {
+                        framed.send(denied).await?;
+                        continue;
+                    }
+                    let parts = framed.into_parts();
+                    return replication::serve_replica(
+                        parts.io,
+                        parts.read_buf,
+                        frame,
+                        state.listening_port,
+                        backend,
+                    )
+                    .await;
+                }
+                if let Some(port) = replication::listening_port(&frame) {
+                    state.listening_port = Some(port);
+                }
                 let request = RedisRequest {
                     frame,
                     backend: cloned_backend,
                 };
-                let response = request_handler(request).await?;
+                let response = request_handler(request, &mut state).await?;
                 info!("Sending response: {:?}", response);
                 // 向 stream 发送响应
-                framed.send(response.frame).await?
+                framed.send(state.reply(response.frame)).await?;
+                if state.closing {
+                    return Ok(());
+                }
             }
             Some(Err(err)) => return Err(err),
             None => return Ok(()),
@@ -48,15 +166,174 @@ pub async fn handle_connection(stream: TcpStream, backend: Backend) -> Result<()
     }
 }
 
+// 连接关闭时从 backend 中移除
+struct ClientGuard {
+    backend: Backend,
+    id: u64,
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.backend.unregister_client(self.id);
+    }
+}
+
+impl ConnState {
+    // RESP2 客户端需要转换 RESP3 的类型
+    fn reply(&self, frame: RespFrame) -> RespFrame {
+        match self.protover {
+            2 => frame.into_resp2(),
+            _ => frame,
+        }
+    }
+
+    // ACL LOG 中记录的客户端信息
+    fn client_info(&self) -> String {
+        format!(
+            "id={} addr={} name={}",
+            self.id,
+            self.addr,
+            self.name.as_deref().unwrap_or("")
+        )
+    }
+
+    // HELLO 成功后更新连接的状态, 回复使用新的协议版本
+    fn hello(&mut self, handshake: Handshake, backend: &Backend) -> RespFrame {
+        if let Some(user) = handshake.user {
+            self.authenticated = true;
+            self.user = user;
+        }
+        if let Some(name) = handshake.name {
+            self.name = Some(name);
+        }
+        if let Some(protover) = handshake.protover {
+            self.protover = protover;
+            match protover {
+                3 if self.pushes.is_none() => {
+                    let (tx, rx) = mpsc::channel(PUSH_BUFFER_LIMIT);
+                    backend.register_client(self.id, tx);
+                    self.pushes = Some(rx);
+                }
+                3 => {}
+                // RESP2 没有 push 类型, 不再接收 push 数据
+                _ => {
+                    backend.unregister_client(self.id);
+                    self.pushes = None;
+                }
+            }
+        }
+        server_info(backend, self.id, self.protover)
+    }
+}
+
+// 等待 push 数据; 不接收 push 时一直等待, channel 被 backend 丢弃时返回 None
+async fn recv_push(rx: &mut Option<mpsc::Receiver<RespFrame>>) -> Option<RespFrame> {
+    match rx {
+        Some(rx) if rx.is_closed() => None,
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
 // 处理一个请求并返回响应
-async fn request_handler(request: RedisRequest) -> Result<RedisResponse> {
+async fn request_handler(request: RedisRequest, state: &mut ConnState) -> Result<RedisResponse> {
     let (frame, backend) = (request.frame, request.backend);
-    let cmd = Command::try_from(frame)?;
-    info!("Executing command: {:?}", cmd);
-    let frame = cmd.execute(&backend);
+    // 写命令执行成功后需要原样传播到 AOF 和 replicas
+    let raw = frame.clone();
+    let cmd = Command::try_from(frame);
+    // 执行前检查 ACL 权限
+    if let (Ok(cmd), true) = (&cmd, state.authenticated) {
+        if let Err(frame) = backend.acl_check(&state.user, cmd, &state.client_info()) {
+            return Ok(RedisResponse { frame });
+        }
+    }
+    let frame = match cmd {
+        // 未认证的连接只能执行 AUTH, HELLO 和 QUIT
+        Ok(Command::Auth(cmd)) => match cmd.authenticate(&backend, &state.client_info()) {
+            Ok(user) => {
+                state.authenticated = true;
+                state.user = user;
+                SimpleString::new("OK").into()
+            }
+            Err(frame) => frame,
+        },
+        Ok(Command::Hello(cmd)) => {
+            match cmd.handshake(&backend, &state.client_info(), state.authenticated) {
+                Ok(handshake) => state.hello(handshake, &backend),
+                Err(frame) => frame,
+            }
+        }
+        Ok(Command::Quit(cmd)) => {
+            state.closing = true;
+            cmd.execute(&backend)
+        }
+        _ if !state.authenticated => SimpleError::new("NOAUTH Authentication required.").into(),
+        Ok(Command::Acl(cmd)) if cmd.is_whoami() => BulkString::new(state.user.clone()).into(),
+        // 集群模式下 key 不由本节点负责时重定向
+        Ok(Command::Asking(cmd)) => {
+            let frame = cmd.execute(&backend);
+            state.asking = matches!(frame, RespFrame::SimpleString(_));
+            frame
+        }
+        // sentinel 只支持 SENTINEL, PING 和 INFO
+        Ok(cmd) if backend.config().sentinel => match cmd {
+            Command::Sentinel(_) | Command::Ping(_) | Command::Info(_) => cmd.execute(&backend),
+            _ => SimpleError::new("ERR unknown command in sentinel mode").into(),
+        },
+        Ok(cmd) => {
+            let asking = std::mem::take(&mut state.asking);
+            match backend.cluster_redirect(&cmd, asking) {
+                Some(redirect) => redirect,
+                None => execute(cmd, raw, &backend, asking).await,
+            }
+        }
+        // 命令解析失败时回复错误, 而不是断开连接
+        Err(e) => SimpleError::new(format!("ERR {}", e)).into(),
+    };
     Ok(RedisResponse { frame })
 }
 
+async fn execute(cmd: Command, raw: RespFrame, backend: &Backend, asking: bool) -> RespFrame {
+    info!("Executing command: {:?}", Redacted(&raw));
+    match cmd {
+        // 阻塞的命令
+        Command::Wait(cmd) => cmd.wait(backend).await,
+        Command::WaitAof(cmd) => cmd.wait(backend).await,
+        // MIGRATE 同步等待目标节点的回复, 保存和重写 AOF 需要阻塞写命令复制数据,
+        // 都不能阻塞 tokio 的工作线程
+        cmd @ (Command::Migrate(_)
+        | Command::Save(_)
+        | Command::BgSave(_)
+        | Command::BgRewriteAof(_)) => {
+            let backend = backend.clone();
+            tokio::task::spawn_blocking(move || cmd.execute(&backend))
+                .await
+                .unwrap_or_else(|e| SimpleError::new(format!("ERR {}", e)).into())
+        }
+        cmd if cmd.is_write() => {
+            if backend.is_replica() && backend.config().replica_read_only {
+                SimpleError::new("READONLY You can't write against a read only replica.").into()
+            } else {
+                let (mut cmd, mut raw) = (cmd, raw);
+                loop {
+                    match backend.try_execute_write(cmd, raw) {
+                        Ok(ret) => break ret,
+                        // 手动故障转移期间等待, 之后 key 可能已经由新的 master 负责
+                        Err(paused) => {
+                            (cmd, raw) = *paused;
+                            tokio::time::sleep(Duration::from_millis(10)).await;
+                            if let Some(redirect) = backend.cluster_redirect(&cmd, asking) {
+                                break redirect;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        cmd => cmd.execute(backend),
+    }
+}
+
 impl Encoder<RespFrame> for RespFrameCodec {
     type Error = anyhow::Error;
 
@@ -65,8 +342,8 @@ impl Encoder<RespFrame> for RespFrameCodec {
         item: RespFrame,
         dst: &mut BytesMut,
     ) -> std::result::Result<(), Self::Error> {
-        let encoded = item.encode();
-        dst.extend_from_slice(&encoded); // 转化成 bytes 并贝到 dst
+        dst.reserve(item.encoded_len());
+        item.encode_to(dst);
         Ok(())
     }
 }
@@ -78,10 +355,267 @@ impl Decoder for RespFrameCodec {
         &mut self,
         src: &mut BytesMut,
     ) -> std::result::Result<Option<Self::Item>, Self::Error> {
-        match RespFrame::decode(src) {
-            Ok(frame) => Ok(Some(frame)),
-            Err(RespError::NotComplete) => Ok(None),
-            Err(err) => Err(err.into()),
+        Ok(self.0.parse(src)?)
+    }
+}
+
+/// 测试中使用的服务器和客户端
+#[cfg(test)]
+pub(crate) mod testing {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::{RespArray, RespDecode};
+
+    /// 在 listener 上接受连接, 每个连接由 handle_connection 处理
+    pub fn serve(listener: TcpListener, backend: Backend) {
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle_connection(stream, backend.clone()));
+            }
+        });
+    }
+
+    /// 在随机端口上启动服务器, 返回端口
+    pub async fn start_server(backend: Backend) -> Result<u16> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        serve(listener, backend);
+        Ok(port)
+    }
+
+    /// 由参数组成的命令
+    pub fn array(args: &[&str]) -> RespArray {
+        RespArray::new(
+            args.iter()
+                .map(|s| BulkString::new(s.to_string()).into())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// 解析参数组成的命令
+    pub fn command(args: &[&str]) -> Result<Command> {
+        Ok(Command::try_from(array(args))?)
+    }
+
+    /// 发送一条命令并读取回复
+    pub async fn request(stream: &mut TcpStream, args: &[&str]) -> Result<RespFrame> {
+        stream.write_all(&array(args).encode()).await?;
+        read_frame(stream).await
+    }
+
+    /// 读取一个 frame, 连接关闭时返回错误
+    pub async fn read_frame(stream: &mut TcpStream) -> Result<RespFrame> {
+        let mut buf = BytesMut::new();
+        loop {
+            if stream.read_buf(&mut buf).await? == 0 {
+                anyhow::bail!("connection closed");
+            }
+            if let Ok(frame) = RespFrame::decode(&mut buf) {
+                return Ok(frame);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::testing::{array, read_frame, request, start_server};
+    use super::*;
+    use crate::{RespNull, RespNullBulkString, RespPush, ServerConfig};
+
+    fn is_error(frame: &RespFrame, code: &str) -> bool {
+        matches!(frame, RespFrame::Error(e) if e.starts_with(code))
+    }
+
+    async fn connect(port: u16) -> Result<TcpStream> {
+        Ok(TcpStream::connect(("127.0.0.1", port)).await?)
+    }
+
+    #[test]
+    fn test_redacted() {
+        let frame = |args: &[&str]| RespFrame::from(array(args));
+        for args in [
+            &["auth", "secret"][..],
+            &["HELLO", "3", "AUTH", "default", "secret"],
+            &["ACL", "SETUSER", "alice", ">secret"],
+            &[
+                "MIGRATE",
+                "127.0.0.1",
+                "6380",
+                "",
+                "0",
+                "1000",
+                "AUTH",
+                "secret",
+            ],
+        ] {
+            let log = format!("{:?}", Redacted(&frame(args)));
+            assert!(!log.contains("secret"), "{}", log);
+        }
+        let set = frame(&["SET", "key", "secret"]);
+        assert_eq!(format!("{:?}", Redacted(&set)), format!("{:?}", set));
+    }
+
+    #[tokio::test]
+    async fn test_requirepass() -> Result<()> {
+        let port = start_server(Backend::with_config(ServerConfig {
+            requirepass: Some("secret".to_string()),
+            ..Default::default()
+        }))
+        .await?;
+
+        let mut stream = connect(port).await?;
+        let reply = request(&mut stream, &["SET", "key", "value"]).await?;
+        assert!(is_error(&reply, "NOAUTH"));
+        let reply = request(&mut stream, &["NOSUCHCOMMAND"]).await?;
+        assert!(is_error(&reply, "NOAUTH"));
+        let reply = request(&mut stream, &["AUTH", "wrong"]).await?;
+        assert!(is_error(&reply, "WRONGPASS"));
+        let reply = request(&mut stream, &["AUTH", "secret"]).await?;
+        assert_eq!(reply, SimpleString::new("OK").into());
+        let reply = request(&mut stream, &["SET", "key", "value"]).await?;
+        assert_eq!(reply, SimpleString::new("OK").into());
+
+        // 认证只对当前连接有效
+        let mut other = connect(port).await?;
+        let reply = request(&mut other, &["GET", "key"]).await?;
+        assert!(is_error(&reply, "NOAUTH"));
+        let reply = request(&mut other, &["QUIT"]).await?;
+        assert_eq!(reply, SimpleString::new("OK").into());
+        assert_eq!(other.read(&mut [0; 16]).await?, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_acl() -> Result<()> {
+        let backend = Backend::new();
+        let rules = ["on", ">pass", "~cache:*", "+@read", "+acl"].map(String::from);
+        backend.acl_setuser("alice", &rules)?;
+        let port = start_server(backend).await?;
+
+        let mut stream = connect(port).await?;
+        let reply = request(&mut stream, &["ACL", "WHOAMI"]).await?;
+        assert_eq!(reply, BulkString::new("default").into());
+        let reply = request(&mut stream, &["AUTH", "alice", "wrong"]).await?;
+        assert!(is_error(&reply, "WRONGPASS"));
+        let reply = request(&mut stream, &["AUTH", "alice", "pass"]).await?;
+        assert_eq!(reply, SimpleString::new("OK").into());
+        let reply = request(&mut stream, &["ACL", "WHOAMI"]).await?;
+        assert_eq!(reply, BulkString::new("alice").into());
+        let reply = request(&mut stream, &["GET", "cache:1"]).await?;
+        assert!(!is_error(&reply, "NOPERM"));
+        let reply = request(&mut stream, &["GET", "secret"]).await?;
+        assert!(is_error(&reply, "NOPERM"));
+        let reply = request(&mut stream, &["SET", "cache:1", "v"]).await?;
+        assert!(is_error(&reply, "NOPERM"));
+        let reply = request(&mut stream, &["PSYNC", "?", "-1"]).await?;
+        assert!(is_error(&reply, "NOPERM"));
+
+        let RespFrame::Array(log) = request(&mut stream, &["ACL", "LOG"]).await? else {
+            panic!("ACL LOG should be an array");
+        };
+        assert_eq!(log.len(), 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hello() -> Result<()> {
+        let port = start_server(Backend::with_config(ServerConfig {
+            requirepass: Some("secret".to_string()),
+            ..Default::default()
+        }))
+        .await?;
+
+        let mut stream = connect(port).await?;
+        let reply = request(&mut stream, &["HELLO", "3"]).await?;
+        assert!(is_error(&reply, "NOAUTH"));
+        let reply = request(&mut stream, &["HELLO", "4"]).await?;
+        assert!(is_error(&reply, "NOPROTO"));
+        let reply = request(&mut stream, &["HELLO", "3", "AUTH", "default", "secret"]).await?;
+        let RespFrame::Map(info) = reply else {
+            panic!("HELLO 3 should reply a map");
+        };
+        assert_eq!(info["proto"], RespFrame::Integer(3));
+        let reply = request(&mut stream, &["GET", "missing"]).await?;
+        assert_eq!(reply, RespFrame::Null(RespNull));
+
+        // RESP2 中 map 展开为数组, null 为 $-1
+        let RespFrame::Array(info) = request(&mut stream, &["HELLO", "2"]).await? else {
+            panic!("HELLO 2 should reply an array");
+        };
+        assert_eq!(info.len(), 14);
+        let reply = request(&mut stream, &["GET", "missing"]).await?;
+        assert_eq!(reply, RespNullBulkString.into());
+        Ok(())
+    }
+
+    // 通过 HELLO 的回复得到连接的 id
+    async fn hello(stream: &mut TcpStream, protover: &str) -> Result<u64> {
+        let info = match request(stream, &["HELLO", protover]).await? {
+            RespFrame::Map(info) => info["id"].clone(),
+            RespFrame::Array(info) => info[7].clone(),
+            other => panic!("unexpected HELLO reply: {:?}", other),
+        };
+        let RespFrame::Integer(id) = info else {
+            panic!("HELLO should reply the client id");
+        };
+        Ok(id as u64)
+    }
+
+    #[tokio::test]
+    async fn test_push() -> Result<()> {
+        let backend = Backend::new();
+        let port = start_server(backend.clone()).await?;
+
+        let mut stream = connect(port).await?;
+        let id = hello(&mut stream, "3").await?;
+        let message = RespPush::new(vec![
+            BulkString::new("message").into(),
+            BulkString::new("news").into(),
+        ]);
+        assert!(backend.push(id, message.clone()));
+        assert!(!backend.push(id + 1000, message.clone()));
+
+        // 没有请求时 push 也会送达, 之后的回复不受影响
+        assert_eq!(read_frame(&mut stream).await?, message.clone().into());
+        let reply = request(&mut stream, &["PING"]).await?;
+        assert_eq!(reply, SimpleString::new("PONG").into());
+
+        // RESP2 连接不接收 push
+        assert_eq!(hello(&mut stream, "2").await?, id);
+        assert!(!backend.push(id, message.clone()));
+        let mut resp2 = connect(port).await?;
+        let resp2_id = hello(&mut resp2, "2").await?;
+        assert!(!backend.push(resp2_id, message.clone()));
+
+        drop(stream);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!backend.push(id, RespPush::new(vec![])));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_push_buffer_limit() -> Result<()> {
+        let backend = Backend::new();
+        let port = start_server(backend.clone()).await?;
+        let mut stream = connect(port).await?;
+        let id = hello(&mut stream, "3").await?;
+
+        // 连接在单线程的运行时中来不及发送, 缓冲区满后断开连接
+        let message = RespPush::new(vec![BulkString::new("message").into()]);
+        for _ in 0..PUSH_BUFFER_LIMIT {
+            assert!(backend.push(id, message.clone()));
         }
+        assert!(!backend.push(id, message.clone()));
+        assert!(!backend.push(id, message));
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await?;
+        Ok(())
     }
 }
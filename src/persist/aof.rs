@@ -0,0 +1,349 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use bytes::BytesMut;
+use tracing::{error, info, warn};
+
+use crate::{
+    cmd::{Command, CommandExecutor},
+    AppendFsync, Backend, RespDecode, RespError, RespFrame,
+};
+
+use super::snapshot::{decode_snapshot_prefix, encode_snapshot, is_snapshot};
+
+/// AOF 的状态.
+///
+/// AOF 文件由两部分组成: 可选的快照前缀 (BGREWRITEAOF 生成) 和之后追加的 RESP 命令.
+#[derive(Debug, Default)]
+pub struct AofState {
+    inner: Mutex<AofInner>,
+    rewrite_in_progress: AtomicBool,
+}
+
+#[derive(Debug, Default)]
+struct AofInner {
+    file: Option<File>,
+    // 重写期间追加的命令, 重写完成后写入新文件末尾
+    rewrite_buf: Option<Vec<u8>>,
+    // everysec 策略下是否有尚未 fsync 的数据
+    unsynced: bool,
+}
+
+impl AofState {
+    fn lock(&self) -> MutexGuard<'_, AofInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Backend {
+    pub fn aof_enabled(&self) -> bool {
+        self.config().appendonly
+    }
+
+    pub(crate) fn aof_append(&self, data: &[u8]) {
+        let mut inner = self.aof.lock();
+        if let Some(buf) = inner.rewrite_buf.as_mut() {
+            buf.extend_from_slice(data);
+        }
+        let Some(file) = inner.file.as_mut() else {
+            return;
+        };
+        let ret = file
+            .write_all(data)
+            .and_then(|_| match self.config().appendfsync {
+                AppendFsync::Always => file.sync_data(),
+                _ => Ok(()),
+            });
+        match ret {
+            Ok(_) => inner.unsynced = self.config().appendfsync == AppendFsync::EverySec,
+            Err(e) => error!("Error writing to the AOF file: {:?}", e),
+        }
+    }
+
+    /// everysec 策略下由后台任务每秒调用, WAITAOF 也会调用.
+    /// 返回需要 fsync 的数据是否都已写盘; no 策略下由操作系统写盘, 总是返回 true
+    pub fn aof_fsync(&self) -> bool {
+        let mut inner = self.aof.lock();
+        if !inner.unsynced {
+            return true;
+        }
+        if let Some(file) = inner.file.as_ref() {
+            if let Err(e) = file.sync_data() {
+                error!("Error syncing the AOF file: {:?}", e);
+                return false;
+            }
+        }
+        inner.unsynced = false;
+        true
+    }
+
+    /// 启动时载入 AOF 并打开文件用于追加, 返回载入的记录数 (快照中的 key 加上命令).
+    /// AOF 不存在时从快照文件载入, 并以当前数据生成新的 AOF.
+    pub fn load_aof(&self) -> Result<usize> {
+        let path = self.config().aof_path();
+        if !path.exists() {
+            let loaded = self.load_snapshot()?;
+            write_aof_base(&path, &encode_snapshot(&self.snapshot()))?;
+            self.open_aof(&path)?;
+            return Ok(loaded);
+        }
+
+        let data = fs::read(&path)?;
+        let mut loaded = 0;
+        let mut offset = 0;
+        if is_snapshot(&data) {
+            let (snapshot, len) = decode_snapshot_prefix(&data)?;
+            loaded += snapshot.entries.len();
+            offset = len;
+            self.restore(snapshot);
+        }
+
+        let mut buf = BytesMut::from(&data[offset..]);
+        while !buf.is_empty() {
+            match RespFrame::decode(&mut buf) {
+                Ok(frame) => {
+                    Command::try_from(frame)?.execute(self);
+                    loaded += 1;
+                    offset = data.len() - buf.len();
+                }
+                // 写入过程中宕机会留下不完整的命令, 截掉它继续启动
+                Err(RespError::NotComplete) => {
+                    warn!(
+                        "AOF {} truncated at offset {}, discarding {} bytes",
+                        path.display(),
+                        offset,
+                        data.len() - offset
+                    );
+                    OpenOptions::new()
+                        .write(true)
+                        .open(&path)?
+                        .set_len(offset as u64)?;
+                    break;
+                }
+                Err(e) => bail!("bad AOF format at offset {}: {}", offset, e),
+            }
+        }
+        self.sub_dirty(self.dirty());
+        self.open_aof(&path)?;
+        Ok(loaded)
+    }
+
+    /// 在后台线程中以当前数据的快照重写 AOF, 重写期间的新命令追加在快照之后
+    pub fn bgrewriteaof(&self) -> Result<()> {
+        if self
+            .aof
+            .rewrite_in_progress
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            bail!("Background append only file rewriting already in progress");
+        }
+        let snapshot = {
+            // 重写缓冲区从快照之后开始记录
+            let guard = self.block_writes();
+            self.aof.lock().rewrite_buf = Some(Vec::new());
+            self.snapshot_locked(&guard)
+        };
+        let backend = self.clone();
+        std::thread::spawn(move || {
+            match backend.rewrite_aof(&encode_snapshot(&snapshot)) {
+                Ok(_) => info!("Background AOF rewrite finished successfully"),
+                Err(e) => {
+                    error!("Background AOF rewrite error: {:?}", e);
+                    backend.aof.lock().rewrite_buf = None;
+                }
+            }
+            backend
+                .aof
+                .rewrite_in_progress
+                .store(false, Ordering::Release);
+        });
+        Ok(())
+    }
+
+    pub fn aof_rewrite_in_progress(&self) -> bool {
+        self.aof.rewrite_in_progress.load(Ordering::Acquire)
+    }
+
+    fn rewrite_aof(&self, base: &[u8]) -> Result<()> {
+        let path = self.config().aof_path();
+        let tmp = path.with_file_name(format!("temp-rewriteaof-{}.aof", std::process::id()));
+        let mut file = File::create(&tmp)?;
+        file.write_all(base)?;
+
+        // 切换文件时持有锁, 期间不会有新命令追加
+        let mut inner = self.aof.lock();
+        if let Some(buf) = inner.rewrite_buf.take() {
+            file.write_all(&buf)?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+        if self.aof_enabled() {
+            inner.file = Some(OpenOptions::new().append(true).open(&path)?);
+            inner.unsynced = false;
+        }
+        Ok(())
+    }
+
+    fn open_aof(&self, path: &Path) -> Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.aof.lock().file = Some(file);
+        Ok(())
+    }
+}
+
+/// appendfsync everysec 时每秒 fsync 一次
+pub async fn aof_fsync_scheduler(backend: Backend) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        backend.aof_fsync();
+    }
+}
+
+fn write_aof_base(path: &Path, base: &[u8]) -> Result<()> {
+    let tmp = path.with_file_name(format!("temp-rewriteaof-{}.aof", std::process::id()));
+    let mut file = File::create(&tmp)?;
+    file.write_all(base)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{network::testing::array, BulkString, ServerConfig};
+
+    fn test_backend(name: &str) -> Backend {
+        let dir =
+            std::env::temp_dir().join(format!("simple-redis-aof-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap_or_default();
+        Backend::with_config(ServerConfig {
+            dir,
+            appendonly: true,
+            appendfsync: AppendFsync::Always,
+            ..Default::default()
+        })
+    }
+
+    fn run(backend: &Backend, args: &[&str]) {
+        let frame: RespFrame = array(args).into();
+        let cmd = Command::try_from(frame.clone()).unwrap();
+        backend.execute_write(cmd, frame);
+    }
+
+    #[test]
+    fn test_aof_append_and_load() -> Result<()> {
+        let backend = test_backend("load");
+        assert_eq!(backend.load_aof()?, 0);
+        run(&backend, &["set", "hello", "world"]);
+        run(&backend, &["hset", "user", "name", "alice"]);
+        run(&backend, &["setbit", "bits", "7", "1"]);
+
+        // 截断最后一条命令
+        let path = backend.config().aof_path();
+        let mut data = fs::read(&path)?;
+        let len = data.len();
+        data.extend_from_slice(b"*3\r\n$3\r\nset\r\n$3\r\nfoo");
+        fs::write(&path, &data)?;
+
+        let restarted = Backend::with_config(backend.config().clone());
+        assert_eq!(restarted.load_aof()?, 3);
+        assert_eq!(fs::metadata(&path)?.len(), len as u64);
+        assert_eq!(
+            restarted.get("hello"),
+            Some(BulkString::new("world").into())
+        );
+        assert_eq!(
+            restarted.hget("user", "name"),
+            Some(BulkString::new("alice").into())
+        );
+        assert_eq!(restarted.get("bits"), Some(BulkString::new(vec![1]).into()));
+
+        // 截断后可以继续追加
+        run(&restarted, &["set", "foo", "bar"]);
+        let again = Backend::with_config(backend.config().clone());
+        assert_eq!(again.load_aof()?, 4);
+        assert_eq!(again.get("foo"), Some(BulkString::new("bar").into()));
+
+        fs::remove_dir_all(&backend.config().dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_bgrewriteaof() -> Result<()> {
+        let backend = test_backend("rewrite");
+        backend.load_aof()?;
+        for i in 0..100 {
+            run(&backend, &["set", "counter", &i.to_string()]);
+        }
+        run(
+            &backend,
+            &["geoadd", "geo", "13.361389", "38.115556", "Palermo"],
+        );
+        let path = backend.config().aof_path();
+        let before = fs::metadata(&path)?.len();
+
+        backend.bgrewriteaof()?;
+        while backend.aof_rewrite_in_progress() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(fs::metadata(&path)?.len() < before);
+        run(&backend, &["set", "after", "rewrite"]);
+
+        let restarted = Backend::with_config(backend.config().clone());
+        assert_eq!(restarted.load_aof()?, 3);
+        assert_eq!(restarted.get("counter"), Some(BulkString::new("99").into()));
+        assert_eq!(
+            restarted.get("after"),
+            Some(BulkString::new("rewrite").into())
+        );
+        assert!(restarted.zget("geo").is_some());
+
+        fs::remove_dir_all(&backend.config().dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_aof_order_matches_concurrent_writes() -> Result<()> {
+        let backend = Backend::with_config(ServerConfig {
+            appendfsync: AppendFsync::No,
+            ..test_backend("order").config().clone()
+        });
+        backend.load_aof()?;
+        // 多个线程依次写同一组 key, 重放 AOF 后每个 key 的值必须与执行顺序的结果一致
+        let threads: Vec<_> = (0..8)
+            .map(|t| {
+                let backend = backend.clone();
+                std::thread::spawn(move || {
+                    for i in 0..2000 {
+                        run(&backend, &["set", &format!("key{}", i), &t.to_string()]);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        let restarted = Backend::with_config(backend.config().clone());
+        assert_eq!(restarted.load_aof()?, 8 * 2000);
+        for i in 0..2000 {
+            let key = format!("key{}", i);
+            assert_eq!(restarted.get(&key), backend.get(&key));
+        }
+
+        fs::remove_dir_all(&backend.config().dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicI64, Ordering},
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use tracing::{error, info};
+
+use crate::{backend::now_ms, Backend};
+
+mod aof;
+mod crc64;
+mod rdb;
+mod snapshot;
+
+pub use aof::{aof_fsync_scheduler, AofState};
+pub use crc64::crc64;
+pub use rdb::{decode_dump, decode_rdb, encode_dump, encode_rdb, RdbFile};
+pub use snapshot::{decode_snapshot, decode_snapshot_prefix, encode_snapshot};
+
+/// 快照保存的状态
+#[derive(Debug)]
+pub struct SaveState {
+    // 上次成功保存的时间, unix 秒
+    lastsave: AtomicI64,
+    bgsave_in_progress: AtomicBool,
+}
+
+impl Default for SaveState {
+    fn default() -> Self {
+        SaveState {
+            lastsave: AtomicI64::new(now_ms() / 1000),
+            bgsave_in_progress: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Backend {
+    /// 同步保存快照, 保存期间阻塞调用方
+    pub fn save(&self) -> Result<()> {
+        if self.save_state.bgsave_in_progress.load(Ordering::Acquire) {
+            bail!("Background save already in progress");
+        }
+        let dirty = self.dirty();
+        write_file(&self.config().db_path(), &encode_snapshot(&self.snapshot()))?;
+        self.save_done(dirty);
+        Ok(())
+    }
+
+    /// 以 Redis 的 RDB 格式同步保存, 可以被 Redis 载入; 返回写入的文件路径
+    pub fn save_rdb(&self) -> Result<PathBuf> {
+        let path = self.config().db_path().with_extension("rdb");
+        write_file(&path, &encode_rdb(&self.snapshot()))?;
+        Ok(path)
+    }
+
+    /// 先在内存中复制一份数据, 再在后台线程写入文件, 不阻塞客户端
+    pub fn bgsave(&self) -> Result<()> {
+        if self
+            .save_state
+            .bgsave_in_progress
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            bail!("Background save already in progress");
+        }
+        let dirty = self.dirty();
+        let snapshot = self.snapshot();
+        let backend = self.clone();
+        std::thread::spawn(move || {
+            match write_file(&backend.config().db_path(), &encode_snapshot(&snapshot)) {
+                Ok(_) => {
+                    info!("Background saving terminated with success");
+                    backend.save_done(dirty);
+                }
+                Err(e) => error!("Background saving error: {:?}", e),
+            }
+            backend
+                .save_state
+                .bgsave_in_progress
+                .store(false, Ordering::Release);
+        });
+        Ok(())
+    }
+
+    pub fn bgsave_in_progress(&self) -> bool {
+        self.save_state.bgsave_in_progress.load(Ordering::Acquire)
+    }
+
+    /// 上次成功保存的时间, unix 秒
+    pub fn lastsave(&self) -> i64 {
+        self.save_state.lastsave.load(Ordering::Relaxed)
+    }
+
+    /// 启动时载入快照文件, 返回载入的 key 数量; 文件不存在时返回 0.
+    /// 也可以载入 Redis 生成的 RDB 文件 (只载入 db 0).
+    pub fn load_snapshot(&self) -> Result<usize> {
+        let path = self.config().db_path();
+        if !path.exists() {
+            return Ok(0);
+        }
+        let data = fs::read(&path)?;
+        let snapshot = if rdb::is_rdb(&data) {
+            decode_rdb(&data)?.into_snapshot(0)
+        } else {
+            decode_snapshot(&data)?
+        };
+        let len = snapshot.entries.len();
+        self.restore(snapshot);
+        self.sub_dirty(self.dirty());
+        Ok(len)
+    }
+
+    /// 是否满足某条 `save <seconds> <changes>` 规则
+    pub fn should_save(&self) -> bool {
+        let elapsed = now_ms() / 1000 - self.lastsave();
+        let dirty = self.dirty();
+        self.config()
+            .save
+            .iter()
+            .any(|&(secs, changes)| dirty >= changes && elapsed >= secs as i64)
+    }
+
+    // 保存期间的新修改仍计入 dirty
+    fn save_done(&self, dirty: u64) {
+        self.sub_dirty(dirty);
+        self.save_state
+            .lastsave
+            .store(now_ms() / 1000, Ordering::Relaxed);
+    }
+}
+
+/// 每秒检查一次自动保存规则
+pub async fn save_scheduler(backend: Backend) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        if !backend.bgsave_in_progress() && backend.should_save() {
+            info!("{} changes, saving...", backend.dirty());
+            // 复制数据期间阻塞写命令, 不能占用 tokio 的工作线程
+            let saver = backend.clone();
+            match tokio::task::spawn_blocking(move || saver.bgsave()).await {
+                Ok(Err(e)) => error!("Background saving error: {:?}", e),
+                Err(e) => error!("Background saving error: {:?}", e),
+                Ok(Ok(())) => {}
+            }
+        }
+    }
+}
+
+// 先写临时文件再 rename, 保证快照文件总是完整的
+pub(crate) fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!("temp-{}-{}", std::process::id(), name));
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BulkString, ServerConfig};
+
+    fn test_backend(name: &str) -> Backend {
+        let dir =
+            std::env::temp_dir().join(format!("simple-redis-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap_or_default();
+        Backend::with_config(ServerConfig {
+            dir,
+            save: vec![(0, 2)],
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_save_and_load() -> Result<()> {
+        let backend = test_backend("save");
+        backend.set("hello".to_string(), BulkString::new("world").into());
+        assert!(!backend.should_save());
+        backend.set("foo".to_string(), BulkString::new("bar").into());
+        assert!(backend.should_save());
+
+        backend.save()?;
+        assert_eq!(backend.dirty(), 0);
+        assert!(!backend.should_save());
+
+        let restarted = Backend::with_config(backend.config().clone());
+        assert_eq!(restarted.load_snapshot()?, 2);
+        assert_eq!(
+            restarted.get("hello"),
+            Some(BulkString::new("world").into())
+        );
+
+        // 导出为 RDB, 与快照保存在同一目录
+        let path = backend.save_rdb()?;
+        assert_eq!(path, backend.config().dir.join("dump.rdb"));
+        let rdb = decode_rdb(&fs::read(&path)?)?;
+        assert_eq!(rdb.into_snapshot(0).entries.len(), 2);
+        fs::remove_dir_all(&backend.config().dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_rdb() -> Result<()> {
+        let mut backend = test_backend("rdb");
+        let config = ServerConfig {
+            dbfilename: "dump.rdb".to_string(),
+            ..backend.config().clone()
+        };
+        fs::write(
+            config.db_path(),
+            include_bytes!("../../fixtures/rdb/strings.rdb"),
+        )?;
+        backend = Backend::with_config(config);
+        // 已过期的 key 不会被载入
+        assert_eq!(backend.load_snapshot()?, 11);
+        assert_eq!(backend.get("expired"), None);
+        assert_eq!(backend.get("int8"), Some(BulkString::new("123").into()));
+        fs::remove_dir_all(&backend.config().dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_bgsave() -> Result<()> {
+        let backend = test_backend("bgsave");
+        backend.set("hello".to_string(), BulkString::new("world").into());
+        backend.bgsave()?;
+        while backend.bgsave_in_progress() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(backend.dirty(), 0);
+
+        let restarted = Backend::with_config(backend.config().clone());
+        assert_eq!(restarted.load_snapshot()?, 1);
+        fs::remove_dir_all(&backend.config().dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::{bail, Result};
+use bytes::{Buf, BufMut};
+
+use crate::{persist::crc64, StoredValue};
+
+use super::reader::RdbReader;
+use super::writer::{put_value, value_type, WRITE_VERSION};
+use super::RDB_VERSION;
+
+/// DUMP 的格式与 Redis 相同:
+///
+/// ```text
+/// <type:u8> <value> <rdb-version:u16> <crc64:u64>
+/// ```
+///
+/// 值的编码与 RDB 文件一致, 整数为小端序, 校验和覆盖之前的全部内容.
+pub fn encode_dump(value: &StoredValue) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.put_u8(value_type(value));
+    put_value(&mut buf, value);
+    buf.put_u16_le(WRITE_VERSION as u16);
+    let checksum = crc64(0, &buf);
+    buf.put_u64_le(checksum);
+    buf
+}
+
+pub fn decode_dump(data: &[u8]) -> Result<StoredValue> {
+    if data.len() < 1 + 2 + 8 {
+        bail!("DUMP payload version or checksum are wrong");
+    }
+    let (body, mut footer) = data.split_at(data.len() - 10);
+    let version = footer.get_u16_le();
+    if version as u32 > RDB_VERSION || crc64(0, &data[..data.len() - 8]) != footer.get_u64_le() {
+        bail!("DUMP payload version or checksum are wrong");
+    }
+
+    let mut r = RdbReader::new(body);
+    let t = r.u8()?;
+    let Some(value) = r.object(t)? else {
+        bail!("DUMP payload type {} is not supported", t);
+    };
+    if r.remaining() != 0 {
+        bail!("Bad data format");
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BulkString;
+
+    #[test]
+    fn test_dump_encode_decode() -> Result<()> {
+        // Redis 文档中 `SET mykey 10` 之后 `DUMP mykey` 的输出
+        let value = StoredValue::String(BulkString::new("10").into());
+        let data = encode_dump(&value);
+        assert_eq!(data, b"\x00\xc0\n\t\x00\xbem\x06\x89Z(\x00\n".to_vec());
+        assert_eq!(decode_dump(&data)?, value);
+
+        let mut corrupted = data.clone();
+        corrupted[2] ^= 0xff;
+        assert!(decode_dump(&corrupted).is_err());
+        assert!(decode_dump(&data[..data.len() - 1]).is_err());
+        Ok(())
+    }
+}
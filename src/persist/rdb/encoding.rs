@@ -0,0 +1,209 @@
+//! Redis 的紧凑编码: ziplist, listpack, intset, zipmap. 整数元素统一转为十进制字符串.
+
+use anyhow::{bail, Result};
+
+const ZIPLIST_HEADER_LEN: usize = 10;
+const LISTPACK_HEADER_LEN: usize = 6;
+const END: u8 = 0xff;
+
+// 从 data[pos..] 读取 n 个字节
+fn slice(data: &[u8], pos: usize, n: usize) -> Result<&[u8]> {
+    match data.get(pos..pos + n) {
+        Some(s) => Ok(s),
+        None => bail!("unexpected end of encoded data"),
+    }
+}
+
+// 读取 n 字节的小端有符号整数
+fn int_le(data: &[u8], pos: usize, n: usize) -> Result<i64> {
+    let mut buf = [0u8; 8];
+    buf[..n].copy_from_slice(slice(data, pos, n)?);
+    // 左移再算术右移完成符号扩展
+    let shift = 64 - 8 * n as u32;
+    Ok(i64::from_le_bytes(buf) << shift >> shift)
+}
+
+fn int_entry(v: i64) -> Vec<u8> {
+    v.to_string().into_bytes()
+}
+
+/// ziplist: `<zlbytes:u32><zltail:u32><zllen:u16> {<prevlen><encoding><data>}* 0xff`
+pub fn ziplist_entries(data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut entries = Vec::new();
+    let mut pos = ZIPLIST_HEADER_LEN;
+    loop {
+        let prevlen = slice(data, pos, 1)?[0];
+        if prevlen == END {
+            return Ok(entries);
+        }
+        pos += if prevlen < 254 { 1 } else { 5 };
+        let enc = slice(data, pos, 1)?[0];
+        pos += 1;
+        let entry = match enc >> 6 {
+            0 => {
+                let len = (enc & 0x3f) as usize;
+                let s = slice(data, pos, len)?;
+                pos += len;
+                s.to_vec()
+            }
+            1 => {
+                let len = ((enc as usize & 0x3f) << 8) | slice(data, pos, 1)?[0] as usize;
+                let s = slice(data, pos + 1, len)?;
+                pos += 1 + len;
+                s.to_vec()
+            }
+            2 => {
+                let len = u32::from_be_bytes(slice(data, pos, 4)?.try_into()?) as usize;
+                let s = slice(data, pos + 4, len)?;
+                pos += 4 + len;
+                s.to_vec()
+            }
+            _ => {
+                let (n, v) = match enc {
+                    0xc0 => (2, int_le(data, pos, 2)?),
+                    0xd0 => (4, int_le(data, pos, 4)?),
+                    0xe0 => (8, int_le(data, pos, 8)?),
+                    0xf0 => (3, int_le(data, pos, 3)?),
+                    0xfe => (1, int_le(data, pos, 1)?),
+                    // 1111xxxx: 立即数 xxxx - 1, 取值 0..=12
+                    0xf1..=0xfd => (0, (enc & 0x0f) as i64 - 1),
+                    _ => bail!("invalid ziplist encoding: {:#x}", enc),
+                };
+                pos += n;
+                int_entry(v)
+            }
+        };
+        entries.push(entry);
+    }
+}
+
+/// listpack: `<total-bytes:u32><num-elements:u16> {<encoding><data><backlen>}* 0xff`
+pub fn listpack_entries(data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut entries = Vec::new();
+    let mut pos = LISTPACK_HEADER_LEN;
+    loop {
+        let enc = slice(data, pos, 1)?[0];
+        if enc == END {
+            return Ok(entries);
+        }
+        let (entry, len) = match enc {
+            // 0xxxxxxx: 7 位无符号整数
+            0x00..=0x7f => (int_entry(enc as i64), 1),
+            // 10xxxxxx: 6 位长度的字符串
+            0x80..=0xbf => {
+                let n = (enc & 0x3f) as usize;
+                (slice(data, pos + 1, n)?.to_vec(), 1 + n)
+            }
+            // 110xxxxx: 13 位有符号整数
+            0xc0..=0xdf => {
+                let v = ((enc as i64 & 0x1f) << 8) | slice(data, pos + 1, 1)?[0] as i64;
+                let v = if v >= 1 << 12 { v - (1 << 13) } else { v };
+                (int_entry(v), 2)
+            }
+            // 1110xxxx: 12 位长度的字符串
+            0xe0..=0xef => {
+                let n = ((enc as usize & 0x0f) << 8) | slice(data, pos + 1, 1)?[0] as usize;
+                (slice(data, pos + 2, n)?.to_vec(), 2 + n)
+            }
+            0xf0 => {
+                let n = u32::from_le_bytes(slice(data, pos + 1, 4)?.try_into()?) as usize;
+                (slice(data, pos + 5, n)?.to_vec(), 5 + n)
+            }
+            0xf1 => (int_entry(int_le(data, pos + 1, 2)?), 3),
+            0xf2 => (int_entry(int_le(data, pos + 1, 3)?), 4),
+            0xf3 => (int_entry(int_le(data, pos + 1, 4)?), 5),
+            0xf4 => (int_entry(int_le(data, pos + 1, 8)?), 9),
+            _ => bail!("invalid listpack encoding: {:#x}", enc),
+        };
+        entries.push(entry);
+        // backlen 记录 encoding + data 的长度, 每字节 7 位
+        pos += len + backlen_size(len);
+    }
+}
+
+fn backlen_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+/// intset: `<encoding:u32><length:u32><contents>`, encoding 为每个整数的字节数
+pub fn intset_entries(data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let width = u32::from_le_bytes(slice(data, 0, 4)?.try_into()?) as usize;
+    if !matches!(width, 2 | 4 | 8) {
+        bail!("invalid intset encoding: {}", width);
+    }
+    let len = u32::from_le_bytes(slice(data, 4, 4)?.try_into()?) as usize;
+    (0..len)
+        .map(|i| Ok(int_entry(int_le(data, 8 + i * width, width)?)))
+        .collect()
+}
+
+/// zipmap (Redis 2.6 之前的小 hash): `<zmlen:u8> {<len>key<len><free>value<free bytes>}* 0xff`
+pub fn zipmap_entries(data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut entries = Vec::new();
+    let mut pos = 1;
+    loop {
+        let b = slice(data, pos, 1)?[0];
+        if b == END {
+            if !entries.len().is_multiple_of(2) {
+                bail!("zipmap has a field without value");
+            }
+            return Ok(entries);
+        }
+        let (len, n) = match b {
+            0..=253 => (b as usize, 1),
+            254 => (
+                u32::from_le_bytes(slice(data, pos + 1, 4)?.try_into()?) as usize,
+                5,
+            ),
+            _ => unreachable!(),
+        };
+        pos += n;
+        // value 之前有 1 字节的 free, 表示 value 之后未使用的字节数
+        let free = if entries.len() % 2 == 1 {
+            pos += 1;
+            slice(data, pos - 1, 1)?[0] as usize
+        } else {
+            0
+        };
+        entries.push(slice(data, pos, len)?.to_vec());
+        pos += len + free;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listpack_entries() -> Result<()> {
+        let mut lp = vec![0, 0, 0, 0, 4, 0];
+        // 7 位整数 5
+        lp.extend_from_slice(&[0x05, 0x01]);
+        // 字符串 "ab"
+        lp.extend_from_slice(&[0x82, b'a', b'b', 0x03]);
+        // 13 位整数 -1
+        lp.extend_from_slice(&[0xdf, 0xff, 0x02]);
+        // 24 位整数 -100000
+        lp.push(0xf2);
+        lp.extend_from_slice(&(-100000i32).to_le_bytes()[..3]);
+        lp.push(0x04);
+        lp.push(END);
+        assert_eq!(
+            listpack_entries(&lp)?,
+            vec![
+                b"5".to_vec(),
+                b"ab".to_vec(),
+                b"-1".to_vec(),
+                b"-100000".to_vec()
+            ]
+        );
+        assert!(listpack_entries(&lp[..lp.len() - 1]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+use anyhow::{bail, Result};
+
+/// 解压 LZF 数据, expected_len 为解压后的长度.
+///
+/// 每段以控制字节开始: 小于 32 时表示其后 `ctrl + 1` 字节为字面量;
+/// 否则为回溯引用, 高 3 位为长度 (7 表示再读一个字节累加), 低 5 位与下一个字节组成偏移.
+pub fn lzf_decompress(input: &[u8], expected_len: usize) -> Result<Vec<u8>> {
+    // expected_len 来自文件, 不可信; 每 3 字节输入最多展开为 264 字节
+    let mut out = Vec::with_capacity(expected_len.min(input.len().saturating_mul(88)));
+    let mut i = 0;
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+        if ctrl < 32 {
+            let len = ctrl + 1;
+            let Some(literal) = input.get(i..i + len) else {
+                bail!("lzf: literal run out of bounds");
+            };
+            out.extend_from_slice(literal);
+            i += len;
+        } else {
+            let mut len = ctrl >> 5;
+            if len == 7 {
+                let Some(&b) = input.get(i) else {
+                    bail!("lzf: unexpected end of input");
+                };
+                len += b as usize;
+                i += 1;
+            }
+            let Some(&b) = input.get(i) else {
+                bail!("lzf: unexpected end of input");
+            };
+            i += 1;
+            let offset = ((ctrl & 0x1f) << 8) + b as usize + 1;
+            if offset > out.len() {
+                bail!("lzf: back reference out of bounds");
+            }
+            // 引用区间可能与输出重叠, 需要逐字节复制
+            let start = out.len() - offset;
+            for k in 0..len + 2 {
+                out.push(out[start + k]);
+            }
+        }
+        if out.len() > expected_len {
+            bail!("lzf: decompressed data too long");
+        }
+    }
+    if out.len() != expected_len {
+        bail!(
+            "lzf: expected {} bytes, got {} bytes",
+            expected_len,
+            out.len()
+        );
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lzf_untrusted_len() {
+        assert!(lzf_decompress(b"\x00a", usize::MAX).is_err());
+        assert_eq!(
+            lzf_decompress(b"\x00a\xe0\x00\x00", 10).unwrap(),
+            b"aaaaaaaaaa"
+        );
+    }
+}
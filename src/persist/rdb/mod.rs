@@ -0,0 +1,73 @@
+//! Redis RDB 文件格式的读写.
+//!
+//! ```text
+//! "REDIS" <version:4 位十进制>
+//! { AUX <key> <value> | SELECTDB <db> | RESIZEDB <size> <expires> | [EXPIRETIME] <type> <key> <value> }*
+//! EOF <crc64:u64>
+//! ```
+
+mod dump;
+mod encoding;
+mod lzf;
+mod reader;
+mod writer;
+
+pub use dump::{decode_dump, encode_dump};
+pub use reader::{decode_rdb, is_rdb, RdbFile};
+pub use writer::encode_rdb;
+
+const MAGIC: &[u8] = b"REDIS";
+/// 可以读取的最高版本 (Redis 7.4)
+const RDB_VERSION: u32 = 12;
+
+// 值的类型
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_MODULE_PRE_GA: u8 = 6;
+const TYPE_MODULE_2: u8 = 7;
+const TYPE_HASH_ZIPMAP: u8 = 9;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_STREAM_LISTPACKS: u8 = 15;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const TYPE_SET_LISTPACK: u8 = 20;
+const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+const TYPE_HASH_METADATA: u8 = 24;
+const TYPE_HASH_LISTPACK_EX: u8 = 25;
+
+// 操作码
+const OPCODE_SLOT_INFO: u8 = 0xf4;
+const OPCODE_FUNCTION2: u8 = 0xf5;
+const OPCODE_FUNCTION_PRE_GA: u8 = 0xf6;
+const OPCODE_MODULE_AUX: u8 = 0xf7;
+const OPCODE_IDLE: u8 = 0xf8;
+const OPCODE_FREQ: u8 = 0xf9;
+const OPCODE_AUX: u8 = 0xfa;
+const OPCODE_RESIZEDB: u8 = 0xfb;
+const OPCODE_EXPIRETIME_MS: u8 = 0xfc;
+const OPCODE_EXPIRETIME: u8 = 0xfd;
+const OPCODE_SELECTDB: u8 = 0xfe;
+const OPCODE_EOF: u8 = 0xff;
+
+// 长度编码: 最高 2 位
+const LEN_6BIT: u8 = 0;
+const LEN_14BIT: u8 = 1;
+const LEN_32BIT: u8 = 0x80;
+const LEN_64BIT: u8 = 0x81;
+const LEN_ENCVAL: u8 = 3;
+
+// 特殊编码的字符串
+const ENC_INT8: u8 = 0;
+const ENC_INT16: u8 = 1;
+const ENC_INT32: u8 = 2;
+const ENC_LZF: u8 = 3;
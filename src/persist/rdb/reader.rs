@@ -0,0 +1,567 @@
+use anyhow::{bail, Result};
+use tracing::warn;
+
+use crate::{backend::now_ms, persist::crc64, BulkString, RespFrame};
+use crate::{Snapshot, SnapshotEntry, SortedSet, StoredValue};
+
+use super::encoding::{intset_entries, listpack_entries, ziplist_entries, zipmap_entries};
+use super::lzf::lzf_decompress;
+use super::*;
+
+/// 解析后的 RDB 文件
+#[derive(Debug, Default, PartialEq)]
+pub struct RdbFile {
+    pub version: u32,
+    pub aux: Vec<(String, String)>,
+    /// 各个数据库的数据, 按 SELECTDB 出现的顺序
+    pub databases: Vec<(u64, Snapshot)>,
+    /// 因类型不受支持 (list, set) 而跳过的 key
+    pub skipped: Vec<String>,
+}
+
+impl RdbFile {
+    /// 取出某个数据库的数据, 其余数据库及跳过的 key 记录警告
+    pub fn into_snapshot(self, db: u64) -> Snapshot {
+        if !self.skipped.is_empty() {
+            warn!(
+                "RDB: skipped {} keys of unsupported types",
+                self.skipped.len()
+            );
+        }
+        let mut snapshot = Snapshot::default();
+        for (n, s) in self.databases {
+            if n == db {
+                snapshot = s;
+            } else {
+                warn!("RDB: skipped {} keys in db {}", s.entries.len(), n);
+            }
+        }
+        snapshot
+    }
+}
+
+pub fn is_rdb(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn decode_rdb(data: &[u8]) -> Result<RdbFile> {
+    if data.len() < MAGIC.len() + 4 || !is_rdb(data) {
+        bail!("not an RDB file");
+    }
+    let version: u32 = std::str::from_utf8(&data[MAGIC.len()..MAGIC.len() + 4])?.parse()?;
+    if version == 0 || version > RDB_VERSION {
+        bail!("unsupported RDB version: {}", version);
+    }
+
+    let mut r = RdbReader::new(&data[MAGIC.len() + 4..]);
+    let mut file = RdbFile {
+        version,
+        ..Default::default()
+    };
+    let mut expire_at = None;
+    // 当前数据库在 file.databases 中的下标
+    let mut current = None;
+    loop {
+        let opcode = r.u8()?;
+        match opcode {
+            OPCODE_EOF => break,
+            OPCODE_SELECTDB => {
+                let db = r.len()?;
+                current = match file.databases.iter().position(|(n, _)| *n == db) {
+                    Some(i) => Some(i),
+                    None => {
+                        file.databases.push((db, Snapshot::default()));
+                        Some(file.databases.len() - 1)
+                    }
+                };
+            }
+            OPCODE_RESIZEDB => {
+                r.len()?;
+                r.len()?;
+            }
+            OPCODE_AUX => {
+                let key = String::from_utf8_lossy(&r.string()?).into_owned();
+                let value = String::from_utf8_lossy(&r.string()?).into_owned();
+                file.aux.push((key, value));
+            }
+            OPCODE_EXPIRETIME_MS => expire_at = Some(r.i64_le()?),
+            OPCODE_EXPIRETIME => expire_at = Some(r.int_le(4)? * 1000),
+            // LRU / LFU 信息, 忽略
+            OPCODE_IDLE => {
+                r.len()?;
+            }
+            OPCODE_FREQ => {
+                r.u8()?;
+            }
+            OPCODE_SLOT_INFO => {
+                for _ in 0..3 {
+                    r.len()?;
+                }
+            }
+            // 函数库不是 key, 跳过
+            OPCODE_FUNCTION2 => {
+                r.string()?;
+            }
+            OPCODE_FUNCTION_PRE_GA | OPCODE_MODULE_AUX => {
+                bail!("unsupported RDB opcode: {:#x}", opcode)
+            }
+            _ => {
+                let key = r.utf8()?;
+                let value = r.object(opcode)?;
+                let expire_at = expire_at.take();
+                let Some(value) = value else {
+                    file.skipped.push(key);
+                    continue;
+                };
+                // 没有 SELECTDB 时默认为 db 0
+                let db = *current.get_or_insert_with(|| {
+                    file.databases.push((0, Snapshot::default()));
+                    file.databases.len() - 1
+                });
+                file.databases[db].1.entries.push(SnapshotEntry {
+                    key,
+                    value,
+                    expire_at,
+                });
+            }
+        }
+    }
+
+    // 版本 5 起 EOF 之后是 CRC64 校验和, 为 0 表示未计算
+    if version >= 5 {
+        let body_len = data.len() - r.remaining();
+        let checksum = r.u64_le()?;
+        if checksum != 0 && checksum != crc64(0, &data[..body_len]) {
+            bail!("RDB checksum mismatch");
+        }
+    }
+    Ok(file)
+}
+
+pub(super) struct RdbReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> RdbReader<'a> {
+    pub(super) fn new(buf: &'a [u8]) -> Self {
+        RdbReader { buf }
+    }
+
+    pub(super) fn remaining(&self) -> usize {
+        self.buf.len()
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.buf.len() < n {
+            bail!("unexpected end of RDB file");
+        }
+        let (data, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(data)
+    }
+
+    pub(super) fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn int_le(&mut self, n: usize) -> Result<i64> {
+        let mut buf = [0u8; 8];
+        buf[..n].copy_from_slice(self.take(n)?);
+        let shift = 64 - 8 * n as u32;
+        Ok(i64::from_le_bytes(buf) << shift >> shift)
+    }
+
+    fn i64_le(&mut self) -> Result<i64> {
+        self.int_le(8)
+    }
+
+    fn u64_le(&mut self) -> Result<u64> {
+        Ok(self.int_le(8)? as u64)
+    }
+
+    // 返回 (长度, 是否为特殊编码)
+    fn length(&mut self) -> Result<(u64, bool)> {
+        let b = self.u8()?;
+        Ok(match b >> 6 {
+            LEN_6BIT => ((b & 0x3f) as u64, false),
+            LEN_14BIT => ((((b & 0x3f) as u64) << 8) | self.u8()? as u64, false),
+            LEN_ENCVAL => ((b & 0x3f) as u64, true),
+            _ => match b {
+                LEN_32BIT => (u32::from_be_bytes(self.take(4)?.try_into()?) as u64, false),
+                LEN_64BIT => (u64::from_be_bytes(self.take(8)?.try_into()?), false),
+                _ => bail!("invalid RDB length encoding: {:#x}", b),
+            },
+        })
+    }
+
+    fn len(&mut self) -> Result<u64> {
+        match self.length()? {
+            (len, false) => Ok(len),
+            (_, true) => bail!("unexpected encoded value in RDB length"),
+        }
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>> {
+        let (len, encoded) = self.length()?;
+        if !encoded {
+            return Ok(self.take(len as usize)?.to_vec());
+        }
+        let v = match len as u8 {
+            ENC_INT8 => self.int_le(1)?,
+            ENC_INT16 => self.int_le(2)?,
+            ENC_INT32 => self.int_le(4)?,
+            ENC_LZF => {
+                let clen = self.len()? as usize;
+                let ulen = self.len()? as usize;
+                return lzf_decompress(self.take(clen)?, ulen);
+            }
+            enc => bail!("invalid RDB string encoding: {}", enc),
+        };
+        Ok(v.to_string().into_bytes())
+    }
+
+    fn utf8(&mut self) -> Result<String> {
+        Ok(String::from_utf8(self.string()?)?)
+    }
+
+    // 旧版 zset 的 score 以字符串保存, 253/254/255 分别表示 nan/+inf/-inf
+    fn double_string(&mut self) -> Result<f64> {
+        match self.u8()? {
+            253 => bail!("invalid zset score: nan"),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            len => parse_score(self.take(len as usize)?),
+        }
+    }
+
+    /// 读取一个值, 不支持的类型 (list, set) 会被完整读取并返回 None
+    pub(super) fn object(&mut self, t: u8) -> Result<Option<StoredValue>> {
+        let value = match t {
+            TYPE_STRING => StoredValue::String(bulk(self.string()?)),
+            TYPE_HASH => {
+                let len = self.len()?;
+                let mut fields = Vec::new();
+                for _ in 0..len {
+                    fields.push((self.utf8()?, bulk(self.string()?)));
+                }
+                StoredValue::Hash(fields)
+            }
+            TYPE_HASH_ZIPMAP => hash_from_entries(zipmap_entries(&self.string()?)?)?,
+            TYPE_HASH_ZIPLIST => hash_from_entries(ziplist_entries(&self.string()?)?)?,
+            TYPE_HASH_LISTPACK => hash_from_entries(listpack_entries(&self.string()?)?)?,
+            // 带有字段过期时间的 hash: 丢弃已过期的字段, 其余字段不保留过期时间
+            TYPE_HASH_METADATA => {
+                let min_expire = self.i64_le()?;
+                let len = self.len()?;
+                let now = now_ms();
+                let mut fields = Vec::new();
+                for _ in 0..len {
+                    let ttl = self.len()?;
+                    let field = self.utf8()?;
+                    let value = self.string()?;
+                    if ttl == 0 || min_expire + ttl as i64 - 1 > now {
+                        fields.push((field, bulk(value)));
+                    }
+                }
+                StoredValue::Hash(fields)
+            }
+            TYPE_HASH_LISTPACK_EX => {
+                self.i64_le()?;
+                let entries = listpack_entries(&self.string()?)?;
+                if !entries.len().is_multiple_of(3) {
+                    bail!("invalid hash listpack with field expiration");
+                }
+                let now = now_ms();
+                let mut fields = Vec::new();
+                for c in entries.chunks(3) {
+                    let ttl: i64 = std::str::from_utf8(&c[2])?.parse()?;
+                    if ttl == 0 || ttl > now {
+                        fields.push((String::from_utf8(c[0].clone())?, bulk(c[1].clone())));
+                    }
+                }
+                StoredValue::Hash(fields)
+            }
+            TYPE_ZSET | TYPE_ZSET_2 => {
+                let len = self.len()?;
+                let mut zset = SortedSet::new();
+                for _ in 0..len {
+                    let member = self.utf8()?;
+                    let score = if t == TYPE_ZSET {
+                        self.double_string()?
+                    } else {
+                        f64::from_le_bytes(self.take(8)?.try_into()?)
+                    };
+                    zset.insert(member, score);
+                }
+                StoredValue::ZSet(zset)
+            }
+            TYPE_ZSET_ZIPLIST => zset_from_entries(ziplist_entries(&self.string()?)?)?,
+            TYPE_ZSET_LISTPACK => zset_from_entries(listpack_entries(&self.string()?)?)?,
+            TYPE_LIST | TYPE_SET => {
+                for _ in 0..self.len()? {
+                    self.string()?;
+                }
+                return Ok(None);
+            }
+            TYPE_LIST_ZIPLIST => {
+                ziplist_entries(&self.string()?)?;
+                return Ok(None);
+            }
+            TYPE_SET_INTSET => {
+                intset_entries(&self.string()?)?;
+                return Ok(None);
+            }
+            TYPE_SET_LISTPACK => {
+                listpack_entries(&self.string()?)?;
+                return Ok(None);
+            }
+            TYPE_LIST_QUICKLIST => {
+                for _ in 0..self.len()? {
+                    ziplist_entries(&self.string()?)?;
+                }
+                return Ok(None);
+            }
+            TYPE_LIST_QUICKLIST_2 => {
+                // 节点容器: 1 为单个元素, 2 为 listpack
+                for _ in 0..self.len()? {
+                    let container = self.len()?;
+                    let node = self.string()?;
+                    if container == 2 {
+                        listpack_entries(&node)?;
+                    }
+                }
+                return Ok(None);
+            }
+            TYPE_MODULE_PRE_GA
+            | TYPE_MODULE_2
+            | TYPE_STREAM_LISTPACKS
+            | TYPE_STREAM_LISTPACKS_2
+            | TYPE_STREAM_LISTPACKS_3 => bail!("unsupported RDB value type: {}", t),
+            _ => bail!("unknown RDB value type: {}", t),
+        };
+        Ok(Some(value))
+    }
+}
+
+fn bulk(data: Vec<u8>) -> RespFrame {
+    BulkString::new(data).into()
+}
+
+fn parse_score(data: &[u8]) -> Result<f64> {
+    let score: f64 = std::str::from_utf8(data)?.parse()?;
+    if score.is_nan() {
+        bail!("invalid zset score: nan");
+    }
+    Ok(score)
+}
+
+// 紧凑编码的 hash: field, value 交替排列
+fn hash_from_entries(entries: Vec<Vec<u8>>) -> Result<StoredValue> {
+    if !entries.len().is_multiple_of(2) {
+        bail!("hash has a field without value");
+    }
+    let mut fields = Vec::with_capacity(entries.len() / 2);
+    let mut iter = entries.into_iter();
+    while let (Some(field), Some(value)) = (iter.next(), iter.next()) {
+        fields.push((String::from_utf8(field)?, bulk(value)));
+    }
+    Ok(StoredValue::Hash(fields))
+}
+
+// 紧凑编码的 zset: member, score 交替排列
+fn zset_from_entries(entries: Vec<Vec<u8>>) -> Result<StoredValue> {
+    if !entries.len().is_multiple_of(2) {
+        bail!("zset has a member without score");
+    }
+    let mut zset = SortedSet::new();
+    let mut iter = entries.into_iter();
+    while let (Some(member), Some(score)) = (iter.next(), iter.next()) {
+        zset.insert(String::from_utf8(member)?, parse_score(&score)?);
+    }
+    Ok(StoredValue::ZSet(zset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRINGS: &[u8] = include_bytes!("../../../fixtures/rdb/strings.rdb");
+    const HASHES: &[u8] = include_bytes!("../../../fixtures/rdb/hashes.rdb");
+    const ZSETS: &[u8] = include_bytes!("../../../fixtures/rdb/zsets.rdb");
+    const MULTIDB: &[u8] = include_bytes!("../../../fixtures/rdb/multidb.rdb");
+
+    fn find<'a>(snapshot: &'a Snapshot, key: &str) -> &'a SnapshotEntry {
+        snapshot.entries.iter().find(|e| e.key == key).unwrap()
+    }
+
+    fn string(snapshot: &Snapshot, key: &str) -> Vec<u8> {
+        match &find(snapshot, key).value {
+            StoredValue::String(RespFrame::BulkString(s)) => s.to_vec(),
+            v => panic!("{} is not a string: {:?}", key, v),
+        }
+    }
+
+    fn hash(snapshot: &Snapshot, key: &str) -> Vec<(String, String)> {
+        let StoredValue::Hash(fields) = &find(snapshot, key).value else {
+            panic!("{} is not a hash", key);
+        };
+        fields
+            .iter()
+            .map(|(f, v)| match v {
+                RespFrame::BulkString(s) => (f.clone(), String::from_utf8_lossy(s).into_owned()),
+                _ => panic!("hash value must be a bulk string"),
+            })
+            .collect()
+    }
+
+    fn zset(snapshot: &Snapshot, key: &str) -> Vec<(String, f64)> {
+        let StoredValue::ZSet(zset) = &find(snapshot, key).value else {
+            panic!("{} is not a zset", key);
+        };
+        zset.iter().map(|(m, s)| (m.to_string(), s)).collect()
+    }
+
+    fn pairs(v: &[(&str, &str)]) -> Vec<(String, String)> {
+        v.iter()
+            .map(|(f, v)| (f.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_rdb_strings() -> Result<()> {
+        let rdb = decode_rdb(STRINGS)?;
+        assert_eq!(rdb.version, 9);
+        assert!(rdb
+            .aux
+            .contains(&("fixture".to_string(), "hand-built".to_string())));
+        assert!(rdb
+            .aux
+            .contains(&("redis-bits".to_string(), "64".to_string())));
+        let snapshot = rdb.into_snapshot(0);
+        assert_eq!(snapshot.entries.len(), 11);
+
+        assert_eq!(string(&snapshot, "plain"), b"hello world");
+        assert_eq!(string(&snapshot, "int8"), b"123");
+        assert_eq!(string(&snapshot, "int16"), b"-12345");
+        assert_eq!(string(&snapshot, "int32"), b"1234567890");
+        assert_eq!(
+            string(&snapshot, "lzf"),
+            format!("{}!", "redis".repeat(50)).as_bytes()
+        );
+        assert_eq!(string(&snapshot, "len14"), vec![b'x'; 300]);
+        assert_eq!(string(&snapshot, "len32"), vec![b'y'; 70000]);
+        assert_eq!(string(&snapshot, "idle"), b"v");
+        assert_eq!(find(&snapshot, "idle").expire_at, None);
+        assert_eq!(find(&snapshot, "future").expire_at, Some(4_102_444_800_000));
+        assert_eq!(
+            find(&snapshot, "future_sec").expire_at,
+            Some(2_000_000_000_000)
+        );
+        assert_eq!(
+            find(&snapshot, "expired").expire_at,
+            Some(1_000_000_000_000)
+        );
+
+        // 校验和错误或文件被截断
+        let mut corrupted = STRINGS.to_vec();
+        corrupted[100] ^= 0xff;
+        assert!(decode_rdb(&corrupted).is_err());
+        assert!(decode_rdb(&STRINGS[..STRINGS.len() - 9]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_rdb_compact_encodings() -> Result<()> {
+        let snapshot = decode_rdb(HASHES)?.into_snapshot(0);
+        assert_eq!(
+            hash(&snapshot, "hash"),
+            pairs(&[("name", "alice"), ("age", "30")])
+        );
+        assert_eq!(
+            hash(&snapshot, "hash_zipmap"),
+            pairs(&[("a", "1"), ("bb", "22")])
+        );
+        let long = "z".repeat(100);
+        assert_eq!(
+            hash(&snapshot, "hash_ziplist"),
+            pairs(&[
+                ("f1", "v1"),
+                ("n", "7"),
+                ("neg", "-200"),
+                ("big", "100000"),
+                ("big64", "1099511627776"),
+                ("i16", "1000"),
+                ("i32", "-1073741824"),
+                ("long", &long),
+            ])
+        );
+        let str12 = "q".repeat(200);
+        assert_eq!(
+            hash(&snapshot, "hash_listpack"),
+            pairs(&[
+                ("f1", "v1"),
+                ("small", "5"),
+                ("neg13", "-100"),
+                ("i16", "30000"),
+                ("i24", "-1000000"),
+                ("i32", "100000000"),
+                ("i64", "-1099511627776"),
+                ("str12", &str12),
+            ])
+        );
+
+        // list 和 set 不受支持, 读取后跳过
+        let rdb = decode_rdb(ZSETS)?;
+        assert_eq!(rdb.skipped.len(), 7);
+        let snapshot = rdb.into_snapshot(0);
+        assert_eq!(snapshot.entries.len(), 4);
+        assert_eq!(
+            zset(&snapshot, "zset_v1"),
+            vec![
+                ("c".to_string(), -3.0),
+                ("a".to_string(), 1.5),
+                ("b".to_string(), f64::INFINITY)
+            ]
+        );
+        assert_eq!(
+            zset(&snapshot, "zset_v2"),
+            vec![("y".to_string(), -1e10), ("x".to_string(), 0.25)]
+        );
+        assert_eq!(
+            zset(&snapshot, "zset_ziplist"),
+            vec![
+                ("m3".to_string(), -7.0),
+                ("m1".to_string(), 1.0),
+                ("m2".to_string(), 2.5)
+            ]
+        );
+        assert_eq!(
+            zset(&snapshot, "zset_listpack"),
+            vec![("p2".to_string(), 2.75), ("p1".to_string(), 10.0)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_rdb_multiple_dbs() -> Result<()> {
+        let rdb = decode_rdb(MULTIDB)?;
+        assert_eq!(rdb.version, 12);
+        let dbs = rdb
+            .databases
+            .iter()
+            .map(|(n, s)| (*n, s.entries.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(dbs, vec![(0, 3), (1, 1), (2, 2)]);
+
+        let snapshot = rdb.into_snapshot(0);
+        assert_eq!(string(&snapshot, "db0key"), b"zero");
+        // 已过期的字段被丢弃
+        let expected = pairs(&[("f1", "v1"), ("f3", "v3")]);
+        assert_eq!(hash(&snapshot, "hash_meta"), expected);
+        assert_eq!(hash(&snapshot, "hash_lpex"), expected);
+
+        let snapshot = decode_rdb(MULTIDB)?.into_snapshot(2);
+        assert_eq!(string(&snapshot, "db2other"), b"2");
+        Ok(())
+    }
+}
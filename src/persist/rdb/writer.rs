@@ -0,0 +1,214 @@
+use bytes::BufMut;
+
+use crate::{backend::now_ms, persist::crc64, RespEncode, RespFrame, Snapshot, StoredValue};
+
+use super::*;
+
+/// 写出的 RDB 版本, Redis 5.0 及之后的版本都可以读取
+pub(super) const WRITE_VERSION: u32 = 9;
+
+/// 将快照编码为 RDB 文件, 全部写入 db 0
+pub fn encode_rdb(snapshot: &Snapshot) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(4096);
+    buf.put_slice(MAGIC);
+    buf.put_slice(format!("{:04}", WRITE_VERSION).as_bytes());
+    put_aux(&mut buf, "redis-bits", "64");
+    put_aux(&mut buf, "ctime", &(now_ms() / 1000).to_string());
+    put_aux(&mut buf, "simple-redis-ver", env!("CARGO_PKG_VERSION"));
+
+    buf.put_u8(OPCODE_SELECTDB);
+    put_len(&mut buf, 0);
+    // Redis 不允许空的 hash 和 zset, RESIZEDB 也只计入实际写出的 key
+    let entries: Vec<_> = snapshot
+        .entries
+        .iter()
+        .filter(|e| match &e.value {
+            StoredValue::Hash(fields) => !fields.is_empty(),
+            StoredValue::ZSet(zset) => !zset.is_empty(),
+            StoredValue::String(_) => true,
+        })
+        .collect();
+    let expires = entries.iter().filter(|e| e.expire_at.is_some());
+    buf.put_u8(OPCODE_RESIZEDB);
+    put_len(&mut buf, entries.len() as u64);
+    put_len(&mut buf, expires.count() as u64);
+
+    for entry in entries {
+        if let Some(t) = entry.expire_at {
+            buf.put_u8(OPCODE_EXPIRETIME_MS);
+            buf.put_i64_le(t);
+        }
+        buf.put_u8(value_type(&entry.value));
+        put_string(&mut buf, entry.key.as_bytes());
+        put_value(&mut buf, &entry.value);
+    }
+
+    buf.put_u8(OPCODE_EOF);
+    let checksum = crc64(0, &buf);
+    buf.put_u64_le(checksum);
+    buf
+}
+
+pub(super) fn value_type(value: &StoredValue) -> u8 {
+    match value {
+        StoredValue::String(_) => TYPE_STRING,
+        StoredValue::Hash(_) => TYPE_HASH,
+        StoredValue::ZSet(_) => TYPE_ZSET_2,
+    }
+}
+
+/// 写入值本身, 类型由 value_type 给出
+pub(super) fn put_value(buf: &mut Vec<u8>, value: &StoredValue) {
+    match value {
+        StoredValue::String(v) => put_string(buf, &frame_bytes(v)),
+        StoredValue::Hash(fields) => {
+            put_len(buf, fields.len() as u64);
+            for (field, v) in fields {
+                put_string(buf, field.as_bytes());
+                put_string(buf, &frame_bytes(v));
+            }
+        }
+        StoredValue::ZSet(zset) => {
+            put_len(buf, zset.len() as u64);
+            for (member, score) in zset.iter() {
+                put_string(buf, member.as_bytes());
+                buf.put_f64_le(score);
+            }
+        }
+    }
+}
+
+// Redis 的值只有字符串: 标量取其文本, 其余类型保存 RESP 编码
+fn frame_bytes(frame: &RespFrame) -> Vec<u8> {
+    match frame {
+        RespFrame::BulkString(s) => s.to_vec(),
+        RespFrame::SimpleString(s) => s.as_bytes().to_vec(),
+        RespFrame::Integer(n) => n.to_string().into_bytes(),
+        RespFrame::Double(d) => d.to_string().into_bytes(),
+        v => v.clone().encode(),
+    }
+}
+
+fn put_aux(buf: &mut Vec<u8>, key: &str, value: &str) {
+    buf.put_u8(OPCODE_AUX);
+    put_string(buf, key.as_bytes());
+    put_string(buf, value.as_bytes());
+}
+
+fn put_len(buf: &mut Vec<u8>, len: u64) {
+    if len < 1 << 6 {
+        buf.put_u8((LEN_6BIT << 6) | len as u8);
+    } else if len < 1 << 14 {
+        buf.put_u8((LEN_14BIT << 6) | (len >> 8) as u8);
+        buf.put_u8(len as u8);
+    } else if len <= u32::MAX as u64 {
+        buf.put_u8(LEN_32BIT);
+        buf.put_u32(len as u32);
+    } else {
+        buf.put_u8(LEN_64BIT);
+        buf.put_u64(len);
+    }
+}
+
+// 与 Redis 相同, 能无损转为 32 位整数的短字符串以整数编码保存
+fn put_string(buf: &mut Vec<u8>, data: &[u8]) {
+    if data.len() <= 11 {
+        let n = std::str::from_utf8(data)
+            .ok()
+            .and_then(|s| s.parse::<i64>().ok().filter(|n| n.to_string() == s));
+        if let Some(n) = n {
+            let enc = LEN_ENCVAL << 6;
+            if let Ok(n) = i8::try_from(n) {
+                buf.put_u8(enc | ENC_INT8);
+                buf.put_i8(n);
+                return;
+            } else if let Ok(n) = i16::try_from(n) {
+                buf.put_u8(enc | ENC_INT16);
+                buf.put_i16_le(n);
+                return;
+            } else if let Ok(n) = i32::try_from(n) {
+                buf.put_u8(enc | ENC_INT32);
+                buf.put_i32_le(n);
+                return;
+            }
+        }
+    }
+    put_len(buf, data.len() as u64);
+    buf.put_slice(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{persist::decode_rdb, BulkString, SnapshotEntry, SortedSet};
+
+    #[test]
+    fn test_encode_rdb() -> anyhow::Result<()> {
+        let mut zset = SortedSet::new();
+        zset.insert("a".to_string(), 1.5);
+        zset.insert("b".to_string(), f64::NEG_INFINITY);
+        let snapshot = Snapshot {
+            entries: vec![
+                SnapshotEntry {
+                    key: "str".to_string(),
+                    value: StoredValue::String(BulkString::new(vec![b'x'; 20000]).into()),
+                    expire_at: Some(4_102_444_800_000),
+                },
+                SnapshotEntry {
+                    key: "hash".to_string(),
+                    value: StoredValue::Hash(vec![(
+                        "field".to_string(),
+                        BulkString::new("value").into(),
+                    )]),
+                    expire_at: None,
+                },
+                SnapshotEntry {
+                    key: "zset".to_string(),
+                    value: StoredValue::ZSet(zset),
+                    expire_at: None,
+                },
+            ],
+        };
+        let data = encode_rdb(&snapshot);
+        assert!(data.starts_with(b"REDIS0009\xfa"));
+
+        let rdb = decode_rdb(&data)?;
+        assert_eq!(rdb.version, WRITE_VERSION);
+        assert!(rdb
+            .aux
+            .contains(&("redis-bits".to_string(), "64".to_string())));
+        assert_eq!(rdb.into_snapshot(0), snapshot);
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_rdb_skip_empty() -> anyhow::Result<()> {
+        let entry = |key: &str, value, expire_at| SnapshotEntry {
+            key: key.to_string(),
+            value,
+            expire_at,
+        };
+        let snapshot = Snapshot {
+            entries: vec![
+                entry(
+                    "str",
+                    StoredValue::String(BulkString::new("v").into()),
+                    None,
+                ),
+                entry("hash", StoredValue::Hash(vec![]), Some(4_102_444_800_000)),
+                entry("zset", StoredValue::ZSet(SortedSet::new()), None),
+            ],
+        };
+        let data = encode_rdb(&snapshot);
+        // RESIZEDB 只计入写出的 key: 1 个 key, 0 个过期时间
+        let pos = data
+            .windows(3)
+            .position(|w| w == [OPCODE_SELECTDB, 0, OPCODE_RESIZEDB])
+            .unwrap();
+        assert_eq!(&data[pos + 3..pos + 5], &[1, 0]);
+
+        let rdb = decode_rdb(&data)?;
+        assert_eq!(rdb.into_snapshot(0).entries, snapshot.entries[..1]);
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+use anyhow::{bail, Result};
+use bytes::{Buf, BufMut, BytesMut};
+
+use crate::{RespDecode, RespEncode, RespFrame, Snapshot, SnapshotEntry, SortedSet, StoredValue};
+
+use super::crc64::crc64;
+
+/// 快照文件格式:
+///
+/// ```text
+/// "SREDIS" <version:u8>
+/// { [EXPIRE <ms:i64>] <type:u8> <key> <value> }*
+/// EOF <crc64:u64>
+/// ```
+///
+/// 所有整数均为小端序, 字符串为 `<len:u32><bytes>`.
+/// 字符串类型的值以 RESP 编码保存, 因此任意 RespFrame 都可以原样恢复.
+const MAGIC: &[u8] = b"SREDIS";
+const VERSION: u8 = 1;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_HASH: u8 = 1;
+const TYPE_ZSET: u8 = 2;
+const OPCODE_EXPIRE: u8 = 0xfc;
+const OPCODE_EOF: u8 = 0xff;
+
+pub fn encode_snapshot(snapshot: &Snapshot) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(4096);
+    buf.put_slice(MAGIC);
+    buf.put_u8(VERSION);
+    for entry in &snapshot.entries {
+        if let Some(t) = entry.expire_at {
+            buf.put_u8(OPCODE_EXPIRE);
+            buf.put_i64_le(t);
+        }
+        match &entry.value {
+            StoredValue::String(v) => {
+                buf.put_u8(TYPE_STRING);
+                put_bytes(&mut buf, entry.key.as_bytes());
+                put_frame(&mut buf, v);
+            }
+            StoredValue::Hash(fields) => {
+                buf.put_u8(TYPE_HASH);
+                put_bytes(&mut buf, entry.key.as_bytes());
+                buf.put_u32_le(fields.len() as u32);
+                for (field, v) in fields {
+                    put_bytes(&mut buf, field.as_bytes());
+                    put_frame(&mut buf, v);
+                }
+            }
+            StoredValue::ZSet(zset) => {
+                buf.put_u8(TYPE_ZSET);
+                put_bytes(&mut buf, entry.key.as_bytes());
+                buf.put_u32_le(zset.len() as u32);
+                for (member, score) in zset.iter() {
+                    put_bytes(&mut buf, member.as_bytes());
+                    buf.put_f64_le(score);
+                }
+            }
+        }
+    }
+    buf.put_u8(OPCODE_EOF);
+    let checksum = crc64(0, &buf);
+    buf.put_u64_le(checksum);
+    buf
+}
+
+pub fn decode_snapshot(data: &[u8]) -> Result<Snapshot> {
+    let (snapshot, len) = decode_snapshot_prefix(data)?;
+    if len != data.len() {
+        bail!("unexpected data after snapshot");
+    }
+    Ok(snapshot)
+}
+
+pub fn is_snapshot(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// 解析位于 data 开头的快照, 返回快照及其占用的字节数, 之后的数据由调用方处理 (如 AOF 的增量命令)
+pub fn decode_snapshot_prefix(data: &[u8]) -> Result<(Snapshot, usize)> {
+    if data.len() < MAGIC.len() + 1 || !data.starts_with(MAGIC) {
+        bail!("not a snapshot file");
+    }
+    let mut buf = &data[MAGIC.len()..];
+    let version = buf.get_u8();
+    if version != VERSION {
+        bail!("unsupported snapshot version: {}", version);
+    }
+
+    let mut entries = Vec::new();
+    let mut expire_at = None;
+    loop {
+        ensure_remaining(buf, 1)?;
+        let opcode = buf.get_u8();
+        let (key, value) = match opcode {
+            OPCODE_EOF => break,
+            OPCODE_EXPIRE => {
+                ensure_remaining(buf, 8)?;
+                expire_at = Some(buf.get_i64_le());
+                continue;
+            }
+            TYPE_STRING => (
+                get_string(&mut buf)?,
+                StoredValue::String(get_frame(&mut buf)?),
+            ),
+            TYPE_HASH => {
+                let key = get_string(&mut buf)?;
+                let len = get_len(&mut buf)?;
+                let mut fields = Vec::with_capacity(len.min(buf.remaining()));
+                for _ in 0..len {
+                    fields.push((get_string(&mut buf)?, get_frame(&mut buf)?));
+                }
+                (key, StoredValue::Hash(fields))
+            }
+            TYPE_ZSET => {
+                let key = get_string(&mut buf)?;
+                let len = get_len(&mut buf)?;
+                let mut zset = SortedSet::new();
+                for _ in 0..len {
+                    let member = get_string(&mut buf)?;
+                    ensure_remaining(buf, 8)?;
+                    zset.insert(member, buf.get_f64_le());
+                }
+                (key, StoredValue::ZSet(zset))
+            }
+            _ => bail!("unknown snapshot opcode: {:#x}", opcode),
+        };
+        entries.push(SnapshotEntry {
+            key,
+            value,
+            expire_at: expire_at.take(),
+        });
+    }
+    let body_len = data.len() - buf.remaining();
+    ensure_remaining(buf, 8)?;
+    if crc64(0, &data[..body_len]) != buf.get_u64_le() {
+        bail!("snapshot checksum mismatch");
+    }
+    Ok((Snapshot { entries }, body_len + 8))
+}
+
+fn put_bytes(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.put_u32_le(data.len() as u32);
+    buf.put_slice(data);
+}
+
+// 与 put_bytes 格式相同, 直接编码到 buf 中
+fn put_frame(buf: &mut Vec<u8>, frame: &RespFrame) {
+    buf.put_u32_le(frame.encoded_len() as u32);
+    frame.encode_to(buf);
+}
+
+fn ensure_remaining(buf: &[u8], n: usize) -> Result<()> {
+    if buf.remaining() < n {
+        bail!("unexpected end of snapshot");
+    }
+    Ok(())
+}
+
+fn get_len(buf: &mut &[u8]) -> Result<usize> {
+    ensure_remaining(buf, 4)?;
+    Ok(buf.get_u32_le() as usize)
+}
+
+fn get_bytes<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = get_len(buf)?;
+    ensure_remaining(buf, len)?;
+    let (data, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(data)
+}
+
+fn get_string(buf: &mut &[u8]) -> Result<String> {
+    Ok(String::from_utf8(get_bytes(buf)?.to_vec())?)
+}
+
+fn get_frame(buf: &mut &[u8]) -> Result<RespFrame> {
+    let mut data = BytesMut::from(get_bytes(buf)?);
+    Ok(RespFrame::decode(&mut data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BulkString, RespArray};
+
+    #[test]
+    fn test_snapshot_encode_decode() -> Result<()> {
+        let mut zset = SortedSet::new();
+        zset.insert("a".to_string(), 1.0);
+        zset.insert("b".to_string(), -2.5);
+        let snapshot = Snapshot {
+            entries: vec![
+                SnapshotEntry {
+                    key: "str".to_string(),
+                    value: StoredValue::String(BulkString::new("hello").into()),
+                    expire_at: Some(1_700_000_000_000),
+                },
+                SnapshotEntry {
+                    key: "arr".to_string(),
+                    value: StoredValue::String(
+                        RespArray::new(vec![1.into(), BulkString::new("x").into()]).into(),
+                    ),
+                    expire_at: None,
+                },
+                SnapshotEntry {
+                    key: "hash".to_string(),
+                    value: StoredValue::Hash(vec![(
+                        "field".to_string(),
+                        BulkString::new("value").into(),
+                    )]),
+                    expire_at: None,
+                },
+                SnapshotEntry {
+                    key: "zset".to_string(),
+                    value: StoredValue::ZSet(zset),
+                    expire_at: None,
+                },
+            ],
+        };
+        let data = encode_snapshot(&snapshot);
+        assert_eq!(decode_snapshot(&data)?, snapshot);
+
+        // 损坏或截断的文件
+        let mut corrupted = data.clone();
+        corrupted[10] ^= 0xff;
+        assert!(decode_snapshot(&corrupted).is_err());
+        assert!(decode_snapshot(&data[..data.len() - 1]).is_err());
+
+        // 快照之后可以跟随其他数据
+        let mut with_tail = data.clone();
+        with_tail.extend_from_slice(b"*1\r\n$4\r\nPING\r\n");
+        assert!(decode_snapshot(&with_tail).is_err());
+        assert_eq!(decode_snapshot_prefix(&with_tail)?, (snapshot, data.len()));
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::VecDeque;
+
+/// 环形的 replication backlog, 保存命令流中最近的 size 个字节
+#[derive(Debug)]
+pub(super) struct Backlog {
+    buf: VecDeque<u8>,
+    size: usize,
+}
+
+impl Backlog {
+    pub fn new(size: usize) -> Self {
+        Backlog {
+            buf: VecDeque::with_capacity(size),
+            size,
+        }
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        if data.len() >= self.size {
+            self.buf.clear();
+            self.buf.extend(&data[data.len() - self.size..]);
+            return;
+        }
+        let overflow = (self.buf.len() + data.len()).saturating_sub(self.size);
+        self.buf.drain(..overflow);
+        self.buf.extend(data);
+    }
+
+    /// 保存的字节数
+    pub fn histlen(&self) -> u64 {
+        self.buf.len() as u64
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// 命令流已写到 end 时, 取出从 from 开始的数据; 这部分数据已被覆盖时返回 None
+    pub fn read_from(&self, end: u64, from: u64) -> Option<Vec<u8>> {
+        let start = end.checked_sub(self.histlen())?;
+        if from < start || from > end {
+            return None;
+        }
+        Some(self.buf.range((from - start) as usize..).copied().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backlog() {
+        let mut backlog = Backlog::new(8);
+        backlog.feed(b"hello");
+        assert_eq!(backlog.read_from(5, 0), Some(b"hello".to_vec()));
+        assert_eq!(backlog.read_from(5, 5), Some(vec![]));
+        assert_eq!(backlog.read_from(5, 6), None);
+
+        // 超出 size 后覆盖最早的数据
+        backlog.feed(b"world");
+        assert_eq!(backlog.histlen(), 8);
+        assert_eq!(backlog.read_from(10, 1), None);
+        assert_eq!(backlog.read_from(10, 2), Some(b"lloworld".to_vec()));
+        assert_eq!(backlog.read_from(10, 3), Some(b"loworld".to_vec()));
+
+        backlog.feed(b"0123456789");
+        assert_eq!(backlog.read_from(20, 12), Some(b"23456789".to_vec()));
+    }
+}
@@ -0,0 +1,176 @@
+use anyhow::Result;
+use bytes::BytesMut;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tracing::info;
+
+use crate::{persist::encode_rdb, Backend, RespDecode, RespError, RespFrame};
+
+use super::{ReplicaSync, SyncStart};
+
+/// PSYNC / SYNC: 连接随后作为 replica 的复制连接使用
+pub fn is_sync_command(frame: &RespFrame) -> bool {
+    matches!(
+        args(frame).first(),
+        Some(name) if name.eq_ignore_ascii_case(b"psync") || name.eq_ignore_ascii_case(b"sync")
+    )
+}
+
+/// REPLCONF listening-port <port>: replica 接受连接的端口
+pub fn listening_port(frame: &RespFrame) -> Option<u16> {
+    match args(frame).as_slice() {
+        [name, option, port]
+            if name.eq_ignore_ascii_case(b"replconf")
+                && option.eq_ignore_ascii_case(b"listening-port") =>
+        {
+            std::str::from_utf8(port).ok()?.parse().ok()
+        }
+        _ => None,
+    }
+}
+
+// 由 bulk string 组成的命令参数
+pub(super) fn args(frame: &RespFrame) -> Vec<&[u8]> {
+    let RespFrame::Array(array) = frame else {
+        return vec![];
+    };
+    array
+        .iter()
+        .map_while(|f| match f {
+            RespFrame::BulkString(s) => Some(&s[..]),
+            _ => None,
+        })
+        .collect()
+}
+
+/// 向 replica 发送全量数据或 backlog 中缺少的数据, 然后持续转发命令流, 直到连接断开.
+/// read_buf 为连接上已经读取但尚未处理的数据.
+pub async fn serve_replica(
+    stream: TcpStream,
+    read_buf: BytesMut,
+    request: RespFrame,
+    listening_port: Option<u16>,
+    backend: Backend,
+) -> Result<()> {
+    let addr = stream.peer_addr()?;
+    info!("Replica {} asks for synchronization", addr);
+    // PSYNC replid offset; 旧的 SYNC 命令只能全量同步, 且没有 +FULLRESYNC 回复
+    let args = args(&request);
+    let psync = match args.as_slice() {
+        [_, replid, offset] => {
+            let replid = String::from_utf8_lossy(replid).into_owned();
+            let offset = std::str::from_utf8(offset)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(-1);
+            Some((replid, offset))
+        }
+        _ => None,
+    };
+    let is_psync = psync.is_some();
+    // 全量同步时 add_replica 阻塞写命令并复制数据, 不能占用 tokio 的工作线程
+    let sync = {
+        let backend = backend.clone();
+        tokio::task::spawn_blocking(move || backend.add_replica(addr, listening_port, psync))
+            .await?
+    };
+    let id = sync.id;
+    let ret = replicate(stream, read_buf, sync, is_psync, &backend).await;
+    backend.remove_replica(id);
+    info!("Connection with replica {} lost", addr);
+    ret
+}
+
+async fn replicate(
+    stream: TcpStream,
+    mut read_buf: BytesMut,
+    sync: ReplicaSync,
+    is_psync: bool,
+    backend: &Backend,
+) -> Result<()> {
+    let ReplicaSync {
+        id,
+        replid,
+        start,
+        mut rx,
+    } = sync;
+    let (mut reader, mut writer) = stream.into_split();
+
+    match start {
+        SyncStart::Full { offset, snapshot } => {
+            info!("Starting full resync with replica, offset {}", offset);
+            if is_psync {
+                writer
+                    .write_all(format!("+FULLRESYNC {} {}\r\n", replid, offset).as_bytes())
+                    .await?;
+            }
+            // RDB 之后没有 CRLF
+            let payload = tokio::task::spawn_blocking(move || encode_rdb(&snapshot)).await?;
+            writer
+                .write_all(format!("${}\r\n", payload.len()).as_bytes())
+                .await?;
+            writer.write_all(&payload).await?;
+        }
+        SyncStart::Partial { backlog } => {
+            info!(
+                "Partial resync accepted, sending {} bytes of backlog",
+                backlog.len()
+            );
+            writer
+                .write_all(format!("+CONTINUE {}\r\n", replid).as_bytes())
+                .await?;
+            writer.write_all(&backlog).await?;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            data = rx.recv() => match data {
+                Some(data) => writer.write_all(&data).await?,
+                // 被 master 主动断开, 例如 master 自己成为了 replica
+                None => return Ok(()),
+            },
+            n = reader.read_buf(&mut read_buf) => {
+                if n? == 0 {
+                    return Ok(());
+                }
+                // replica 只会发送 REPLCONF ACK <offset>, 不需要回复
+                loop {
+                    match RespFrame::decode(&mut read_buf) {
+                        Ok(frame) => {
+                            if let Some((offset, aof_offset)) = ack_offset(&frame) {
+                                backend.replica_ack(id, offset, aof_offset);
+                            }
+                        }
+                        Err(RespError::NotComplete) => break,
+                        Err(_) => {
+                            read_buf.clear();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// REPLCONF ACK <offset> [FACK <aofoffset>]
+fn ack_offset(frame: &RespFrame) -> Option<(u64, Option<u64>)> {
+    let parse = |s: &[u8]| std::str::from_utf8(s).ok()?.parse().ok();
+    match args(frame).as_slice() {
+        [name, option, rest @ ..]
+            if name.eq_ignore_ascii_case(b"replconf") && option.eq_ignore_ascii_case(b"ack") =>
+        {
+            match rest {
+                [offset] => Some((parse(offset)?, None)),
+                [offset, fack, aof_offset] if fack.eq_ignore_ascii_case(b"fack") => {
+                    Some((parse(offset)?, Some(parse(aof_offset)?)))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,783 @@
+use std::{
+    fmt::Write,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use tokio::{
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        Notify,
+    },
+    task::JoinHandle,
+};
+use tracing::warn;
+
+use crate::{
+    backend::random_id,
+    cmd::{Command, CommandExecutor},
+    Backend, BulkString, RespArray, RespEncode, RespFrame, Snapshot,
+};
+
+mod backlog;
+mod master;
+mod replica;
+
+use backlog::Backlog;
+pub use master::{is_sync_command, listening_port, serve_replica};
+
+/// 主从复制的状态. 同一个实例既可以是 master, 也可以在作为 replica 的同时带有自己的 replica.
+#[derive(Debug)]
+pub struct ReplicationState {
+    inner: Mutex<ReplInner>,
+    // 存在 backlog 时写命令才需要编码并写入命令流
+    backlog_active: AtomicBool,
+    // 收到 REPLCONF ACK 时唤醒 WAIT / WAITAOF
+    acked: Notify,
+}
+
+#[derive(Debug)]
+struct ReplInner {
+    replid: String,
+    // 成为 master 之前所属的复制历史, 在 second_replid_offset 之前的部分与当前历史相同
+    replid2: String,
+    second_replid_offset: i64,
+    // 已写入命令流的字节数
+    offset: u64,
+    backlog: Option<Backlog>,
+    master: Option<MasterLink>,
+    replicas: Vec<ReplicaLink>,
+    next_id: u64,
+}
+
+// replica 一侧与 master 的连接
+#[derive(Debug)]
+struct MasterLink {
+    host: String,
+    port: u16,
+    task: JoinHandle<()>,
+    up: bool,
+    last_io: Instant,
+}
+
+// master 一侧的一个 replica, 命令流通过 channel 交给该 replica 的连接任务发送
+#[derive(Debug)]
+struct ReplicaLink {
+    id: u64,
+    addr: SocketAddr,
+    // REPLCONF listening-port
+    listening_port: Option<u16>,
+    tx: UnboundedSender<Bytes>,
+    // REPLCONF ACK 确认的 offset, 以及 replica 的 AOF 已写盘的 offset (FACK)
+    ack_offset: u64,
+    aof_ack_offset: Option<u64>,
+    last_ack: Instant,
+}
+
+/// replica 同步的起点, 以及之后的命令流
+#[derive(Debug)]
+pub(crate) struct ReplicaSync {
+    pub id: u64,
+    pub replid: String,
+    pub start: SyncStart,
+    pub rx: UnboundedReceiver<Bytes>,
+}
+
+#[derive(Debug)]
+pub(crate) enum SyncStart {
+    /// 全量同步: offset 处的数据副本
+    Full { offset: u64, snapshot: Snapshot },
+    /// 部分重同步: replica 缺少的 backlog 数据
+    Partial { backlog: Vec<u8> },
+}
+
+/// replica 同步的对象: 接下来的 PSYNC 请求以哪个位置为起点
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SyncPoint {
+    pub replid: String,
+    pub offset: u64,
+}
+
+const NULL_REPLID: &str = "0000000000000000000000000000000000000000";
+
+impl Default for ReplicationState {
+    fn default() -> Self {
+        ReplicationState {
+            inner: Mutex::new(ReplInner {
+                replid: random_id(),
+                replid2: NULL_REPLID.to_string(),
+                second_replid_offset: -1,
+                offset: 0,
+                backlog: None,
+                master: None,
+                replicas: Vec::new(),
+                next_id: 0,
+            }),
+            backlog_active: AtomicBool::new(false),
+            acked: Notify::new(),
+        }
+    }
+}
+
+impl ReplicationState {
+    fn lock(&self) -> MutexGuard<'_, ReplInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_active(&self) -> bool {
+        self.backlog_active.load(Ordering::Acquire)
+    }
+}
+
+impl ReplInner {
+    // 写入命令流: backlog 和所有 replica
+    fn feed(&mut self, data: &[u8]) {
+        let Some(backlog) = self.backlog.as_mut() else {
+            return;
+        };
+        backlog.feed(data);
+        self.offset += data.len() as u64;
+        let data = Bytes::copy_from_slice(data);
+        // 发送失败说明 replica 的连接已经断开
+        self.replicas.retain(|r| r.tx.send(data.clone()).is_ok());
+    }
+
+    // 当前历史中 psync_offset 之后的数据, 对应 Redis 的 PSYNC 规则
+    fn partial_data(&self, replid: &str, psync_offset: i64) -> Option<Vec<u8>> {
+        let matched = replid == self.replid
+            || (replid == self.replid2 && psync_offset <= self.second_replid_offset);
+        if !matched || psync_offset < 1 {
+            return None;
+        }
+        // psync_offset 为 replica 需要的下一个字节
+        self.backlog
+            .as_ref()?
+            .read_from(self.offset, psync_offset as u64 - 1)
+    }
+
+    // 确认已收到 offset 之前全部命令的 replica 数量
+    fn acked(&self, offset: u64, aof: bool) -> usize {
+        self.replicas
+            .iter()
+            .filter(|r| match aof {
+                true => r.aof_ack_offset.is_some_and(|o| o >= offset),
+                false => r.ack_offset >= offset,
+            })
+            .count()
+    }
+
+    // 开始新的复制历史, 旧的历史仍可以用于部分重同步
+    fn shift_replid(&mut self) {
+        self.replid2 = std::mem::replace(&mut self.replid, random_id());
+        self.second_replid_offset = self.offset as i64 + 1;
+    }
+
+    fn register(
+        &mut self,
+        addr: SocketAddr,
+        listening_port: Option<u16>,
+    ) -> (u64, UnboundedReceiver<Bytes>) {
+        let (tx, rx) = unbounded_channel();
+        let id = self.next_id;
+        self.next_id += 1;
+        self.replicas.push(ReplicaLink {
+            id,
+            addr,
+            listening_port,
+            tx,
+            ack_offset: 0,
+            aof_ack_offset: None,
+            last_ack: Instant::now(),
+        });
+        (id, rx)
+    }
+}
+
+impl Backend {
+    /// 执行写命令, 成功后传播到 AOF 和 replicas
+    pub fn execute_write(&self, cmd: Command, frame: RespFrame) -> RespFrame {
+        let _guard = self.write_barrier();
+        self.execute_write_locked(cmd, frame)
+    }
+
+    /// 与 execute_write 相同, 但集群手动故障转移暂停写入期间不执行, 原样返回命令.
+    /// 在持有 write_barrier 时检查暂停, 暂停开始后不会再有写命令进入命令流
+    pub fn try_execute_write(
+        &self,
+        cmd: Command,
+        frame: RespFrame,
+    ) -> Result<RespFrame, Box<(Command, RespFrame)>> {
+        let _guard = self.write_barrier();
+        if self.cluster_writes_paused() {
+            return Err(Box::new((cmd, frame)));
+        }
+        Ok(self.execute_write_locked(cmd, frame))
+    }
+
+    fn execute_write_locked(&self, mut cmd: Command, frame: RespFrame) -> RespFrame {
+        // 相对过期时间改写为绝对时间后传播, 否则 replica 和 AOF 重放时会推迟过期
+        let frame = match &mut cmd {
+            Command::Restore(c) => c.make_absttl().unwrap_or(frame),
+            _ => frame,
+        };
+        // 并发的写命令按执行顺序写入 AOF 和命令流, 否则重放或 replica 上的结果可能不同
+        let _order = self.write_order();
+        let ret = cmd.execute(self);
+        if !matches!(ret, RespFrame::Error(_)) {
+            self.propagate(frame);
+        }
+        ret
+    }
+
+    /// 传播一条执行成功的写命令
+    pub fn propagate(&self, frame: RespFrame) {
+        if !self.aof_enabled() && !self.repl.is_active() {
+            return;
+        }
+        let data = frame.encode();
+        if self.aof_enabled() {
+            self.aof_append(&data);
+        }
+        if self.repl.is_active() {
+            let mut inner = self.repl.lock();
+            // 可写的 replica 上的写入不属于 master 的命令流
+            if inner.master.is_none() {
+                inner.feed(&data);
+            }
+        }
+    }
+
+    // replica 执行 master 发来的命令, 并原样转发给自己的 replica
+    pub(crate) fn apply_from_master(&self, frame: RespFrame, raw: &[u8]) {
+        let _guard = self.write_barrier();
+        // 可写的 replica 上本地写命令也会写入 AOF, 执行和转发期间同样需要保持顺序
+        let _order = self.write_order();
+        match Command::try_from(frame) {
+            Ok(cmd) => {
+                let is_write = cmd.is_write();
+                cmd.execute(self);
+                if is_write && self.aof_enabled() {
+                    self.aof_append(raw);
+                }
+            }
+            Err(e) => warn!("Invalid command from MASTER: {}", e),
+        }
+        let mut inner = self.repl.lock();
+        inner.feed(raw);
+        if let Some(link) = inner.master.as_mut() {
+            link.last_io = Instant::now();
+        }
+    }
+
+    /// REPLICAOF host port / REPLICAOF NO ONE, 返回是否已经是该 master 的 replica
+    pub fn replicaof(&self, master: Option<(String, u16)>) -> bool {
+        let mut inner = self.repl.lock();
+        if let (Some(link), Some((host, port))) = (&inner.master, &master) {
+            if link.host == *host && link.port == *port {
+                return true;
+            }
+        }
+        let was_replica = match inner.master.take() {
+            Some(link) => {
+                link.task.abort();
+                true
+            }
+            None => false,
+        };
+        match master {
+            Some((host, port)) => {
+                // 数据将被 master 的数据替换, 断开自己的 replica 让它们重新同步
+                inner.replicas.clear();
+                let task = tokio::spawn(replica::run(self.clone(), host.clone(), port));
+                inner.master = Some(MasterLink {
+                    host,
+                    port,
+                    task,
+                    up: false,
+                    last_io: Instant::now(),
+                });
+            }
+            // 成为 master 后开始新的复制历史
+            None if was_replica => inner.shift_replid(),
+            None => {}
+        }
+        false
+    }
+
+    pub fn is_replica(&self) -> bool {
+        self.repl.lock().master.is_some()
+    }
+
+    /// 连接的 replica 地址
+    pub fn replicas(&self) -> Vec<SocketAddr> {
+        self.repl.lock().replicas.iter().map(|r| r.addr).collect()
+    }
+
+    /// 命令流的 replication ID 和 offset
+    pub fn repl_offset(&self) -> (String, u64) {
+        let inner = self.repl.lock();
+        (inner.replid.clone(), inner.offset)
+    }
+
+    // replica 发送 PSYNC 时使用的复制历史和 offset
+    pub(crate) fn sync_point(&self) -> SyncPoint {
+        let inner = self.repl.lock();
+        SyncPoint {
+            replid: inner.replid.clone(),
+            offset: inner.offset,
+        }
+    }
+
+    // replica 全量同步: 替换数据, 并从 master 的 offset 处开始新的 backlog
+    pub(crate) fn full_resync(&self, point: SyncPoint, snapshot: Snapshot) {
+        let _guard = self.block_writes();
+        self.restore(snapshot);
+        let mut inner = self.repl.lock();
+        inner.replid = point.replid;
+        inner.offset = point.offset;
+        inner.replid2 = NULL_REPLID.to_string();
+        inner.second_replid_offset = -1;
+        inner.backlog = Some(Backlog::new(self.config().repl_backlog_size));
+        self.repl.backlog_active.store(true, Ordering::Release);
+    }
+
+    // replica 部分重同步成功; master 可能已经切换到新的复制历史
+    pub(crate) fn continue_sync(&self, replid: Option<String>) {
+        let mut inner = self.repl.lock();
+        if let Some(replid) = replid.filter(|id| *id != inner.replid) {
+            inner.replid2 = std::mem::replace(&mut inner.replid, replid);
+            inner.second_replid_offset = inner.offset as i64 + 1;
+        }
+    }
+
+    pub(crate) fn set_master_link_up(&self, up: bool) {
+        if let Some(link) = self.repl.lock().master.as_mut() {
+            link.up = up;
+            link.last_io = Instant::now();
+        }
+    }
+
+    // 注册 replica. 可以部分重同步时只需要 backlog 中的数据, 否则在阻塞写命令的情况下复制数据
+    pub(crate) fn add_replica(
+        &self,
+        addr: SocketAddr,
+        listening_port: Option<u16>,
+        psync: Option<(String, i64)>,
+    ) -> ReplicaSync {
+        if let Some((replid, psync_offset)) = psync {
+            let mut inner = self.repl.lock();
+            if let Some(backlog) = inner.partial_data(&replid, psync_offset) {
+                let (id, rx) = inner.register(addr, listening_port);
+                return ReplicaSync {
+                    id,
+                    replid: inner.replid.clone(),
+                    start: SyncStart::Partial { backlog },
+                    rx,
+                };
+            }
+        }
+
+        let guard = self.block_writes();
+        let snapshot = self.snapshot_locked(&guard);
+        let mut inner = self.repl.lock();
+        if inner.backlog.is_none() {
+            // 之前的写命令没有记录到命令流中, 不能再用旧的 ID 部分重同步
+            if inner.master.is_none() {
+                inner.replid = random_id();
+                inner.replid2 = NULL_REPLID.to_string();
+                inner.second_replid_offset = -1;
+            }
+            inner.backlog = Some(Backlog::new(self.config().repl_backlog_size));
+            self.repl.backlog_active.store(true, Ordering::Release);
+        }
+        let (id, rx) = inner.register(addr, listening_port);
+        ReplicaSync {
+            id,
+            replid: inner.replid.clone(),
+            start: SyncStart::Full {
+                offset: inner.offset,
+                snapshot,
+            },
+            rx,
+        }
+    }
+
+    pub(crate) fn remove_replica(&self, id: u64) {
+        self.repl.lock().replicas.retain(|r| r.id != id);
+    }
+
+    // REPLCONF ACK <offset> [FACK <aofoffset>]
+    pub(crate) fn replica_ack(&self, id: u64, offset: u64, aof_offset: Option<u64>) {
+        let mut inner = self.repl.lock();
+        if let Some(r) = inner.replicas.iter_mut().find(|r| r.id == id) {
+            r.ack_offset = offset;
+            r.aof_ack_offset = aof_offset;
+            r.last_ack = Instant::now();
+        }
+        self.repl.acked.notify_waiters();
+    }
+
+    /// 已确认收到 (aof 为 true 时为已写入 AOF 并落盘) 当前命令流的 replica 数量
+    pub fn acked_replicas(&self, aof: bool) -> usize {
+        let inner = self.repl.lock();
+        inner.acked(inner.offset, aof)
+    }
+
+    /// WAIT / WAITAOF: 等待至少 numreplicas 个 replica 确认当前命令流, timeout 为 None 时一直等待.
+    /// 返回超时前确认的 replica 数量
+    pub async fn wait_replicas(
+        &self,
+        numreplicas: usize,
+        timeout: Option<Duration>,
+        aof: bool,
+    ) -> usize {
+        let deadline = timeout.map(|t| tokio::time::Instant::now() + t);
+        let target = self.repl.lock().offset;
+        let mut getack_sent = false;
+        loop {
+            // 先注册再检查, 避免错过检查之后到达的 ACK
+            let notified = self.repl.acked.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let acked = {
+                let mut inner = self.repl.lock();
+                let acked = inner.acked(target, aof);
+                // replica 每秒才发送一次 ACK, 通过命令流要求它们立即确认
+                if acked < numreplicas && !getack_sent {
+                    inner.feed(&getack_command());
+                    getack_sent = true;
+                }
+                acked
+            };
+            if acked >= numreplicas {
+                return acked;
+            }
+            match deadline {
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                        return self.repl.lock().acked(target, aof);
+                    }
+                }
+                None => notified.await,
+            }
+        }
+    }
+
+    /// INFO replication
+    pub fn info_replication(&self) -> String {
+        let inner = self.repl.lock();
+        let mut info = String::from("# Replication\r\n");
+        let mut field = |name: &str, value: &dyn std::fmt::Display| {
+            let _ = write!(info, "{}:{}\r\n", name, value);
+        };
+        match &inner.master {
+            Some(link) => {
+                field("role", &"slave");
+                field("master_host", &link.host);
+                field("master_port", &link.port);
+                field("master_link_status", &if link.up { "up" } else { "down" });
+                field(
+                    "master_last_io_seconds_ago",
+                    &link.last_io.elapsed().as_secs(),
+                );
+                field("slave_repl_offset", &inner.offset);
+                field("slave_read_only", &(self.config().replica_read_only as u8));
+            }
+            None => field("role", &"master"),
+        }
+        field("connected_slaves", &inner.replicas.len());
+        for (i, r) in inner.replicas.iter().enumerate() {
+            let value = format!(
+                "ip={},port={},state=online,offset={},lag={}",
+                r.addr.ip(),
+                r.listening_port.unwrap_or(r.addr.port()),
+                r.ack_offset,
+                r.last_ack.elapsed().as_secs()
+            );
+            field(&format!("slave{}", i), &value);
+        }
+        field("master_replid", &inner.replid);
+        field("master_replid2", &inner.replid2);
+        field("master_repl_offset", &inner.offset);
+        field("second_repl_offset", &inner.second_replid_offset);
+        match &inner.backlog {
+            Some(backlog) => {
+                field("repl_backlog_active", &1);
+                field("repl_backlog_size", &backlog.size());
+                field(
+                    "repl_backlog_first_byte_offset",
+                    &(inner.offset - backlog.histlen() + 1),
+                );
+                field("repl_backlog_histlen", &backlog.histlen());
+            }
+            None => {
+                field("repl_backlog_active", &0);
+                field("repl_backlog_size", &self.config().repl_backlog_size);
+                field("repl_backlog_first_byte_offset", &0);
+                field("repl_backlog_histlen", &0);
+            }
+        }
+        info
+    }
+}
+
+// REPLCONF GETACK *
+fn getack_command() -> Vec<u8> {
+    let frame: RespFrame = RespArray::new(vec![
+        BulkString::new("REPLCONF").into(),
+        BulkString::new("GETACK").into(),
+        BulkString::new("*").into(),
+    ])
+    .into();
+    frame.encode()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use bytes::BytesMut;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    use super::*;
+    use crate::{
+        network::testing::{request, start_server},
+        BulkString, RespArray, RespDecode, ServerConfig,
+    };
+
+    async fn wait_for(backend: &Backend, key: &str) -> Option<RespFrame> {
+        for _ in 0..500 {
+            if let Some(v) = backend.get(key) {
+                return Some(v);
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        None
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_replication() -> Result<()> {
+        let master = Backend::new();
+        master.set("before".to_string(), BulkString::new("sync").into());
+        let master_port = start_server(master.clone()).await?;
+        let replica = Backend::with_config(ServerConfig {
+            port: 0,
+            ..Default::default()
+        });
+        let replica_port = start_server(replica.clone()).await?;
+
+        // 全量同步
+        assert!(!replica.replicaof(Some(("127.0.0.1".to_string(), master_port))));
+        assert!(replica.replicaof(Some(("127.0.0.1".to_string(), master_port))));
+        assert_eq!(
+            wait_for(&replica, "before").await,
+            Some(BulkString::new("sync").into())
+        );
+        assert_eq!(master.replicas().len(), 1);
+
+        // 命令流
+        let mut client = TcpStream::connect(("127.0.0.1", master_port)).await?;
+        request(&mut client, &["SET", "after", "stream"]).await?;
+        request(&mut client, &["HSET", "hash", "field", "value"]).await?;
+        assert_eq!(
+            wait_for(&replica, "after").await,
+            Some(BulkString::new("stream").into())
+        );
+        for _ in 0..500 {
+            if replica.hget("hash", "field").is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            replica.hget("hash", "field"),
+            Some(BulkString::new("value").into())
+        );
+
+        // WAIT 通过 GETACK 立即得到确认; replica 没有开启 AOF, WAITAOF 等不到它
+        let ret = request(&mut client, &["WAIT", "1", "0"]).await?;
+        assert_eq!(ret, RespFrame::Integer(1));
+        assert_eq!(master.acked_replicas(false), 1);
+        let ret = request(&mut client, &["WAITAOF", "0", "1", "50"]).await?;
+        assert_eq!(
+            ret,
+            RespArray::new(vec![RespFrame::Integer(0), RespFrame::Integer(0)]).into()
+        );
+
+        // replica 默认只读
+        let mut client = TcpStream::connect(("127.0.0.1", replica_port)).await?;
+        let ret = request(&mut client, &["SET", "local", "x"]).await?;
+        assert!(matches!(ret, RespFrame::Error(e) if e.starts_with("READONLY")));
+        assert!(replica.is_replica());
+
+        // 提升 replica 后, 原来的 master 可以部分重同步到它
+        for _ in 0..500 {
+            if replica.repl_offset() == master.repl_offset() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let (old_replid, offset) = master.repl_offset();
+        assert_eq!(replica.repl_offset(), (old_replid.clone(), offset));
+        replica.replicaof(None);
+        assert!(!replica.is_replica());
+        let ret = request(&mut client, &["SET", "local", "x"]).await?;
+        assert_eq!(ret, RespFrame::SimpleString("OK".into()));
+
+        master.replicaof(Some(("127.0.0.1".to_string(), replica_port)));
+        assert_eq!(
+            wait_for(&master, "local").await,
+            Some(BulkString::new("x").into())
+        );
+        let info = master.info_replication();
+        assert!(info.contains(&format!("master_replid2:{}\r\n", old_replid)));
+        assert_eq!(master.repl_offset(), replica.repl_offset());
+        Ok(())
+    }
+
+    async fn read_line(stream: &mut TcpStream, buf: &mut BytesMut) -> Result<String> {
+        loop {
+            if let Some(end) = buf.windows(2).position(|w| w == b"\r\n") {
+                let line = buf.split_to(end + 2);
+                return Ok(String::from_utf8_lossy(&line[..end]).into_owned());
+            }
+            stream.read_buf(buf).await?;
+        }
+    }
+
+    async fn read_exact(stream: &mut TcpStream, buf: &mut BytesMut, n: usize) -> Result<Vec<u8>> {
+        while buf.len() < n {
+            stream.read_buf(buf).await?;
+        }
+        Ok(buf.split_to(n).to_vec())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_partial_resync() -> Result<()> {
+        let master = Backend::new();
+        let port = start_server(master.clone()).await?;
+        let mut client = TcpStream::connect(("127.0.0.1", port)).await?;
+
+        // 全量同步
+        let mut replica = TcpStream::connect(("127.0.0.1", port)).await?;
+        let mut buf = BytesMut::new();
+        request(&mut replica, &["REPLCONF", "listening-port", "7000"]).await?;
+        let psync: RespFrame = RespArray::new(vec![
+            BulkString::new("PSYNC").into(),
+            BulkString::new("?").into(),
+            BulkString::new("-1").into(),
+        ])
+        .into();
+        replica.write_all(&psync.encode()).await?;
+        let line = read_line(&mut replica, &mut buf).await?;
+        let parts = line.split(' ').collect::<Vec<_>>();
+        assert_eq!(parts[0], "+FULLRESYNC");
+        let (replid, offset) = (parts[1].to_string(), parts[2].parse::<u64>()?);
+        let len: usize = read_line(&mut replica, &mut buf).await?[1..].parse()?;
+        read_exact(&mut replica, &mut buf, len).await?;
+
+        let set_a = b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n";
+        request(&mut client, &["SET", "a", "1"]).await?;
+        assert_eq!(
+            read_exact(&mut replica, &mut buf, set_a.len()).await?,
+            set_a
+        );
+        assert!(master
+            .info_replication()
+            .contains("slave0:ip=127.0.0.1,port=7000,state=online"));
+
+        // 断线期间的写命令保存在 backlog 中
+        drop(replica);
+        let set_b = b"*3\r\n$3\r\nSET\r\n$1\r\nb\r\n$1\r\n2\r\n";
+        request(&mut client, &["SET", "b", "2"]).await?;
+        let offset = offset + set_a.len() as u64;
+        assert_eq!(
+            master.repl_offset(),
+            (replid.clone(), offset + set_b.len() as u64)
+        );
+
+        let mut replica = TcpStream::connect(("127.0.0.1", port)).await?;
+        let mut buf = BytesMut::new();
+        let psync: RespFrame = RespArray::new(vec![
+            BulkString::new("PSYNC").into(),
+            BulkString::new(replid.clone()).into(),
+            BulkString::new((offset + 1).to_string()).into(),
+        ])
+        .into();
+        replica.write_all(&psync.encode()).await?;
+        assert_eq!(
+            read_line(&mut replica, &mut buf).await?,
+            format!("+CONTINUE {}", replid)
+        );
+        assert_eq!(
+            read_exact(&mut replica, &mut buf, set_b.len()).await?,
+            set_b
+        );
+
+        // 未知的复制历史只能全量同步
+        let mut other = TcpStream::connect(("127.0.0.1", port)).await?;
+        let mut buf = BytesMut::new();
+        let psync: RespFrame = RespArray::new(vec![
+            BulkString::new("PSYNC").into(),
+            BulkString::new(random_id()).into(),
+            BulkString::new("1").into(),
+        ])
+        .into();
+        other.write_all(&psync.encode()).await?;
+        assert!(read_line(&mut other, &mut buf)
+            .await?
+            .starts_with("+FULLRESYNC"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_order_matches_concurrent_writes() -> Result<()> {
+        let master = Backend::new();
+        let mut sync = master.add_replica("127.0.0.1:7000".parse()?, None, None);
+        let threads: Vec<_> = (0..8)
+            .map(|t| {
+                let master = master.clone();
+                std::thread::spawn(move || {
+                    for i in 0..2000 {
+                        let frame: RespFrame = RespArray::new(vec![
+                            BulkString::new("SET").into(),
+                            BulkString::new(format!("key{}", i)).into(),
+                            BulkString::new(t.to_string()).into(),
+                        ])
+                        .into();
+                        master.execute_write(Command::try_from(frame.clone()).unwrap(), frame);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        // 按命令流的顺序执行, 结果必须与 master 一致
+        let replica = Backend::new();
+        let mut buf = BytesMut::new();
+        while let Ok(data) = sync.rx.try_recv() {
+            buf.extend_from_slice(&data);
+        }
+        while !buf.is_empty() {
+            let frame = RespFrame::decode(&mut buf)?;
+            Command::try_from(frame)?.execute(&replica);
+        }
+        for i in 0..2000 {
+            let key = format!("key{}", i);
+            assert_eq!(replica.get(&key), master.get(&key));
+        }
+        Ok(())
+    }
+}
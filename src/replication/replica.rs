@@ -0,0 +1,192 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{Buf, Bytes, BytesMut};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tracing::{info, warn};
+
+use super::{master::args, SyncPoint};
+use crate::{
+    persist::{decode_rdb, decode_snapshot},
+    Backend, BulkString, RespArray, RespEncode, RespFrame, RespParser,
+};
+
+/// replica 一侧的复制任务: 连接断开后每秒重连一次, 直到被 REPLICAOF 取消
+pub(super) async fn run(backend: Backend, host: String, port: u16) {
+    loop {
+        info!("Connecting to MASTER {}:{}", host, port);
+        match sync_with_master(&backend, &host, port).await {
+            Ok(_) => info!("Connection with master lost"),
+            Err(e) => warn!("Error syncing with MASTER {}:{}: {:?}", host, port, e),
+        }
+        backend.set_master_link_up(false);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+async fn sync_with_master(backend: &Backend, host: &str, port: u16) -> Result<()> {
+    let mut conn = MasterConn {
+        stream: TcpStream::connect((host, port)).await?,
+        buf: BytesMut::new(),
+        parser: RespParser::new(),
+    };
+    if let Some(password) = &backend.config().masterauth {
+        conn.command(&["AUTH", password]).await?;
+    }
+    conn.command(&["PING"]).await?;
+    let listening_port = backend.config().port.to_string();
+    conn.command(&["REPLCONF", "listening-port", &listening_port])
+        .await?;
+    conn.command(&["REPLCONF", "capa", "psync2"]).await?;
+
+    // 用当前的复制历史尝试部分重同步, master 无法满足时回复 FULLRESYNC
+    let point = backend.sync_point();
+    let psync_offset = (point.offset + 1).to_string();
+    let reply = conn
+        .command(&["PSYNC", &point.replid, &psync_offset])
+        .await?;
+    let RespFrame::SimpleString(reply) = reply else {
+        bail!("unexpected reply to PSYNC: {:?}", reply);
+    };
+    let mut parts = reply.split_whitespace();
+    match parts.next() {
+        Some("FULLRESYNC") => {
+            let (Some(replid), Some(offset)) = (parts.next(), parts.next()) else {
+                bail!("invalid FULLRESYNC reply: {}", reply.as_str());
+            };
+            let point = SyncPoint {
+                replid: replid.to_string(),
+                offset: offset.parse()?,
+            };
+            let payload = conn.read_payload().await?;
+            info!("MASTER <-> REPLICA sync: received {} bytes", payload.len());
+            let snapshot = if payload.starts_with(b"REDIS") {
+                decode_rdb(&payload)?.into_snapshot(0)
+            } else {
+                decode_snapshot(&payload)?
+            };
+            // 替换数据期间阻塞写命令, 不能占用 tokio 的工作线程
+            let resync = backend.clone();
+            tokio::task::spawn_blocking(move || resync.full_resync(point, snapshot)).await?;
+            info!("MASTER <-> REPLICA sync: finished with success");
+        }
+        Some("CONTINUE") => {
+            backend.continue_sync(parts.next().map(|s| s.to_string()));
+            info!("Successful partial resynchronization with master");
+        }
+        _ => bail!("unexpected reply to PSYNC: {}", reply.as_str()),
+    }
+    backend.set_master_link_up(true);
+
+    // 每秒向 master 确认已处理的 offset
+    let mut ack = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            ret = conn.read_raw_frame() => {
+                let (frame, raw) = ret?;
+                let getack = is_getack(&frame);
+                backend.apply_from_master(frame, &raw);
+                if getack {
+                    send_ack(backend, &mut conn).await?;
+                }
+            }
+            _ = ack.tick() => send_ack(backend, &mut conn).await?,
+        }
+    }
+}
+
+// REPLCONF ACK <offset> [FACK <aofoffset>], 开启 AOF 时先写盘再确认
+async fn send_ack(backend: &Backend, conn: &mut MasterConn) -> Result<()> {
+    let offset = backend.sync_point().offset.to_string();
+    let cloned = backend.clone();
+    if backend.aof_enabled() && tokio::task::spawn_blocking(move || cloned.aof_fsync()).await? {
+        conn.send(&["REPLCONF", "ACK", &offset, "FACK", &offset])
+            .await
+    } else {
+        conn.send(&["REPLCONF", "ACK", &offset]).await
+    }
+}
+
+fn is_getack(frame: &RespFrame) -> bool {
+    matches!(
+        args(frame).as_slice(),
+        [name, option, ..] if name.eq_ignore_ascii_case(b"replconf") && option.eq_ignore_ascii_case(b"getack")
+    )
+}
+
+struct MasterConn {
+    stream: TcpStream,
+    buf: BytesMut,
+    // 命令流中的 frame 可能很大, 在多次读取之间保留解析的进度
+    parser: RespParser,
+}
+
+impl MasterConn {
+    // 发送命令并读取回复, 错误回复视为失败
+    async fn command(&mut self, args: &[&str]) -> Result<RespFrame> {
+        self.send(args).await?;
+        match self.read_frame().await? {
+            RespFrame::Error(e) => bail!("{} failed: {}", args[0], e.as_str()),
+            reply => Ok(reply),
+        }
+    }
+
+    async fn send(&mut self, args: &[&str]) -> Result<()> {
+        let frame: RespFrame = RespArray::new(
+            args.iter()
+                .map(|s| BulkString::new(s.to_string()).into())
+                .collect::<Vec<_>>(),
+        )
+        .into();
+        self.stream.write_all(&frame.encode()).await?;
+        Ok(())
+    }
+
+    // 命令流中的一条命令, 以及它的原始数据, 用于计算 offset 和转发
+    async fn read_raw_frame(&mut self) -> Result<(RespFrame, Bytes)> {
+        loop {
+            match self.parser.parse_raw(&mut self.buf)? {
+                Some(ret) => return Ok(ret),
+                None => self.read_more().await?,
+            }
+        }
+    }
+
+    async fn read_frame(&mut self) -> Result<RespFrame> {
+        Ok(self.read_raw_frame().await?.0)
+    }
+
+    // 全量同步的数据: `$<len>\r\n<payload>`, 之后没有 CRLF.
+    // master 准备数据期间可能发送 `\n` 保持连接
+    async fn read_payload(&mut self) -> Result<Vec<u8>> {
+        let len = loop {
+            while self.buf.first() == Some(&b'\n') {
+                self.buf.advance(1);
+            }
+            if let Some(end) = self.buf.windows(2).position(|w| w == b"\r\n") {
+                let line = String::from_utf8_lossy(&self.buf[..end]).into_owned();
+                self.buf.advance(end + 2);
+                let len = line
+                    .strip_prefix('$')
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .ok_or_else(|| anyhow!("invalid sync payload header: {}", line))?;
+                break len;
+            }
+            self.read_more().await?;
+        };
+        while self.buf.len() < len {
+            self.read_more().await?;
+        }
+        Ok(self.buf.split_to(len).to_vec())
+    }
+
+    async fn read_more(&mut self) -> Result<()> {
+        if self.stream.read_buf(&mut self.buf).await? == 0 {
+            bail!("connection closed by MASTER");
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,10 @@
 use std::ops::Deref;
 
-use bytes::{Buf, BytesMut};
+use bytes::{BufMut, BytesMut};
 
 use crate::{RespDecode, RespEncode, RespError, RespFrame};
 
-use super::{calc_total_len, extract_fixed_data, parse_length, BUF_CAP, CRLF_LEN};
+use super::{extract_fixed_data, header_len, parser::decode_as, put_header};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct RespArray(pub(crate) Vec<RespFrame>);
@@ -16,13 +16,20 @@ pub struct RespNullArray;
 ///   An additional RESP type for every element of the Array.
 ///  `*<number-of-element>\r\n<element-1>...<element-n>`
 impl RespEncode for RespArray {
-    fn encode(self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(BUF_CAP);
-        buf.extend_from_slice(&format!("*{}\r\n", self.0.len()).into_bytes());
-        for frame in self.0 {
-            buf.extend_from_slice(&frame.encode());
+    fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        put_header(buf, b'*', self.0.len());
+        for frame in &self.0 {
+            frame.encode_to(buf);
         }
-        buf
+    }
+
+    fn encoded_len(&self) -> usize {
+        header_len(self.0.len())
+            + self
+                .0
+                .iter()
+                .map(|frame| frame.encoded_len())
+                .sum::<usize>()
     }
 }
 
@@ -30,27 +37,9 @@ impl RespEncode for RespArray {
 // "*2\r\n$3\r\nget\r\n$5\r\nhello\r\n"
 impl RespDecode for RespArray {
     const PREFIX: &'static str = "*";
-    fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
-        let (end, len) = parse_length(buf, Self::PREFIX)?;
-
-        let total_len = calc_total_len(buf, end, len, Self::PREFIX)?;
-
-        if buf.len() < total_len {
-            return Err(RespError::NotComplete);
-        }
-        buf.advance(end + CRLF_LEN);
 
-        let mut frames: Vec<RespFrame> = Vec::with_capacity(len);
-        for _ in 0..len {
-            frames.push(RespFrame::decode(buf)?);
-        }
-
-        Ok(RespArray::new(frames))
-    }
-
-    fn expect_length(buf: &[u8]) -> anyhow::Result<usize, RespError> {
-        let (end, len) = parse_length(buf, Self::PREFIX)?;
-        calc_total_len(buf, end, len, Self::PREFIX)
+    fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
+        decode_as(buf)
     }
 }
 
@@ -62,16 +51,16 @@ impl RespDecode for RespNullArray {
         extract_fixed_data(buf, "*-1\r\n", "RespNullArray")?;
         Ok(RespNullArray)
     }
-
-    fn expect_length(_buf: &[u8]) -> anyhow::Result<usize, RespError> {
-        Ok(5)
-    }
 }
 
 ///  NullArray "*-1\r\n"
 impl RespEncode for RespNullArray {
-    fn encode(self) -> Vec<u8> {
-        b"*-1\r\n".to_vec()
+    fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        buf.put_slice(b"*-1\r\n");
+    }
+
+    fn encoded_len(&self) -> usize {
+        5
     }
 }
 
@@ -103,7 +92,7 @@ impl Default for RespNullArray {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{BulkString, RespFrame, SimpleString};
+    use crate::{BulkString, RespFrame, RespMap, SimpleString};
 
     #[test]
     fn test_array_encode() {
@@ -162,4 +151,46 @@ mod tests {
 
         anyhow::Ok(())
     }
+
+    #[test]
+    fn test_array_decode_partial_element() -> anyhow::Result<()> {
+        // 最后一个元素只收到一部分时应等待更多数据, 而不是越界 panic
+        let mut buf = BytesMut::from(&b"*2\r\n$3\r\nset\r\n$5\r\nhel"[..]);
+        let ret = RespArray::decode(&mut buf);
+        assert_eq!(ret.unwrap_err(), RespError::NotComplete);
+
+        buf.extend_from_slice(b"lo\r\n");
+        let frame = RespArray::decode(&mut buf)?;
+        assert_eq!(
+            frame,
+            RespArray::new([
+                BulkString::new("set").into(),
+                BulkString::new("hello").into()
+            ])
+        );
+
+        let mut buf = BytesMut::from(&b"%1\r\n+key\r\n$5\r\nva"[..]);
+        let ret = RespMap::decode(&mut buf);
+        assert_eq!(ret.unwrap_err(), RespError::NotComplete);
+
+        anyhow::Ok(())
+    }
+
+    #[test]
+    fn test_null_array_in_array() -> anyhow::Result<()> {
+        // 数组中的 `*-1` 长度为 5, 不是 -1 个元素
+        let mut buf = BytesMut::from(&b"*3\r\n*-1\r\n:1\r\n*-1\r\n"[..]);
+        let frame = RespArray::decode(&mut buf)?;
+        assert_eq!(
+            frame,
+            RespArray::new(vec![
+                RespNullArray.into(),
+                RespFrame::Integer(1),
+                RespNullArray.into(),
+            ])
+        );
+        assert!(buf.is_empty());
+
+        anyhow::Ok(())
+    }
 }
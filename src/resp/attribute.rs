@@ -0,0 +1,110 @@
+use bytes::{BufMut, BytesMut};
+
+use crate::{RespDecode, RespEncode, RespError, RespFrame, RespMap};
+
+use super::{header_len, parser::decode_as, put_header};
+
+/// 附加在回复之前的元数据, 不了解 attribute 的客户端可以直接忽略, 只使用之后的回复
+#[derive(Debug, PartialEq, Clone)]
+pub struct RespAttribute {
+    pub(crate) attributes: RespMap,
+    pub(crate) frame: Box<RespFrame>,
+}
+
+/// attribute "|<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n><reply>"
+impl RespEncode for RespAttribute {
+    fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        put_header(buf, b'|', self.attributes.len());
+        for (key, value) in self.attributes.iter() {
+            key.encode_to(buf);
+            value.encode_to(buf);
+        }
+        self.frame.encode_to(buf);
+    }
+
+    fn encoded_len(&self) -> usize {
+        header_len(self.attributes.len())
+            + self
+                .attributes
+                .iter()
+                .map(|(key, value)| key.encoded_len() + value.encoded_len())
+                .sum::<usize>()
+            + self.frame.encoded_len()
+    }
+}
+// attribute "|<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n><reply>"
+impl RespDecode for RespAttribute {
+    const PREFIX: &'static str = "|";
+
+    fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
+        decode_as(buf)
+    }
+}
+
+impl RespAttribute {
+    pub fn new(attributes: RespMap, frame: impl Into<RespFrame>) -> Self {
+        RespAttribute {
+            attributes,
+            frame: Box::new(frame.into()),
+        }
+    }
+
+    pub fn attributes(&self) -> &RespMap {
+        &self.attributes
+    }
+
+    pub fn frame(&self) -> &RespFrame {
+        &self.frame
+    }
+
+    /// 去掉 attribute, 只保留回复
+    pub fn into_frame(self) -> RespFrame {
+        *self.frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RespArray, RespFrame, SimpleString};
+
+    fn attribute() -> RespAttribute {
+        let mut key_popularity = RespMap::new();
+        key_popularity.insert(SimpleString::new("a"), 0.1923.into());
+        let mut attributes = RespMap::new();
+        attributes.insert(SimpleString::new("key-popularity"), key_popularity.into());
+        RespAttribute::new(
+            attributes,
+            RespArray::new(vec![RespFrame::Integer(2039123)]),
+        )
+    }
+
+    #[test]
+    fn test_attribute_encode() {
+        let frame: RespFrame = attribute().into();
+        assert_eq!(
+            frame.encode(),
+            b"|1\r\n+key-popularity\r\n%1\r\n+a\r\n,+0.1923\r\n*1\r\n:+2039123\r\n"
+        );
+    }
+
+    #[test]
+    fn test_attribute_decode() -> anyhow::Result<()> {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(
+            b"|1\r\n+key-popularity\r\n%1\r\n+a\r\n,0.1923\r\n*1\r\n:2039123\r\n",
+        );
+        let frame = RespAttribute::decode(&mut buf)?;
+        assert_eq!(frame, attribute());
+        assert!(buf.is_empty());
+
+        // 回复还没有收到时不完整
+        buf.extend_from_slice(b"|1\r\n+ttl\r\n:3600\r\n");
+        assert_eq!(RespAttribute::decode(&mut buf), Err(RespError::NotComplete));
+        buf.extend_from_slice(b"$3\r\nbar\r\n");
+        let frame = RespAttribute::decode(&mut buf)?;
+        assert_eq!(frame.into_frame(), crate::BulkString::new("bar").into());
+
+        anyhow::Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use std::ops::Deref;
+
+use bytes::{BufMut, BytesMut};
+
+use crate::{RespDecode, RespEncode, RespError};
+
+use super::{parser::decode_as, CRLF, CRLF_LEN};
+
+/// 任意精度的整数, 保存十进制的数字
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BigNumber(pub(crate) String);
+
+/// big number "([+|-]<number>\r\n"
+impl RespEncode for BigNumber {
+    fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        buf.put_u8(b'(');
+        buf.put_slice(self.0.as_bytes());
+        buf.put_slice(CRLF);
+    }
+
+    fn encoded_len(&self) -> usize {
+        1 + self.0.len() + CRLF_LEN
+    }
+}
+// big number "([+|-]<number>\r\n"
+impl RespDecode for BigNumber {
+    const PREFIX: &'static str = "(";
+
+    fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
+        decode_as(buf)
+    }
+}
+
+impl BigNumber {
+    pub fn new(s: impl Into<String>) -> Self {
+        BigNumber(s.into())
+    }
+}
+
+impl From<i128> for BigNumber {
+    fn from(n: i128) -> Self {
+        BigNumber(n.to_string())
+    }
+}
+
+impl From<u128> for BigNumber {
+    fn from(n: u128) -> Self {
+        BigNumber(n.to_string())
+    }
+}
+
+impl Deref for BigNumber {
+    type Target = String;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::RespFrame;
+
+    use super::*;
+
+    #[test]
+    fn test_big_number_encode() {
+        let s: RespFrame = BigNumber::from(i128::MIN).into();
+        assert_eq!(s.encode(), b"(-170141183460469231731687303715884105728\r\n");
+
+        let s: RespFrame = BigNumber::new("3492890328409238509324850943850943825024385").into();
+        assert_eq!(
+            s.encode(),
+            b"(3492890328409238509324850943850943825024385\r\n"
+        );
+    }
+
+    #[test]
+    fn test_big_number_decode() -> anyhow::Result<()> {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"(3492890328409238509324850943850943825024385\r\n");
+        let frame = BigNumber::decode(&mut buf)?;
+        assert_eq!(
+            frame,
+            BigNumber::new("3492890328409238509324850943850943825024385")
+        );
+
+        buf.extend_from_slice(b"(12\r");
+        assert_eq!(BigNumber::decode(&mut buf), Err(RespError::NotComplete));
+        buf.clear();
+
+        buf.extend_from_slice(b"(1.5\r\n");
+        assert!(matches!(
+            BigNumber::decode(&mut buf),
+            Err(RespError::InvalidFrame(_))
+        ));
+
+        anyhow::Ok(())
+    }
+}
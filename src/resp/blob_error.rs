@@ -0,0 +1,71 @@
+use std::ops::Deref;
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{RespDecode, RespEncode, RespError};
+
+use super::{header_len, parser::decode_as, put_header, CRLF, CRLF_LEN};
+
+/// 二进制安全的错误, 可以包含换行
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BlobError(pub(crate) Bytes);
+
+/// blob error "!<length>\r\n<bytes>\r\n"
+impl RespEncode for BlobError {
+    fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        put_header(buf, b'!', self.len());
+        buf.put_slice(self);
+        buf.put_slice(CRLF);
+    }
+
+    fn encoded_len(&self) -> usize {
+        header_len(self.len()) + self.len() + CRLF_LEN
+    }
+}
+// blob error "!<length>\r\n<bytes>\r\n"
+impl RespDecode for BlobError {
+    const PREFIX: &'static str = "!";
+
+    fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
+        decode_as(buf)
+    }
+}
+
+impl BlobError {
+    pub fn new(s: impl Into<Vec<u8>>) -> Self {
+        BlobError(Bytes::from(s.into()))
+    }
+}
+
+impl Deref for BlobError {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::RespFrame;
+
+    use super::*;
+
+    #[test]
+    fn test_blob_error_encode() {
+        let s: RespFrame = BlobError::new("SYNTAX invalid syntax").into();
+        assert_eq!(s.encode(), b"!21\r\nSYNTAX invalid syntax\r\n");
+    }
+
+    #[test]
+    fn test_blob_error_decode() -> anyhow::Result<()> {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"!10\r\nERR a\r\nb c\r\n");
+        let frame = BlobError::decode(&mut buf)?;
+        assert_eq!(frame, BlobError::new("ERR a\r\nb c"));
+
+        buf.extend_from_slice(b"!10\r\nERR");
+        assert_eq!(BlobError::decode(&mut buf), Err(RespError::NotComplete));
+
+        anyhow::Ok(())
+    }
+}
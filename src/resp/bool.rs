@@ -1,30 +1,25 @@
-use bytes::BytesMut;
+use bytes::{BufMut, BytesMut};
 
 use crate::{RespDecode, RespEncode, RespError};
 
-use super::extract_fixed_data;
+use super::parser::decode_as;
 
 ///  boolean "#<t|f>\r\n"
 impl RespEncode for bool {
-    fn encode(self) -> Vec<u8> {
-        format!("#{}\r\n", if self { "t" } else { "f" }).into_bytes()
+    fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        buf.put_slice(if *self { b"#t\r\n" } else { b"#f\r\n" });
+    }
+
+    fn encoded_len(&self) -> usize {
+        4
     }
 }
 // - boolean "#<t|f>\r\n"
 impl RespDecode for bool {
     const PREFIX: &'static str = "#";
-    fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
-        match extract_fixed_data(buf, "#t\r\n", "Bool") {
-            Ok(_) => Ok(true),
-            Err(_) => match extract_fixed_data(buf, "#f\r\n", "Bool") {
-                Ok(_) => Ok(false),
-                Err(e) => Err(e),
-            },
-        }
-    }
 
-    fn expect_length(_buf: &[u8]) -> anyhow::Result<usize, RespError> {
-        Ok(4)
+    fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
+        decode_as(buf)
     }
 }
 
@@ -1,30 +1,37 @@
 use std::ops::Deref;
 
-use bytes::{Buf, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
 
 use crate::{RespDecode, RespEncode, RespError};
 
-use super::{extract_fixed_data, parse_length, CRLF_LEN};
+use super::{extract_fixed_data, header_len, parser::decode_as, put_header, CRLF, CRLF_LEN};
 
+/// 解码时为读缓冲区的切片, 不需要复制数据
 #[derive(Debug, PartialEq, Clone)]
-pub struct BulkString(pub(crate) Vec<u8>);
+pub struct BulkString(pub(crate) Bytes);
 #[derive(Debug, PartialEq, Clone)]
 pub struct RespNullBulkString;
 
 /// Bulk Strings `"$6\r\nfoobar\r\n"` `"$0\r\n\r\n"`
 impl RespEncode for BulkString {
-    fn encode(self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(self.len() + 16);
-        buf.extend_from_slice(&format!("${}\r\n", self.len()).into_bytes());
-        buf.extend_from_slice(&self);
-        buf.extend_from_slice(b"\r\n");
-        buf
+    fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        put_header(buf, b'$', self.len());
+        buf.put_slice(self);
+        buf.put_slice(CRLF);
+    }
+
+    fn encoded_len(&self) -> usize {
+        header_len(self.len()) + self.len() + CRLF_LEN
     }
 }
 ///  NullBulkString "$-1\r\n"
 impl RespEncode for RespNullBulkString {
-    fn encode(self) -> Vec<u8> {
-        b"$-1\r\n".to_vec()
+    fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        buf.put_slice(b"$-1\r\n");
+    }
+
+    fn encoded_len(&self) -> usize {
+        5
     }
 }
 // Bulk Strings `"$6\r\nfoobar\r\n"` `"$0\r\n\r\n"`
@@ -32,21 +39,7 @@ impl RespDecode for BulkString {
     const PREFIX: &'static str = "$";
 
     fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
-        let (end, len) = parse_length(buf, Self::PREFIX)?;
-
-        let remained = &buf[end + CRLF_LEN..];
-        if remained.len() < len + CRLF_LEN {
-            return Err(RespError::NotComplete);
-        }
-        buf.advance(end + CRLF_LEN);
-
-        let data_str = buf.split_to(len + CRLF_LEN);
-        Ok(BulkString::new(data_str[..len].to_vec()))
-    }
-
-    fn expect_length(buf: &[u8]) -> anyhow::Result<usize, RespError> {
-        let (end, len) = parse_length(buf, Self::PREFIX)?;
-        Ok(end + CRLF_LEN + len + CRLF_LEN)
+        decode_as(buf)
     }
 }
 // NullBulkString "$-1\r\n"
@@ -57,45 +50,51 @@ impl RespDecode for RespNullBulkString {
         extract_fixed_data(buf, "$-1\r\n", "RespNullBulkString")?;
         Ok(RespNullBulkString::new())
     }
-
-    fn expect_length(_buf: &[u8]) -> anyhow::Result<usize, RespError> {
-        Ok(5)
-    }
 }
 
 impl BulkString {
     pub fn new(s: impl Into<Vec<u8>>) -> Self {
-        BulkString(s.into())
+        // Vec 转为 Bytes 不需要复制
+        BulkString(Bytes::from(s.into()))
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        self.0
     }
 }
 
 impl Deref for BulkString {
-    type Target = Vec<u8>;
+    type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
-impl AsRef<Vec<u8>> for BulkString {
-    fn as_ref(&self) -> &Vec<u8> {
+impl AsRef<[u8]> for BulkString {
+    fn as_ref(&self) -> &[u8] {
         &self.0
     }
 }
 
+impl From<Bytes> for BulkString {
+    fn from(s: Bytes) -> Self {
+        BulkString(s)
+    }
+}
 impl From<&str> for BulkString {
     fn from(s: &str) -> Self {
-        BulkString(s.as_bytes().to_vec())
+        BulkString::new(s)
     }
 }
 impl From<&[u8]> for BulkString {
     fn from(s: &[u8]) -> Self {
-        BulkString(s.to_vec())
+        BulkString::new(s)
     }
 }
 
 impl<const N: usize> From<&[u8; N]> for BulkString {
     fn from(s: &[u8; N]) -> Self {
-        BulkString(s.to_vec())
+        BulkString::new(s)
     }
 }
 
@@ -113,7 +112,7 @@ impl Default for RespNullBulkString {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::RespFrame;
+    use crate::{RespArray, RespFrame};
 
     #[test]
     fn test_bulk_string_encode() {
@@ -156,4 +155,23 @@ mod tests {
 
         anyhow::Ok(())
     }
+
+    #[test]
+    fn test_null_bulk_string_in_array() -> anyhow::Result<()> {
+        // 数组中的 `$-1` 长度为 5, 不是 -1 字节的数据
+        let mut buf = BytesMut::from(&b"*3\r\n$-1\r\n$1\r\na\r\n$-1\r\n"[..]);
+        let frame = RespFrame::decode(&mut buf)?;
+        assert_eq!(
+            frame,
+            RespArray::new(vec![
+                RespNullBulkString.into(),
+                BulkString::new("a").into(),
+                RespNullBulkString.into(),
+            ])
+            .into()
+        );
+        assert!(buf.is_empty());
+
+        anyhow::Ok(())
+    }
 }
@@ -1,21 +1,29 @@
-use bytes::BytesMut;
+use std::fmt::{self, Write};
+
+use bytes::{BufMut, BytesMut};
 
 use crate::{RespDecode, RespEncode, RespError};
 
-use super::{extract_simple_frame_data, CRLF_LEN};
+use super::{parser::decode_as, FmtBuf, FmtLen};
 
 ///  double ",[<+|->]<integral>[.<fractional>][<E|e>[sign][exponent]]\r\n"
 impl RespEncode for f64 {
-    fn encode(self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(32);
-        let ret = if self.abs() > 1e+8 || self.abs() < 1e-8 {
-            format!(",{:+e}\r\n", self)
-        } else {
-            let sign = if self < 0.0 { "" } else { "+" };
-            format!(",{}{}\r\n", sign, self)
-        };
-        buf.extend_from_slice(&ret.into_bytes());
-        buf
+    fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        let _ = write_double(&mut FmtBuf(buf), *self);
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut len = FmtLen(0);
+        let _ = write_double(&mut len, *self);
+        len.0
+    }
+}
+fn write_double(w: &mut impl Write, v: f64) -> fmt::Result {
+    if v.abs() > 1e+8 || v.abs() < 1e-8 {
+        write!(w, ",{:+e}\r\n", v)
+    } else {
+        let sign = if v < 0.0 { "" } else { "+" };
+        write!(w, ",{}{}\r\n", sign, v)
     }
 }
 // double ",[<+|->]<integral>[.<fractional>][<E|e>[sign][exponent]]\r\n"
@@ -23,17 +31,7 @@ impl RespDecode for f64 {
     const PREFIX: &'static str = ",";
 
     fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
-        let end = extract_simple_frame_data(buf, Self::PREFIX)?;
-
-        let data = buf.split_to(end + CRLF_LEN);
-        let s = String::from_utf8_lossy(&data[Self::PREFIX.len()..end]);
-        let num = s.parse::<Self>()?;
-        Ok(num)
-    }
-
-    fn expect_length(buf: &[u8]) -> anyhow::Result<usize, RespError> {
-        let end = extract_simple_frame_data(buf, Self::PREFIX)?;
-        Ok(end + CRLF_LEN)
+        decode_as(buf)
     }
 }
 
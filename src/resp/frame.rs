@@ -1,8 +1,8 @@
 use super::{
-    BulkString, RespArray, RespMap, RespNull, RespNullArray, RespNullBulkString, RespSet,
-    SimpleError, SimpleString,
+    BigNumber, BlobError, BulkString, RespArray, RespAttribute, RespMap, RespNull, RespNullArray,
+    RespNullBulkString, RespPush, RespSet, SimpleError, SimpleString, VerbatimString,
 };
-use crate::{RespDecode, RespError};
+use crate::{RespDecode, RespError, RespParser};
 use bytes::BytesMut;
 use enum_dispatch::enum_dispatch;
 
@@ -21,6 +21,10 @@ use enum_dispatch::enum_dispatch;
 /// - boolean "#<t|f>\r\n"
 /// - double ",[<+|->]<integral>[.<fractional>][<E|e>[sign][exponent]]\r\n"
 /// - big number "([+|-]<number>\r\n"
+/// - verbatim string "=<length>\r\n<format>:<data>\r\n"
+/// - blob error "!<length>\r\n<bytes>\r\n"
+/// - push "><number-of-elements>\r\n<element-1>...<element-n>"
+/// - attribute "|<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n><reply>"
 /// - map "%<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n>"
 /// - set "~<number-of-elements>\r\n<element-1>...<element-n>"
 ///
@@ -40,91 +44,53 @@ pub enum RespFrame {
     Double(f64),
     Map(RespMap),
     Set(RespSet),
+    BigNumber(BigNumber),
+    VerbatimString(VerbatimString),
+    BlobError(BlobError),
+    Push(RespPush),
+    Attribute(RespAttribute),
 }
 
 impl RespDecode for RespFrame {
     const PREFIX: &'static str = "";
 
     fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
-        let mut iter = buf.iter().peekable();
-        match iter.peek() {
-            Some(b'+') => {
-                let frame = SimpleString::decode(buf)?;
-
-                Ok(frame.into())
-            }
-            Some(b'-') => {
-                let frame = SimpleError::decode(buf)?;
-                Ok(frame.into())
-            }
-            Some(b':') => {
-                let frame = i64::decode(buf)?;
-                Ok(frame.into())
-            }
-            Some(b'$') => {
-                // try null bulk string first
-                match RespNullArray::decode(buf) {
-                    Ok(frame) => Ok(frame.into()),
-                    Err(RespError::NotComplete) => Err(RespError::NotComplete),
-                    Err(_) => {
-                        let frame = BulkString::decode(buf)?;
-                        Ok(frame.into())
-                    }
-                }
-            }
-            Some(b'*') => {
-                // try null array first
-                match RespNullArray::decode(buf) {
-                    Ok(frame) => Ok(frame.into()),
-                    Err(RespError::NotComplete) => Err(RespError::NotComplete),
-                    Err(_) => {
-                        let frame = RespArray::decode(buf)?;
-                        Ok(frame.into())
-                    }
-                }
-            }
-            Some(b'_') => {
-                let frame = RespNull::decode(buf)?;
-                Ok(frame.into())
-            }
-            Some(b'#') => {
-                let frame = bool::decode(buf)?;
-                Ok(frame.into())
-            }
-            Some(b',') => {
-                let frame = f64::decode(buf)?;
-                Ok(frame.into())
-            }
-            Some(b'%') => {
-                let frame = RespMap::decode(buf)?;
-                Ok(frame.into())
-            }
-            Some(b'~') => {
-                let frame = RespSet::decode(buf)?;
-                Ok(frame.into())
-            }
-            None => Err(RespError::NotComplete),
-            _ => Err(RespError::InvalidFrameType(format!(
-                "Invalid frame type: {:?}",
-                buf
-            ))),
-        }
+        // 一次性解析, 需要在多次读取之间保留进度时直接使用 RespParser
+        RespParser::new().parse(buf)?.ok_or(RespError::NotComplete)
     }
-    fn expect_length(buf: &[u8]) -> anyhow::Result<usize, RespError> {
-        let mut iter = buf.iter().peekable();
-        match iter.peek() {
-            Some(b'*') => RespArray::expect_length(buf),
-            Some(b'~') => RespSet::expect_length(buf),
-            Some(b'%') => RespMap::expect_length(buf),
-            Some(b'$') => BulkString::expect_length(buf),
-            Some(b':') => i64::expect_length(buf),
-            Some(b'+') => SimpleString::expect_length(buf),
-            Some(b'-') => SimpleError::expect_length(buf),
-            Some(b'#') => bool::expect_length(buf),
-            Some(b',') => f64::expect_length(buf),
-            Some(b'_') => RespNull::expect_length(buf),
+}
 
-            _ => Err(RespError::NotComplete),
+impl RespFrame {
+    /// RESP2 客户端不认识 RESP3 的类型, 转换为 RESP2 中对应的类型:
+    /// map 展开为 key, value 交替的数组, set 转为数组, null 转为 `$-1`, boolean 转为整数,
+    /// double, big number 和 verbatim string 转为 bulk string, blob error 转为单行的错误,
+    /// attribute 只保留之后的回复. RESP2 没有 push, 连接只向 RESP3 客户端发送 push,
+    /// 出现在回复中的 push 转为错误, 而不是当作普通的数组
+    pub fn into_resp2(self) -> RespFrame {
+        let convert = |frames: Vec<RespFrame>| -> Vec<RespFrame> {
+            frames.into_iter().map(RespFrame::into_resp2).collect()
+        };
+        match self {
+            RespFrame::Null(_) => RespNullBulkString.into(),
+            RespFrame::Boolean(b) => RespFrame::Integer(b as i64),
+            RespFrame::Double(d) => BulkString::new(d.to_string()).into(),
+            RespFrame::BigNumber(n) => BulkString::new(n.0).into(),
+            RespFrame::VerbatimString(s) => BulkString(s.data).into(),
+            RespFrame::BlobError(e) => {
+                SimpleError::new(String::from_utf8_lossy(&e).replace(['\r', '\n'], " ")).into()
+            }
+            RespFrame::Map(map) => RespArray::new(
+                map.0
+                    .into_iter()
+                    .flat_map(|(k, v)| [k.into_resp2(), v.into_resp2()])
+                    .collect::<Vec<_>>(),
+            )
+            .into(),
+            RespFrame::Set(set) => RespArray::new(convert(set.0)).into(),
+            RespFrame::Push(_) => SimpleError::new("ERR push data requires RESP3").into(),
+            RespFrame::Attribute(attribute) => attribute.into_frame().into_resp2(),
+            RespFrame::Array(array) => RespArray::new(convert(array.0)).into(),
+            frame => frame,
         }
     }
 }
@@ -136,11 +102,90 @@ impl From<&str> for RespFrame {
 }
 impl From<&[u8]> for RespFrame {
     fn from(s: &[u8]) -> Self {
-        BulkString(s.to_vec()).into()
+        BulkString::new(s).into()
     }
 }
 impl<const N: usize> From<&[u8; N]> for RespFrame {
     fn from(s: &[u8; N]) -> Self {
-        BulkString(s.to_vec()).into()
+        BulkString::new(s).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RespEncode;
+
+    #[test]
+    fn test_encode_to() {
+        let mut map = RespMap::new();
+        map.insert(BulkString::new("name"), BulkString::new("redis").into());
+        map.insert(12, RespFrame::Double(-1.5e-10));
+        let frame: RespFrame = RespArray::new(vec![
+            map.into(),
+            RespSet::new(vec![RespFrame::Boolean(true), RespNull.into()]).into(),
+            RespPush::new(vec![SimpleString::new("message").into()]).into(),
+            VerbatimString::text("hello").into(),
+            BigNumber::from(-12345678901234567890i128).into(),
+            BlobError::new("SYNTAX invalid").into(),
+            RespNullBulkString.into(),
+            RespNullArray.into(),
+            SimpleError::new("ERR").into(),
+            RespFrame::Integer(-42),
+            RespFrame::Double(3.25),
+        ])
+        .into();
+
+        let mut buf = BytesMut::from(&b"+OK\r\n"[..]);
+        frame.encode_to(&mut buf);
+        assert_eq!(&buf[..5], b"+OK\r\n");
+        assert_eq!(frame.encoded_len(), buf.len() - 5);
+        assert_eq!(frame.clone().encode(), &buf[5..]);
+
+        let decoded = RespFrame::decode(&mut buf.split_off(5)).unwrap();
+        assert_eq!(decoded, frame);
+    }
+
+    #[test]
+    fn test_null_frames_decode() {
+        // `$-1` 是 null bulk string, 不能解析为 null array
+        let mut buf = BytesMut::from(&b"$-1\r\n*-1\r\n_\r\n"[..]);
+        assert_eq!(RespFrame::decode(&mut buf), Ok(RespNullBulkString.into()));
+        assert_eq!(RespFrame::decode(&mut buf), Ok(RespNullArray.into()));
+        assert_eq!(RespFrame::decode(&mut buf), Ok(RespNull.into()));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_into_resp2() {
+        let mut map = RespMap::new();
+        map.insert(SimpleString::new("ok"), RespFrame::Boolean(true));
+        let frame: RespFrame = RespArray::new(vec![
+            map.into(),
+            RespNull.into(),
+            RespFrame::Double(1.5),
+            RespSet::new(vec![RespFrame::Boolean(false)]).into(),
+            SimpleString::new("OK").into(),
+            BigNumber::new("12345678901234567890").into(),
+            VerbatimString::text("a\r\nb").into(),
+            BlobError::new("ERR a\r\nb").into(),
+            RespPush::new(vec![RespNull.into()]).into(),
+            RespAttribute::new(RespMap::new(), RespFrame::Boolean(true)).into(),
+        ])
+        .into();
+        let expected: RespFrame = RespArray::new(vec![
+            RespArray::new(vec![SimpleString::new("ok").into(), RespFrame::Integer(1)]).into(),
+            RespNullBulkString.into(),
+            BulkString::new("1.5").into(),
+            RespArray::new(vec![RespFrame::Integer(0)]).into(),
+            SimpleString::new("OK").into(),
+            BulkString::new("12345678901234567890").into(),
+            BulkString::new("a\r\nb").into(),
+            SimpleError::new("ERR a  b").into(),
+            SimpleError::new("ERR push data requires RESP3").into(),
+            RespFrame::Integer(1),
+        ])
+        .into();
+        assert_eq!(frame.into_resp2(), expected);
     }
 }
@@ -1,13 +1,24 @@
-use super::{extract_simple_frame_data, CRLF_LEN};
+use super::{fmt_len, parser::decode_as, put_fmt};
 use crate::{RespDecode, RespEncode, RespError};
-use bytes::BytesMut;
+use bytes::{BufMut, BytesMut};
 
 ///  Integers This type is just a CRLF terminated string representing an integer, prefixed by a ":" byte.
 /// integer: ":[<+|->]<value>\r\n" For example ":0\r\n", or ":1000\r\n" are integer replies.
 impl RespEncode for i64 {
-    fn encode(self) -> Vec<u8> {
-        let sign = if self < 0 { "" } else { "+" };
-        format!(":{}{}\r\n", sign, self).into_bytes()
+    fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        put_fmt(buf, format_args!(":{}{}\r\n", sign(*self), self));
+    }
+
+    fn encoded_len(&self) -> usize {
+        fmt_len(format_args!(":{}{}\r\n", sign(*self), self))
+    }
+}
+// 非负数带 `+` 号
+fn sign(n: i64) -> &'static str {
+    if n < 0 {
+        ""
+    } else {
+        "+"
     }
 }
 // - Integers This type is just a CRLF terminated string representing an integer, prefixed by a ":" byte.
@@ -15,18 +26,9 @@ impl RespEncode for i64 {
 //   For example ":0\r\n", or ":1000\r\n" are integer replies.
 impl RespDecode for i64 {
     const PREFIX: &'static str = ":";
-    fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
-        // let (end, s) = parse_length(buf, Self::PREFIX)?;
-        let end: usize = extract_simple_frame_data(buf, Self::PREFIX)?;
-        let data = buf.split_to(end + CRLF_LEN);
-        let s = String::from_utf8_lossy(&data[Self::PREFIX.len()..end]);
-
-        Ok(s.parse::<Self>()?)
-    }
 
-    fn expect_length(buf: &[u8]) -> anyhow::Result<usize, RespError> {
-        let end = extract_simple_frame_data(buf, Self::PREFIX)?;
-        Ok(end + CRLF_LEN)
+    fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
+        decode_as(buf)
     }
 }
 
@@ -1,28 +1,34 @@
-use std::collections::HashMap;
-use std::ops::{Deref, DerefMut};
+use std::ops::Index;
 
-use bytes::{Buf, BytesMut};
+use bytes::{BufMut, BytesMut};
 
-use crate::{RespDecode, RespEncode, RespError, RespFrame, SimpleString};
+use crate::{RespDecode, RespEncode, RespError, RespFrame};
 
-use super::BUF_CAP;
-use super::{calc_total_len, parse_length, CRLF_LEN};
+use super::{header_len, parser::decode_as, put_header};
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct RespMap(pub(crate) HashMap<String, RespFrame>);
+/// key 可以是任意的 frame, 按插入的顺序保存, 编码的结果是确定的.
+/// RespFrame 中有 f64, 不能作为 HashMap 的 key, 使用 Vec 保存, 查找为线性的.
+/// 只有 insert 会替换重复的 key, 解码时按收到的内容保存, 重复的 key 也会保留
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct RespMap(pub(crate) Vec<(RespFrame, RespFrame)>);
 
 /// map "%<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n>"
-/// key 仅支持 String， 使用SimpleString
 impl RespEncode for RespMap {
-    fn encode(self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(BUF_CAP);
-        buf.extend_from_slice(&format!("%{}\r\n", self.len()).into_bytes());
-        for (key, value) in self.0 {
-            buf.extend_from_slice(&SimpleString::new(key).encode());
-            buf.extend_from_slice(&value.encode());
+    fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        put_header(buf, b'%', self.0.len());
+        for (key, value) in &self.0 {
+            key.encode_to(buf);
+            value.encode_to(buf);
         }
+    }
 
-        buf
+    fn encoded_len(&self) -> usize {
+        header_len(self.0.len())
+            + self
+                .0
+                .iter()
+                .map(|(key, value)| key.encoded_len() + value.encoded_len())
+                .sum::<usize>()
     }
 }
 // - map "%<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n>"
@@ -30,71 +36,118 @@ impl RespDecode for RespMap {
     const PREFIX: &'static str = "%";
 
     fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
-        let (end, len) = parse_length(buf, Self::PREFIX)?;
-        let total_len = calc_total_len(buf, end, len, Self::PREFIX)?;
+        decode_as(buf)
+    }
+}
 
-        if buf.len() < total_len {
-            return Err(RespError::NotComplete);
-        }
-        buf.advance(end + CRLF_LEN);
+impl RespMap {
+    pub fn new() -> Self {
+        RespMap(Vec::new())
+    }
 
-        let mut frame = RespMap::new();
-        for _ in 0..len {
-            let key = SimpleString::decode(buf)?;
-            let value = RespFrame::decode(buf)?;
-            frame.insert(key.0, value);
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// 插入 key-value, key 已经存在时替换 value 并保持原来的位置
+    pub fn insert(&mut self, key: impl Into<RespFrame>, value: RespFrame) -> Option<RespFrame> {
+        let key = key.into();
+        match self.0.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => Some(std::mem::replace(v, value)),
+            None => {
+                self.0.push((key, value));
+                None
+            }
         }
-        Ok(frame)
     }
 
-    fn expect_length(buf: &[u8]) -> anyhow::Result<usize, RespError> {
-        let (end, len) = parse_length(buf, Self::PREFIX)?;
-        let total_len = calc_total_len(buf, end, len, Self::PREFIX)?;
-        Ok(total_len)
+    /// 按字符串查找, SimpleString 和 BulkString 的 key 都可以匹配
+    pub fn get(&self, key: &str) -> Option<&RespFrame> {
+        self.0
+            .iter()
+            .find(|(k, _)| key_matches(k, key))
+            .map(|(_, v)| v)
     }
-}
 
-impl Deref for RespMap {
-    type Target = HashMap<String, RespFrame>;
+    pub fn get_frame(&self, key: &RespFrame) -> Option<&RespFrame> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+    pub fn remove(&mut self, key: &str) -> Option<RespFrame> {
+        let pos = self.0.iter().position(|(k, _)| key_matches(k, key))?;
+        Some(self.0.remove(pos).1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&RespFrame, &RespFrame)> {
+        self.0.iter().map(|(k, v)| (k, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &RespFrame> {
+        self.0.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &RespFrame> {
+        self.0.iter().map(|(_, v)| v)
     }
 }
-impl DerefMut for RespMap {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+
+fn key_matches(key: &RespFrame, s: &str) -> bool {
+    match key {
+        RespFrame::SimpleString(k) => k.as_str() == s,
+        RespFrame::BulkString(k) => k[..] == *s.as_bytes(),
+        _ => false,
     }
 }
 
-impl RespMap {
-    pub fn new() -> Self {
-        RespMap(HashMap::new())
+impl Index<&str> for RespMap {
+    type Output = RespFrame;
+
+    fn index(&self, key: &str) -> &Self::Output {
+        self.get(key)
+            .unwrap_or_else(|| panic!("key not found in map: {}", key))
+    }
+}
+
+impl<K: Into<RespFrame>> FromIterator<(K, RespFrame)> for RespMap {
+    fn from_iter<T: IntoIterator<Item = (K, RespFrame)>>(iter: T) -> Self {
+        let mut map = RespMap::new();
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+        map
     }
 }
-impl Default for RespMap {
-    fn default() -> Self {
-        Self::new()
+
+impl IntoIterator for RespMap {
+    type Item = (RespFrame, RespFrame);
+    type IntoIter = std::vec::IntoIter<(RespFrame, RespFrame)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{BulkString, RespMap};
+    use crate::{BulkString, RespArray, RespMap, SimpleString};
     use bytes::BytesMut;
 
-    // #[test]
-    // fn test_map_encode() {
-    //     let mut map = RespMap::new();
-    //     map.insert("hello".to_string(), BulkString::new("world").into());
-    //     map.insert("foo".to_string(), (-123456.789).into());
-    //     let frame: RespFrame = map.into();
-    //     assert_eq!(
-    //         frame.encode(),
-    //         b"%2\r\n+foo\r\n,-123456.789\r\n+hello\r\n$5\r\nworld\r\n"
-    //     );
-    // }
+    #[test]
+    fn test_map_encode() {
+        let mut map = RespMap::new();
+        map.insert(BulkString::new("hello"), BulkString::new("world").into());
+        map.insert(SimpleString::new("foo"), (-123456.789).into());
+        let frame: RespFrame = map.into();
+        assert_eq!(
+            frame.encode(),
+            b"%2\r\n$5\r\nhello\r\n$5\r\nworld\r\n+foo\r\n,-123456.789\r\n"
+        );
+    }
 
     #[test]
     fn test_map_decode() -> anyhow::Result<()> {
@@ -105,13 +158,91 @@ mod tests {
 
         let mut map = RespMap::new();
         map.insert(
-            "hello".to_string(),
+            SimpleString::new("hello"),
             BulkString::new(b"world".to_vec()).into(),
         );
-        map.insert("foo".to_string(), BulkString::new(b"bar".to_vec()).into());
+        map.insert(
+            SimpleString::new("foo"),
+            BulkString::new(b"bar".to_vec()).into(),
+        );
 
         assert_eq!(frame, map);
 
         anyhow::Ok(())
     }
+
+    #[test]
+    fn test_map_frame_keys() -> anyhow::Result<()> {
+        // Redis 7 对 HELLO 3 的回复, key 为 bulk string
+        let hello: &[u8] = b"%7\r\n$6\r\nserver\r\n$5\r\nredis\r\n$7\r\nversion\r\n$5\r\n7.2.4\r\n$5\r\nproto\r\n:3\r\n$2\r\nid\r\n:5\r\n$4\r\nmode\r\n$10\r\nstandalone\r\n$4\r\nrole\r\n$6\r\nmaster\r\n$7\r\nmodules\r\n*0\r\n";
+        let frame = RespFrame::decode(&mut BytesMut::from(hello))?;
+        let RespFrame::Map(map) = &frame else {
+            panic!("HELLO should reply a map");
+        };
+        assert_eq!(map["proto"], RespFrame::Integer(3));
+        assert_eq!(map.get("server"), Some(&BulkString::new("redis").into()));
+        let keys: Vec<_> = map.keys().cloned().collect();
+        assert_eq!(keys[0], BulkString::new("server").into());
+        assert_eq!(keys[6], BulkString::new("modules").into());
+        // 编码保持顺序, 可以解码为相同的 map
+        let encoded = frame.clone().encode();
+        assert!(encoded.starts_with(b"%7\r\n$6\r\nserver\r\n$5\r\nredis\r\n$7\r\nversion\r\n"));
+        assert_eq!(RespFrame::decode(&mut BytesMut::from(&encoded[..]))?, frame);
+
+        // CLIENT TRACKINGINFO 等回复中的整数和数组 key 也可以解码
+        let mut buf = BytesMut::from(&b"%2\r\n:1\r\n$3\r\none\r\n*1\r\n:2\r\n$3\r\ntwo\r\n"[..]);
+        let map = RespMap::decode(&mut buf)?;
+        assert_eq!(
+            map.get_frame(&RespFrame::Integer(1)),
+            Some(&BulkString::new("one").into())
+        );
+        let key: RespFrame = RespArray::new(vec![RespFrame::Integer(2)]).into();
+        assert_eq!(map.get_frame(&key), Some(&BulkString::new("two").into()));
+
+        // 重复的 key 替换 value, 位置不变
+        let mut map: RespMap = [("a", 1), ("b", 2)]
+            .into_iter()
+            .map(|(k, v)| (BulkString::new(k), RespFrame::Integer(v)))
+            .collect();
+        assert_eq!(
+            map.insert(BulkString::new("a"), RespFrame::Integer(3)),
+            Some(RespFrame::Integer(1))
+        );
+        assert_eq!(
+            map.into_iter().map(|(_, v)| v).collect::<Vec<_>>(),
+            vec![RespFrame::Integer(3), RespFrame::Integer(2)]
+        );
+        anyhow::Ok(())
+    }
+
+    #[test]
+    fn test_map_decode_keeps_wire_entries() -> anyhow::Result<()> {
+        // 解码不合并重复的 key, 普通的 map 和 streamed map 相同
+        for data in [
+            &b"%2\r\n+a\r\n:1\r\n+a\r\n:2\r\n"[..],
+            &b"%?\r\n+a\r\n:1\r\n+a\r\n:2\r\n.\r\n"[..],
+        ] {
+            let expected = RespMap(vec![
+                (SimpleString::new("a").into(), RespFrame::Integer(1)),
+                (SimpleString::new("a").into(), RespFrame::Integer(2)),
+            ]);
+            assert_eq!(RespMap::decode(&mut BytesMut::from(data))?, expected);
+            let frame = RespFrame::decode(&mut BytesMut::from(data))?;
+            assert_eq!(frame, expected.into());
+        }
+
+        // 很多 key 时解码为线性的
+        let n = 50_000;
+        let mut map = RespMap(Vec::with_capacity(n));
+        for i in 0..n {
+            map.0
+                .push((RespFrame::Integer(i as i64), RespFrame::Integer(0)));
+        }
+        let encoded = RespFrame::from(map).encode();
+        let RespFrame::Map(map) = RespFrame::decode(&mut BytesMut::from(&encoded[..]))? else {
+            panic!("expect a map");
+        };
+        assert_eq!(map.len(), n);
+        anyhow::Ok(())
+    }
 }
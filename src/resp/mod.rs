@@ -1,20 +1,32 @@
+use std::fmt::{self, Write};
+
 use anyhow::Result;
-use bytes::{Buf, BytesMut};
+use bytes::{Buf, BufMut, BytesMut};
 use enum_dispatch::enum_dispatch;
 use thiserror::Error;
 
 pub use self::{
     array::{RespArray, RespNullArray},
+    attribute::RespAttribute,
+    big_number::BigNumber,
+    blob_error::BlobError,
     bulk_string::{BulkString, RespNullBulkString},
     frame::RespFrame,
     map::RespMap,
     null::RespNull,
+    parser::RespParser,
+    push::RespPush,
     set::RespSet,
     simple_error::SimpleError,
     simple_string::SimpleString,
+    streamed::{RespSink, StreamedAggregate, StreamedMap, StreamedString},
+    verbatim_string::VerbatimString,
 };
 
 mod array;
+mod attribute;
+mod big_number;
+mod blob_error;
 mod bool;
 mod bulk_string;
 mod double;
@@ -22,24 +34,37 @@ mod frame;
 mod integer;
 mod map;
 mod null;
+mod parser;
+mod push;
 mod set;
 mod simple_error;
 mod simple_string;
+mod streamed;
+mod verbatim_string;
 
-const BUF_CAP: usize = 4096_usize;
 const CRLF: &[u8] = b"\r\n";
 const CRLF_LEN: usize = CRLF.len();
 
 /// 编码
 #[enum_dispatch]
 pub trait RespEncode {
-    fn encode(self) -> Vec<u8>;
+    /// 直接写入调用方提供的缓冲区, 嵌套的数组和 map 不会逐层分配
+    fn encode_to<B: BufMut>(&self, buf: &mut B);
+    /// 编码后的字节数, 可用于预先分配缓冲区
+    fn encoded_len(&self) -> usize;
+    fn encode(self) -> Vec<u8>
+    where
+        Self: Sized,
+    {
+        let mut buf = Vec::with_capacity(self.encoded_len());
+        self.encode_to(&mut buf);
+        buf
+    }
 }
 /// 解码
 pub trait RespDecode: Sized {
     const PREFIX: &'static str;
     fn decode(buf: &mut BytesMut) -> Result<Self, RespError>;
-    fn expect_length(buf: &[u8]) -> Result<usize, RespError>;
 }
 #[derive(Error, Debug, PartialEq, Eq)]
 pub enum RespError {
@@ -60,68 +85,45 @@ pub enum RespError {
     ParseBulkStringError(#[from] std::str::Utf8Error),
 }
 
-fn extract_simple_frame_data(buf: &[u8], prefix: &str) -> Result<usize, RespError> {
-    if buf.len() < 3 {
-        return Err(RespError::NotComplete);
-    }
-    if !buf.starts_with(prefix.as_bytes()) {
-        return Err(RespError::InvalidFrameType(format!(
-            "expect: SimpleString({}), got: {:?}",
-            prefix, buf
-        )));
-    }
-    let mut end = 0_usize;
-    for i in 0..buf.len() - 1 {
-        if buf[i] == b'\r' && buf[i + 1] == b'\n' {
-            end = i;
-            break;
-        }
-    }
-    if end == 0 {
-        return Err(RespError::NotComplete);
+// 以 fmt::Write 的方式写入 BufMut, 格式化数字时不需要中间的 String
+struct FmtBuf<'a, B>(&'a mut B);
+
+impl<B: BufMut> Write for FmtBuf<'_, B> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.put_slice(s.as_bytes());
+        Ok(())
     }
-    Ok(end)
 }
 
-pub fn calc_total_len(
-    buf: &[u8],
-    end: usize,
-    len: usize,
-    prefix: &str,
-) -> Result<usize, RespError> {
-    let mut total = end + CRLF_LEN;
-    let mut data = &buf[total..];
-    match prefix {
-        "*" | "~" => {
-            // For array or set, we need to calculate each element length.
-            for _ in 0..len {
-                let len = RespFrame::expect_length(data)?;
-                data = &data[len..];
-                total += len;
-            }
-            Ok(total)
-        }
-        "%" => {
-            // Find nth CRLF in the buffer. For map, we need to find 2 CRLF for each key-value pair.
-            for _ in 0..len {
-                let len1 = SimpleString::expect_length(data)?;
-                data = &data[len1..];
-                total += len1;
-
-                let len2 = RespFrame::expect_length(data)?;
-                data = &data[len2..];
-                total += len2;
-            }
-            Ok(total)
-        }
-        _ => Ok(len + CRLF_LEN),
+// 只统计格式化后的长度
+struct FmtLen(usize);
+
+impl Write for FmtLen {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
     }
 }
 
-pub fn parse_length(buf: &[u8], prefix: &str) -> Result<(usize, usize), RespError> {
-    let end: usize = extract_simple_frame_data(buf, prefix)?;
-    let s = String::from_utf8_lossy(&buf[prefix.len()..end]);
-    Ok((end, s.parse()?))
+fn put_fmt<B: BufMut>(buf: &mut B, args: fmt::Arguments) {
+    let _ = FmtBuf(buf).write_fmt(args);
+}
+
+fn fmt_len(args: fmt::Arguments) -> usize {
+    let mut len = FmtLen(0);
+    let _ = len.write_fmt(args);
+    len.0
+}
+
+// 聚合类型和以长度开始的字符串的头部 `<prefix><len>\r\n`
+fn put_header<B: BufMut>(buf: &mut B, prefix: u8, len: usize) {
+    buf.put_u8(prefix);
+    put_fmt(buf, format_args!("{}", len));
+    buf.put_slice(CRLF);
+}
+
+fn header_len(len: usize) -> usize {
+    1 + fmt_len(format_args!("{}", len)) + CRLF_LEN
 }
 
 /// Extracts a fixed amount of data from the buffer.
@@ -142,13 +144,15 @@ pub fn extract_fixed_data(
     expect: &str,
     expect_type: &str,
 ) -> Result<(), RespError> {
-    if buf.len() < expect.len() {
+    // 数据不足时, 只有已收到的部分与期望的一致才需要等待更多数据, 如 `*0\r\n` 不是 `*-1\r\n`
+    if buf.len() < expect.len() && expect.as_bytes().starts_with(buf) {
         return Err(RespError::NotComplete);
     }
     if !buf.starts_with(expect.as_bytes()) {
         return Err(RespError::InvalidFrameType(format!(
             "expect: {}, got {:?}",
-            expect_type, buf
+            expect_type,
+            &buf[..buf.len().min(expect.len())]
         )));
     }
 
@@ -162,17 +166,51 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_calc_array_length() -> Result<()> {
-        let buf = b"*2\r\n$3\r\nset\r\n$5\r\nhello\r\n";
-        let (end, len) = parse_length(buf, "*")?;
-        let total_len = calc_total_len(buf, end, len, "*")?;
-        assert_eq!(total_len, buf.len());
+    fn test_decode_consumes_one_frame() -> Result<()> {
+        // 每种类型只取出一个完整的 frame, 之后的数据留在 buf 中
+        for data in [
+            &b"*2\r\n$3\r\nset\r\n$5\r\nhello\r\n"[..],
+            b"%2\r\n+hello\r\n$5\r\nworld\r\n+foo\r\n$3\r\nbar\r\n",
+            b"=15\r\ntxt:Some string\r\n",
+        ] {
+            let mut buf = BytesMut::from(data);
+            buf.extend_from_slice(b"+next\r\n");
+            RespFrame::decode(&mut buf)?;
+            assert_eq!(&buf[..], b"+next\r\n");
+        }
 
-        let buf = b"%2\r\n+hello\r\n$5\r\nworld\r\n+foo\r\n$3\r\nbar\r\n";
-        let (end, len) = parse_length(buf, "%")?;
-        let total_len = calc_total_len(buf, end, len, "%")?;
-        assert_eq!(total_len, buf.len());
+        // 类型不符时返回错误, 不消耗数据
+        let mut buf = BytesMut::from(&b"$-1\r\n"[..]);
+        assert!(matches!(
+            BulkString::decode(&mut buf),
+            Err(RespError::InvalidFrameType(_))
+        ));
+        assert!(matches!(
+            RespArray::decode(&mut buf),
+            Err(RespError::InvalidFrameType(_))
+        ));
+        assert_eq!(RespNullBulkString::decode(&mut buf)?, RespNullBulkString);
+        assert!(buf.is_empty());
+        anyhow::Ok(())
+    }
 
+    #[test]
+    fn test_extract_fixed_data() -> Result<()> {
+        // 数据不足且与期望一致时等待更多数据
+        let mut buf = BytesMut::from(&b"*-"[..]);
+        let ret = extract_fixed_data(&mut buf, "*-1\r\n", "RespNullArray");
+        assert_eq!(ret, Err(RespError::NotComplete));
+
+        // 长度不足但已经不一致, 如空数组 `*0\r\n` 不是 null array
+        let mut buf = BytesMut::from(&b"*0\r\n"[..]);
+        let ret = extract_fixed_data(&mut buf, "*-1\r\n", "RespNullArray");
+        assert!(matches!(ret, Err(RespError::InvalidFrameType(_))));
+
+        let mut buf = BytesMut::from(&b"*1\r\n*0\r\n"[..]);
+        assert_eq!(
+            RespFrame::decode(&mut buf)?,
+            RespArray::new(vec![RespArray::new(vec![]).into()]).into()
+        );
         anyhow::Ok(())
     }
 }
@@ -1,14 +1,18 @@
-use super::extract_fixed_data;
+use super::parser::decode_as;
 use crate::{RespDecode, RespEncode, RespError};
-use bytes::BytesMut;
+use bytes::{BufMut, BytesMut};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct RespNull;
 
 ///  Null "_\r\n"
 impl RespEncode for RespNull {
-    fn encode(self) -> Vec<u8> {
-        b"_\r\n".to_vec()
+    fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        buf.put_slice(b"_\r\n");
+    }
+
+    fn encoded_len(&self) -> usize {
+        3
     }
 }
 
@@ -17,12 +21,7 @@ impl RespDecode for RespNull {
     const PREFIX: &'static str = "_";
 
     fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
-        extract_fixed_data(buf, "_\r\n", "RespNull")?;
-        Ok(RespNull)
-    }
-
-    fn expect_length(_buf: &[u8]) -> anyhow::Result<usize, RespError> {
-        Ok(3)
+        decode_as(buf)
     }
 }
 
@@ -0,0 +1,593 @@
+// 增量解析 RESP: 只扫描一遍, 数据不完整时记住已解析的位置和未完成的聚合类型,
+// 收到更多数据后从上次停下的地方继续, 不需要从头计算整个 frame 的长度
+
+use std::ops::Range;
+
+use bytes::{Bytes, BytesMut};
+
+use crate::{
+    BigNumber, BlobError, BulkString, RespArray, RespAttribute, RespError, RespFrame, RespMap,
+    RespNull, RespNullArray, RespNullBulkString, RespPush, RespSet, SimpleError, SimpleString,
+    VerbatimString,
+};
+
+use super::{RespDecode, CRLF, CRLF_LEN};
+
+// 与 Redis 的 proto-max-bulk-len 相同, 字符串 (包括 streamed string 的全部 chunk) 的最大长度
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+// 聚合类型的最大元素个数, map 和 attribute 为 entry 的个数
+const MAX_AGGREGATE_LEN: usize = 1 << 31;
+// 与 Redis 相同的最大嵌套层数, 生成 frame 和释放 frame 时按层递归, 层数过多会栈溢出
+const MAX_NESTING: usize = 128;
+
+/// 可恢复的解析器, `buf[..pos]` 已经解析过, 直到一个完整的 frame 解析完成才从 buf 中取出
+#[derive(Debug, Default)]
+pub struct RespParser {
+    pos: usize,
+    // 未完成的聚合类型和 streamed string, 栈顶为最内层
+    stack: Vec<Pending>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Array,
+    Set,
+    Map,
+    Push,
+    Attribute,
+}
+
+#[derive(Debug)]
+enum Pending {
+    // remaining 为 None 时是 streamed aggregate, 以 `.` 结束
+    Aggregate {
+        kind: Kind,
+        remaining: Option<usize>,
+        items: Vec<Node>,
+    },
+    StreamedString(Vec<u8>),
+}
+
+// 已解析的元素, 字符串数据只记录在 buf 中的范围, 整个 frame 完成后再切片, 不复制数据
+#[derive(Debug)]
+enum Node {
+    Frame(RespFrame),
+    Bulk(Range<usize>),
+    Verbatim([u8; 3], Range<usize>),
+    BlobError(Range<usize>),
+    Aggregate(Kind, Vec<Node>),
+}
+
+enum Step {
+    Node(Node),
+    Open(Pending),
+}
+
+impl RespParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 解析下一个 frame; 数据不完整时返回 `Ok(None)`, 调用方追加数据后再次调用
+    pub fn parse(&mut self, buf: &mut BytesMut) -> Result<Option<RespFrame>, RespError> {
+        Ok(self.parse_raw(buf)?.map(|(frame, _)| frame))
+    }
+
+    /// 与 parse 相同, 同时返回 frame 的原始数据
+    pub fn parse_raw(
+        &mut self,
+        buf: &mut BytesMut,
+    ) -> Result<Option<(RespFrame, Bytes)>, RespError> {
+        match self.advance(buf) {
+            Ok(Some(node)) => {
+                let data = buf.split_to(self.pos).freeze();
+                self.pos = 0;
+                Ok(Some((node.into_frame(&data), data)))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => {
+                self.reset();
+                Err(e)
+            }
+        }
+    }
+
+    /// 丢弃未完成的解析状态
+    pub fn reset(&mut self) {
+        self.pos = 0;
+        self.stack.clear();
+    }
+
+    // 逐个解析元素, 返回完整的顶层元素
+    fn advance(&mut self, buf: &[u8]) -> Result<Option<Node>, RespError> {
+        loop {
+            let step = match self.stack.last_mut() {
+                Some(Pending::StreamedString(data)) => match parse_chunk(buf, self.pos, data)? {
+                    Some((pos, done)) => {
+                        self.pos = pos;
+                        if !done {
+                            continue;
+                        }
+                        let Some(Pending::StreamedString(data)) = self.stack.pop() else {
+                            unreachable!()
+                        };
+                        Step::Node(Node::Frame(BulkString::new(data).into()))
+                    }
+                    None => return Ok(None),
+                },
+                Some(Pending::Aggregate {
+                    remaining: None, ..
+                }) if buf[self.pos..].starts_with(b".") => {
+                    if buf.len() < self.pos + 1 + CRLF_LEN {
+                        return Ok(None);
+                    }
+                    if !buf[self.pos..].starts_with(b".\r\n") {
+                        return Err(RespError::InvalidFrame(
+                            "expect: end of streamed aggregate \".\\r\\n\"".to_string(),
+                        ));
+                    }
+                    self.pos += 1 + CRLF_LEN;
+                    let Some(Pending::Aggregate { kind, items, .. }) = self.stack.pop() else {
+                        unreachable!()
+                    };
+                    Step::Node(Node::Aggregate(kind, items))
+                }
+                _ => match parse_element(buf, self.pos)? {
+                    Some((pos, step)) => {
+                        self.pos = pos;
+                        step
+                    }
+                    None => return Ok(None),
+                },
+            };
+            match step {
+                Step::Open(_) if self.stack.len() >= MAX_NESTING => {
+                    return Err(RespError::InvalidFrame(
+                        "too many nested levels".to_string(),
+                    ));
+                }
+                Step::Open(pending) => self.stack.push(pending),
+                Step::Node(node) => {
+                    if let Some(node) = self.complete(node)? {
+                        return Ok(Some(node));
+                    }
+                }
+            }
+        }
+    }
+
+    // 把元素加入所在的聚合类型, 聚合类型完成后继续加入上一层; 返回完成的顶层元素
+    fn complete(&mut self, mut node: Node) -> Result<Option<Node>, RespError> {
+        loop {
+            let Some(Pending::Aggregate {
+                kind,
+                remaining,
+                items,
+            }) = self.stack.last_mut()
+            else {
+                return Ok(Some(node));
+            };
+            // streamed aggregate 没有长度, 元素个数同样受限
+            if items.len() >= MAX_AGGREGATE_LEN {
+                return Err(RespError::InvalidFrame(
+                    "too many elements in streamed aggregate".to_string(),
+                ));
+            }
+            items.push(node);
+            if *remaining != Some(items.len()) {
+                return Ok(None);
+            }
+            node = Node::Aggregate(*kind, std::mem::take(items));
+            self.stack.pop();
+        }
+    }
+}
+
+// 从 pos 开始的一行 (不含 CRLF) 及下一行的位置
+fn line(buf: &[u8], pos: usize) -> Option<(&[u8], usize)> {
+    let rest = buf.get(pos..)?;
+    let end = rest.windows(CRLF_LEN).position(|w| w == CRLF)?;
+    Some((&rest[..end], pos + end + CRLF_LEN))
+}
+
+// 解析长度, 超过 max 时为错误
+fn parse_len(s: &[u8], max: usize) -> Result<usize, RespError> {
+    let len = String::from_utf8_lossy(s).parse()?;
+    if len > max {
+        return Err(RespError::InvalidFrame(format!("invalid length: {}", len)));
+    }
+    Ok(len)
+}
+
+// 字符串数据之后必须是 CRLF, 调用方已经确认数据足够
+fn expect_crlf(buf: &[u8], end: usize) -> Result<(), RespError> {
+    if &buf[end..end + CRLF_LEN] != CRLF {
+        return Err(RespError::InvalidFrame(
+            "expect: CRLF after string data".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn overflow() -> RespError {
+    RespError::InvalidFrame("length overflow".to_string())
+}
+
+// 解析 pos 处的一个元素; 数据不完整时返回 None
+fn parse_element(buf: &[u8], pos: usize) -> Result<Option<(usize, Step)>, RespError> {
+    let Some(&prefix) = buf.get(pos) else {
+        return Ok(None);
+    };
+    if !b"+-:$*_#,%~(=!>|".contains(&prefix) {
+        return Err(RespError::InvalidFrameType(format!(
+            "Invalid frame type: {:?}",
+            prefix as char
+        )));
+    }
+    let Some((line, next)) = line(buf, pos + 1) else {
+        return Ok(None);
+    };
+    let node = |frame: RespFrame| Ok(Some((next, Step::Node(Node::Frame(frame)))));
+    match prefix {
+        b'+' => node(SimpleString::new(String::from_utf8_lossy(line)).into()),
+        b'-' => node(SimpleError::new(String::from_utf8_lossy(line)).into()),
+        b':' => node(String::from_utf8_lossy(line).parse::<i64>()?.into()),
+        b',' => node(String::from_utf8_lossy(line).parse::<f64>()?.into()),
+        b'_' if line.is_empty() => node(RespNull.into()),
+        b'_' => Err(RespError::InvalidFrameType(format!(
+            "expect: RespNull, got {:?}",
+            line
+        ))),
+        b'#' => match line {
+            b"t" => node(true.into()),
+            b"f" => node(false.into()),
+            _ => Err(RespError::InvalidFrameType(format!(
+                "expect: Bool, got {:?}",
+                line
+            ))),
+        },
+        b'(' => {
+            let s = String::from_utf8_lossy(line).into_owned();
+            let digits = s.strip_prefix(['+', '-']).unwrap_or(&s);
+            if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(RespError::InvalidFrame(format!(
+                    "invalid big number: {}",
+                    s
+                )));
+            }
+            node(BigNumber::new(s).into())
+        }
+        b'$' if line == b"-1" => node(RespNullBulkString.into()),
+        b'$' if line == b"?" => Ok(Some((next, Step::Open(Pending::StreamedString(vec![]))))),
+        b'*' if line == b"-1" => node(RespNullArray.into()),
+        b'$' | b'=' | b'!' => {
+            let len = parse_len(line, MAX_BULK_LEN)?;
+            let end = next.checked_add(len).ok_or_else(overflow)?;
+            if buf.len() < end.checked_add(CRLF_LEN).ok_or_else(overflow)? {
+                return Ok(None);
+            }
+            expect_crlf(buf, end)?;
+            let node = match prefix {
+                b'$' => Node::Bulk(next..end),
+                b'!' => Node::BlobError(next..end),
+                _ => {
+                    if len < 4 || buf[next + 3] != b':' {
+                        return Err(RespError::InvalidFrame(format!(
+                            "invalid verbatim string: {:?}",
+                            &buf[next..end]
+                        )));
+                    }
+                    let format = [buf[next], buf[next + 1], buf[next + 2]];
+                    Node::Verbatim(format, next + 4..end)
+                }
+            };
+            Ok(Some((end + CRLF_LEN, Step::Node(node))))
+        }
+        _ => {
+            let kind = match prefix {
+                b'*' => Kind::Array,
+                b'~' => Kind::Set,
+                b'%' => Kind::Map,
+                b'>' => Kind::Push,
+                _ => Kind::Attribute,
+            };
+            // 只有 array, set 和 map 支持 streamed
+            let remaining = match (kind, line) {
+                (Kind::Array | Kind::Set | Kind::Map, b"?") => None,
+                // map 的 key 和 value 各为一个元素, attribute 之后还有一个回复
+                (Kind::Map, _) => Some(pairs(line)?),
+                (Kind::Attribute, _) => Some(pairs(line)?.checked_add(1).ok_or_else(overflow)?),
+                _ => Some(parse_len(line, MAX_AGGREGATE_LEN)?),
+            };
+            let step = if remaining == Some(0) {
+                Step::Node(Node::Aggregate(kind, vec![]))
+            } else {
+                Step::Open(Pending::Aggregate {
+                    kind,
+                    remaining,
+                    items: Vec::with_capacity(remaining.unwrap_or_default().min(1024)),
+                })
+            };
+            Ok(Some((next, step)))
+        }
+    }
+}
+
+// map 和 attribute 的元素个数, 每个 entry 为两个元素
+fn pairs(line: &[u8]) -> Result<usize, RespError> {
+    parse_len(line, MAX_AGGREGATE_LEN)?
+        .checked_mul(2)
+        .ok_or_else(overflow)
+}
+
+// 解析 streamed string 的一个 chunk `;<length>\r\n<data>\r\n`, 返回下一个位置和是否结束
+fn parse_chunk(
+    buf: &[u8],
+    pos: usize,
+    data: &mut Vec<u8>,
+) -> Result<Option<(usize, bool)>, RespError> {
+    let Some(&prefix) = buf.get(pos) else {
+        return Ok(None);
+    };
+    if prefix != b';' {
+        return Err(RespError::InvalidFrameType(format!(
+            "expect: SimpleString(;), got: {:?}",
+            prefix as char
+        )));
+    }
+    let Some((line, next)) = line(buf, pos + 1) else {
+        return Ok(None);
+    };
+    // 所有 chunk 的总长度不超过 MAX_BULK_LEN
+    let len = parse_len(line, MAX_BULK_LEN - data.len())?;
+    if len == 0 {
+        return Ok(Some((next, true)));
+    }
+    let end = next.checked_add(len).ok_or_else(overflow)?;
+    if buf.len() < end.checked_add(CRLF_LEN).ok_or_else(overflow)? {
+        return Ok(None);
+    }
+    expect_crlf(buf, end)?;
+    data.extend_from_slice(&buf[next..end]);
+    Ok(Some((end + CRLF_LEN, false)))
+}
+
+impl Node {
+    // data 为整个 frame 的数据, 字符串都是它的切片
+    fn into_frame(self, data: &Bytes) -> RespFrame {
+        match self {
+            Node::Frame(frame) => frame,
+            Node::Bulk(range) => BulkString::from(data.slice(range)).into(),
+            Node::Verbatim(format, range) => VerbatimString {
+                format,
+                data: data.slice(range),
+            }
+            .into(),
+            Node::BlobError(range) => BlobError(data.slice(range)).into(),
+            Node::Aggregate(kind, items) => {
+                let mut frames = items.into_iter().map(|node| node.into_frame(data));
+                match kind {
+                    Kind::Array => RespArray::new(frames.collect::<Vec<_>>()).into(),
+                    Kind::Set => RespSet::new(frames.collect::<Vec<_>>()).into(),
+                    Kind::Push => RespPush::new(frames.collect::<Vec<_>>()).into(),
+                    Kind::Map => collect_map(&mut frames).into(),
+                    Kind::Attribute => {
+                        let attributes = collect_map(&mut frames);
+                        // 解析时保证了最后一个元素存在
+                        let frame = frames.next().unwrap_or_else(|| RespNull.into());
+                        RespAttribute::new(attributes, frame).into()
+                    }
+                }
+            }
+        }
+    }
+}
+
+// attribute 的最后一个元素不属于 map, 所以不能用 collect.
+// 按收到的顺序保存, 不查找重复的 key
+fn collect_map(frames: &mut impl ExactSizeIterator<Item = RespFrame>) -> RespMap {
+    let mut entries = Vec::with_capacity(frames.len() / 2);
+    while frames.len() >= 2 {
+        if let (Some(key), Some(value)) = (frames.next(), frames.next()) {
+            entries.push((key, value));
+        }
+    }
+    RespMap(entries)
+}
+
+/// 各类型的 RespDecode::decode 通过 RespParser 解析. 先检查类型, 类型不符时返回错误,
+/// 不消耗 buf 中的数据; `$-1` 和 `*-1` 是单独的 null 类型, 不是 bulk string 和 array
+pub(crate) fn decode_as<T: RespDecode>(buf: &mut BytesMut) -> Result<T, RespError>
+where
+    RespFrame: TryInto<T>,
+{
+    let Some(&prefix) = buf.first() else {
+        return Err(RespError::NotComplete);
+    };
+    if T::PREFIX.as_bytes() != [prefix]
+        || buf.starts_with(b"$-1\r\n")
+        || buf.starts_with(b"*-1\r\n")
+    {
+        let got = &buf[..buf.len().min(8)];
+        return Err(RespError::InvalidFrameType(format!(
+            "expect: {}, got: {:?}",
+            T::PREFIX,
+            String::from_utf8_lossy(got)
+        )));
+    }
+    let frame = RespParser::new()
+        .parse(buf)?
+        .ok_or(RespError::NotComplete)?;
+    frame
+        .try_into()
+        .map_err(|_| RespError::InvalidFrameType(format!("expect: {}", T::PREFIX)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_resumable() -> anyhow::Result<()> {
+        let data = b"*3\r\n$3\r\nset\r\n$5\r\nhello\r\n%1\r\n+a\r\n~2\r\n:1\r\n#t\r\n+OK\r\n";
+        let mut parser = RespParser::new();
+        let mut buf = BytesMut::new();
+        let mut frames = vec![];
+        // 每次只收到一个字节
+        for b in data {
+            buf.extend_from_slice(&[*b]);
+            while let Some(frame) = parser.parse(&mut buf)? {
+                frames.push(frame);
+            }
+        }
+        assert!(buf.is_empty());
+
+        let mut map = RespMap::new();
+        map.insert(
+            SimpleString::new("a"),
+            RespSet::new(vec![RespFrame::Integer(1), true.into()]).into(),
+        );
+        let expected: Vec<RespFrame> = vec![
+            RespArray::new(vec![
+                BulkString::new("set").into(),
+                BulkString::new("hello").into(),
+                map.into(),
+            ])
+            .into(),
+            SimpleString::new("OK").into(),
+        ];
+        assert_eq!(frames, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_streamed_and_attribute() -> anyhow::Result<()> {
+        let data = b"*?\r\n$?\r\n;2\r\nhe\r\n;3\r\nllo\r\n;0\r\n|1\r\n+ttl\r\n:3\r\n=7\r\ntxt:abc\r\n.\r\n";
+        let mut parser = RespParser::new();
+        let mut buf = BytesMut::new();
+        for chunk in data.chunks(5) {
+            buf.extend_from_slice(chunk);
+            if let Some(frame) = parser.parse(&mut buf)? {
+                let mut attributes = RespMap::new();
+                attributes.insert(SimpleString::new("ttl"), RespFrame::Integer(3));
+                let expected: RespFrame = RespArray::new(vec![
+                    BulkString::new("hello").into(),
+                    RespAttribute::new(attributes, VerbatimString::text("abc")).into(),
+                ])
+                .into();
+                assert_eq!(frame, expected);
+                assert!(buf.is_empty());
+                return Ok(());
+            }
+        }
+        panic!("frame not complete");
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let mut parser = RespParser::new();
+        let mut buf = BytesMut::from(&b"*2\r\n:1\r\n"[..]);
+        assert_eq!(parser.parse(&mut buf), Ok(None));
+        buf.extend_from_slice(b"?oops\r\n");
+        assert!(matches!(
+            parser.parse(&mut buf),
+            Err(RespError::InvalidFrameType(_))
+        ));
+
+        // 出错后状态被重置
+        let mut buf = BytesMut::from(&b":abc\r\n"[..]);
+        assert!(matches!(
+            parser.parse(&mut buf),
+            Err(RespError::ParseIntError(_))
+        ));
+        let mut buf = BytesMut::from(&b"$-1\r\n"[..]);
+        assert_eq!(parser.parse(&mut buf), Ok(Some(RespNullBulkString.into())));
+
+        // 字符串数据之后必须是 CRLF
+        for data in [
+            &b"$2\r\nabXY"[..],
+            b"=6\r\ntxt:abXY",
+            b"!2\r\nabXY",
+            b"$?\r\n;2\r\nabXY;0\r\n",
+        ] {
+            let mut buf = BytesMut::from(data);
+            assert!(matches!(
+                parser.parse(&mut buf),
+                Err(RespError::InvalidFrame(_))
+            ));
+        }
+
+        // streamed aggregate 必须以 ".\r\n" 结束
+        let mut buf = BytesMut::from(&b"*?\r\n:1\r\n.XY"[..]);
+        assert!(matches!(
+            parser.parse(&mut buf),
+            Err(RespError::InvalidFrame(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let invalid = |data: &[u8]| {
+            let mut buf = BytesMut::from(data);
+            matches!(
+                RespParser::new().parse(&mut buf),
+                Err(RespError::InvalidFrame(_))
+            )
+        };
+        // 长度溢出或超过限制时为错误, 而不是等待更多数据或 panic
+        assert!(invalid(format!("${}\r\n", usize::MAX).as_bytes()));
+        assert!(invalid(format!("${}\r\n", MAX_BULK_LEN + 1).as_bytes()));
+        assert!(invalid(format!("={}\r\n", MAX_BULK_LEN + 1).as_bytes()));
+        assert!(invalid(
+            format!("*{}\r\n", MAX_AGGREGATE_LEN + 1).as_bytes()
+        ));
+        assert!(invalid(format!("%{}\r\n", usize::MAX / 2 + 1).as_bytes()));
+        assert!(invalid(format!("|{}\r\n", usize::MAX / 2).as_bytes()));
+        assert!(invalid(
+            format!("$?\r\n;{}\r\n", MAX_BULK_LEN + 1).as_bytes()
+        ));
+
+        // 最大长度本身是允许的, 只是数据不完整
+        let mut buf = BytesMut::from(format!("${}\r\n", MAX_BULK_LEN).as_bytes());
+        assert_eq!(RespParser::new().parse(&mut buf), Ok(None));
+        let mut buf = BytesMut::from(format!("%{}\r\n", MAX_AGGREGATE_LEN).as_bytes());
+        assert_eq!(RespParser::new().parse(&mut buf), Ok(None));
+
+        // streamed string 所有 chunk 的总长度也受限制
+        let mut parser = RespParser::new();
+        let mut buf = BytesMut::from(&b"$?\r\n;3\r\nabc\r\n"[..]);
+        assert_eq!(parser.parse(&mut buf), Ok(None));
+        buf.extend_from_slice(format!(";{}\r\n", MAX_BULK_LEN - 2).as_bytes());
+        assert!(matches!(
+            parser.parse(&mut buf),
+            Err(RespError::InvalidFrame(_))
+        ));
+
+        // 嵌套层数受限制, 否则生成 frame 时会栈溢出
+        let nested = |depth: usize| {
+            let mut data = "*1\r\n".repeat(depth);
+            data.push_str(":1\r\n");
+            BytesMut::from(data.as_bytes())
+        };
+        assert!(RespParser::new()
+            .parse(&mut nested(MAX_NESTING))
+            .unwrap()
+            .is_some());
+        assert!(invalid(&nested(MAX_NESTING + 1)));
+        assert!(invalid(&nested(1 << 20)));
+    }
+
+    #[test]
+    fn test_parse_raw() -> anyhow::Result<()> {
+        let mut parser = RespParser::new();
+        let mut buf = BytesMut::from(&b"*1\r\n$4\r\nPING\r\n+OK"[..]);
+        let (frame, raw) = parser.parse_raw(&mut buf)?.unwrap();
+        assert_eq!(
+            frame,
+            RespArray::new(vec![BulkString::new("PING").into()]).into()
+        );
+        assert_eq!(&raw[..], b"*1\r\n$4\r\nPING\r\n");
+        assert_eq!(&buf[..], b"+OK");
+        Ok(())
+    }
+}
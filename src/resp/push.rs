@@ -0,0 +1,91 @@
+use std::ops::Deref;
+
+use bytes::{BufMut, BytesMut};
+
+use crate::{RespDecode, RespEncode, RespError, RespFrame};
+
+use super::{header_len, parser::decode_as, put_header};
+
+/// 服务器主动发送的数据, 如 pub/sub 的消息和 client tracking 的失效通知,
+/// 可以出现在任意两个回复之间
+#[derive(Debug, PartialEq, Clone)]
+pub struct RespPush(pub(crate) Vec<RespFrame>);
+
+/// push "><number-of-elements>\r\n<element-1>...<element-n>"
+impl RespEncode for RespPush {
+    fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        put_header(buf, b'>', self.0.len());
+        for frame in &self.0 {
+            frame.encode_to(buf);
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        header_len(self.0.len())
+            + self
+                .0
+                .iter()
+                .map(|frame| frame.encoded_len())
+                .sum::<usize>()
+    }
+}
+// push "><number-of-elements>\r\n<element-1>...<element-n>"
+impl RespDecode for RespPush {
+    const PREFIX: &'static str = ">";
+
+    fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
+        decode_as(buf)
+    }
+}
+
+impl RespPush {
+    pub fn new(s: impl Into<Vec<RespFrame>>) -> Self {
+        RespPush(s.into())
+    }
+}
+
+impl Deref for RespPush {
+    type Target = Vec<RespFrame>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BulkString;
+
+    #[test]
+    fn test_push_encode() {
+        let frame: RespFrame = RespPush::new(vec![
+            BulkString::new("message").into(),
+            BulkString::new("news").into(),
+            BulkString::new("hello").into(),
+        ])
+        .into();
+        assert_eq!(
+            frame.encode(),
+            b">3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n"
+        );
+    }
+
+    #[test]
+    fn test_push_decode() -> anyhow::Result<()> {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b">2\r\n$10\r\ninvalidate\r\n*1\r\n$3\r\nfoo\r\n");
+        let frame = RespPush::decode(&mut buf)?;
+        assert_eq!(
+            frame,
+            RespPush::new(vec![
+                BulkString::new("invalidate").into(),
+                crate::RespArray::new(vec![BulkString::new("foo").into()]).into(),
+            ])
+        );
+
+        buf.extend_from_slice(b">2\r\n$10\r\ninvalidate\r\n");
+        assert_eq!(RespPush::decode(&mut buf), Err(RespError::NotComplete));
+
+        anyhow::Ok(())
+    }
+}
@@ -1,24 +1,30 @@
 use std::ops::Deref;
 
-use bytes::{Buf, BytesMut};
+use bytes::{BufMut, BytesMut};
 
 use crate::{RespDecode, RespEncode, RespError, RespFrame};
 
-use super::BUF_CAP;
-use super::{calc_total_len, parse_length, CRLF_LEN};
+use super::{header_len, parser::decode_as, put_header};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct RespSet(pub(crate) Vec<RespFrame>);
 
 /// set "~<number-of-elements>\r\n<element-1>...<element-n>"
 impl RespEncode for RespSet {
-    fn encode(self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(BUF_CAP);
-        buf.extend_from_slice(&format!("~{}\r\n", self.len()).into_bytes());
-        for frame in self.0 {
-            buf.extend_from_slice(&frame.encode());
+    fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        put_header(buf, b'~', self.0.len());
+        for frame in &self.0 {
+            frame.encode_to(buf);
         }
-        buf
+    }
+
+    fn encoded_len(&self) -> usize {
+        header_len(self.0.len())
+            + self
+                .0
+                .iter()
+                .map(|frame| frame.encoded_len())
+                .sum::<usize>()
     }
 }
 // - set "~<number-of-elements>\r\n<element-1>...<element-n>"
@@ -26,25 +32,7 @@ impl RespDecode for RespSet {
     const PREFIX: &'static str = "~";
 
     fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
-        let (end, len) = parse_length(buf, Self::PREFIX)?;
-        let total_len = calc_total_len(buf, end, len, Self::PREFIX)?;
-
-        if buf.len() < total_len {
-            return Err(RespError::NotComplete);
-        }
-        buf.advance(end + CRLF_LEN);
-
-        let mut frame = Vec::new();
-        for _ in 0..len {
-            frame.push(RespFrame::decode(buf)?);
-        }
-
-        Ok(RespSet::new(frame))
-    }
-
-    fn expect_length(buf: &[u8]) -> anyhow::Result<usize, RespError> {
-        let (end, len) = parse_length(buf, Self::PREFIX)?;
-        calc_total_len(buf, end, len, Self::PREFIX)
+        decode_as(buf)
     }
 }
 
@@ -1,35 +1,32 @@
 use std::ops::Deref;
 
-use bytes::BytesMut;
+use bytes::{BufMut, BytesMut};
 
 use crate::{RespDecode, RespEncode, RespError};
 
-use super::{extract_simple_frame_data, CRLF_LEN};
+use super::{parser::decode_as, CRLF, CRLF_LEN};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct SimpleError(pub(crate) String);
 
 /// Errors "-Error message\r\n"
 impl RespEncode for SimpleError {
-    fn encode(self) -> Vec<u8> {
-        format!("-{}\r\n", self.0).into_bytes()
+    fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        buf.put_u8(b'-');
+        buf.put_slice(self.0.as_bytes());
+        buf.put_slice(CRLF);
+    }
+
+    fn encoded_len(&self) -> usize {
+        1 + self.0.len() + CRLF_LEN
     }
 }
 // - Errors "-Error message\r\n"
 impl RespDecode for SimpleError {
     const PREFIX: &'static str = "-";
-    fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
-        let end = extract_simple_frame_data(buf, Self::PREFIX)?;
-
-        let data = buf.split_to(end + CRLF_LEN);
-        let s = String::from_utf8_lossy(&data[1..end]);
 
-        Ok(SimpleError::new(s))
-    }
-
-    fn expect_length(buf: &[u8]) -> anyhow::Result<usize, RespError> {
-        let end = extract_simple_frame_data(buf, Self::PREFIX)?;
-        Ok(end + CRLF_LEN)
+    fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
+        decode_as(buf)
     }
 }
 
@@ -0,0 +1,265 @@
+// RESP3 streamed 的字符串和集合: 长度未知时可以一边生成一边发送
+// - streamed string "$?\r\n;<length>\r\n<data>\r\n...;0\r\n"
+// - streamed aggregate "*?\r\n<element-1>...<element-n>.\r\n", `~?` 和 `%?` 相同
+// 编码通过 RespSink 写入 writer, array/set 的元素和 map 的 key-value 使用不同的类型
+// 解码由 RespParser 完成
+
+use std::io;
+
+use bytes::{BufMut, BytesMut};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{RespEncode, RespFrame, SimpleString};
+
+const STREAM_END: &[u8] = b".\r\n";
+// RespSink 缓冲的数据超过此大小时写入 writer
+const FLUSH_THRESHOLD: usize = 64 * 1024;
+
+/// 一边编码一边写入 writer, 缓冲的数据超过 FLUSH_THRESHOLD 时写入,
+/// 很大的回复不需要一次生成完整的数据
+#[derive(Debug)]
+pub struct RespSink<W> {
+    writer: W,
+    buf: BytesMut,
+}
+
+impl<W: AsyncWrite + Unpin> RespSink<W> {
+    pub fn new(writer: W) -> Self {
+        RespSink {
+            writer,
+            buf: BytesMut::with_capacity(FLUSH_THRESHOLD),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// 写入缓冲的数据后返回 writer
+    pub async fn into_inner(mut self) -> io::Result<W> {
+        self.flush().await?;
+        Ok(self.writer)
+    }
+
+    /// 编码一个完整的 frame
+    pub async fn frame(&mut self, frame: &RespFrame) -> io::Result<()> {
+        frame.encode_to(&mut self.buf);
+        self.flush_if_full().await
+    }
+
+    /// 开始一个 streamed string
+    pub async fn string(&mut self) -> io::Result<StreamedString<'_, W>> {
+        self.put(b"$?\r\n").await?;
+        Ok(StreamedString { sink: self })
+    }
+
+    /// 开始一个 streamed array
+    pub async fn array(&mut self) -> io::Result<StreamedAggregate<'_, W>> {
+        self.put(b"*?\r\n").await?;
+        Ok(StreamedAggregate { sink: self })
+    }
+
+    /// 开始一个 streamed set
+    pub async fn set(&mut self) -> io::Result<StreamedAggregate<'_, W>> {
+        self.put(b"~?\r\n").await?;
+        Ok(StreamedAggregate { sink: self })
+    }
+
+    /// 开始一个 streamed map
+    pub async fn map(&mut self) -> io::Result<StreamedMap<'_, W>> {
+        self.put(b"%?\r\n").await?;
+        Ok(StreamedMap { sink: self })
+    }
+
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.writer.write_all(&self.buf).await?;
+        self.buf.clear();
+        self.writer.flush().await
+    }
+
+    async fn put(&mut self, data: &[u8]) -> io::Result<()> {
+        self.buf.put_slice(data);
+        self.flush_if_full().await
+    }
+
+    async fn flush_if_full(&mut self) -> io::Result<()> {
+        if self.buf.len() >= FLUSH_THRESHOLD {
+            self.writer.write_all(&self.buf).await?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+}
+
+/// 增量编码 streamed string, 每个 chunk 单独编码
+#[derive(Debug)]
+pub struct StreamedString<'a, W> {
+    sink: &'a mut RespSink<W>,
+}
+
+impl<W: AsyncWrite + Unpin> StreamedString<'_, W> {
+    pub async fn chunk(&mut self, data: &[u8]) -> io::Result<()> {
+        // 长度为 0 的 chunk 表示结束, 跳过
+        if data.is_empty() {
+            return Ok(());
+        }
+        let buf = &mut self.sink.buf;
+        buf.put_slice(format!(";{}\r\n", data.len()).as_bytes());
+        buf.put_slice(data);
+        buf.put_slice(b"\r\n");
+        self.sink.flush_if_full().await
+    }
+
+    pub async fn finish(self) -> io::Result<()> {
+        self.sink.put(b";0\r\n").await
+    }
+}
+
+/// 增量编码 streamed array 或 set, 每个元素单独编码
+#[derive(Debug)]
+pub struct StreamedAggregate<'a, W> {
+    sink: &'a mut RespSink<W>,
+}
+
+impl<W: AsyncWrite + Unpin> StreamedAggregate<'_, W> {
+    pub async fn element(&mut self, frame: &RespFrame) -> io::Result<()> {
+        self.sink.frame(frame).await
+    }
+
+    /// 元素本身也是 streamed 的类型时, 通过 sink 开始
+    pub fn sink(&mut self) -> &mut RespSink<W> {
+        self.sink
+    }
+
+    pub async fn finish(self) -> io::Result<()> {
+        self.sink.put(STREAM_END).await
+    }
+}
+
+/// 增量编码 streamed map, 每个 key-value 单独编码
+#[derive(Debug)]
+pub struct StreamedMap<'a, W> {
+    sink: &'a mut RespSink<W>,
+}
+
+impl<W: AsyncWrite + Unpin> StreamedMap<'_, W> {
+    pub async fn entry(&mut self, key: impl Into<String>, value: &RespFrame) -> io::Result<()> {
+        SimpleString::new(key).encode_to(&mut self.sink.buf);
+        self.sink.frame(value).await
+    }
+
+    pub async fn finish(self) -> io::Result<()> {
+        self.sink.put(STREAM_END).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+    use crate::{BulkString, RespArray, RespDecode, RespError, RespMap, RespSet};
+
+    #[test]
+    fn test_streamed_string_decode() -> anyhow::Result<()> {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"$?\r\n;4\r\nHell\r\n;5\r\no wor\r\n;2\r\nld\r\n");
+        assert_eq!(RespFrame::decode(&mut buf), Err(RespError::NotComplete));
+        buf.extend_from_slice(b";0\r\n");
+        let frame = RespFrame::decode(&mut buf)?;
+        assert_eq!(frame, BulkString::new("Hello world").into());
+        assert!(buf.is_empty());
+        anyhow::Ok(())
+    }
+
+    #[test]
+    fn test_streamed_aggregate_decode() -> anyhow::Result<()> {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"*?\r\n:1\r\n$?\r\n;2\r\nab\r\n;0\r\n*?\r\n:2\r\n.\r\n");
+        assert_eq!(RespFrame::decode(&mut buf), Err(RespError::NotComplete));
+        buf.extend_from_slice(b".\r\n");
+        let expected: RespFrame = RespArray::new(vec![
+            RespFrame::Integer(1),
+            BulkString::new("ab").into(),
+            RespArray::new(vec![RespFrame::Integer(2)]).into(),
+        ])
+        .into();
+        assert_eq!(RespFrame::decode(&mut buf)?, expected);
+
+        buf.extend_from_slice(b"~?\r\n+a\r\n+b\r\n.\r\n");
+        let expected = RespSet::new(vec![
+            SimpleString::new("a").into(),
+            SimpleString::new("b").into(),
+        ]);
+        assert_eq!(RespFrame::decode(&mut buf)?, expected.into());
+        anyhow::Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sink_encode() -> anyhow::Result<()> {
+        let mut sink = RespSink::new(Vec::new());
+        let mut string = sink.string().await?;
+        for chunk in ["Hell", "", "o world"] {
+            string.chunk(chunk.as_bytes()).await?;
+        }
+        string.finish().await?;
+
+        // 嵌套在普通的 array 中
+        sink.frame(&RespArray::new(vec![]).into()).await?;
+        let mut set = sink.set().await?;
+        set.element(&RespFrame::Boolean(true)).await?;
+        let mut map = set.sink().map().await?;
+        map.entry("first", &RespFrame::Integer(1)).await?;
+        map.entry("second", &RespFrame::Integer(2)).await?;
+        map.finish().await?;
+        set.finish().await?;
+        // 数据都在缓冲中, 没有写入 writer
+        assert!(sink.get_ref().is_empty());
+
+        let data = sink.into_inner().await?;
+        assert!(data.starts_with(b"$?\r\n;4\r\nHell\r\n;7\r\no world\r\n;0\r\n*0\r\n~?\r\n"));
+        let mut buf = BytesMut::from(&data[..]);
+        assert_eq!(
+            RespFrame::decode(&mut buf)?,
+            BulkString::new("Hello world").into()
+        );
+        assert_eq!(RespFrame::decode(&mut buf)?, RespArray::new(vec![]).into());
+        let mut map = RespMap::new();
+        map.insert(SimpleString::new("first"), RespFrame::Integer(1));
+        map.insert(SimpleString::new("second"), RespFrame::Integer(2));
+        let expected = RespSet::new(vec![RespFrame::Boolean(true), map.into()]);
+        assert_eq!(RespFrame::decode(&mut buf)?, expected.into());
+        assert!(buf.is_empty());
+        anyhow::Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sink_flush() -> anyhow::Result<()> {
+        let (writer, mut reader) = tokio::io::duplex(FLUSH_THRESHOLD * 4);
+        let mut sink = RespSink::new(writer);
+        let mut array = sink.array().await?;
+        let element: RespFrame = BulkString::new(vec![b'x'; 1000]).into();
+        // 缓冲超过 FLUSH_THRESHOLD 后写入, 读取方不需要等到整个回复结束
+        for _ in 0..FLUSH_THRESHOLD / 1000 + 1 {
+            array.element(&element).await?;
+        }
+        let mut buf = BytesMut::new();
+        let read = async {
+            while buf.len() < FLUSH_THRESHOLD {
+                reader.read_buf(&mut buf).await?;
+            }
+            io::Result::Ok(())
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(5), read).await??;
+        assert!(buf.starts_with(b"*?\r\n$1000\r\n"));
+
+        array.finish().await?;
+        drop(sink.into_inner().await?);
+        while reader.read_buf(&mut buf).await? > 0 {}
+        let RespFrame::Array(frames) = RespFrame::decode(&mut buf)? else {
+            panic!("expect an array");
+        };
+        assert_eq!(frames.len(), FLUSH_THRESHOLD / 1000 + 1);
+        anyhow::Ok(())
+    }
+}
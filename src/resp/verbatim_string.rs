@@ -0,0 +1,94 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{RespDecode, RespEncode, RespError};
+
+use super::{header_len, parser::decode_as, put_header, CRLF, CRLF_LEN};
+
+/// 带格式的文本, 格式为 3 个字符, 如 `txt` 和 `mkd`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct VerbatimString {
+    pub(crate) format: [u8; 3],
+    pub(crate) data: Bytes,
+}
+
+/// verbatim string "=<length>\r\n<format>:<data>\r\n", length 包括 format 和 `:`
+impl RespEncode for VerbatimString {
+    fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        // 数据前有 `txt:` 4 个字节
+        put_header(buf, b'=', self.data.len() + 4);
+        buf.put_slice(&self.format);
+        buf.put_u8(b':');
+        buf.put_slice(&self.data);
+        buf.put_slice(CRLF);
+    }
+
+    fn encoded_len(&self) -> usize {
+        header_len(self.data.len() + 4) + self.data.len() + 4 + CRLF_LEN
+    }
+}
+// verbatim string "=<length>\r\n<format>:<data>\r\n"
+impl RespDecode for VerbatimString {
+    const PREFIX: &'static str = "=";
+
+    fn decode(buf: &mut BytesMut) -> anyhow::Result<Self, RespError> {
+        decode_as(buf)
+    }
+}
+
+impl VerbatimString {
+    pub fn new(format: [u8; 3], data: impl Into<Vec<u8>>) -> Self {
+        VerbatimString {
+            format,
+            data: Bytes::from(data.into()),
+        }
+    }
+
+    /// 纯文本
+    pub fn text(data: impl Into<Vec<u8>>) -> Self {
+        VerbatimString::new(*b"txt", data)
+    }
+
+    pub fn format(&self) -> &[u8] {
+        &self.format
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::RespFrame;
+
+    use super::*;
+
+    #[test]
+    fn test_verbatim_string_encode() {
+        let s: RespFrame = VerbatimString::text("Some string").into();
+        assert_eq!(s.encode(), b"=15\r\ntxt:Some string\r\n");
+    }
+
+    #[test]
+    fn test_verbatim_string_decode() -> anyhow::Result<()> {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"=15\r\nmkd:Some string\r\n");
+        let frame = VerbatimString::decode(&mut buf)?;
+        assert_eq!(frame, VerbatimString::new(*b"mkd", "Some string"));
+
+        buf.extend_from_slice(b"=15\r\ntxt:Some str");
+        assert_eq!(
+            VerbatimString::decode(&mut buf),
+            Err(RespError::NotComplete)
+        );
+        buf.clear();
+
+        buf.extend_from_slice(b"=5\r\nhello\r\n");
+        assert!(matches!(
+            VerbatimString::decode(&mut buf),
+            Err(RespError::InvalidFrame(_))
+        ));
+
+        anyhow::Ok(())
+    }
+}
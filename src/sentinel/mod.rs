@@ -0,0 +1,656 @@
+//! Sentinel 模式: 监控 master 和它的 replica. 超过 down-after-milliseconds 没有有效回复时
+//! 认为实例主观下线 (SDOWN), 达到 quorum 个 sentinel 认为 master 下线时为客观下线 (ODOWN).
+//! 之后 sentinel 之间选出一个 leader, 由它把一个 replica 提升为新的 master.
+//!
+//! Redis 的 sentinel 通过 master 的 pub/sub 频道发现彼此, 这里由 sentinel 之间直接发送
+//! `SENTINEL HELLO`, 内容与 Redis 的 hello 消息相同. 配置的 known-sentinel 会把自己告知对方,
+//! 因此只需要每个 sentinel 知道一个其他的 sentinel.
+//!
+//! HELLO 可以改变 master 的地址, 所以 sentinel 与普通节点一样受 protected mode 限制;
+//! 跨主机部署时设置 requirepass, 并用 `sentinel-pass` 配置连接其他 sentinel 的密码.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Mutex, MutexGuard},
+};
+
+use anyhow::{bail, Result};
+use tracing::info;
+
+use crate::{
+    backend::{now_ms, random_id},
+    Backend, BulkString, RespArray, RespFrame, RespMap, ServerConfig,
+};
+
+mod monitor;
+
+pub use monitor::run;
+
+#[derive(Debug)]
+pub struct SentinelState {
+    inner: Mutex<SentinelInner>,
+}
+
+#[derive(Debug)]
+pub(crate) struct SentinelInner {
+    pub myid: String,
+    pub current_epoch: u64,
+    pub masters: BTreeMap<String, MasterInstance>,
+}
+
+/// 被监控的实例的地址和最近一次有效回复的时间
+#[derive(Debug, Clone)]
+pub(crate) struct Instance {
+    pub host: String,
+    pub port: u16,
+    pub last_ok: i64,
+    // 开始监控的时间, 从未回复的实例从这时开始计算下线时间
+    pub since: i64,
+}
+
+#[derive(Debug)]
+pub(crate) struct MasterInstance {
+    pub name: String,
+    pub quorum: usize,
+    pub down_after: i64,
+    pub failover_timeout: i64,
+    pub instance: Instance,
+    // 每次故障转移后增加, 用于在 sentinel 之间传播最新的 master 地址
+    pub config_epoch: u64,
+    // 以 ip:port 为 key
+    pub replicas: BTreeMap<String, ReplicaInstance>,
+    pub sentinels: BTreeMap<String, PeerSentinel>,
+    // 本 sentinel 在 leader_epoch 投票选出的 leader
+    pub leader: Option<String>,
+    pub leader_epoch: u64,
+    pub failover_in_progress: bool,
+    // SENTINEL FAILOVER: 不需要其他 sentinel 同意
+    pub force_failover: bool,
+    // 最近一次开始故障转移或为其他 sentinel 投票的时间, 之后 2 倍 failover-timeout 内不再发起
+    pub failover_start: i64,
+    pub last_check: i64,
+}
+
+#[derive(Debug)]
+pub(crate) struct ReplicaInstance {
+    pub instance: Instance,
+    pub offset: u64,
+    // INFO 中报告的角色和 master
+    pub reported_master: bool,
+    pub master_host: Option<String>,
+    pub master_port: u16,
+    pub master_link_up: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct PeerSentinel {
+    pub instance: Instance,
+    pub runid: Option<String>,
+    // 对方认为 master 主观下线
+    pub master_down: bool,
+}
+
+/// `SENTINEL HELLO`: sentinel 的地址和它所知道的 master 配置
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hello {
+    pub ip: String,
+    pub port: u16,
+    pub runid: String,
+    pub current_epoch: u64,
+    pub master_name: String,
+    pub master_ip: String,
+    pub master_port: u16,
+    pub master_config_epoch: u64,
+}
+
+impl SentinelState {
+    pub fn new(config: &ServerConfig) -> Self {
+        let now = now_ms();
+        let masters = config
+            .sentinel_monitors
+            .iter()
+            .map(|m| {
+                let sentinels = m
+                    .known_sentinels
+                    .iter()
+                    .map(|(host, port)| {
+                        let peer = PeerSentinel {
+                            instance: Instance::new(host.clone(), *port, now),
+                            runid: None,
+                            master_down: false,
+                        };
+                        (format!("{}:{}", host, port), peer)
+                    })
+                    .collect();
+                let master = MasterInstance {
+                    name: m.name.clone(),
+                    quorum: m.quorum,
+                    down_after: m.down_after as i64,
+                    failover_timeout: m.failover_timeout as i64,
+                    instance: Instance::new(m.host.clone(), m.port, now),
+                    config_epoch: 0,
+                    replicas: BTreeMap::new(),
+                    sentinels,
+                    leader: None,
+                    leader_epoch: 0,
+                    failover_in_progress: false,
+                    force_failover: false,
+                    failover_start: 0,
+                    last_check: 0,
+                };
+                (m.name.clone(), master)
+            })
+            .collect();
+        SentinelState {
+            inner: Mutex::new(SentinelInner {
+                myid: random_id(),
+                current_epoch: 0,
+                masters,
+            }),
+        }
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, SentinelInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Instance {
+    fn new(host: String, port: u16, now: i64) -> Self {
+        Instance {
+            host,
+            port,
+            last_ok: 0,
+            since: now,
+        }
+    }
+
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    // 主观下线: 超过 down_after 没有有效回复
+    pub fn is_sdown(&self, now: i64, down_after: i64) -> bool {
+        now - self.last_ok.max(self.since) > down_after
+    }
+}
+
+impl MasterInstance {
+    // master 下线, 或者报告自己是 replica 已经超过 down_after (这时不更新 last_ok)
+    pub fn is_sdown(&self, now: i64) -> bool {
+        self.instance.is_sdown(now, self.down_after)
+    }
+
+    // 客观下线: 加上自己至少 quorum 个 sentinel 认为 master 主观下线
+    pub fn is_odown(&self, now: i64) -> bool {
+        let votes = 1 + self
+            .sentinels
+            .values()
+            .filter(|s| s.master_down && !s.instance.is_sdown(now, self.down_after))
+            .count();
+        self.is_sdown(now) && votes >= self.quorum
+    }
+
+    fn flags(&self, now: i64) -> String {
+        let mut flags = vec!["master"];
+        if self.is_sdown(now) {
+            flags.push("s_down");
+        }
+        if self.is_odown(now) {
+            flags.push("o_down");
+        }
+        if self.failover_in_progress {
+            flags.push("failover_in_progress");
+        }
+        flags.join(",")
+    }
+
+    // master 变为新的地址, 原来的 master 作为 replica 继续监控, 恢复后让它复制新的 master
+    pub fn switch_master(&mut self, host: String, port: u16, config_epoch: u64, now: i64) {
+        let old = std::mem::replace(&mut self.instance, Instance::new(host, port, now));
+        info!(
+            "+switch-master {} {} {} {} {}",
+            self.name, old.host, old.port, self.instance.host, self.instance.port
+        );
+        self.replicas.remove(&self.instance.addr());
+        self.replicas
+            .entry(old.addr())
+            .or_insert_with(|| ReplicaInstance::new(old));
+        self.config_epoch = config_epoch;
+        self.failover_in_progress = false;
+        self.force_failover = false;
+        for peer in self.sentinels.values_mut() {
+            peer.master_down = false;
+        }
+    }
+}
+
+impl ReplicaInstance {
+    pub fn new(instance: Instance) -> Self {
+        ReplicaInstance {
+            instance,
+            offset: 0,
+            reported_master: false,
+            master_host: None,
+            master_port: 0,
+            master_link_up: false,
+        }
+    }
+}
+
+impl Backend {
+    fn sentinel_master<T>(
+        &self,
+        name: &str,
+        f: impl FnOnce(&mut MasterInstance, &str) -> T,
+    ) -> Result<T> {
+        let mut inner = self.sentinel.lock();
+        let myid = inner.myid.clone();
+        match inner.masters.get_mut(name) {
+            Some(master) => Ok(f(master, &myid)),
+            None => bail!("No such master with that name"),
+        }
+    }
+
+    pub fn sentinel_myid(&self) -> String {
+        self.sentinel.lock().myid.clone()
+    }
+
+    /// SENTINEL GET-MASTER-ADDR-BY-NAME
+    pub fn sentinel_master_addr(&self, name: &str) -> Option<(String, u16)> {
+        let inner = self.sentinel.lock();
+        let master = inner.masters.get(name)?;
+        Some((master.instance.host.clone(), master.instance.port))
+    }
+
+    /// SENTINEL MASTERS
+    pub fn sentinel_masters(&self) -> RespFrame {
+        let now = now_ms();
+        let inner = self.sentinel.lock();
+        let masters = inner
+            .masters
+            .values()
+            .map(|m| master_info(m, now))
+            .collect::<Vec<_>>();
+        RespArray::new(masters).into()
+    }
+
+    /// SENTINEL MASTER <name>
+    pub fn sentinel_master_info(&self, name: &str) -> Result<RespFrame> {
+        self.sentinel_master(name, |m, _| master_info(m, now_ms()))
+    }
+
+    /// SENTINEL REPLICAS <name>
+    pub fn sentinel_replicas(&self, name: &str) -> Result<RespFrame> {
+        let now = now_ms();
+        self.sentinel_master(name, |m, _| {
+            let replicas = m
+                .replicas
+                .values()
+                .map(|r| {
+                    let mut flags = "slave".to_string();
+                    if r.instance.is_sdown(now, m.down_after) {
+                        flags.push_str(",s_down");
+                    }
+                    let mut map = instance_info(&r.instance, &flags, now);
+                    let link = if r.master_link_up { "ok" } else { "err" };
+                    let master_host = r.master_host.clone().unwrap_or_else(|| "?".to_string());
+                    map.insert(
+                        BulkString::new("master-link-status"),
+                        BulkString::new(link).into(),
+                    );
+                    map.insert(
+                        BulkString::new("master-host"),
+                        BulkString::new(master_host).into(),
+                    );
+                    map.insert(
+                        BulkString::new("master-port"),
+                        BulkString::new(r.master_port.to_string()).into(),
+                    );
+                    map.insert(
+                        BulkString::new("slave-repl-offset"),
+                        BulkString::new(r.offset.to_string()).into(),
+                    );
+                    map.into()
+                })
+                .collect::<Vec<RespFrame>>();
+            RespArray::new(replicas).into()
+        })
+    }
+
+    /// SENTINEL SENTINELS <name>
+    pub fn sentinel_sentinels(&self, name: &str) -> Result<RespFrame> {
+        let now = now_ms();
+        self.sentinel_master(name, |m, _| {
+            let sentinels = m
+                .sentinels
+                .values()
+                .map(|s| {
+                    let mut flags = "sentinel".to_string();
+                    if s.instance.is_sdown(now, m.down_after) {
+                        flags.push_str(",s_down");
+                    }
+                    let mut map = instance_info(&s.instance, &flags, now);
+                    let runid = s.runid.clone().unwrap_or_default();
+                    map.insert(BulkString::new("runid"), BulkString::new(runid).into());
+                    map.into()
+                })
+                .collect::<Vec<RespFrame>>();
+            RespArray::new(sentinels).into()
+        })
+    }
+
+    /// SENTINEL IS-MASTER-DOWN-BY-ADDR <ip> <port> <current-epoch> <runid>:
+    /// 回复自己是否认为 master 主观下线; runid 不是 * 时为该 sentinel 投票, 每个 epoch 只投一次.
+    /// 回复 [down-state, leader-runid, leader-epoch]
+    pub fn sentinel_is_master_down(
+        &self,
+        host: &str,
+        port: u16,
+        epoch: u64,
+        runid: &str,
+    ) -> RespFrame {
+        let now = now_ms();
+        let mut inner = self.sentinel.lock();
+        if runid != "*" && epoch > inner.current_epoch {
+            inner.current_epoch = epoch;
+        }
+        let (current_epoch, myid) = (inner.current_epoch, inner.myid.clone());
+        let master = inner
+            .masters
+            .values_mut()
+            .find(|m| m.instance.host == host && m.instance.port == port);
+        let (down, leader, leader_epoch) = match master {
+            Some(master) => {
+                if runid != "*" && master.leader_epoch < epoch && current_epoch <= epoch {
+                    info!(
+                        "+vote-for-leader {} {} for master {}",
+                        runid, current_epoch, master.name
+                    );
+                    master.leader = Some(runid.to_string());
+                    master.leader_epoch = current_epoch;
+                    // 为其他 sentinel 投票后, 一段时间内自己不发起故障转移
+                    if runid != myid {
+                        master.failover_start = now;
+                    }
+                }
+                let leader = match runid {
+                    "*" => "*".to_string(),
+                    _ => master.leader.clone().unwrap_or_else(|| "*".to_string()),
+                };
+                (master.is_sdown(now), leader, master.leader_epoch)
+            }
+            None => (false, "*".to_string(), 0),
+        };
+        RespArray::new(vec![
+            RespFrame::Integer(down as i64),
+            BulkString::new(leader).into(),
+            RespFrame::Integer(leader_epoch as i64),
+        ])
+        .into()
+    }
+
+    /// SENTINEL HELLO: 记录发送的 sentinel; 对方的 master 配置更新时切换到新的 master
+    pub fn sentinel_hello(&self, hello: Hello) {
+        let now = now_ms();
+        let mut inner = self.sentinel.lock();
+        if hello.runid == inner.myid {
+            return;
+        }
+        if hello.current_epoch > inner.current_epoch {
+            inner.current_epoch = hello.current_epoch;
+        }
+        let Some(master) = inner.masters.get_mut(&hello.master_name) else {
+            return;
+        };
+        let addr = format!("{}:{}", hello.ip, hello.port);
+        // 同一个 sentinel 换了地址时删除旧的记录
+        master
+            .sentinels
+            .retain(|a, s| *a == addr || s.runid.as_ref() != Some(&hello.runid));
+        let peer = master.sentinels.entry(addr).or_insert_with(|| {
+            info!("+sentinel {} {}:{}", hello.runid, hello.ip, hello.port);
+            PeerSentinel {
+                instance: Instance::new(hello.ip.clone(), hello.port, now),
+                runid: None,
+                master_down: false,
+            }
+        });
+        peer.runid = Some(hello.runid.clone());
+        peer.instance.last_ok = now;
+        let current = (master.instance.host.as_str(), master.instance.port);
+        if hello.master_config_epoch > master.config_epoch
+            && current != (hello.master_ip.as_str(), hello.master_port)
+        {
+            master.switch_master(
+                hello.master_ip,
+                hello.master_port,
+                hello.master_config_epoch,
+                now,
+            );
+        } else if hello.master_config_epoch > master.config_epoch {
+            master.config_epoch = hello.master_config_epoch;
+        }
+    }
+
+    /// SENTINEL FAILOVER <name>: 不需要其他 sentinel 同意, 立即开始故障转移
+    pub fn sentinel_failover(&self, name: &str) -> Result<()> {
+        let now = now_ms();
+        self.sentinel_master(name, |m, _| {
+            if m.failover_in_progress || m.force_failover {
+                bail!("INPROG Failover already in progress");
+            }
+            let has_replica = m
+                .replicas
+                .values()
+                .any(|r| !r.instance.is_sdown(now, m.down_after));
+            if !has_replica {
+                bail!("NOGOODSLAVE No suitable replica to promote");
+            }
+            m.force_failover = true;
+            Ok(())
+        })?
+    }
+
+    /// INFO sentinel
+    pub fn info_sentinel(&self) -> String {
+        let now = now_ms();
+        let inner = self.sentinel.lock();
+        let mut info = String::from("# Sentinel\r\n");
+        let _ = write!(info, "sentinel_masters:{}\r\n", inner.masters.len());
+        info.push_str("sentinel_tilt:0\r\nsentinel_running_scripts:0\r\n");
+        for (i, m) in inner.masters.values().enumerate() {
+            let status = if m.is_odown(now) {
+                "odown"
+            } else if m.is_sdown(now) {
+                "sdown"
+            } else {
+                "ok"
+            };
+            let _ = write!(
+                info,
+                "master{}:name={},status={},address={},slaves={},sentinels={}\r\n",
+                i,
+                m.name,
+                status,
+                m.instance.addr(),
+                m.replicas.len(),
+                m.sentinels.len() + 1
+            );
+        }
+        info
+    }
+}
+
+fn instance_info(instance: &Instance, flags: &str, now: i64) -> RespMap {
+    let mut map = RespMap::new();
+    let last_ok = if instance.last_ok == 0 {
+        now - instance.since
+    } else {
+        now - instance.last_ok
+    };
+    map.insert(
+        BulkString::new("name"),
+        BulkString::new(instance.addr()).into(),
+    );
+    map.insert(
+        BulkString::new("ip"),
+        BulkString::new(instance.host.clone()).into(),
+    );
+    map.insert(
+        BulkString::new("port"),
+        BulkString::new(instance.port.to_string()).into(),
+    );
+    map.insert(
+        BulkString::new("flags"),
+        BulkString::new(flags.to_string()).into(),
+    );
+    map.insert(
+        BulkString::new("last-ok-ping-reply"),
+        BulkString::new(last_ok.to_string()).into(),
+    );
+    map
+}
+
+fn master_info(m: &MasterInstance, now: i64) -> RespFrame {
+    let mut map = instance_info(&m.instance, &m.flags(now), now);
+    let mut field = |name: &str, value: String| {
+        map.insert(BulkString::new(name), BulkString::new(value).into());
+    };
+    field("name", m.name.clone());
+    field("num-slaves", m.replicas.len().to_string());
+    field("num-other-sentinels", m.sentinels.len().to_string());
+    field("quorum", m.quorum.to_string());
+    field("config-epoch", m.config_epoch.to_string());
+    field("down-after-milliseconds", m.down_after.to_string());
+    field("failover-timeout", m.failover_timeout.to_string());
+    map.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use tokio::{net::TcpListener, task::JoinSet};
+
+    use super::*;
+    use crate::{network, SentinelMonitor};
+
+    // 在 listener 上运行服务器, 任务被取消时关闭所有连接
+    async fn serve(listener: TcpListener, backend: Backend) {
+        let mut conns = JoinSet::new();
+        loop {
+            tokio::select! {
+                Ok((stream, _)) = listener.accept() => {
+                    conns.spawn(network::handle_connection(stream, backend.clone()));
+                }
+                Some(_) = conns.join_next() => {}
+            }
+        }
+    }
+
+    async fn bind() -> Result<(TcpListener, ServerConfig)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let config = ServerConfig {
+            port: listener.local_addr()?.port(),
+            ..Default::default()
+        };
+        Ok((listener, config))
+    }
+
+    async fn wait_until(mut f: impl FnMut() -> bool) -> bool {
+        for _ in 0..1000 {
+            if f() {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        false
+    }
+
+    fn master_port(backend: &Backend) -> Option<u16> {
+        backend
+            .info_replication()
+            .lines()
+            .find_map(|l| l.strip_prefix("master_port:"))
+            .and_then(|p| p.parse().ok())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_sentinel_failover() -> Result<()> {
+        let (listener, config) = bind().await?;
+        let old_port = config.port;
+        let master = Backend::with_config(config);
+        let master_task = tokio::spawn(serve(listener, master.clone()));
+        let mut replicas = vec![];
+        for _ in 0..2 {
+            let (listener, config) = bind().await?;
+            let port = config.port;
+            let replica = Backend::with_config(config);
+            tokio::spawn(serve(listener, replica.clone()));
+            replica.replicaof(Some(("127.0.0.1".to_string(), old_port)));
+            replicas.push((port, replica));
+        }
+        assert!(wait_until(|| master.replicas().len() == 2).await);
+
+        // 三个 sentinel, 每个只知道下一个
+        let mut listeners = vec![];
+        for _ in 0..3 {
+            listeners.push(bind().await?);
+        }
+        let ports = listeners.iter().map(|(_, c)| c.port).collect::<Vec<_>>();
+        let mut sentinels = vec![];
+        for (i, (listener, config)) in listeners.into_iter().enumerate() {
+            let config = ServerConfig {
+                sentinel: true,
+                sentinel_monitors: vec![SentinelMonitor {
+                    name: "mymaster".to_string(),
+                    host: "127.0.0.1".to_string(),
+                    port: old_port,
+                    quorum: 2,
+                    down_after: 300,
+                    failover_timeout: 2000,
+                    known_sentinels: vec![("127.0.0.1".to_string(), ports[(i + 1) % 3])],
+                }],
+                ..config
+            };
+            let sentinel = Backend::with_config(config);
+            tokio::spawn(serve(listener, sentinel.clone()));
+            tokio::spawn(run(sentinel.clone()));
+            sentinels.push(sentinel);
+        }
+        let discovered = |s: &Backend| {
+            let inner = s.sentinel.lock();
+            let m = &inner.masters["mymaster"];
+            m.replicas.len() == 2 && m.sentinels.len() == 2
+        };
+        assert!(wait_until(|| sentinels.iter().all(discovered)).await);
+        assert!(sentinels[0].info_sentinel().contains("status=ok"));
+
+        // master 下线后所有 sentinel 都切换到被提升的 replica
+        master_task.abort();
+        let _ = master_task.await;
+        let promoted = |s: &Backend| {
+            s.sentinel_master_addr("mymaster")
+                .map(|(_, port)| port)
+                .filter(|port| *port != old_port)
+        };
+        assert!(wait_until(|| sentinels.iter().all(|s| promoted(s).is_some())).await);
+        let new_port = promoted(&sentinels[0]).unwrap();
+        assert!(sentinels.iter().all(|s| promoted(s) == Some(new_port)));
+        let (new_master, other) = match replicas[0].0 == new_port {
+            true => (&replicas[0].1, &replicas[1].1),
+            false => (&replicas[1].1, &replicas[0].1),
+        };
+        assert!(!new_master.is_replica());
+        assert!(wait_until(|| master_port(other) == Some(new_port)).await);
+
+        // 旧的 master 恢复后成为新 master 的 replica
+        let listener = TcpListener::bind(("127.0.0.1", old_port)).await?;
+        tokio::spawn(serve(listener, master.clone()));
+        assert!(wait_until(|| master_port(&master) == Some(new_port)).await);
+        Ok(())
+    }
+}
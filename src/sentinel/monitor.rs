@@ -0,0 +1,772 @@
+//! sentinel 的定时任务: 定期向 master、replica 发送 PING 和 INFO, 向其他 sentinel 发送 HELLO;
+//! master 客观下线后选举 leader, 由 leader 提升 replica 并让其他 replica 复制新的 master.
+//! 每次检查都是独立的任务, 连接超时或出错即视为没有有效回复.
+
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use bytes::BytesMut;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    task::JoinSet,
+};
+use tracing::{info, warn};
+
+use super::{Hello, Instance, ReplicaInstance};
+use crate::{
+    backend::{now_ms, random_u64},
+    Backend, BulkString, RespArray, RespDecode, RespEncode, RespError, RespFrame,
+};
+
+const TICK: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+enum Task {
+    CheckMaster {
+        name: String,
+        host: String,
+        port: u16,
+    },
+    CheckReplica {
+        name: String,
+        host: String,
+        port: u16,
+    },
+    Hello {
+        name: String,
+        host: String,
+        port: u16,
+        hello: Hello,
+        // master 主观下线时询问对方是否也这样认为
+        ask_down: bool,
+    },
+    Failover {
+        name: String,
+        force: bool,
+        // 开始的时间, 之后为其他 sentinel 投过票则放弃
+        start: i64,
+    },
+}
+
+// 选举所需的信息
+#[derive(Debug)]
+struct Election {
+    epoch: u64,
+    myid: String,
+    host: String,
+    port: u16,
+    peers: Vec<(String, u16)>,
+    needed: usize,
+    failover_timeout: Duration,
+}
+
+/// sentinel 的主循环, 所有检查任务都属于这个任务, 任务被取消时一起结束
+pub async fn run(backend: Backend) {
+    info!("Sentinel ID is {}", backend.sentinel_myid());
+    let mut tasks = JoinSet::new();
+    let mut tick = tokio::time::interval(TICK);
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {
+                for (timeout, task) in backend.sentinel_cron() {
+                    tasks.spawn(task.run(backend.clone(), timeout));
+                }
+            }
+            Some(_) = tasks.join_next() => {}
+        }
+    }
+}
+
+impl Task {
+    // 检查失败表示实例没有回复, 由 SDOWN 体现, 不需要处理错误
+    async fn run(self, backend: Backend, timeout: Duration) {
+        match self {
+            Task::CheckMaster { name, host, port } => {
+                let _ = check_master(&backend, &name, &host, port, timeout).await;
+            }
+            Task::CheckReplica { name, host, port } => {
+                let _ = check_replica(&backend, &name, &host, port, timeout).await;
+            }
+            Task::Hello {
+                name,
+                host,
+                port,
+                hello,
+                ask_down,
+            } => {
+                let auth = backend.config().sentinel_pass.as_deref();
+                let ret = send_hello(&host, port, auth, hello, ask_down, timeout).await;
+                backend.sentinel_peer_reply(&name, &host, port, ret);
+            }
+            Task::Failover { name, force, start } => {
+                let ret = failover(&backend, &name, force, start, timeout).await;
+                backend.sentinel_failover_end(&name);
+                if let Err(e) = ret {
+                    warn!("-failover-abort {}: {}", name, e);
+                }
+            }
+        }
+    }
+}
+
+async fn check_master(
+    backend: &Backend,
+    name: &str,
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<()> {
+    let mut conn = Conn::connect(host, port, timeout).await?;
+    conn.command(&["PING"]).await?;
+    let info = parse_info(&conn.command(&["INFO", "replication"]).await?);
+    backend.sentinel_master_reply(name, host, port, &info);
+    Ok(())
+}
+
+async fn check_replica(
+    backend: &Backend,
+    name: &str,
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<()> {
+    let mut conn = Conn::connect(host, port, timeout).await?;
+    conn.command(&["PING"]).await?;
+    let info = parse_info(&conn.command(&["INFO", "replication"]).await?);
+    // 没有复制当前的 master, 例如恢复后的旧 master
+    if let Some((master_host, master_port)) =
+        backend.sentinel_replica_reply(name, host, port, &info)
+    {
+        info!(
+            "+fix-slave-config {}:{} {} {}:{}",
+            host, port, name, master_host, master_port
+        );
+        conn.command(&["REPLICAOF", &master_host, &master_port.to_string()])
+            .await?;
+    }
+    Ok(())
+}
+
+// 返回对方是否认为 master 主观下线, 没有询问时为 false
+async fn send_hello(
+    host: &str,
+    port: u16,
+    auth: Option<&str>,
+    mut hello: Hello,
+    ask_down: bool,
+    timeout: Duration,
+) -> Result<bool> {
+    let mut conn = Conn::connect_sentinel(host, port, auth, timeout).await?;
+    hello.ip = conn.stream.local_addr()?.ip().to_string();
+    let master_port = hello.master_port.to_string();
+    conn.command(&[
+        "SENTINEL",
+        "HELLO",
+        &hello.ip,
+        &hello.port.to_string(),
+        &hello.runid,
+        &hello.current_epoch.to_string(),
+        &hello.master_name,
+        &hello.master_ip,
+        &master_port,
+        &hello.master_config_epoch.to_string(),
+    ])
+    .await?;
+    if !ask_down {
+        return Ok(false);
+    }
+    let epoch = hello.current_epoch.to_string();
+    let reply = conn
+        .command(&[
+            "SENTINEL",
+            "IS-MASTER-DOWN-BY-ADDR",
+            &hello.master_ip,
+            &master_port,
+            &epoch,
+            "*",
+        ])
+        .await?;
+    Ok(parse_vote(&reply)?.0)
+}
+
+async fn failover(
+    backend: &Backend,
+    name: &str,
+    force: bool,
+    start: i64,
+    timeout: Duration,
+) -> Result<()> {
+    if !force {
+        // 随机等待, 避免多个 sentinel 同时发起选举而都得不到多数票
+        let delay = random_u64() % timeout.as_millis().max(1) as u64;
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+    }
+    let election = backend
+        .sentinel_new_epoch(name, force, start)
+        .ok_or_else(|| anyhow!("voted for another sentinel"))?;
+    info!("+new-epoch {}", election.epoch);
+    info!("+try-failover {} {}:{}", name, election.host, election.port);
+
+    if !force {
+        let auth = backend.config().sentinel_pass.clone();
+        let votes = request_votes(&election, auth, timeout).await;
+        if votes < election.needed {
+            bail!("not elected: got {} votes, need {}", votes, election.needed);
+        }
+        info!("+elected-leader {} epoch {}", name, election.epoch);
+    }
+
+    let (host, port) = backend
+        .sentinel_select_replica(name)
+        .ok_or_else(|| anyhow!("no good replica"))?;
+    info!("+selected-slave {}:{} {}", host, port, name);
+
+    // 提升为 master, 并等待它在 INFO 中报告角色变化
+    let mut conn = Conn::connect(&host, port, timeout).await?;
+    conn.command(&["REPLICAOF", "NO", "ONE"]).await?;
+    let deadline = tokio::time::Instant::now() + election.failover_timeout;
+    loop {
+        let info = parse_info(&conn.command(&["INFO", "replication"]).await?);
+        if info.get("role").map(String::as_str) == Some("master") {
+            break;
+        }
+        if tokio::time::Instant::now() > deadline {
+            bail!("timeout waiting for the promoted replica");
+        }
+        tokio::time::sleep(TICK).await;
+    }
+    info!("+promoted-slave {}:{} {}", host, port, name);
+
+    let replicas = backend.sentinel_promoted(name, &host, port, election.epoch);
+    let port_str = port.to_string();
+    for (replica_host, replica_port) in replicas {
+        let ret = async {
+            let mut conn = Conn::connect(&replica_host, replica_port, timeout).await?;
+            conn.command(&["REPLICAOF", &host, &port_str]).await
+        }
+        .await;
+        // 下线的 replica 恢复后由定时检查重新配置
+        if ret.is_ok() {
+            info!("+slave-reconf-sent {}:{}", replica_host, replica_port);
+        }
+    }
+    info!("+failover-end {}", name);
+    Ok(())
+}
+
+// 向其他 sentinel 请求投票, 返回包括自己在内的票数
+async fn request_votes(election: &Election, auth: Option<String>, timeout: Duration) -> usize {
+    let mut tasks = JoinSet::new();
+    for (host, port) in election.peers.clone() {
+        let args = [
+            "SENTINEL".to_string(),
+            "IS-MASTER-DOWN-BY-ADDR".to_string(),
+            election.host.clone(),
+            election.port.to_string(),
+            election.epoch.to_string(),
+            election.myid.clone(),
+        ];
+        let auth = auth.clone();
+        tasks.spawn(async move {
+            let mut conn = Conn::connect_sentinel(&host, port, auth.as_deref(), timeout).await?;
+            parse_vote(&conn.command(&args).await?)
+        });
+    }
+    let mut votes = 1;
+    while let Some(ret) = tasks.join_next().await {
+        if let Ok(Ok((_, Some(leader), epoch))) = ret {
+            if leader == election.myid && epoch == election.epoch {
+                votes += 1;
+            }
+        }
+    }
+    votes
+}
+
+impl Backend {
+    // 到期的检查任务, 以及需要开始的故障转移
+    fn sentinel_cron(&self) -> Vec<(Duration, Task)> {
+        let now = now_ms();
+        let my_port = self.config().port;
+        let mut inner = self.sentinel.lock();
+        let (myid, current_epoch) = (inner.myid.clone(), inner.current_epoch);
+        let mut tasks = vec![];
+        for m in inner.masters.values_mut() {
+            let period = (m.down_after / 2).clamp(100, 1000);
+            if now - m.last_check < period {
+                continue;
+            }
+            m.last_check = now;
+            let timeout = Duration::from_millis(period as u64);
+            let name = m.name.clone();
+            tasks.push((
+                timeout,
+                Task::CheckMaster {
+                    name: name.clone(),
+                    host: m.instance.host.clone(),
+                    port: m.instance.port,
+                },
+            ));
+            for r in m.replicas.values() {
+                tasks.push((
+                    timeout,
+                    Task::CheckReplica {
+                        name: name.clone(),
+                        host: r.instance.host.clone(),
+                        port: r.instance.port,
+                    },
+                ));
+            }
+            let ask_down = m.is_sdown(now);
+            for s in m.sentinels.values() {
+                let hello = Hello {
+                    ip: String::new(),
+                    port: my_port,
+                    runid: myid.clone(),
+                    current_epoch,
+                    master_name: name.clone(),
+                    master_ip: m.instance.host.clone(),
+                    master_port: m.instance.port,
+                    master_config_epoch: m.config_epoch,
+                };
+                tasks.push((
+                    timeout,
+                    Task::Hello {
+                        name: name.clone(),
+                        host: s.instance.host.clone(),
+                        port: s.instance.port,
+                        hello,
+                        ask_down,
+                    },
+                ));
+            }
+            let can_start = m.is_odown(now) && now - m.failover_start > 2 * m.failover_timeout;
+            if !m.failover_in_progress && (m.force_failover || can_start) {
+                if !m.force_failover {
+                    info!("+odown master {} {}", name, m.instance.addr());
+                }
+                m.failover_in_progress = true;
+                m.failover_start = now;
+                tasks.push((
+                    timeout,
+                    Task::Failover {
+                        name,
+                        force: m.force_failover,
+                        start: now,
+                    },
+                ));
+            }
+        }
+        tasks
+    }
+
+    // master 的 INFO: 只有报告角色为 master 时才是有效回复, 同时发现它的 replica
+    fn sentinel_master_reply(
+        &self,
+        name: &str,
+        host: &str,
+        port: u16,
+        info: &HashMap<String, String>,
+    ) {
+        let now = now_ms();
+        let mut inner = self.sentinel.lock();
+        let Some(m) = inner.masters.get_mut(name) else {
+            return;
+        };
+        if (m.instance.host.as_str(), m.instance.port) != (host, port)
+            || info.get("role").map(String::as_str) != Some("master")
+        {
+            return;
+        }
+        m.instance.last_ok = now;
+        for (key, value) in info {
+            let is_replica = key
+                .strip_prefix("slave")
+                .is_some_and(|n| !n.is_empty() && n.bytes().all(|c| c.is_ascii_digit()));
+            if !is_replica {
+                continue;
+            }
+            let fields = value
+                .split(',')
+                .filter_map(|f| f.split_once('='))
+                .collect::<HashMap<_, _>>();
+            let (Some(ip), Some(Ok(port))) = (
+                fields.get("ip"),
+                fields.get("port").map(|p| p.parse::<u16>()),
+            ) else {
+                continue;
+            };
+            let addr = format!("{}:{}", ip, port);
+            m.replicas.entry(addr).or_insert_with(|| {
+                info!("+slave {}:{} {}", ip, port, name);
+                ReplicaInstance::new(Instance::new(ip.to_string(), port, now))
+            });
+        }
+    }
+
+    // replica 的 INFO; 返回它需要复制的 master, 如果它没有复制当前的 master
+    fn sentinel_replica_reply(
+        &self,
+        name: &str,
+        host: &str,
+        port: u16,
+        info: &HashMap<String, String>,
+    ) -> Option<(String, u16)> {
+        let now = now_ms();
+        let mut inner = self.sentinel.lock();
+        let m = inner.masters.get_mut(name)?;
+        let r = m.replicas.get_mut(&format!("{}:{}", host, port))?;
+        r.instance.last_ok = now;
+        r.reported_master = info.get("role").map(String::as_str) == Some("master");
+        r.master_host = info.get("master_host").cloned();
+        r.master_port = info
+            .get("master_port")
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(0);
+        r.master_link_up = info.get("master_link_status").map(String::as_str) == Some("up");
+        r.offset = info
+            .get("slave_repl_offset")
+            .and_then(|o| o.parse().ok())
+            .unwrap_or(0);
+        let follows = !r.reported_master
+            && r.master_host.as_deref() == Some(m.instance.host.as_str())
+            && r.master_port == m.instance.port;
+        // 故障转移期间和 master 下线时不修改 replica 的配置
+        (!follows && !m.failover_in_progress && !m.is_sdown(now))
+            .then(|| (m.instance.host.clone(), m.instance.port))
+    }
+
+    // 其他 sentinel 对 HELLO 的回复
+    fn sentinel_peer_reply(&self, name: &str, host: &str, port: u16, reply: Result<bool>) {
+        let mut inner = self.sentinel.lock();
+        let Some(m) = inner.masters.get_mut(name) else {
+            return;
+        };
+        if let Some(peer) = m.sentinels.get_mut(&format!("{}:{}", host, port)) {
+            match reply {
+                Ok(down) => {
+                    peer.instance.last_ok = now_ms();
+                    peer.master_down = down;
+                }
+                Err(_) => peer.master_down = false,
+            }
+        }
+    }
+
+    // 进入新的 epoch 并为自己投票; 等待期间为其他 sentinel 投过票时返回 None
+    fn sentinel_new_epoch(&self, name: &str, force: bool, start: i64) -> Option<Election> {
+        let mut inner = self.sentinel.lock();
+        if !force && inner.masters.get(name)?.failover_start != start {
+            return None;
+        }
+        inner.current_epoch += 1;
+        let (epoch, myid) = (inner.current_epoch, inner.myid.clone());
+        let m = inner.masters.get_mut(name)?;
+        m.leader = Some(myid.clone());
+        m.leader_epoch = epoch;
+        let peers = m
+            .sentinels
+            .values()
+            .map(|s| (s.instance.host.clone(), s.instance.port))
+            .collect::<Vec<_>>();
+        // 包括自己在内的多数
+        let total = peers.len() + 1;
+        let majority = total / 2 + 1;
+        Some(Election {
+            epoch,
+            myid,
+            host: m.instance.host.clone(),
+            port: m.instance.port,
+            needed: majority.max(m.quorum),
+            peers,
+            failover_timeout: Duration::from_millis(m.failover_timeout as u64),
+        })
+    }
+
+    // 在线的 replica 中复制进度最新的一个
+    fn sentinel_select_replica(&self, name: &str) -> Option<(String, u16)> {
+        let now = now_ms();
+        let inner = self.sentinel.lock();
+        let m = inner.masters.get(name)?;
+        m.replicas
+            .values()
+            .filter(|r| !r.instance.is_sdown(now, m.down_after) && !r.reported_master)
+            .max_by(|a, b| {
+                a.offset
+                    .cmp(&b.offset)
+                    .then_with(|| b.instance.addr().cmp(&a.instance.addr()))
+            })
+            .map(|r| (r.instance.host.clone(), r.instance.port))
+    }
+
+    // replica 已被提升, 切换 master 并返回需要重新配置的其他 replica
+    fn sentinel_promoted(
+        &self,
+        name: &str,
+        host: &str,
+        port: u16,
+        epoch: u64,
+    ) -> Vec<(String, u16)> {
+        let now = now_ms();
+        let mut inner = self.sentinel.lock();
+        let Some(m) = inner.masters.get_mut(name) else {
+            return vec![];
+        };
+        m.switch_master(host.to_string(), port, epoch, now);
+        m.replicas
+            .values()
+            .map(|r| (r.instance.host.clone(), r.instance.port))
+            .collect()
+    }
+
+    fn sentinel_failover_end(&self, name: &str) {
+        let mut inner = self.sentinel.lock();
+        if let Some(m) = inner.masters.get_mut(name) {
+            m.failover_in_progress = false;
+            m.force_failover = false;
+        }
+    }
+}
+
+// IS-MASTER-DOWN-BY-ADDR 的回复: (down, leader, leader-epoch)
+fn parse_vote(reply: &RespFrame) -> Result<(bool, Option<String>, u64)> {
+    let RespFrame::Array(array) = reply else {
+        bail!("unexpected reply: {:?}", reply);
+    };
+    match array.as_slice() {
+        [RespFrame::Integer(down), RespFrame::BulkString(leader), RespFrame::Integer(epoch)] => {
+            let leader = String::from_utf8_lossy(leader).into_owned();
+            Ok((*down == 1, (leader != "*").then_some(leader), *epoch as u64))
+        }
+        _ => bail!("unexpected reply: {:?}", reply),
+    }
+}
+
+// INFO 的回复解析为 field -> value
+fn parse_info(reply: &RespFrame) -> HashMap<String, String> {
+    match reply {
+        RespFrame::BulkString(s) => String::from_utf8_lossy(s)
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(k, v)| (k.to_string(), v.trim().to_string()))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+// 到实例的连接, 每个请求都有超时
+struct Conn {
+    stream: TcpStream,
+    buf: BytesMut,
+    timeout: Duration,
+}
+
+impl Conn {
+    async fn connect(host: &str, port: u16, timeout: Duration) -> Result<Self> {
+        let stream = tokio::time::timeout(timeout, TcpStream::connect((host, port))).await??;
+        Ok(Conn {
+            stream,
+            buf: BytesMut::new(),
+            timeout,
+        })
+    }
+
+    // 连接其他 sentinel, 配置了 sentinel-pass 时先认证
+    async fn connect_sentinel(
+        host: &str,
+        port: u16,
+        auth: Option<&str>,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let mut conn = Self::connect(host, port, timeout).await?;
+        if let Some(password) = auth {
+            conn.command(&["AUTH", password]).await?;
+        }
+        Ok(conn)
+    }
+
+    // 发送命令并读取回复, 错误回复视为失败
+    async fn command(&mut self, args: &[impl AsRef<str>]) -> Result<RespFrame> {
+        let frame: RespFrame = RespArray::new(
+            args.iter()
+                .map(|s| BulkString::new(s.as_ref().to_string()).into())
+                .collect::<Vec<_>>(),
+        )
+        .into();
+        tokio::time::timeout(self.timeout, self.request(frame)).await?
+    }
+
+    async fn request(&mut self, frame: RespFrame) -> Result<RespFrame> {
+        self.stream.write_all(&frame.encode()).await?;
+        loop {
+            match RespFrame::decode(&mut self.buf) {
+                Ok(RespFrame::Error(e)) => bail!("{}", e.as_str()),
+                Ok(reply) => return Ok(reply),
+                Err(RespError::NotComplete) => {
+                    if self.stream.read_buf(&mut self.buf).await? == 0 {
+                        bail!("connection closed");
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{network::testing::start_server, SentinelMonitor, ServerConfig};
+
+    const MASTER: &str = "mymaster";
+
+    fn sentinel(requirepass: Option<&str>) -> Backend {
+        Backend::with_config(ServerConfig {
+            sentinel: true,
+            sentinel_monitors: vec![SentinelMonitor {
+                name: MASTER.to_string(),
+                host: "127.0.0.1".to_string(),
+                port: 6379,
+                quorum: 2,
+                down_after: 5000,
+                failover_timeout: 60000,
+                known_sentinels: vec![
+                    ("127.0.0.1".to_string(), 26380),
+                    ("127.0.0.1".to_string(), 26381),
+                ],
+            }],
+            requirepass: requirepass.map(String::from),
+            ..Default::default()
+        })
+    }
+
+    // IS-MASTER-DOWN-BY-ADDR 的回复: (down, leader, leader-epoch)
+    fn vote(backend: &Backend, epoch: u64, runid: &str) -> (bool, Option<String>, u64) {
+        parse_vote(&backend.sentinel_is_master_down("127.0.0.1", 6379, epoch, runid)).unwrap()
+    }
+
+    #[test]
+    fn test_parse_vote() -> Result<()> {
+        let reply: RespFrame = RespArray::new(vec![
+            RespFrame::Integer(1),
+            BulkString::new("abc").into(),
+            RespFrame::Integer(5),
+        ])
+        .into();
+        assert_eq!(parse_vote(&reply)?, (true, Some("abc".to_string()), 5));
+        let reply: RespFrame = RespArray::new(vec![
+            RespFrame::Integer(0),
+            BulkString::new("*").into(),
+            RespFrame::Integer(0),
+        ])
+        .into();
+        assert_eq!(parse_vote(&reply)?, (false, None, 0));
+        let reply: RespFrame = RespArray::new(vec![RespFrame::Integer(1)]).into();
+        assert!(parse_vote(&reply).is_err());
+        assert!(parse_vote(&RespFrame::Integer(1)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_one_vote_per_epoch() {
+        let backend = sentinel(None);
+        // 只询问是否下线时不投票
+        assert_eq!(vote(&backend, 1, "*"), (false, None, 0));
+        assert_eq!(vote(&backend, 1, "a"), (false, Some("a".to_string()), 1));
+        // 同一个 epoch 只投一次票
+        assert_eq!(vote(&backend, 1, "b"), (false, Some("a".to_string()), 1));
+        // 更旧的 epoch 不改变投票
+        assert_eq!(vote(&backend, 2, "b"), (false, Some("b".to_string()), 2));
+        assert_eq!(vote(&backend, 1, "c"), (false, Some("b".to_string()), 2));
+    }
+
+    #[test]
+    fn test_select_replica() {
+        let backend = sentinel(None);
+        assert_eq!(backend.sentinel_select_replica(MASTER), None);
+        let now = now_ms();
+        {
+            let mut inner = backend.sentinel.lock();
+            let m = inner.masters.get_mut(MASTER).unwrap();
+            let mut add = |port: u16, offset: u64, last_ok: i64, reported_master: bool| {
+                let mut r = ReplicaInstance::new(Instance::new("127.0.0.1".to_string(), port, 0));
+                r.instance.last_ok = last_ok;
+                r.offset = offset;
+                r.reported_master = reported_master;
+                m.replicas.insert(r.instance.addr(), r);
+            };
+            // 下线的和自称 master 的 replica 即使进度更新也不会被选中
+            add(7000, 300, 0, false);
+            add(7001, 400, now, true);
+            // 进度相同时选择地址较小的
+            add(7003, 200, now, false);
+            add(7002, 200, now, false);
+            add(7004, 100, now, false);
+        }
+        assert_eq!(
+            backend.sentinel_select_replica(MASTER),
+            Some(("127.0.0.1".to_string(), 7002))
+        );
+    }
+
+    #[test]
+    fn test_new_epoch() {
+        let backend = sentinel(None);
+        let start = backend.sentinel.lock().masters[MASTER].failover_start;
+        let election = backend.sentinel_new_epoch(MASTER, false, start).unwrap();
+        assert_eq!(election.epoch, 1);
+        assert_eq!(election.peers.len(), 2);
+        // 3 个 sentinel 的多数为 2, 与 quorum 相同
+        assert_eq!(election.needed, 2);
+        assert_eq!(
+            backend.sentinel.lock().masters[MASTER].leader,
+            Some(election.myid.clone())
+        );
+
+        // 为其他 sentinel 投票后放弃已经计划的故障转移
+        vote(&backend, 2, "other");
+        assert!(backend.sentinel_new_epoch(MASTER, false, start).is_none());
+        assert_eq!(backend.sentinel.lock().current_epoch, 2);
+        // SENTINEL FAILOVER 不需要检查
+        assert_eq!(
+            backend
+                .sentinel_new_epoch(MASTER, true, start)
+                .map(|e| e.epoch),
+            Some(3)
+        );
+        assert!(backend.sentinel_new_epoch("other", true, start).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_hello_auth() -> Result<()> {
+        let backend = sentinel(Some("secret"));
+        let port = start_server(backend.clone()).await?;
+        let hello = Hello {
+            ip: String::new(),
+            port: 26390,
+            runid: "peer".to_string(),
+            current_epoch: 1,
+            master_name: MASTER.to_string(),
+            master_ip: "10.0.0.1".to_string(),
+            master_port: 6379,
+            master_config_epoch: 1,
+        };
+        let timeout = Duration::from_secs(1);
+        // 需要认证的 sentinel 拒绝没有密码的 HELLO
+        assert!(
+            send_hello("127.0.0.1", port, None, hello.clone(), false, timeout)
+                .await
+                .is_err()
+        );
+        let master = |b: &Backend| {
+            let inner = b.sentinel.lock();
+            inner.masters[MASTER].instance.addr()
+        };
+        assert_eq!(master(&backend), "127.0.0.1:6379");
+        let auth = Some("secret");
+        assert!(!send_hello("127.0.0.1", port, auth, hello, false, timeout).await?);
+        assert_eq!(master(&backend), "10.0.0.1:6379");
+        Ok(())
+    }
+}